@@ -1,4 +1,5 @@
 mod io;
+mod sync_cli;
 
 use std::{
     env,
@@ -22,7 +23,7 @@ use axum::{
     response::IntoResponse,
     routing::any,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use directories::{BaseDirs, ProjectDirs};
 use eframe::{
     egui::{self},
@@ -48,7 +49,10 @@ use tracing_subscriber::EnvFilter;
 
 #[cfg(feature = "embed-jre")]
 use crate::io::extract_zip;
-use crate::io::{extract_file, resolve_java};
+use crate::{
+    io::{extract_file, resolve_java},
+    sync_cli::SyncArgs,
+};
 
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 const APP_NAME: &str = "Manatan";
@@ -183,6 +187,15 @@ struct Cli {
     /// Local novel directory (absolute or relative to data dir)
     #[arg(long, env = "MANATAN_LOCAL_LN_PATH")]
     local_novel_path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Commands {
+    /// Sync reading data from the command line (stop the server first)
+    Sync(SyncArgs),
 }
 
 fn parse_boolish(value: &str) -> Result<bool, String> {
@@ -607,10 +620,31 @@ fn main() -> eframe::Result<()> {
         true => EnvFilter::builder().parse_lossy("info"),
         false => EnvFilter::builder().parse_lossy(rust_log),
     };
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(env_filter);
+    // Subcommands may write their output to stdout, so keep logs off it
+    match args.command {
+        Some(_) => subscriber.with_writer(std::io::stderr).init(),
+        None => subscriber.init(),
+    }
 
     let data_dir = resolve_data_dir();
 
+    if let Some(Commands::Sync(sync_args)) = args.command.clone() {
+        let local_novel_path =
+            resolve_path_option(args.local_novel_path.as_ref(), &data_dir, "local-novel");
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+        if let Err(err) = rt.block_on(sync_cli::run(
+            sync_args,
+            &data_dir,
+            PathBuf::from(local_novel_path),
+            args.port,
+        )) {
+            error!("Sync failed: {err:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let server_data_dir = data_dir.clone();
     let gui_data_dir = data_dir.clone();
 
@@ -1184,8 +1218,12 @@ async fn run_server(
     let ocr_router = manatan_ocr_server::create_router(data_dir.clone());
     let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone());
     let audio_router = manatan_audio_server::create_router(data_dir.clone());
-    let sync_router = manatan_sync_server::create_router(data_dir.clone());
-    let novel_router = manatan_novel_server::create_router(data_dir.clone(), PathBuf::from(local_novel_path_str));
+    let novel_state = manatan_novel_server::NovelState::new(data_dir.clone(), PathBuf::from(local_novel_path_str));
+    let sync_router = manatan_sync_server::create_router_with_library(
+        data_dir.clone(),
+        std::sync::Arc::new(novel_state.clone()),
+    );
+    let novel_router = manatan_novel_server::create_router_with_state(novel_state);
     let system_router = Router::new().route("/version", any(current_version_handler));
 
     let cors = CorsLayer::new()
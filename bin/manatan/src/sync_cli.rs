@@ -0,0 +1,242 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, anyhow};
+use clap::{Args, Subcommand};
+use manatan_sync_server::{SyncPayload, SyncState, archive, cli, profile};
+
+/// `manatan sync`: drive the sync backend without the web UI.
+///
+/// Runs against the same data directory as the server, so stop the server
+/// first; the sync database can only be opened by one process at a time.
+#[derive(Args, Debug, Clone)]
+pub struct SyncArgs {
+    /// Profile to work on (defaults to the default profile)
+    #[arg(long, global = true, env = "MANATAN_PROFILE")]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: SyncCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SyncCommand {
+    /// Show the connected backend, account and last sync
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Connect Google Drive by opening a URL on any device and pasting back the redirect
+    Login {
+        /// Redirect URI for the OAuth consent screen (defaults to this server's callback)
+        #[arg(long)]
+        redirect_uri: Option<String>,
+    },
+    /// Replace local reading data with the remote copy
+    Pull,
+    /// Replace the remote copy with local reading data
+    Push {
+        /// Push even if the remote changed since the last sync
+        #[arg(long)]
+        force: bool,
+    },
+    /// Merge local and remote reading data, like a background sync
+    Merge,
+    /// Write reading data as JSON, for backups
+    Export {
+        /// Output file (defaults to stdout)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Export the remote copy instead of local data
+        #[arg(long)]
+        remote: bool,
+    },
+    /// Read reading data from an exported JSON file into the local library
+    Import {
+        /// Exported file, or `-` for stdin
+        input: PathBuf,
+    },
+    /// Write all sync data and book files to a backup archive
+    Backup {
+        /// Archive to create
+        output: PathBuf,
+    },
+    /// Merge a backup archive into the local data
+    Restore {
+        /// Archive written by `backup` or the backup endpoint
+        input: PathBuf,
+    },
+    /// List the created profiles
+    Profiles,
+    /// Create a profile, so the server serves requests for it
+    CreateProfile {
+        /// Up to 32 letters, digits, '-' or '_'
+        name: String,
+    },
+}
+
+pub async fn run(
+    args: SyncArgs,
+    data_dir: &Path,
+    local_novel_path: PathBuf,
+    port: u16,
+) -> anyhow::Result<()> {
+    // The profile list is a plain file, these need no databases
+    match &args.command {
+        SyncCommand::Profiles => {
+            for name in profile::list(data_dir)? {
+                println!("{name}");
+            }
+            return Ok(());
+        }
+        SyncCommand::CreateProfile { name } => {
+            match profile::create(data_dir, name)? {
+                true => println!("Created profile {name}"),
+                false => println!("Profile {name} already exists"),
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut novel_state =
+        manatan_novel_server::NovelState::new(data_dir.to_path_buf(), local_novel_path);
+    let mut sync_dir = data_dir.to_path_buf();
+    if let Some(name) = &args.profile {
+        profile::validate_name(name)?;
+        if !profile::exists(data_dir, name)? {
+            return Err(anyhow!(
+                "No profile named {name}; create it with `manatan sync create-profile {name}`"
+            ));
+        }
+        novel_state = novel_state.profile(name);
+        sync_dir = profile::profile_dir(data_dir, name);
+    }
+    let state = SyncState::new(sync_dir).with_library(Arc::new(novel_state));
+
+    match args.command {
+        SyncCommand::Status { json } => {
+            let status = cli::status(&state).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+                return Ok(());
+            }
+            println!("Backend:    {:?}", status.backend);
+            println!(
+                "Connected:  {}",
+                if status.connected { "yes" } else { "no" }
+            );
+            if let Some(email) = &status.email {
+                println!("Account:    {email}");
+            }
+            println!("Device:     {}", status.device_id);
+            println!(
+                "Last sync:  {}",
+                status
+                    .last_sync
+                    .map_or_else(|| "never".to_string(), |ms| ms.to_string())
+            );
+            println!("Conflicts:  {}", status.pending_conflicts);
+        }
+        SyncCommand::Login { redirect_uri } => {
+            let redirect_uri = redirect_uri.unwrap_or_else(|| {
+                format!("http://127.0.0.1:{port}/api/sync/auth/google/callback")
+            });
+            let flow = cli::google_login_start(&state, &redirect_uri)?;
+            println!("Open this URL in a browser on any device and allow access:\n");
+            println!("  {}\n", flow.auth_url);
+            println!("The browser then tries to open {redirect_uri}, which may fail to load.");
+            print!("Paste the full URL from its address bar (or just the code): ");
+            io::stdout().flush()?;
+
+            let mut pasted = String::new();
+            io::stdin().lock().read_line(&mut pasted)?;
+            cli::google_login_finish(&state, &pasted).await?;
+            println!("Connected to Google Drive");
+        }
+        SyncCommand::Pull => match cli::pull(&state).await? {
+            Some(payload) => println!("Pulled {}", summary(&payload)),
+            None => println!("No remote data found"),
+        },
+        SyncCommand::Push { force } => {
+            let response = cli::push(&state, force)
+                .await
+                .context("Push failed; run `manatan sync merge` or retry with --force")?;
+            println!("Pushed (etag {})", response.etag);
+        }
+        SyncCommand::Merge => {
+            let pending = cli::merge(&state).await?;
+            println!("Merged with remote");
+            for deletion in pending {
+                println!(
+                    "Kept {:?} {}: its deletion needs confirming in the app",
+                    deletion.section, deletion.id
+                );
+            }
+        }
+        SyncCommand::Export { output, remote } => {
+            let payload = cli::export(&state, remote)
+                .await?
+                .ok_or_else(|| anyhow!("No remote data found"))?;
+            let json = serde_json::to_vec_pretty(&payload)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, json)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    eprintln!("Exported {} to {}", summary(&payload), path.display());
+                }
+                None => io::stdout().write_all(&json)?,
+            }
+        }
+        SyncCommand::Import { input } => {
+            let bytes = match input.as_os_str() == "-" {
+                true => {
+                    let mut bytes = Vec::new();
+                    io::stdin().read_to_end(&mut bytes)?;
+                    bytes
+                }
+                false => std::fs::read(&input)
+                    .with_context(|| format!("Failed to read {}", input.display()))?,
+            };
+            let payload = cli::import(&state, &bytes).await?;
+            println!("Imported {}", summary(&payload));
+        }
+        SyncCommand::Backup { output } => {
+            let file = std::fs::File::create(&output)
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+            let manifest = archive::export(&state, file).await?;
+            println!(
+                "Wrote {} files to {}",
+                manifest.files.len(),
+                output.display()
+            );
+        }
+        SyncCommand::Restore { input } => {
+            let file = std::fs::File::open(&input)
+                .with_context(|| format!("Failed to open {}", input.display()))?;
+            let imported = archive::import(&state, file).await?;
+            println!(
+                "Restored {} progress, {} metadata entries and {} book files ({} conflicts)",
+                imported.progress,
+                imported.metadata,
+                imported.books_restored,
+                imported.conflicts.len()
+            );
+        }
+        SyncCommand::Profiles | SyncCommand::CreateProfile { .. } => {}
+    }
+
+    Ok(())
+}
+
+fn summary(payload: &SyncPayload) -> String {
+    format!(
+        "{} progress, {} metadata entries",
+        payload.ln_progress.len(),
+        payload.ln_metadata.len()
+    )
+}
@@ -1239,8 +1239,12 @@ async fn start_web_server(
     // but the actual router initialization below uses it.
     let manatan_state = build_state(manatan_config).await?;
     let manatan_router = build_router_without_cors(manatan_state);
-    let sync_router = manatan_sync_server::create_router(data_dir.clone());
-    let novel_router = manatan_novel_server::create_router(data_dir.clone(), PathBuf::from(local_novel_path.clone()));
+    let novel_state = manatan_novel_server::NovelState::new(data_dir.clone(), PathBuf::from(local_novel_path.clone()));
+    let sync_router = manatan_sync_server::create_router_with_library(
+        data_dir.clone(),
+        std::sync::Arc::new(novel_state.clone()),
+    );
+    let novel_router = manatan_novel_server::create_router_with_state(novel_state);
 
     let ocr_router = manatan_ocr_server::create_router(data_dir.clone());
     let yomitan_router = manatan_yomitan_server::create_router(data_dir.clone());
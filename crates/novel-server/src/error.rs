@@ -20,6 +20,8 @@ pub enum NovelError {
     Multipart(#[from] axum::extract::multipart::MultipartError),
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Search index error: {0}")]
+    Search(#[from] rusqlite::Error),
 }
 
 impl IntoResponse for NovelError {
@@ -31,6 +33,7 @@ impl IntoResponse for NovelError {
             NovelError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "IO Error"),
             NovelError::Multipart(_) => (StatusCode::BAD_REQUEST, "Multipart Error"),
             NovelError::BadRequest(ref msg) => (StatusCode::BAD_REQUEST, msg.as_str()),
+            NovelError::Search(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Search Index Error"),
         };
 
         let body = Json(json!({
@@ -0,0 +1,703 @@
+use std::io::{Cursor, Write};
+
+use rusqlite::{Connection, params};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    error::NovelError,
+    search::{Block, chapter_blocks},
+    state::NovelState,
+    types::{HighlightExportFormat, LNMetadata, LNParsedBook, LNProgress},
+};
+
+// ============================================================================
+// Highlight Export
+// ============================================================================
+//
+// Highlights with the sentence around them, for sentence mining. The
+// sentence is cut from the chapter text of the highlight's block, on
+// Japanese and Western sentence endings.
+
+const SENTENCE_ENDINGS: &[char] = &['。', '！', '？', '!', '?', '.', '\n'];
+const CLOSING_MARKS: &[char] = &['」', '』', '）', '】', '"', '”', '’', ')'];
+
+const DELIMITED_COLUMNS: [&str; 5] = ["Text", "Sentence", "Book", "Chapter", "Created"];
+
+/// Note type and deck of exported cards stay the same across exports, and
+/// notes are keyed by highlight ID, so importing again updates them
+const ANKI_MODEL_ID: i64 = 1_718_032_114_001;
+const ANKI_MODEL_NAME: &str = "Manatan Highlight";
+const ANKI_DECK_ID: i64 = 1_718_032_114_002;
+const ANKI_DECK_NAME: &str = "Manatan Highlights";
+const ANKI_FIELD_SEPARATOR: char = '\u{1f}';
+const ANKI_CARD_CSS: &str =
+    ".card { font-family: sans-serif; font-size: 24px; text-align: center; }
+.source { font-size: 14px; opacity: 0.6; }";
+
+struct HighlightRow {
+    id: String,
+    book_title: String,
+    chapter_label: String,
+    text: String,
+    sentence: String,
+    created_at: i64,
+    /// Chapter, block and offset, for reading order
+    position: (i32, usize, i32),
+}
+
+/// Highlights of one book, or of the whole library, in the given format
+pub fn export_highlights(
+    state: &NovelState,
+    book_id: Option<&str>,
+    format: HighlightExportFormat,
+) -> Result<Vec<u8>, NovelError> {
+    let rows = collect_rows(state, book_id)?;
+    match format {
+        HighlightExportFormat::Csv => Ok(to_delimited(&rows, ',').into_bytes()),
+        HighlightExportFormat::Tsv => Ok(to_delimited(&rows, '\t').into_bytes()),
+        HighlightExportFormat::Markdown => Ok(to_markdown(&rows).into_bytes()),
+        HighlightExportFormat::Apkg => to_apkg(&rows),
+    }
+}
+
+fn collect_rows(
+    state: &NovelState,
+    book_id: Option<&str>,
+) -> Result<Vec<HighlightRow>, NovelError> {
+    let progress_entries = match book_id {
+        Some(id) => state
+            .db
+            .get(format!("progress:{id}"))?
+            .map(|value| (id.to_string(), value))
+            .into_iter()
+            .collect(),
+        None => state
+            .db
+            .scan_prefix("progress:")
+            .map(|item| {
+                item.map(|(key, value)| {
+                    let id = String::from_utf8_lossy(&key["progress:".len()..]).to_string();
+                    (id, value)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let mut books = Vec::new();
+    for (id, value) in progress_entries {
+        let progress: LNProgress = serde_json::from_slice(&value)?;
+        if progress.highlights.is_empty() {
+            continue;
+        }
+        let metadata: Option<LNMetadata> = state
+            .db
+            .get(format!("metadata:{id}"))?
+            .map(|bytes| serde_json::from_slice(&bytes))
+            .transpose()?;
+        let content: Option<LNParsedBook> = state
+            .db
+            .get(format!("content:{id}"))?
+            .map(|bytes| serde_json::from_slice(&bytes))
+            .transpose()?;
+        let chapters: Vec<Vec<Block>> = content
+            .map(|content| {
+                content
+                    .chapters
+                    .iter()
+                    .map(|html| chapter_blocks(html))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let book_title = metadata
+            .as_ref()
+            .map_or_else(|| id.clone(), |metadata| metadata.title.clone());
+
+        let mut rows: Vec<HighlightRow> = progress
+            .highlights
+            .iter()
+            .map(|highlight| {
+                let blocks = usize::try_from(highlight.chapter_index)
+                    .ok()
+                    .and_then(|index| chapters.get(index))
+                    .map_or(&[][..], Vec::as_slice);
+                let block_index = blocks
+                    .iter()
+                    .position(|block| block.id.as_deref() == Some(highlight.block_id.as_str()));
+                let sentence = block_index
+                    .and_then(|index| {
+                        sentence_around(
+                            &blocks[index].text,
+                            &highlight.text,
+                            highlight.start_offset,
+                        )
+                    })
+                    .or_else(|| {
+                        blocks
+                            .iter()
+                            .find_map(|block| sentence_around(&block.text, &highlight.text, 0))
+                    })
+                    .unwrap_or_else(|| highlight.text.trim().to_string());
+
+                HighlightRow {
+                    id: highlight.id.clone(),
+                    book_title: book_title.clone(),
+                    chapter_label: chapter_label(metadata.as_ref(), highlight.chapter_index),
+                    text: highlight.text.trim().to_string(),
+                    sentence,
+                    created_at: highlight.created_at,
+                    position: (
+                        highlight.chapter_index,
+                        block_index.unwrap_or(usize::MAX),
+                        highlight.start_offset,
+                    ),
+                }
+            })
+            .collect();
+        rows.sort_by_key(|row| row.position);
+        books.push((book_title, rows));
+    }
+
+    books.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(books.into_iter().flat_map(|(_, rows)| rows).collect())
+}
+
+/// Label of the last TOC entry at or before the chapter
+fn chapter_label(metadata: Option<&LNMetadata>, chapter_index: i32) -> String {
+    metadata
+        .and_then(|metadata| {
+            metadata
+                .toc
+                .iter()
+                .filter(|item| item.chapter_index <= chapter_index)
+                .max_by_key(|item| item.chapter_index)
+        })
+        .map(|item| item.label.trim().to_string())
+        .unwrap_or_else(|| format!("Chapter {}", chapter_index + 1))
+}
+
+/// The sentence of `text` that holds the highlighted text, taking the
+/// occurrence closest to the highlight's offset
+fn sentence_around(text: &str, highlight: &str, offset: i32) -> Option<String> {
+    let highlight = highlight.trim();
+    if highlight.is_empty() {
+        return None;
+    }
+    let offset = usize::try_from(offset).unwrap_or_default();
+    let (start, _) = text
+        .match_indices(highlight)
+        .min_by_key(|(start, _)| text[..*start].chars().count().abs_diff(offset))?;
+    let end = start + highlight.len();
+
+    // After the previous sentence's ending and its closing marks
+    let mut sentence_start = 0;
+    let mut before = text[..start].char_indices().rev().peekable();
+    while let Some((index, c)) = before.next() {
+        if SENTENCE_ENDINGS.contains(&c) {
+            sentence_start = index + c.len_utf8();
+            break;
+        }
+        if CLOSING_MARKS.contains(&c)
+            && before
+                .peek()
+                .is_some_and(|(_, previous)| SENTENCE_ENDINGS.contains(previous))
+        {
+            sentence_start = index + c.len_utf8();
+            break;
+        }
+    }
+
+    // Through the ending and the closing marks after it
+    let mut sentence_end = text.len();
+    let mut after = text[end..].char_indices().peekable();
+    while let Some((index, c)) = after.next() {
+        if SENTENCE_ENDINGS.contains(&c) {
+            sentence_end = end + index + c.len_utf8();
+            while let Some((index, c)) = after.next_if(|(_, c)| {
+                (SENTENCE_ENDINGS.contains(c) && *c != '\n') || CLOSING_MARKS.contains(c)
+            }) {
+                sentence_end = end + index + c.len_utf8();
+            }
+            break;
+        }
+    }
+
+    Some(
+        text[sentence_start..sentence_end.max(end)]
+            .trim()
+            .to_string(),
+    )
+}
+
+fn to_delimited(rows: &[HighlightRow], delimiter: char) -> String {
+    let mut output = String::new();
+    let mut push_line = |fields: &[&str]| {
+        let line: Vec<String> = fields
+            .iter()
+            .map(|field| quote_field(field, delimiter))
+            .collect();
+        output.push_str(&line.join(&delimiter.to_string()));
+        output.push_str("\r\n");
+    };
+
+    push_line(&DELIMITED_COLUMNS);
+    for row in rows {
+        let created = format_time(row.created_at);
+        push_line(&[
+            &row.text,
+            &row.sentence,
+            &row.book_title,
+            &row.chapter_label,
+            &created,
+        ]);
+    }
+    output
+}
+
+pub(crate) fn quote_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn to_markdown(rows: &[HighlightRow]) -> String {
+    let mut output = String::from("# Highlights\n");
+    let mut book = None;
+    let mut chapter = None;
+    for row in rows {
+        if book != Some(&row.book_title) {
+            output.push_str(&format!("\n## {}\n", escape_markdown(&row.book_title)));
+            book = Some(&row.book_title);
+            chapter = None;
+        }
+        if chapter != Some(&row.chapter_label) {
+            output.push_str(&format!("\n### {}\n", escape_markdown(&row.chapter_label)));
+            chapter = Some(&row.chapter_label);
+        }
+
+        let sentence = emphasize(
+            &row.sentence,
+            &row.text,
+            |text| format!("**{}**", escape_markdown(text)),
+            escape_markdown,
+        );
+        output.push_str(&format!("\n> {}\n", sentence.replace('\n', "\n> ")));
+        if !row.sentence.contains(&row.text) {
+            output.push_str(&format!("\n- {}\n", escape_markdown(&row.text)));
+        }
+    }
+    output
+}
+
+/// `sentence` with the first occurrence of `text` marked up by `mark`, and
+/// everything else by `escape`
+fn emphasize(
+    sentence: &str,
+    text: &str,
+    mark: impl Fn(&str) -> String,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    match sentence.find(text).filter(|_| !text.is_empty()) {
+        Some(start) => {
+            let end = start + text.len();
+            format!(
+                "{}{}{}",
+                escape(&sentence[..start]),
+                mark(text),
+                escape(&sentence[end..])
+            )
+        }
+        None => escape(sentence),
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_time(millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// An Anki package: a zip with a legacy (schema 11) collection and an empty
+/// media map
+fn to_apkg(rows: &[HighlightRow]) -> Result<Vec<u8>, NovelError> {
+    let path =
+        std::env::temp_dir().join(format!("manatan-highlights-{}.anki2", uuid::Uuid::new_v4()));
+    let collection =
+        write_collection(&path, rows).and_then(|()| std::fs::read(&path).map_err(NovelError::from));
+    let _ = std::fs::remove_file(&path);
+    let collection = collection?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("collection.anki2", SimpleFileOptions::default())
+        .map_err(std::io::Error::other)?;
+    zip.write_all(&collection)?;
+    zip.start_file("media", SimpleFileOptions::default())
+        .map_err(std::io::Error::other)?;
+    zip.write_all(b"{}")?;
+    Ok(zip.finish().map_err(std::io::Error::other)?.into_inner())
+}
+
+fn write_collection(path: &std::path::Path, rows: &[HighlightRow]) -> Result<(), NovelError> {
+    let now = chrono::Utc::now();
+    let now_secs = now.timestamp();
+    let now_millis = now.timestamp_millis();
+
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(ANKI_SCHEMA)?;
+
+    let fields: Vec<_> = ["Text", "Sentence", "Book", "Chapter"]
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name, "ord": ord, "sticky": false, "rtl": false,
+                "font": "Arial", "size": 20, "media": [],
+            })
+        })
+        .collect();
+    let models = json!({
+        ANKI_MODEL_ID.to_string(): {
+            "id": ANKI_MODEL_ID,
+            "name": ANKI_MODEL_NAME,
+            "type": 0,
+            "mod": now_secs,
+            "usn": -1,
+            "sortf": 0,
+            "did": ANKI_DECK_ID,
+            "flds": fields,
+            "tmpls": [{
+                "name": "Sentence",
+                "ord": 0,
+                "qfmt": "{{Sentence}}",
+                "afmt": "{{FrontSide}}<hr id=answer>{{Text}}<div class=\"source\">{{Book}} · {{Chapter}}</div>",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null,
+            }],
+            "css": ANKI_CARD_CSS,
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "latexsvg": false,
+            "req": [[0, "any", [1]]],
+            "tags": [],
+            "vers": [],
+        }
+    });
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "desc": "", "mod": now_secs, "usn": -1,
+            "collapsed": false, "browserCollapsed": false, "dyn": 0, "conf": 1,
+            "extendNew": 0, "extendRev": 0,
+            "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+        })
+    };
+    let decks = json!({
+        "1": deck(1, "Default"),
+        ANKI_DECK_ID.to_string(): deck(ANKI_DECK_ID, ANKI_DECK_NAME),
+    });
+    let deck_configs = json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60,
+            "autoplay": true, "timer": 0, "replayq": true, "dyn": false,
+            "new": {
+                "delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500,
+                "order": 1, "perDay": 20, "bury": false, "separate": true,
+            },
+            "rev": {
+                "perDay": 200, "ease4": 1.3, "fuzz": 0.05, "minSpace": 1,
+                "ivlFct": 1, "maxIvl": 36500, "bury": false, "hardFactor": 1.2,
+            },
+            "lapse": {
+                "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0,
+            },
+        }
+    });
+    let config = json!({
+        "activeDecks": [1], "curDeck": ANKI_DECK_ID, "newSpread": 0,
+        "collapseTime": 1200, "timeLim": 0, "estTimes": true, "dueCounts": true,
+        "curModel": ANKI_MODEL_ID.to_string(), "nextPos": rows.len() + 1,
+        "sortType": "noteFld", "sortBackwards": false, "addToCur": true,
+    });
+
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            now_secs,
+            now_millis,
+            config.to_string(),
+            models.to_string(),
+            decks.to_string(),
+            deck_configs.to_string()
+        ],
+    )?;
+    {
+        let mut insert_note = transaction
+            .prepare("INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')")?;
+        let mut insert_card = transaction.prepare(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+        )?;
+        for (index, row) in rows.iter().enumerate() {
+            let sentence = emphasize(
+                &row.sentence,
+                &row.text,
+                |text| format!("<b>{}</b>", escape_html(text)),
+                escape_html,
+            );
+            let fields = [
+                escape_html(&row.text),
+                sentence,
+                escape_html(&row.book_title),
+                escape_html(&row.chapter_label),
+            ]
+            .join(&ANKI_FIELD_SEPARATOR.to_string());
+            let id = now_millis + index as i64;
+
+            insert_note.execute(params![
+                id,
+                row.id,
+                ANKI_MODEL_ID,
+                now_secs,
+                fields,
+                row.text,
+                field_checksum(&row.text)
+            ])?;
+            insert_card.execute(params![id, id, ANKI_DECK_ID, now_secs, index as i64 + 1])?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// First 32 bits of the SHA-1 of the sort field, which Anki uses to find
+/// duplicates
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3],
+    ]))
+}
+
+const ANKI_SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null,
+    usn integer not null, ls integer not null, conf text not null,
+    models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null,
+    flds text not null, sfld integer not null, csum integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null,
+    type integer not null, queue integer not null, due integer not null,
+    ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null,
+    factor integer not null, time integer not null, type integer not null
+);
+CREATE TABLE graves (
+    usn integer not null, oid integer not null, type integer not null
+);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::types::LNHighlight;
+
+    fn unique_temp_dir(label: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("manatan-novel-export-{label}-{nanos}"));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        dir
+    }
+
+    fn highlight(
+        id: &str,
+        chapter_index: i32,
+        block_id: &str,
+        text: &str,
+        start: i32,
+    ) -> LNHighlight {
+        LNHighlight {
+            id: id.to_string(),
+            chapter_index,
+            block_id: block_id.to_string(),
+            text: text.to_string(),
+            start_offset: start,
+            end_offset: start + text.chars().count() as i32,
+            created_at: 1_700_000_000_000,
+            updated_at: None,
+        }
+    }
+
+    /// A library with one highlighted book
+    fn state() -> NovelState {
+        let root = unique_temp_dir("library");
+        let state = NovelState::new(root.join("data"), root.join("local-novel"));
+        let metadata = json!({
+            "id": "neko", "title": "吾輩は猫である", "author": "夏目漱石", "addedAt": 0,
+            "stats": { "chapterLengths": [], "totalLength": 0 }, "chapterCount": 2,
+            "toc": [{ "label": "一", "href": "ch1.xhtml", "chapterIndex": 1 }],
+        });
+        let content = LNParsedBook {
+            chapters: vec![
+                r#"<p data-block-id="ch0-b0">表紙</p>"#.to_string(),
+                r#"<p data-block-id="ch1-b0">吾輩は<ruby>猫<rt>ねこ</rt></ruby>である。名前はまだ無い。</p>
+<p data-block-id="ch1-b1">「どこで生れたか、とんと見当がつかぬ。」</p>"#
+                    .to_string(),
+            ],
+            image_blobs: Default::default(),
+            chapter_filenames: Vec::new(),
+            css: None,
+        };
+        let progress = LNProgress {
+            highlights: vec![
+                highlight("h2", 1, "ch1-b1", "見当", 10),
+                highlight("h1", 1, "ch1-b0", "名前", 8),
+            ],
+            ..LNProgress::default()
+        };
+        for (key, value) in [
+            ("metadata:neko", serde_json::to_vec(&metadata)),
+            ("content:neko", serde_json::to_vec(&content)),
+            ("progress:neko", serde_json::to_vec(&progress)),
+        ] {
+            state
+                .db
+                .insert(key, value.expect("value should serialize"))
+                .expect("value should be stored");
+        }
+        state
+    }
+
+    #[test]
+    fn cuts_the_sentence_around_a_highlight() {
+        let text = "猫が好き。猫が嫌い。";
+        assert_eq!(
+            sentence_around(text, "猫", 0).as_deref(),
+            Some("猫が好き。")
+        );
+        assert_eq!(
+            sentence_around(text, "猫", 5).as_deref(),
+            Some("猫が嫌い。")
+        );
+        assert_eq!(
+            sentence_around("He left. The weather is nice! Really?", "weather", 0).as_deref(),
+            Some("The weather is nice!")
+        );
+        assert_eq!(
+            sentence_around("「行こう！」と言った。雨だ", "雨", 0).as_deref(),
+            Some("雨だ")
+        );
+        assert_eq!(sentence_around(text, "犬", 0), None);
+    }
+
+    #[test]
+    fn exports_rows_in_reading_order() {
+        let csv = export_highlights(&state(), None, HighlightExportFormat::Csv)
+            .expect("csv should export");
+        let csv = String::from_utf8(csv).expect("csv should be utf-8");
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "Text,Sentence,Book,Chapter,Created");
+        assert!(lines[1].starts_with("名前,名前はまだ無い。,吾輩は猫である,一,2023-11-14"));
+        assert!(lines[2].starts_with("見当,「どこで生れたか、とんと見当がつかぬ。」,"));
+
+        let markdown = export_highlights(&state(), Some("neko"), HighlightExportFormat::Markdown)
+            .expect("markdown should export");
+        let markdown = String::from_utf8(markdown).expect("markdown should be utf-8");
+        assert!(markdown.contains("## 吾輩は猫である\n\n### 一\n\n> **名前**はまだ無い。\n"));
+
+        assert_eq!(
+            export_highlights(&state(), Some("missing"), HighlightExportFormat::Tsv)
+                .expect("tsv should export"),
+            b"Text\tSentence\tBook\tChapter\tCreated\r\n"
+        );
+    }
+
+    #[test]
+    fn exports_anki_packages() {
+        let apkg = export_highlights(&state(), None, HighlightExportFormat::Apkg)
+            .expect("apkg should export");
+        let mut zip = ZipArchive::new(Cursor::new(apkg)).expect("apkg should be a zip");
+        let mut collection = Vec::new();
+        zip.by_name("collection.anki2")
+            .expect("collection should exist")
+            .read_to_end(&mut collection)
+            .expect("collection should be read");
+        assert!(zip.by_name("media").is_ok());
+
+        let path = unique_temp_dir("apkg").join("collection.anki2");
+        std::fs::write(&path, collection).expect("collection should be written");
+        let connection = Connection::open(&path).expect("collection should open");
+        let notes: Vec<(String, String)> = connection
+            .prepare("SELECT guid, flds FROM notes ORDER BY id")
+            .expect("query should prepare")
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .expect("query should run")
+            .collect::<Result<_, _>>()
+            .expect("notes should be read");
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].0, "h1");
+        assert_eq!(
+            notes[0].1,
+            "名前\u{1f}<b>名前</b>はまだ無い。\u{1f}吾輩は猫である\u{1f}一"
+        );
+        let cards: i64 = connection
+            .query_row(
+                "SELECT count(*) FROM cards WHERE did = ?1",
+                [ANKI_DECK_ID],
+                |row| row.get(0),
+            )
+            .expect("cards should be counted");
+        assert_eq!(cards, 2);
+    }
+}
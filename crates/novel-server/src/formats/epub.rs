@@ -0,0 +1,525 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read, Seek},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use roxmltree::{Document, ParsingOptions};
+use scraper::{ElementRef, Html};
+use tracing::warn;
+use zip::ZipArchive;
+
+use super::{
+    BookInfo, ParsedBook, assemble, expand_self_closing, is_image, normalize_language,
+    process_chapter, resize_cover, resolve_path,
+};
+use crate::{error::NovelError, types::TocItem};
+
+// ============================================================================
+// EPUB
+// ============================================================================
+//
+// The OPF gives metadata, manifest and spine; the table of contents comes
+// from the NCX, or from the EPUB 3 nav document when there is none.
+
+struct ManifestItem {
+    id: String,
+    href: String,
+    /// Path of the file in the archive
+    path: String,
+    media_type: String,
+    properties: String,
+}
+
+pub fn parse_epub(data: &[u8], book_id: &str) -> Result<ParsedBook, NovelError> {
+    let mut zip = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| NovelError::BadRequest(format!("Invalid EPUB: {e}")))?;
+
+    // Container & OPF
+    let container = read_text(&mut zip, "META-INF/container.xml")
+        .ok_or_else(|| invalid("Missing container.xml"))?;
+    let container_doc = parse_xml(&container)?;
+    let opf_path = container_doc
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or_else(|| invalid("Missing rootfile"))?
+        .to_string();
+    let opf = read_text(&mut zip, &opf_path).ok_or_else(|| invalid("Missing OPF"))?;
+    let opf_doc = parse_xml(&opf)?;
+
+    // Metadata
+    let title = metadata_text(&opf_doc, "title").unwrap_or_else(|| "Unknown Title".to_string());
+    let author = metadata_text(&opf_doc, "creator").unwrap_or_else(|| "Unknown Author".to_string());
+    let language = metadata_text(&opf_doc, "language")
+        .and_then(|language| normalize_language(&language))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Manifest & spine
+    let manifest: Vec<ManifestItem> = opf_doc
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|node| {
+            let id = node.attribute("id")?;
+            let href = node.attribute("href")?;
+            Some(ManifestItem {
+                id: id.to_string(),
+                href: href.to_string(),
+                path: resolve_path(&opf_path, href),
+                media_type: node.attribute("media-type").unwrap_or_default().to_string(),
+                properties: node.attribute("properties").unwrap_or_default().to_string(),
+            })
+        })
+        .collect();
+    let by_id: HashMap<&str, &ManifestItem> = manifest
+        .iter()
+        .map(|item| (item.id.as_str(), item))
+        .collect();
+    let spine: Vec<&ManifestItem> = opf_doc
+        .descendants()
+        .filter(|node| node.has_tag_name("itemref"))
+        .filter_map(|node| by_id.get(node.attribute("idref")?).copied())
+        .collect();
+    if spine.is_empty() {
+        return Err(NovelError::BadRequest(
+            "No readable content in spine".to_string(),
+        ));
+    }
+
+    // Cover
+    let cover = find_cover(&opf_doc, &manifest, &by_id)
+        .and_then(|item| read_bytes(&mut zip, &item.path))
+        .and_then(|bytes| match resize_cover(&bytes) {
+            Ok(cover) => Some(cover),
+            Err(e) => {
+                warn!("[EPUB] Cover of {book_id} could not be resized: {e}");
+                None
+            }
+        });
+
+    // CSS
+    let mut css = String::new();
+    let mut seen_css = HashSet::new();
+    for item in manifest.iter().filter(|item| item.media_type == "text/css") {
+        if seen_css.insert(item.path.as_str())
+            && let Some(text) = read_text(&mut zip, &item.path)
+        {
+            css.push_str(&text);
+            css.push('\n');
+        }
+    }
+
+    // Images
+    let mut image_blobs = HashMap::new();
+    for index in 0..zip.len() {
+        let Ok(mut file) = zip.by_index(index) else {
+            continue;
+        };
+        let path = file.name().to_string();
+        if file.is_dir() || !is_image(&path) {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        match file.read_to_end(&mut bytes) {
+            Ok(_) => {
+                image_blobs.insert(path, BASE64.encode(bytes));
+            }
+            Err(e) => warn!("[EPUB] Failed to read image {path} of {book_id}: {e}"),
+        }
+    }
+
+    // Chapters
+    let mut chapters = Vec::new();
+    // Spine position -> chapter it ended up in, or the next one when it was
+    // skipped for having no content
+    let mut spine_chapters = Vec::with_capacity(spine.len());
+    for item in &spine {
+        spine_chapters.push(chapters.len());
+        let Some(raw) = read_text(&mut zip, &item.path) else {
+            continue;
+        };
+        let Some(chapter) = process_chapter(&raw, &item.path, chapters.len()) else {
+            continue;
+        };
+        let file_name = item.path.rsplit('/').next().unwrap_or(&item.path);
+        chapters.push((file_name.to_string(), chapter));
+    }
+    if chapters.is_empty() {
+        return Err(NovelError::BadRequest(
+            "No readable content in spine".to_string(),
+        ));
+    }
+
+    // Table of contents, NCX first and the EPUB 3 nav document otherwise
+    let spine_paths: Vec<&str> = spine.iter().map(|item| item.path.as_str()).collect();
+    let mut toc_links = manifest
+        .iter()
+        .find(|item| item.media_type == "application/x-dtbncx+xml")
+        .and_then(|item| Some((item, read_text(&mut zip, &item.path)?)))
+        .map(|(item, ncx)| ncx_links(&ncx, &item.path))
+        .unwrap_or_default();
+    if toc_links.is_empty() {
+        toc_links = manifest
+            .iter()
+            .find(|item| item.properties.split_whitespace().any(|p| p == "nav"))
+            .and_then(|item| Some((item, read_text(&mut zip, &item.path)?)))
+            .map(|(item, nav)| nav_links(&nav, &item.path))
+            .unwrap_or_default();
+    }
+    let last_chapter = chapters.len() - 1;
+    let toc: Vec<TocItem> = toc_links
+        .into_iter()
+        .filter_map(|(label, href, target)| {
+            let position = spine_paths.iter().position(|path| *path == target)?;
+            Some(TocItem {
+                label,
+                href,
+                chapter_index: spine_chapters[position].min(last_chapter) as i32,
+            })
+        })
+        .collect();
+
+    let info = BookInfo {
+        title,
+        author,
+        language,
+        cover,
+    };
+    Ok(assemble(book_id, info, chapters, toc, image_blobs, css))
+}
+
+fn invalid(reason: &str) -> NovelError {
+    NovelError::BadRequest(format!("Invalid EPUB: {reason}"))
+}
+
+fn parse_xml(text: &str) -> Result<Document<'_>, NovelError> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(text, options)
+        .map_err(|e| NovelError::BadRequest(format!("Invalid EPUB: {e}")))
+}
+
+fn read_bytes<R: Read + Seek>(zip: &mut ZipArchive<R>, path: &str) -> Option<Vec<u8>> {
+    // Paths in the OPF don't always match the archive's casing
+    let name = if zip.index_for_name(path).is_some() {
+        path.to_string()
+    } else {
+        zip.file_names()
+            .find(|name| name.eq_ignore_ascii_case(path))?
+            .to_string()
+    };
+    let mut file = zip.by_name(&name).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_text<R: Read + Seek>(zip: &mut ZipArchive<R>, path: &str) -> Option<String> {
+    let bytes = read_bytes(zip, path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    Some(text.trim_start_matches('\u{FEFF}').to_string())
+}
+
+fn metadata_text(opf: &Document, name: &str) -> Option<String> {
+    opf.descendants()
+        .find(|node| node.has_tag_name("metadata"))?
+        .descendants()
+        .find(|node| node.has_tag_name(name))
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+fn find_cover<'a>(
+    opf: &Document,
+    manifest: &'a [ManifestItem],
+    by_id: &HashMap<&str, &'a ManifestItem>,
+) -> Option<&'a ManifestItem> {
+    let is_image = |item: &&ManifestItem| item.media_type.starts_with("image/");
+
+    // EPUB 3 `properties="cover-image"`
+    manifest
+        .iter()
+        .find(|item| {
+            item.properties
+                .split_whitespace()
+                .any(|property| property == "cover-image")
+        })
+        // EPUB 2 `<meta name="cover" content="item-id" />`
+        .or_else(|| {
+            let id = opf
+                .descendants()
+                .find(|node| node.has_tag_name("meta") && node.attribute("name") == Some("cover"))?
+                .attribute("content")?;
+            by_id.get(id).copied()
+        })
+        // Id conventions, then any image named like a cover
+        .or_else(|| {
+            manifest
+                .iter()
+                .filter(is_image)
+                .find(|item| item.id == "cover" || item.id == "cover-image")
+        })
+        .or_else(|| {
+            manifest
+                .iter()
+                .filter(is_image)
+                .find(|item| item.href.to_lowercase().contains("cover"))
+        })
+}
+
+/// `(label, href, target path)` of every NCX nav point
+fn ncx_links(ncx: &str, ncx_path: &str) -> Vec<(String, String, String)> {
+    let Ok(doc) = parse_xml(ncx) else {
+        return Vec::new();
+    };
+    doc.descendants()
+        .filter(|node| node.has_tag_name("navPoint"))
+        .filter_map(|point| {
+            let label = point
+                .children()
+                .find(|node| node.has_tag_name("navLabel"))
+                .and_then(|label| label.descendants().find(|node| node.has_tag_name("text")))
+                .and_then(|text| text.text())
+                .map(str::trim)
+                .filter(|label| !label.is_empty())
+                .unwrap_or("Untitled")
+                .to_string();
+            let src = point
+                .children()
+                .find(|node| node.has_tag_name("content"))?
+                .attribute("src")?;
+            toc_link(label, src, ncx_path)
+        })
+        .collect()
+}
+
+/// `(label, href, target path)` of every link in the nav document's TOC
+fn nav_links(nav: &str, nav_path: &str) -> Vec<(String, String, String)> {
+    let doc = Html::parse_document(&expand_self_closing(nav));
+    let is_toc = |nav: &ElementRef| {
+        nav.value().attrs().any(|(name, value)| {
+            (name.ends_with("type") && value.split_whitespace().any(|v| v == "toc"))
+                || (name == "id" && value == "toc")
+        })
+    };
+
+    doc.root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().name() == "nav" && is_toc(element))
+        .flat_map(|nav| nav.descendants().filter_map(ElementRef::wrap))
+        .filter(|element| element.value().name() == "a")
+        .filter_map(|link| {
+            let label = link.text().collect::<String>().trim().to_string();
+            let label = if label.is_empty() {
+                "Untitled".to_string()
+            } else {
+                label
+            };
+            toc_link(label, link.value().attr("href")?, nav_path)
+        })
+        .collect()
+}
+
+fn toc_link(label: String, href: &str, base_path: &str) -> Option<(String, String, String)> {
+    let file = href.split('#').next().unwrap_or_default();
+    if file.is_empty() {
+        return None;
+    }
+    Some((label, href.to_string(), resolve_path(base_path, file)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    const OPF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Test Book</dc:title>
+    <dc:creator>Test Author</dc:creator>
+    <dc:language>ja-JP</dc:language>
+    <meta name="cover" content="cover-image"/>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="style" href="css/style.css" media-type="text/css"/>
+    <item id="cover-image" href="images/cover.png" media-type="image/png"/>
+    <item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="blank" href="text/blank.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine toc="ncx">
+    <itemref idref="ch1"/>
+    <itemref idref="blank"/>
+    <itemref idref="ch2"/>
+  </spine>
+</package>"#;
+
+    const NCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE ncx PUBLIC "-//NISO//DTD ncx 2005-1//EN" "http://www.daisy.org/z3986/2005/ncx-2005-1.dtd">
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <navMap>
+    <navPoint id="n1" playOrder="1">
+      <navLabel><text>Chapter 1</text></navLabel>
+      <content src="text/ch1.xhtml"/>
+    </navPoint>
+    <navPoint id="n2" playOrder="2">
+      <navLabel><text>Illustration</text></navLabel>
+      <content src="text/ch2.xhtml#top"/>
+    </navPoint>
+  </navMap>
+</ncx>"#;
+
+    const CHAPTER_1: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title/><link rel="stylesheet" href="../css/style.css"/></head>
+<body>
+  <h1>第一章</h1>
+  <p><ruby>漢字<rt>かんじ</rt></ruby>です。</p>
+  <a id="page1"/>
+  <p onclick="steal()">Second paragraph here.</p>
+  <script>alert(1)</script>
+</body>
+</html>"#;
+
+    const BLANK: &str =
+        r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p> </p></body></html>"#;
+
+    const CHAPTER_2: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml">
+<body><div id="top"><img src="../images/cover.png" width="600" height="800"/></div></body>
+</html>"#;
+
+    /// A small EPUB 2 book with a ruby heading, a blank spine item and an
+    /// illustration
+    pub(crate) fn sample_epub() -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(4, 6)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .expect("png should be encoded");
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let files: [(&str, &[u8]); 8] = [
+            ("mimetype", b"application/epub+zip"),
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("OEBPS/content.opf", OPF.as_bytes()),
+            ("OEBPS/toc.ncx", NCX.as_bytes()),
+            ("OEBPS/css/style.css", b"body { margin: 0; }"),
+            ("OEBPS/text/ch1.xhtml", CHAPTER_1.as_bytes()),
+            ("OEBPS/text/blank.xhtml", BLANK.as_bytes()),
+            ("OEBPS/text/ch2.xhtml", CHAPTER_2.as_bytes()),
+        ];
+        for (name, data) in files {
+            zip.start_file(name, SimpleFileOptions::default())
+                .expect("zip entry should start");
+            zip.write_all(data).expect("zip entry should be written");
+        }
+        zip.start_file("OEBPS/images/cover.png", SimpleFileOptions::default())
+            .expect("zip entry should start");
+        zip.write_all(png.get_ref())
+            .expect("zip entry should be written");
+        zip.finish().expect("zip should be finished").into_inner()
+    }
+
+    #[test]
+    fn parses_epub_like_the_web_reader() {
+        let ParsedBook { metadata, content } =
+            parse_epub(&sample_epub(), "book").expect("epub should parse");
+
+        assert_eq!(metadata.title, "Test Book");
+        assert_eq!(metadata.author, "Test Author");
+        assert_eq!(metadata.language.as_deref(), Some("ja"));
+        assert!(
+            metadata
+                .cover
+                .as_deref()
+                .is_some_and(|cover| cover.starts_with("data:image/jpeg;base64,"))
+        );
+
+        // The blank spine item is skipped
+        assert_eq!(content.chapter_filenames, vec!["ch1.xhtml", "ch2.xhtml"]);
+        assert_eq!(metadata.chapter_count, 2);
+        let toc: Vec<(&str, i32)> = metadata
+            .toc
+            .iter()
+            .map(|item| (item.label.as_str(), item.chapter_index))
+            .collect();
+        assert_eq!(toc, vec![("Chapter 1", 0), ("Illustration", 1)]);
+
+        let chapter = &content.chapters[0];
+        assert!(chapter.contains("Second paragraph here."));
+        assert!(!chapter.contains("<script"));
+        assert!(!chapter.contains("onclick"));
+        assert!(chapter.contains(r#"data-block-id="ch0-b2""#));
+
+        // 第一章 + 漢字です (furigana and punctuation don't count) + Secondparagraphhere
+        assert_eq!(metadata.stats.chapter_lengths, vec![26, 0]);
+        assert_eq!(metadata.stats.total_length, 26);
+        let blocks: Vec<(&str, i32, i32)> = metadata
+            .stats
+            .block_maps
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|block| {
+                (
+                    block.block_id.as_str(),
+                    block.start_offset,
+                    block.end_offset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                ("ch0-b0", 0, 3),
+                ("ch0-b1", 3, 7),
+                ("ch0-b2", 7, 26),
+                ("ch1-b0", 0, 0),
+            ]
+        );
+
+        let illustration = &content.chapters[1];
+        assert!(illustration.contains("image-only-chapter"));
+        assert!(illustration.contains(r#"data-epub-src="OEBPS/images/cover.png""#));
+        assert!(!illustration.contains("width"));
+        assert!(content.image_blobs.contains_key("OEBPS/images/cover.png"));
+        assert!(
+            content
+                .css
+                .as_deref()
+                .is_some_and(|css| css.contains("margin"))
+        );
+    }
+
+    #[test]
+    fn rejects_archives_without_container() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("mimetype", SimpleFileOptions::default())
+            .expect("zip entry should start");
+        zip.write_all(b"application/epub+zip")
+            .expect("zip entry should be written");
+        let data = zip.finish().expect("zip should be finished").into_inner();
+
+        assert!(matches!(
+            parse_epub(&data, "book"),
+            Err(NovelError::BadRequest(message)) if message.contains("container.xml")
+        ));
+    }
+}
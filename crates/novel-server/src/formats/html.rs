@@ -0,0 +1,198 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+
+use super::{
+    BookInfo, ParsedBook, decode_text, escape_html, from_chapters, guess_language,
+    normalize_language,
+};
+use crate::error::NovelError;
+
+// ============================================================================
+// HTML
+// ============================================================================
+//
+// Single-page HTML, usually a web novel saved from its site. Everything
+// inside the body is kept except the page's own styles; chapters are split
+// at the heading level that repeats among the top-level elements.
+
+static CHARSET_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([\w-]+)"#)
+        .expect("charset regex should compile")
+});
+
+static TITLE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("title").expect("title selector should parse"));
+static H1_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("h1").expect("h1 selector should parse"));
+static AUTHOR_SELECTOR: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse(r#"meta[name="author"]"#).expect("author selector should parse")
+});
+static BODY_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("body").expect("body selector should parse"));
+
+/// How far into the file a `<meta charset>` is looked for
+const CHARSET_SCAN_BYTES: usize = 2048;
+
+pub(super) fn parse_html(
+    data: &[u8],
+    book_id: &str,
+    title: &str,
+) -> Result<ParsedBook, NovelError> {
+    let text = decode_html(data);
+    let doc = Html::parse_document(&text);
+
+    let info = BookInfo {
+        title: doc
+            .select(&TITLE_SELECTOR)
+            .chain(doc.select(&H1_SELECTOR))
+            .map(|element| element.text().collect::<String>().trim().to_string())
+            .find(|title| !title.is_empty())
+            .unwrap_or_else(|| title.to_string()),
+        author: doc
+            .select(&AUTHOR_SELECTOR)
+            .filter_map(|element| element.value().attr("content"))
+            .map(str::trim)
+            .find(|author| !author.is_empty())
+            .unwrap_or("Unknown Author")
+            .to_string(),
+        language: doc
+            .root_element()
+            .value()
+            .attr("lang")
+            .and_then(normalize_language)
+            .unwrap_or_else(|| guess_language(&text)),
+        cover: None,
+    };
+
+    let Some(mut root) = doc.select(&BODY_SELECTOR).next() else {
+        return Err(NovelError::BadRequest(
+            "No readable content in book".to_string(),
+        ));
+    };
+    // Sites wrap their content in layers of `div`s
+    while let Some(only_child) = single_element_child(root) {
+        root = only_child;
+    }
+
+    let chapters = split_chapters(root)
+        .into_iter()
+        .enumerate()
+        .map(|(index, parts)| {
+            (
+                format!("chapter{:04}.html", index + 1),
+                format!("<html><body>{}</body></html>", parts.join("\n")),
+            )
+        })
+        .collect();
+
+    from_chapters(book_id, info, chapters, HashMap::new(), String::new())
+}
+
+/// A BOM wins, then the charset the page declares, then the usual guess
+fn decode_html(data: &[u8]) -> String {
+    if encoding_rs::Encoding::for_bom(data).is_none() {
+        let head = String::from_utf8_lossy(&data[..data.len().min(CHARSET_SCAN_BYTES)]);
+        if let Some(encoding) = CHARSET_REGEX
+            .captures(&head)
+            .and_then(|caps| encoding_rs::Encoding::for_label(caps[1].as_bytes()))
+        {
+            return encoding.decode(data).0.into_owned();
+        }
+    }
+    decode_text(data)
+}
+
+fn single_element_child(element: ElementRef) -> Option<ElementRef> {
+    let has_text = element.children().any(|node| match node.value() {
+        Node::Text(text) => !text.trim().is_empty(),
+        _ => false,
+    });
+    let mut children = element.children().filter_map(ElementRef::wrap);
+    match (children.next(), children.next()) {
+        (Some(child), None) if !has_text => Some(child),
+        _ => None,
+    }
+}
+
+/// HTML of the root's children, grouped into chapters at the highest heading
+/// level that appears more than once
+fn split_chapters(root: ElementRef) -> Vec<Vec<String>> {
+    let headings: Vec<&str> = root
+        .children()
+        .filter_map(ElementRef::wrap)
+        .map(|element| element.value().name())
+        .collect();
+    let split_at = ["h1", "h2", "h3"]
+        .into_iter()
+        .find(|level| headings.iter().filter(|name| *name == level).count() >= 2);
+
+    let mut chapters = vec![Vec::new()];
+    for node in root.children() {
+        let html = match node.value() {
+            Node::Text(text) if !text.trim().is_empty() => escape_html(text),
+            Node::Element(element) if matches!(element.name(), "style" | "link") => continue,
+            Node::Element(element) => {
+                if split_at == Some(element.name())
+                    && chapters.last().is_some_and(|chapter| !chapter.is_empty())
+                {
+                    chapters.push(Vec::new());
+                }
+                ElementRef::wrap(node).map_or_else(String::new, |element| element.html())
+            }
+            _ => continue,
+        };
+        if let Some(chapter) = chapters.last_mut() {
+            chapter.push(html);
+        }
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="ja-JP">
+<head>
+  <meta charset="Shift_JIS">
+  <meta name="author" content="作者名">
+  <title>小説のタイトル</title>
+  <style>body { color: red; }</style>
+</head>
+<body>
+  <div id="wrapper"><div class="novel">
+    <h1>小説のタイトル</h1>
+    <h2>第一話　出会い</h2>
+    <p>　ある日、<ruby>少年<rt>しょうねん</rt></ruby>は森で不思議な光を見つけた。</p>
+    <h2>第二話　別れ</h2>
+    <p onclick="alert(1)">　光はやがて空へと消えていった。<script>alert(1)</script></p>
+  </div></div>
+</body>
+</html>"#;
+
+    #[test]
+    fn splits_web_novel_pages_at_repeated_headings() {
+        let (data, _, _) = encoding_rs::SHIFT_JIS.encode(PAGE);
+        let ParsedBook { metadata, content } =
+            parse_html(&data, "book", "page").expect("html should parse");
+
+        assert_eq!(metadata.title, "小説のタイトル");
+        assert_eq!(metadata.author, "作者名");
+        assert_eq!(metadata.language.as_deref(), Some("ja"));
+        assert_eq!(content.chapters.len(), 2);
+        let labels: Vec<&str> = metadata
+            .toc
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["第一話　出会い", "第二話　別れ"]);
+
+        assert!(content.chapters[0].contains("<ruby>少年<rt>しょうねん</rt></ruby>"));
+        assert!(!content.chapters[1].contains("<script"));
+        assert!(!content.chapters[1].contains("onclick"));
+        assert!(content.css.is_none());
+    }
+}
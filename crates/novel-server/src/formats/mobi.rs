@@ -0,0 +1,620 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use regex::{Captures, Regex};
+use tracing::warn;
+
+use super::{
+    BookInfo, ParsedBook, from_chapters, guess_language, normalize_language, resize_cover,
+};
+use crate::error::NovelError;
+
+// ============================================================================
+// MOBI & AZW3
+// ============================================================================
+//
+// Both are PalmDB files whose first record holds the headers and whose text
+// is split over PalmDOC-compressed records. MOBI 6 text is one HTML document
+// with `<mbp:pagebreak/>` between chapters and `recindex` image references.
+// KF8 (AZW3) text is a series of skeleton documents, each followed by the
+// fragments that fill it, plus extra flows for CSS listed in the FDST record.
+
+static PAGEBREAK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)<mbp:pagebreak\s*/?>").expect("pagebreak regex should compile")
+});
+
+static BODY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<body[^>]*>(.*)</body>").expect("body regex should compile")
+});
+
+static RECINDEX_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)recindex\s*=\s*["']?(\d+)["']?"#).expect("recindex regex should compile")
+});
+
+static KINDLE_EMBED_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"kindle:embed:([0-9A-Va-v]+)(?:\?[^'\x22)\s]*)?")
+        .expect("kindle embed regex should compile")
+});
+
+static KINDLE_FLOW_LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<link[^>]+kindle:flow:[^>]*>"#).expect("kindle flow regex should compile")
+});
+
+const PALMDB_HEADER_LENGTH: usize = 78;
+const MOBI_HEADER_OFFSET: usize = 16;
+const NO_INDEX: u32 = 0xFFFF_FFFF;
+
+const COMPRESSION_NONE: u16 = 1;
+const COMPRESSION_PALMDOC: u16 = 2;
+const COMPRESSION_HUFF_CDIC: u16 = 17480;
+
+const ENCODING_UTF8: u32 = 65001;
+
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_COVER_OFFSET: u32 = 201;
+const EXTH_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+
+struct Header {
+    compression: u16,
+    text_length: usize,
+    text_records: usize,
+    utf8: bool,
+    version: u32,
+    first_image: usize,
+    fdst: Option<usize>,
+    extra_flags: u16,
+}
+
+pub(super) fn parse_mobi(data: &[u8], book_id: &str) -> Result<ParsedBook, NovelError> {
+    let records = palmdb_records(data)?;
+    let record0 = records[0];
+
+    let header = read_header(record0)?;
+    let exth = read_exth(record0);
+
+    let mut text = Vec::with_capacity(header.text_length);
+    for record in records.iter().skip(1).take(header.text_records) {
+        let record = strip_trailing_entries(record, header.extra_flags);
+        match header.compression {
+            COMPRESSION_PALMDOC => text.extend(palmdoc_decompress(record)),
+            _ => text.extend_from_slice(record),
+        }
+    }
+    text.truncate(header.text_length);
+    let text = if header.utf8 {
+        String::from_utf8_lossy(&text).into_owned()
+    } else {
+        encoding_rs::WINDOWS_1252.decode(&text).0.into_owned()
+    };
+
+    // Images are numbered from one, counting every record after the first
+    // image, whatever it holds
+    let mut image_paths = HashMap::new();
+    let mut image_blobs = HashMap::new();
+    for (index, record) in records.iter().enumerate().skip(header.first_image) {
+        let Some(extension) = image_extension(record) else {
+            continue;
+        };
+        let number = index - header.first_image + 1;
+        let path = format!("images/{number:05}.{extension}");
+        image_blobs.insert(path.clone(), BASE64.encode(record));
+        image_paths.insert(number, path);
+    }
+
+    let cover = exth
+        .get(&EXTH_COVER_OFFSET)
+        .and_then(|offset| <[u8; 4]>::try_from(offset.as_slice()).ok())
+        .and_then(|offset| {
+            let index = header
+                .first_image
+                .checked_add(u32::from_be_bytes(offset) as usize)?;
+            records.get(index)
+        })
+        .and_then(|record| match resize_cover(record) {
+            Ok(cover) => Some(cover),
+            Err(e) => {
+                warn!("[MOBI] Cover of {book_id} could not be resized: {e}");
+                None
+            }
+        });
+
+    let (documents, css) = if header.version >= 8
+        && let Some(fdst) = header.fdst.and_then(|index| records.get(index))
+    {
+        kf8_documents(&text, fdst, &image_paths)
+    } else {
+        (mobi6_documents(&text, &image_paths), String::new())
+    };
+
+    let info = BookInfo {
+        title: exth
+            .get(&EXTH_TITLE)
+            .map(|title| decode_string(title, header.utf8))
+            .or_else(|| full_name(record0, header.utf8))
+            .unwrap_or_else(|| "Unknown Title".to_string()),
+        author: exth
+            .get(&EXTH_AUTHOR)
+            .map(|author| decode_string(author, header.utf8))
+            .unwrap_or_else(|| "Unknown Author".to_string()),
+        language: exth
+            .get(&EXTH_LANGUAGE)
+            .and_then(|language| normalize_language(&decode_string(language, header.utf8)))
+            .unwrap_or_else(|| guess_language(&text)),
+        cover,
+    };
+
+    let chapters = documents
+        .into_iter()
+        .enumerate()
+        .map(|(index, html)| (format!("chapter{:04}.html", index + 1), html))
+        .collect();
+    from_chapters(book_id, info, chapters, image_blobs, css)
+}
+
+fn palmdb_records(data: &[u8]) -> Result<Vec<&[u8]>, NovelError> {
+    let invalid = || NovelError::BadRequest("Invalid MOBI file".to_string());
+
+    let count = read_u16(data, 76).ok_or_else(invalid)? as usize;
+    let offsets = (0..count)
+        .map(|index| read_u32(data, PALMDB_HEADER_LENGTH + index * 8).map(|offset| offset as usize))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+
+    let mut records = Vec::with_capacity(count);
+    for (index, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(index + 1).copied().unwrap_or(data.len());
+        records.push(data.get(start..end).ok_or_else(invalid)?);
+    }
+    if records.is_empty() {
+        return Err(invalid());
+    }
+    Ok(records)
+}
+
+fn read_header(record0: &[u8]) -> Result<Header, NovelError> {
+    let invalid = || NovelError::BadRequest("Invalid MOBI file".to_string());
+
+    let compression = read_u16(record0, 0).ok_or_else(invalid)?;
+    if compression == COMPRESSION_HUFF_CDIC {
+        return Err(NovelError::BadRequest(
+            "HUFF/CDIC compressed MOBI files are not supported".to_string(),
+        ));
+    }
+    if !matches!(compression, COMPRESSION_NONE | COMPRESSION_PALMDOC) {
+        return Err(invalid());
+    }
+    if read_u16(record0, 12).ok_or_else(invalid)? != 0 {
+        return Err(NovelError::BadRequest(
+            "DRM-protected books can't be imported".to_string(),
+        ));
+    }
+    if record0.get(MOBI_HEADER_OFFSET..MOBI_HEADER_OFFSET + 4) != Some(b"MOBI") {
+        return Err(invalid());
+    }
+
+    let header_length = read_u32(record0, 20).ok_or_else(invalid)? as usize;
+    let version = read_u32(record0, 36).unwrap_or(6);
+    let fdst = read_u32(record0, 0xC0)
+        .filter(|&index| version >= 8 && index != NO_INDEX && index != 0)
+        .map(|index| index as usize);
+    let extra_flags = if header_length >= 0xE4 {
+        read_u16(record0, 0xF2).unwrap_or(0)
+    } else {
+        0
+    };
+
+    Ok(Header {
+        compression,
+        text_length: read_u32(record0, 4).ok_or_else(invalid)? as usize,
+        text_records: read_u16(record0, 8).ok_or_else(invalid)? as usize,
+        utf8: read_u32(record0, 28) == Some(ENCODING_UTF8),
+        version,
+        first_image: read_u32(record0, 108)
+            .filter(|&index| index != NO_INDEX)
+            .map_or(usize::MAX, |index| index as usize),
+        fdst,
+        extra_flags,
+    })
+}
+
+/// EXTH records by type; the first one wins when a type repeats
+fn read_exth(record0: &[u8]) -> HashMap<u32, Vec<u8>> {
+    let mut records = HashMap::new();
+    let has_exth = read_u32(record0, 128).is_some_and(|flags| flags & 0x40 != 0);
+    let Some(start) = read_u32(record0, 20).map(|length| MOBI_HEADER_OFFSET + length as usize)
+    else {
+        return records;
+    };
+    if !has_exth || record0.get(start..start + 4) != Some(b"EXTH") {
+        return records;
+    }
+
+    let count = read_u32(record0, start + 8).unwrap_or(0);
+    let mut offset = start + 12;
+    for _ in 0..count {
+        let (Some(kind), Some(length)) = (read_u32(record0, offset), read_u32(record0, offset + 4))
+        else {
+            break;
+        };
+        let length = length as usize;
+        let Some(value) = length
+            .checked_sub(8)
+            .and_then(|size| record0.get(offset + 8..offset + 8 + size))
+        else {
+            break;
+        };
+        records.entry(kind).or_insert_with(|| value.to_vec());
+        offset += length;
+    }
+    records
+}
+
+fn full_name(record0: &[u8], utf8: bool) -> Option<String> {
+    let offset = read_u32(record0, 84)? as usize;
+    let length = read_u32(record0, 88)? as usize;
+    let name = decode_string(record0.get(offset..offset + length)?, utf8);
+    (!name.trim().is_empty()).then_some(name)
+}
+
+fn decode_string(bytes: &[u8], utf8: bool) -> String {
+    if utf8 {
+        String::from_utf8_lossy(bytes).trim().to_string()
+    } else {
+        encoding_rs::WINDOWS_1252.decode(bytes).0.trim().to_string()
+    }
+}
+
+/// Text records can end with extra data entries, flagged in the header, that
+/// aren't part of the text. Sizes are stored backwards from the record end;
+/// the multibyte entry (bit 0) always comes last.
+fn strip_trailing_entries(record: &[u8], extra_flags: u16) -> &[u8] {
+    let mut size = record.len();
+    let mut flags = extra_flags >> 1;
+    while flags != 0 {
+        if flags & 1 != 0 {
+            let mut entry = 0;
+            let mut shift = 0;
+            let mut position = size;
+            while position > 0 {
+                position -= 1;
+                let byte = record[position];
+                entry |= usize::from(byte & 0x7F) << shift;
+                shift += 7;
+                if byte & 0x80 != 0 || shift >= 28 {
+                    break;
+                }
+            }
+            size = size.saturating_sub(entry);
+        }
+        flags >>= 1;
+    }
+    if extra_flags & 1 != 0 && size > 0 {
+        size = size.saturating_sub(usize::from(record[size - 1] & 0x3) + 1);
+    }
+    &record[..size]
+}
+
+/// PalmDOC's LZ77 variant
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut index = 0;
+    while index < data.len() {
+        let byte = data[index];
+        index += 1;
+        match byte {
+            // Literal run of the next 1-8 bytes
+            0x01..=0x08 => {
+                let end = (index + byte as usize).min(data.len());
+                output.extend_from_slice(&data[index..end]);
+                index = end;
+            }
+            // Back-reference: 11 bits of distance, 3 bits of length - 3
+            0x80..=0xBF => {
+                let Some(&next) = data.get(index) else {
+                    break;
+                };
+                index += 1;
+                let pair = (usize::from(byte) << 8 | usize::from(next)) & 0x3FFF;
+                let distance = pair >> 3;
+                let length = (pair & 0x07) + 3;
+                if distance == 0 || distance > output.len() {
+                    continue;
+                }
+                let start = output.len() - distance;
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            }
+            // Space followed by a character
+            0xC0..=0xFF => {
+                output.push(b' ');
+                output.push(byte ^ 0x80);
+            }
+            _ => output.push(byte),
+        }
+    }
+    output
+}
+
+fn image_extension(record: &[u8]) -> Option<&'static str> {
+    if record.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if record.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if record.starts_with(b"GIF8") {
+        Some("gif")
+    } else if record.starts_with(b"BM") {
+        Some("bmp")
+    } else {
+        None
+    }
+}
+
+/// One document per page break, images pointing at their extracted path
+fn mobi6_documents(text: &str, image_paths: &HashMap<usize, String>) -> Vec<String> {
+    let body = BODY_REGEX
+        .captures(text)
+        .and_then(|caps| caps.get(1))
+        .map_or(text, |body| body.as_str());
+    let body = RECINDEX_REGEX.replace_all(body, |caps: &Captures| {
+        caps[1]
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| image_paths.get(&number))
+            .map_or_else(String::new, |path| format!(r#"src="{path}""#))
+    });
+
+    PAGEBREAK_REGEX
+        .split(&body)
+        .map(|part| format!("<html><body>{part}</body></html>"))
+        .collect()
+}
+
+/// Skeleton documents with their fragments, and the CSS flows. Without the
+/// SKEL/FRAG indexes the fragments are placed at the end of their skeleton's
+/// body, which is where converters put them in the common case.
+fn kf8_documents(
+    text: &str,
+    fdst: &[u8],
+    image_paths: &HashMap<usize, String>,
+) -> (Vec<String>, String) {
+    let mut flows = Vec::new();
+    if fdst.starts_with(b"FDST") {
+        let count = read_u32(fdst, 8).unwrap_or(0) as usize;
+        for index in 0..count {
+            let (Some(start), Some(end)) = (
+                read_u32(fdst, 12 + index * 8),
+                read_u32(fdst, 16 + index * 8),
+            ) else {
+                break;
+            };
+            if let Some(flow) = text.get(start as usize..end as usize) {
+                flows.push(flow);
+            }
+        }
+    }
+    if flows.is_empty() {
+        flows.push(text);
+    }
+
+    let css = flows[1..]
+        .iter()
+        .filter(|flow| !flow.trim_start().starts_with('<'))
+        .map(|flow| embed_images(flow, image_paths))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let documents = flows[0]
+        .match_indices("<html")
+        .map(|(start, _)| start)
+        .chain([flows[0].len()])
+        .collect::<Vec<_>>()
+        .windows(2)
+        .map(|bounds| {
+            let part = &flows[0][bounds[0]..bounds[1]];
+            let document = match part.find("</html>") {
+                Some(end) => {
+                    let (skeleton, fragments) = part.split_at(end + "</html>".len());
+                    match skeleton.rfind("</body>") {
+                        Some(body_end) => format!(
+                            "{}{fragments}{}",
+                            &skeleton[..body_end],
+                            &skeleton[body_end..]
+                        ),
+                        None => part.to_string(),
+                    }
+                }
+                None => part.to_string(),
+            };
+            let document = KINDLE_FLOW_LINK_REGEX.replace_all(&document, "");
+            embed_images(&document, image_paths)
+        })
+        .collect();
+
+    (documents, css)
+}
+
+/// `kindle:embed:000A?mime=image/jpeg` -> `images/00010.jpg`, base 32 digits
+fn embed_images(text: &str, image_paths: &HashMap<usize, String>) -> String {
+    KINDLE_EMBED_REGEX
+        .replace_all(text, |caps: &Captures| {
+            usize::from_str_radix(&caps[1], 32)
+                .ok()
+                .and_then(|number| image_paths.get(&number))
+                .cloned()
+                .unwrap_or_default()
+        })
+        .into_owned()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A PalmDB file with the given record 0 and further records
+    fn palmdb(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; PALMDB_HEADER_LENGTH];
+        data[..4].copy_from_slice(b"book");
+        data[60..68].copy_from_slice(b"BOOKMOBI");
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = PALMDB_HEADER_LENGTH + records.len() * 8 + 2;
+        for (index, record) in records.iter().enumerate() {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(index as u32 * 2).to_be_bytes());
+            offset += record.len();
+        }
+        data.extend_from_slice(&[0, 0]);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    fn record0(text_length: usize, text_records: u16, version: u32, first_image: u32) -> Vec<u8> {
+        let header_length = 0xE8;
+        let mut record = vec![0; MOBI_HEADER_OFFSET + header_length];
+        record[0..2].copy_from_slice(&COMPRESSION_NONE.to_be_bytes());
+        record[4..8].copy_from_slice(&(text_length as u32).to_be_bytes());
+        record[8..10].copy_from_slice(&text_records.to_be_bytes());
+        record[16..20].copy_from_slice(b"MOBI");
+        record[20..24].copy_from_slice(&(header_length as u32).to_be_bytes());
+        record[28..32].copy_from_slice(&ENCODING_UTF8.to_be_bytes());
+        record[36..40].copy_from_slice(&version.to_be_bytes());
+        record[108..112].copy_from_slice(&first_image.to_be_bytes());
+        record[128..132].copy_from_slice(&0x40_u32.to_be_bytes());
+        record[0xC0..0xC4].copy_from_slice(&NO_INDEX.to_be_bytes());
+
+        let exth_records: [(u32, &str); 4] = [
+            (EXTH_AUTHOR, "夏目漱石"),
+            (EXTH_TITLE, "吾輩は猫である"),
+            (EXTH_LANGUAGE, "ja-JP"),
+            (EXTH_COVER_OFFSET, ""),
+        ];
+        let mut exth = Vec::new();
+        for (kind, value) in exth_records {
+            let value = if kind == EXTH_COVER_OFFSET {
+                0_u32.to_be_bytes().to_vec()
+            } else {
+                value.as_bytes().to_vec()
+            };
+            exth.extend_from_slice(&kind.to_be_bytes());
+            exth.extend_from_slice(&(value.len() as u32 + 8).to_be_bytes());
+            exth.extend_from_slice(&value);
+        }
+        record.extend_from_slice(b"EXTH");
+        record.extend_from_slice(&(exth.len() as u32 + 12).to_be_bytes());
+        record.extend_from_slice(&(exth_records.len() as u32).to_be_bytes());
+        record.extend_from_slice(&exth);
+        record
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(4, 6)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .expect("png should be encoded");
+        png.into_inner()
+    }
+
+    #[test]
+    fn decompresses_palmdoc() {
+        assert_eq!(
+            palmdoc_decompress(&[b'a', b'b', b'c', 0x80, 0x18, 0xC1]),
+            b"abcabc A"
+        );
+        assert_eq!(palmdoc_decompress(&[0x02, 0xC1, 0x00, b'!']), b"\xC1\x00!");
+    }
+
+    #[test]
+    fn strips_trailing_entries() {
+        // A two byte trailing entry after a two byte multibyte tail
+        let record = [b'a', b'b', 0xE3, 0x81, 0x01, 0x82];
+        assert_eq!(strip_trailing_entries(&record, 0b11), b"ab");
+    }
+
+    #[test]
+    fn parses_mobi6_books() {
+        let text = "<html><head><guide></guide></head><body>\
+            <h2>第一章</h2><p>吾輩は猫である。名前はまだ無い。</p>\
+            <mbp:pagebreak/>\
+            <h2>第二章</h2><p>どこで生れたかとんと見当がつかぬ。</p>\
+            <p><img recindex=\"00001\" /></p>\
+            </body></html>";
+        let data = palmdb(&[
+            record0(text.len(), 1, 6, 2),
+            text.as_bytes().to_vec(),
+            png(),
+        ]);
+
+        let ParsedBook { metadata, content } =
+            parse_mobi(&data, "book").expect("mobi should parse");
+
+        assert_eq!(metadata.title, "吾輩は猫である");
+        assert_eq!(metadata.author, "夏目漱石");
+        assert_eq!(metadata.language.as_deref(), Some("ja"));
+        assert!(
+            metadata
+                .cover
+                .is_some_and(|cover| cover.starts_with("data:image/jpeg;base64,"))
+        );
+        let labels: Vec<&str> = metadata
+            .toc
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["第一章", "第二章"]);
+        assert_eq!(content.chapters.len(), 2);
+        assert!(content.chapters[1].contains(r#"data-epub-src="images/00001.png""#));
+        assert!(content.image_blobs.contains_key("images/00001.png"));
+    }
+
+    #[test]
+    fn parses_kf8_books() {
+        let css = "p { text-indent: 1em; }";
+        let flow = "<html><head><link href=\"kindle:flow:0002?mime=text/css\" rel=\"stylesheet\" type=\"text/css\"/></head><body></body></html>\
+            <h1>一</h1><p>最初の章の本文がここにあります。</p>\
+            <html><head></head><body></body></html>\
+            <h1>二</h1><p><img src=\"kindle:embed:0001?mime=image/png\"/></p>";
+        let text = format!("{flow}{css}");
+
+        let mut fdst = b"FDST".to_vec();
+        fdst.extend_from_slice(&12_u32.to_be_bytes());
+        fdst.extend_from_slice(&2_u32.to_be_bytes());
+        for (start, end) in [(0, flow.len()), (flow.len(), text.len())] {
+            fdst.extend_from_slice(&(start as u32).to_be_bytes());
+            fdst.extend_from_slice(&(end as u32).to_be_bytes());
+        }
+        let mut record0 = record0(text.len(), 1, 8, 2);
+        record0[0xC0..0xC4].copy_from_slice(&3_u32.to_be_bytes());
+        let data = palmdb(&[record0, text.into_bytes(), png(), fdst]);
+
+        let ParsedBook { metadata, content } =
+            parse_mobi(&data, "book").expect("azw3 should parse");
+
+        let labels: Vec<&str> = metadata
+            .toc
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["一", "二"]);
+        assert!(content.chapters[0].contains("最初の章の本文"));
+        assert!(!content.chapters[0].contains("kindle:flow"));
+        assert!(content.chapters[1].contains(r#"data-epub-src="images/00001.png""#));
+        assert_eq!(content.css.as_deref(), Some(css));
+    }
+}
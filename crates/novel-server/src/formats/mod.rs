@@ -0,0 +1,561 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use html5ever::{LocalName, Namespace, QualName};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType};
+use regex::{Captures, Regex};
+use scraper::{ElementRef, Html, Node};
+
+use crate::{
+    error::NovelError,
+    types::{BlockIndexMap, BookStats, LNMetadata, LNParsedBook, TocItem},
+};
+
+mod epub;
+mod html;
+mod mobi;
+mod text;
+
+#[cfg(test)]
+pub(crate) use epub::tests::sample_epub;
+
+// ============================================================================
+// Book Import
+// ============================================================================
+//
+// Server-side counterpart of the web UI's `epubParser.ts`, so weak devices
+// don't have to unpack large books themselves. Every format ends up as what
+// the browser would upload for an EPUB: chapter HTML with `data-epub-src`
+// image markers and `data-block-id` blocks, image blobs keyed by their path,
+// and the same character counts for `BookStats`.
+
+/// Extensions of the book files the server can import
+pub const SUPPORTED_EXTENSIONS: &[&str] =
+    &["epub", "txt", "html", "htm", "xhtml", "mobi", "azw3", "azw"];
+
+/// Media type a stored book is served with
+pub fn media_type(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        "epub" => "application/epub+zip",
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "mobi" => "application/x-mobipocket-ebook",
+        "azw3" => "application/x-mobi8-ebook",
+        "azw" => "application/vnd.amazon.ebook",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Letters, numbers and anything else that is not whitespace, punctuation
+/// or a symbol count towards reading progress
+static NON_COUNTABLE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[\s\u{200B}-\u{200D}\u{FEFF}\u{00A0}\p{P}\p{S}]+")
+        .expect("non-countable regex should compile")
+});
+
+static SELF_CLOSING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<([A-Za-z][\w:.-]*)(\s[^<>]*?)?\s*/>").expect("self-closing regex should compile")
+});
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "svg", "bmp"];
+
+const PRIMARY_BLOCK_TAGS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "pre"];
+const SECONDARY_BLOCK_TAGS: &[&str] = &["li", "figcaption", "caption", "th", "td"];
+const SKIP_BLOCK_TAGS: &[&str] = &["script", "style", "noscript", "rt", "rp", "ruby"];
+
+/// Elements dropped from chapters before they reach the reader
+const UNSAFE_TAGS: &[&str] = &[
+    "script", "iframe", "frame", "frameset", "object", "embed", "form", "input", "button",
+    "textarea", "select", "link", "meta", "base",
+];
+
+/// HTML void elements, the only ones that may stay self-closing
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+const COVER_WIDTH: u32 = 300;
+const COVER_QUALITY: u8 = 70;
+
+/// A book ready to be stored under `metadata:` and `content:`
+pub struct ParsedBook {
+    pub metadata: LNMetadata,
+    pub content: LNParsedBook,
+}
+
+/// What a format knows about a book besides its chapters
+struct BookInfo {
+    title: String,
+    author: String,
+    language: String,
+    cover: Option<String>,
+}
+
+struct Chapter {
+    html: String,
+    length: i32,
+    blocks: Vec<BlockIndexMap>,
+    /// Text of the first `h1`-`h3`, used as TOC label by formats without a
+    /// table of contents
+    heading: Option<String>,
+}
+
+/// Parse a book file, picking the format by its extension. `title` names
+/// books whose format has no metadata of its own.
+pub fn parse_book(
+    data: &[u8],
+    extension: &str,
+    book_id: &str,
+    title: &str,
+) -> Result<ParsedBook, NovelError> {
+    match extension.to_ascii_lowercase().as_str() {
+        "epub" => epub::parse_epub(data, book_id),
+        "txt" => text::parse_text(data, book_id, title),
+        "html" | "htm" | "xhtml" => html::parse_html(data, book_id, title),
+        "mobi" | "azw3" | "azw" => mobi::parse_mobi(data, book_id),
+        other => Err(NovelError::BadRequest(format!(
+            "Unsupported book format: .{other}"
+        ))),
+    }
+}
+
+/// Books converted from formats without a table of contents of their own
+/// get one TOC entry per chapter heading
+fn from_chapters(
+    book_id: &str,
+    info: BookInfo,
+    chapters: Vec<(String, String)>,
+    image_blobs: HashMap<String, String>,
+    css: String,
+) -> Result<ParsedBook, NovelError> {
+    let mut processed = Vec::new();
+    let mut toc = Vec::new();
+    for (file_name, html) in chapters {
+        let Some(chapter) = process_chapter(&html, &file_name, processed.len()) else {
+            continue;
+        };
+        if let Some(heading) = &chapter.heading {
+            toc.push(TocItem {
+                label: heading.clone(),
+                href: file_name.clone(),
+                chapter_index: processed.len() as i32,
+            });
+        }
+        processed.push((file_name, chapter));
+    }
+    if processed.is_empty() {
+        return Err(NovelError::BadRequest(
+            "No readable content in book".to_string(),
+        ));
+    }
+
+    Ok(assemble(book_id, info, processed, toc, image_blobs, css))
+}
+
+/// Metadata and content of processed `(file name, chapter)` pairs
+fn assemble(
+    book_id: &str,
+    info: BookInfo,
+    chapters: Vec<(String, Chapter)>,
+    toc: Vec<TocItem>,
+    image_blobs: HashMap<String, String>,
+    css: String,
+) -> ParsedBook {
+    let mut htmls = Vec::with_capacity(chapters.len());
+    let mut chapter_filenames = Vec::with_capacity(chapters.len());
+    let mut chapter_lengths = Vec::with_capacity(chapters.len());
+    let mut block_maps = Vec::new();
+    for (file_name, chapter) in chapters {
+        htmls.push(chapter.html);
+        chapter_filenames.push(file_name);
+        chapter_lengths.push(chapter.length);
+        block_maps.extend(chapter.blocks);
+    }
+
+    let total_length = chapter_lengths.iter().sum();
+    let metadata = LNMetadata {
+        id: book_id.to_string(),
+        title: info.title,
+        author: info.author,
+        cover: info.cover,
+        added_at: chrono::Utc::now().timestamp_millis(),
+        is_processing: Some(false),
+        is_error: None,
+        error_msg: None,
+        stats: BookStats {
+            chapter_lengths,
+            total_length,
+            block_maps: Some(block_maps),
+        },
+        chapter_count: htmls.len() as i32,
+        toc,
+        has_progress: None,
+        last_modified: None,
+        sync_version: None,
+        language: Some(info.language),
+        category_ids: Vec::new(),
+        language_settings: HashMap::new(),
+    };
+    let content = LNParsedBook {
+        chapters: htmls,
+        image_blobs,
+        chapter_filenames,
+        css: (!css.is_empty()).then_some(css),
+    };
+
+    ParsedBook { metadata, content }
+}
+
+/// Text of a file in an unknown encoding: a BOM wins, then UTF-8, then
+/// Shift_JIS, which most Japanese TXT files still use
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding.decode_with_bom_removal(bytes).0.into_owned();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::SHIFT_JIS.decode(bytes).0.into_owned(),
+    }
+}
+
+/// Books without a language of their own are Japanese when they contain kana
+fn guess_language(text: &str) -> String {
+    let has_kana = text
+        .chars()
+        .any(|c| matches!(c, '\u{3040}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}'));
+    if has_kana { "ja" } else { "unknown" }.to_string()
+}
+
+/// `en-US` -> `en`
+fn normalize_language(language: &str) -> Option<String> {
+    language
+        .trim()
+        .split(['-', '_'])
+        .next()
+        .map(str::to_lowercase)
+        .filter(|language| !language.is_empty())
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn resize_cover(bytes: &[u8]) -> Result<String, image::ImageError> {
+    let image = image::load_from_memory(bytes)?;
+    let scale = f64::from(COVER_WIDTH) / f64::from(image.width().max(1));
+    let height = ((f64::from(image.height()) * scale).round() as u32).max(1);
+    let thumbnail = image
+        .resize_exact(COVER_WIDTH, height, FilterType::Triangle)
+        .to_rgb8();
+
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, COVER_QUALITY).encode_image(&thumbnail)?;
+    Ok(format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg)))
+}
+
+/// Chapter HTML ready for the reader, `None` when the file has nothing to
+/// show
+fn process_chapter(raw: &str, path: &str, index: usize) -> Option<Chapter> {
+    let mut doc = Html::parse_document(&expand_self_closing(raw));
+    sanitize(&mut doc, path);
+
+    let body = doc
+        .root_element()
+        .children()
+        .filter_map(ElementRef::wrap)
+        .find(|element| element.value().name() == "body")?;
+    let heading = body
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .find(|element| matches!(element.value().name(), "h1" | "h2" | "h3"))
+        .map(|element| clean_text(element).trim().to_string())
+        .filter(|heading| !heading.is_empty());
+    let text_length = body.text().collect::<String>().trim().chars().count();
+    let has_images = body
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .any(|element| matches!(element.value().name(), "img" | "image"));
+    let has_svg = body
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .any(|element| element.value().name() == "svg");
+    if text_length <= 10 && !has_images {
+        return None;
+    }
+
+    // Illustrations are a single block
+    if text_length < 20 && (has_images || has_svg) {
+        let length = character_count(&clean_text(body));
+        let block_id = format!("ch{index}-b0");
+        let html = format!(
+            r#"<div class="image-only-chapter" data-block-id="{block_id}">{}</div>"#,
+            body.inner_html()
+        );
+        return Some(Chapter {
+            html,
+            length,
+            blocks: vec![BlockIndexMap {
+                block_id,
+                start_offset: 0,
+                end_offset: length,
+            }],
+            heading,
+        });
+    }
+
+    let body_id = body.id();
+    let blocks: Vec<_> = find_blocks(body)
+        .into_iter()
+        .map(|block| (block.id(), character_count(&clean_text(block))))
+        .collect();
+
+    if blocks.is_empty() {
+        let length = character_count(&clean_text(body));
+        let block_id = format!("ch{index}-b0");
+        let html = format!(
+            r#"<div data-block-id="{block_id}">{}</div>"#,
+            body.inner_html()
+        );
+        return Some(Chapter {
+            html,
+            length,
+            blocks: vec![BlockIndexMap {
+                block_id,
+                start_offset: 0,
+                end_offset: length,
+            }],
+            heading,
+        });
+    }
+
+    let mut length = 0;
+    let mut block_maps = Vec::with_capacity(blocks.len());
+    for (order, (node_id, count)) in blocks.into_iter().enumerate() {
+        let block_id = format!("ch{index}-b{order}");
+        if let Some(mut node) = doc.tree.get_mut(node_id)
+            && let Node::Element(element) = node.value()
+        {
+            element
+                .attrs
+                .insert(attribute("data-block-id"), block_id.clone().into());
+        }
+        block_maps.push(BlockIndexMap {
+            block_id,
+            start_offset: length,
+            end_offset: length + count,
+        });
+        length += count;
+    }
+
+    let html = doc
+        .tree
+        .get(body_id)
+        .and_then(ElementRef::wrap)
+        .map(|body| body.inner_html())
+        .unwrap_or_default();
+    Some(Chapter {
+        html,
+        length,
+        blocks: block_maps,
+        heading,
+    })
+}
+
+/// Chapters are XHTML but parsed as HTML, where `<a id="x"/>` would swallow
+/// everything after it
+fn expand_self_closing(xhtml: &str) -> String {
+    SELF_CLOSING_REGEX
+        .replace_all(xhtml, |caps: &Captures| {
+            let tag = &caps[1];
+            let attrs = caps.get(2).map_or("", |attrs| attrs.as_str());
+            if VOID_TAGS.contains(&tag.to_ascii_lowercase().as_str()) {
+                caps[0].to_string()
+            } else {
+                format!("<{tag}{attrs}></{tag}>")
+            }
+        })
+        .into_owned()
+}
+
+/// Drop scripts and event handlers, and mark images for the reader to load
+/// from the extracted files
+fn sanitize(doc: &mut Html, chapter_path: &str) {
+    let mut unsafe_nodes = Vec::new();
+    let mut elements = Vec::new();
+    for node in doc.root_element().descendants() {
+        if let Node::Element(element) = node.value() {
+            if UNSAFE_TAGS.contains(&element.name()) {
+                unsafe_nodes.push(node.id());
+            } else {
+                elements.push(node.id());
+            }
+        }
+    }
+
+    for id in unsafe_nodes {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    for id in elements {
+        let Some(mut node) = doc.tree.get_mut(id) else {
+            continue;
+        };
+        let Node::Element(element) = node.value() else {
+            continue;
+        };
+        element.attrs.retain(|name, value| {
+            !name.local.starts_with("on")
+                && !value.trim_start().to_lowercase().starts_with("javascript:")
+        });
+
+        if !matches!(element.name(), "img" | "image") {
+            continue;
+        }
+        let src = element
+            .attrs
+            .iter()
+            .find(|(name, _)| matches!(&*name.local, "src" | "href"))
+            .map(|(_, value)| value.to_string());
+        if let Some(src) = src
+            && !src.starts_with("http")
+            && !src.starts_with("data:")
+        {
+            element
+                .attrs
+                .retain(|name, _| !matches!(&*name.local, "src" | "href"));
+            element.attrs.insert(
+                attribute("data-epub-src"),
+                resolve_path(chapter_path, &src).into(),
+            );
+        }
+        // Fixed dimensions break responsive display
+        element
+            .attrs
+            .retain(|name, _| !matches!(&*name.local, "width" | "height"));
+    }
+}
+
+/// Outermost block elements of the body, in document order
+fn find_blocks(body: ElementRef) -> Vec<ElementRef> {
+    let body_id = body.id();
+    let mut candidates = HashSet::new();
+    let is_eligible = |element: &ElementRef| {
+        element.id() != body_id
+            && !SKIP_BLOCK_TAGS.contains(&element.value().name())
+            && !element
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| matches!(ancestor.value().name(), "rt" | "rp"))
+    };
+
+    for element in body.descendants().filter_map(ElementRef::wrap) {
+        let name = element.value().name();
+        let class = element.value().attr("class").unwrap_or_default();
+        let is_block = PRIMARY_BLOCK_TAGS.contains(&name)
+            || SECONDARY_BLOCK_TAGS.contains(&name)
+            || name == "figure"
+            || (name == "div"
+                && (class.contains("image")
+                    || class.contains("img")
+                    || (!has_primary_block(element) && !clean_text(element).trim().is_empty())));
+        if is_block && is_eligible(&element) {
+            candidates.insert(element.id());
+        }
+
+        // Standalone images use their parent as block
+        if matches!(name, "img" | "svg" | "image")
+            && let Some(parent) = element.parent().and_then(ElementRef::wrap)
+            && is_eligible(&parent)
+        {
+            candidates.insert(parent.id());
+        }
+    }
+
+    body.descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| {
+            candidates.contains(&element.id())
+                && !element
+                    .ancestors()
+                    .any(|ancestor| candidates.contains(&ancestor.id()))
+        })
+        .collect()
+}
+
+fn has_primary_block(element: ElementRef) -> bool {
+    element
+        .descendants()
+        .skip(1)
+        .filter_map(ElementRef::wrap)
+        .any(|child| PRIMARY_BLOCK_TAGS.contains(&child.value().name()))
+}
+
+/// Text of an element without furigana
+pub(crate) fn clean_text(element: ElementRef) -> String {
+    element
+        .descendants()
+        .filter_map(|node| {
+            let Node::Text(text) = node.value() else {
+                return None;
+            };
+            let in_furigana = node
+                .ancestors()
+                .take_while(|ancestor| ancestor.id() != element.id())
+                .filter_map(ElementRef::wrap)
+                .any(|ancestor| matches!(ancestor.value().name(), "rt" | "rp"));
+            (!in_furigana).then_some(&**text)
+        })
+        .collect()
+}
+
+pub(crate) fn character_count(text: &str) -> i32 {
+    NON_COUNTABLE_REGEX.replace_all(text, "").chars().count() as i32
+}
+
+fn attribute(name: &str) -> QualName {
+    QualName::new(None, Namespace::from(""), LocalName::from(name))
+}
+
+fn is_image(path: &str) -> bool {
+    path.rsplit_once('.').is_some_and(|(_, extension)| {
+        IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+    })
+}
+
+/// Resolve `relative` against the directory of `base`, both archive paths
+fn resolve_path(base: &str, relative: &str) -> String {
+    if let Some(absolute) = relative.strip_prefix('/') {
+        return absolute.to_string();
+    }
+    if relative.starts_with("http://") || relative.starts_with("https://") {
+        return relative.to_string();
+    }
+
+    let decoded = urlencoding::decode(relative)
+        .map(|decoded| decoded.into_owned())
+        .unwrap_or_else(|_| relative.to_string());
+    let mut parts: Vec<&str> = match base.rfind('/') {
+        Some(index) => base[..index].split('/').collect(),
+        None => Vec::new(),
+    };
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
@@ -0,0 +1,290 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use super::{BookInfo, ParsedBook, decode_text, escape_html, from_chapters, guess_language};
+use crate::error::NovelError;
+
+// ============================================================================
+// Plain Text & Aozora Bunko
+// ============================================================================
+//
+// Aozora Bunko files start with the title and author, followed by a block
+// explaining the markup between two dashed lines, and end with the `底本：`
+// colophon. Their markup is ruby (`｜漢字《かんじ》`, or `漢字《かんじ》`
+// for a run of kanji) and `［＃…］` annotations, of which headings and page
+// breaks are kept. Plain text gets chapters from lines that look like
+// chapter titles, or is cut into parts when it has none.
+
+static RUBY_WITH_MARKER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[｜|]([^｜|《》\n]+)《([^《》\n]+)》").expect("ruby regex should compile")
+});
+
+static RUBY_AFTER_KANJI_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"([\p{Han}々〆〇ヶ]+)《([^《》\n]+)》").expect("ruby regex should compile")
+});
+
+static HEADING_RANGE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"［＃(大|中|小)見出し］(.*?)［＃(?:大|中|小)見出し終わり］")
+        .expect("heading regex should compile")
+});
+
+static ANNOTATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"［＃([^］]*)］").expect("annotation regex should compile"));
+
+static CHAPTER_TITLE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(第[0-9０-９一二三四五六七八九十百千〇]+[章話部幕節回]|序章|終章|プロローグ|エピローグ|(?i:chapter|prologue|epilogue)\b)",
+    )
+    .expect("chapter title regex should compile")
+});
+
+const AOZORA_NOTATION_HEADER: &str = "【テキスト中に現れる記号について】";
+const AOZORA_COLOPHON: &str = "底本：";
+/// Longest line still taken for a chapter title in plain text
+const MAX_TITLE_CHARS: usize = 40;
+/// Paragraphs per part of plain text without chapter titles
+const PART_PARAGRAPHS: usize = 800;
+
+enum Line {
+    Text(String),
+    Heading(u8, String),
+    PageBreak,
+}
+
+pub(super) fn parse_text(
+    data: &[u8],
+    book_id: &str,
+    title: &str,
+) -> Result<ParsedBook, NovelError> {
+    let text = decode_text(data).replace("\r\n", "\n").replace('\r', "\n");
+    let is_aozora = text.contains(AOZORA_NOTATION_HEADER) || text.contains("［＃");
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    let mut info = BookInfo {
+        title: title.to_string(),
+        author: "Unknown Author".to_string(),
+        language: guess_language(&text),
+        cover: None,
+    };
+
+    if is_aozora {
+        if let Some(start) = lines.iter().position(|line| is_dashed(line)) {
+            let heading: Vec<&str> = lines[..start]
+                .iter()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .collect();
+            if let Some(first) = heading.first() {
+                info.title = first.to_string();
+            }
+            if heading.len() > 1
+                && let Some(last) = heading.last()
+            {
+                info.author = last.to_string();
+            }
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| is_dashed(line))
+                .map_or(start, |end| start + 1 + end);
+            lines.drain(..=end);
+        }
+        if let Some(colophon) = lines
+            .iter()
+            .position(|line| line.starts_with(AOZORA_COLOPHON))
+        {
+            lines.truncate(colophon);
+        }
+    }
+
+    let lines: Vec<Line> = lines
+        .into_iter()
+        .flat_map(|line| {
+            if is_aozora {
+                aozora_line(line)
+            } else {
+                vec![plain_line(line)]
+            }
+        })
+        .collect();
+    let has_headings = lines
+        .iter()
+        .any(|line| matches!(line, Line::Heading(level, _) if *level <= 3));
+
+    let mut chapters = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut paragraphs = 0;
+    let mut finish = |current: &mut Vec<String>, paragraphs: &mut usize| {
+        if *paragraphs > 0 {
+            let file_name = format!("chapter{:04}.html", chapters.len() + 1);
+            chapters.push((file_name, current.join("\n")));
+        }
+        current.clear();
+        *paragraphs = 0;
+    };
+    for line in lines {
+        match line {
+            Line::PageBreak => finish(&mut current, &mut paragraphs),
+            Line::Heading(level, text) => {
+                if level <= 3 {
+                    finish(&mut current, &mut paragraphs);
+                }
+                current.push(format!("<h{level}>{text}</h{level}>"));
+                paragraphs += 1;
+            }
+            Line::Text(text) if text.trim().is_empty() => {
+                if paragraphs > 0 {
+                    current.push("<p><br/></p>".to_string());
+                }
+            }
+            Line::Text(text) => {
+                if !has_headings && paragraphs >= PART_PARAGRAPHS {
+                    finish(&mut current, &mut paragraphs);
+                }
+                current.push(format!("<p>{text}</p>"));
+                paragraphs += 1;
+            }
+        }
+    }
+    finish(&mut current, &mut paragraphs);
+
+    from_chapters(book_id, info, chapters, HashMap::new(), String::new())
+}
+
+fn is_dashed(line: &str) -> bool {
+    line.trim().starts_with("-----")
+}
+
+fn plain_line(line: &str) -> Line {
+    let text = ruby(&escape_html(line.trim_end()));
+    let trimmed = line.trim();
+    if !trimmed.is_empty()
+        && trimmed.chars().count() <= MAX_TITLE_CHARS
+        && CHAPTER_TITLE_REGEX.is_match(trimmed)
+    {
+        Line::Heading(2, text.trim().to_string())
+    } else {
+        Line::Text(text)
+    }
+}
+
+fn aozora_line(line: &str) -> Vec<Line> {
+    let mut level = None;
+    let mut page_break = false;
+
+    let escaped = escape_html(line.trim_end());
+    let text = HEADING_RANGE_REGEX.replace_all(&escaped, |caps: &Captures| {
+        level.get_or_insert(heading_level(&caps[1]));
+        caps[2].to_string()
+    });
+    let text = ANNOTATION_REGEX.replace_all(&text, |caps: &Captures| {
+        let note = &caps[1];
+        if matches!(note, "改ページ" | "改丁" | "改見開き" | "改段") {
+            page_break = true;
+        } else if let Some(kind) = note.strip_suffix("見出し") {
+            // `大見出し` or `「…」は大見出し`
+            level.get_or_insert(heading_level(kind));
+        }
+        String::new()
+    });
+    let text = ruby(&text);
+
+    let mut lines = Vec::new();
+    if page_break {
+        lines.push(Line::PageBreak);
+    }
+    match level {
+        Some(level) if !text.trim().is_empty() => {
+            lines.push(Line::Heading(level, text.trim().to_string()))
+        }
+        _ if page_break && text.trim().is_empty() => {}
+        _ => lines.push(Line::Text(text)),
+    }
+    lines
+}
+
+/// `大`, `中` and `小` headings become `h2` to `h4`; the first two start a
+/// chapter
+fn heading_level(kind: &str) -> u8 {
+    if kind.ends_with('大') {
+        2
+    } else if kind.ends_with('中') {
+        3
+    } else {
+        4
+    }
+}
+
+fn ruby(text: &str) -> String {
+    let text = RUBY_WITH_MARKER_REGEX.replace_all(text, "<ruby>$1<rt>$2</rt></ruby>");
+    RUBY_AFTER_KANJI_REGEX
+        .replace_all(&text, "<ruby>$1<rt>$2</rt></ruby>")
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AOZORA: &str = "羅生門
+芥川龍之介
+
+-------------------------------------------------------
+【テキスト中に現れる記号について】
+
+《》：ルビ
+（例）下人《げにん》
+-------------------------------------------------------
+
+［＃３字下げ］一［＃「一」は中見出し］
+　ある日の暮方の事である。一人の下人《げにん》が、｜羅生門《らしょうもん》の下で雨やみを待っていた。
+［＃改ページ］
+［＃中見出し］二［＃中見出し終わり］
+　広い門の下には、この男のほかに誰もいない。<script>
+
+底本：「芥川龍之介全集1」ちくま文庫、筑摩書房
+";
+
+    #[test]
+    fn converts_aozora_ruby_and_headings() {
+        let (data, _, _) = encoding_rs::SHIFT_JIS.encode(AOZORA);
+        let ParsedBook { metadata, content } =
+            parse_text(&data, "book", "rashomon").expect("text should parse");
+
+        assert_eq!(metadata.title, "羅生門");
+        assert_eq!(metadata.author, "芥川龍之介");
+        assert_eq!(metadata.language.as_deref(), Some("ja"));
+        assert_eq!(content.chapters.len(), 2);
+        let toc: Vec<(&str, i32)> = metadata
+            .toc
+            .iter()
+            .map(|item| (item.label.as_str(), item.chapter_index))
+            .collect();
+        assert_eq!(toc, vec![("一", 0), ("二", 1)]);
+
+        let first = &content.chapters[0];
+        assert!(first.contains("<ruby>下人<rt>げにん</rt></ruby>"));
+        assert!(first.contains("<ruby>羅生門<rt>らしょうもん</rt></ruby>"));
+        assert!(!first.contains("［＃"));
+        assert!(!first.contains("テキスト中に現れる記号"));
+        let second = &content.chapters[1];
+        assert!(second.contains("&lt;script&gt;"));
+        assert!(!second.contains("底本"));
+    }
+
+    #[test]
+    fn splits_plain_text_on_chapter_titles() {
+        let text = "前書きの文章がここにあります。\n\n第一章　始まり\n最初の章の本文がここにあります。\n\nChapter 2\nThe second chapter starts here.\n";
+        let ParsedBook { metadata, content } =
+            parse_text(text.as_bytes(), "book", "My Novel").expect("text should parse");
+
+        assert_eq!(metadata.title, "My Novel");
+        assert_eq!(content.chapters.len(), 3);
+        let labels: Vec<&str> = metadata
+            .toc
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["第一章　始まり", "Chapter 2"]);
+    }
+}
@@ -4,8 +4,14 @@ use axum::{Router, extract::DefaultBodyLimit};
 use tower_http::cors::{Any, CorsLayer};
 
 pub mod error;
+pub mod export;
+pub mod formats;
+pub mod opds;
 pub mod routes;
+pub mod search;
 pub mod state;
+pub mod stats;
+pub mod sync;
 pub mod types;
 
 use axum::http::header::{CACHE_CONTROL, HeaderValue};
@@ -21,20 +27,40 @@ use walkdir::WalkDir;
 use crate::types::*;
 
 pub fn create_router(data_dir: PathBuf, local_novel_path: PathBuf) -> Router {
-    let state = NovelState::new(data_dir, local_novel_path);
+    create_router_with_state(NovelState::new(data_dir, local_novel_path))
+}
+
+/// Build the router around an existing state, so it can be shared with the
+/// sync server's background scheduler. Requests for a created profile (see
+/// `manatan_sync_server::profile`) go to that profile's state.
+pub fn create_router_with_state(state: NovelState) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
 
+    let default_router = router_with_state(state.clone());
+    let data_dir = state.data_dir.clone();
+    manatan_sync_server::profile::dispatch(data_dir, default_router, move |name| {
+        router_with_state(state.profile(name))
+    })
+    .layer(cors)
+    .layer(DefaultBodyLimit::max(250 * 1024 * 1024))
+}
+
+fn router_with_state(state: NovelState) -> Router {
+    // `store_content` and `delete_book` keep the search index current after
+    // this, so the backfill only runs once per start
     let state_clone = state.clone();
-    tokio::spawn(async move {
+    tokio::task::spawn_blocking(move || {
         if let Err(e) = scan_local_novel(&state_clone) {
             warn!("Failed to scan local-novel: {:?}", e);
         }
+        if let Err(e) = index_missing_books(&state_clone) {
+            warn!("Failed to index stored books for search: {:?}", e);
+        }
     });
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
     let metadata_root = state.get_novel_metadata_root();
     if let Err(err) = fs::create_dir_all(&metadata_root) {
         warn!(
@@ -52,11 +78,25 @@ pub fn create_router(data_dir: PathBuf, local_novel_path: PathBuf) -> Router {
 
     routes::router()
         .nest_service("/static", static_service)
-        .layer(cors)
-        .layer(DefaultBodyLimit::max(250 * 1024 * 1024))
         .with_state(state)
 }
 
+/// Index content stored before the search index existed, or restored from
+/// sidecars by `scan_local_novel`
+fn index_missing_books(state: &NovelState) -> anyhow::Result<()> {
+    let indexed = state.search.indexed_books()?;
+    for item in state.db.scan_prefix("content:") {
+        let (key, value) = item?;
+        let id = String::from_utf8_lossy(&key["content:".len()..]).to_string();
+        if indexed.contains(&id) {
+            continue;
+        }
+        let content: LNParsedBook = serde_json::from_slice(&value)?;
+        state.search.index_book(&id, &content)?;
+    }
+    Ok(())
+}
+
 fn scan_local_novel(state: &NovelState) -> anyhow::Result<()> {
     let local_path = state.get_local_novel_path();
 
@@ -66,7 +106,10 @@ fn scan_local_novel(state: &NovelState) -> anyhow::Result<()> {
 
     info!("Scanning local-novel for novels: {}", local_path.display());
 
-    migrate_legacy_local_novel_layout(state)?;
+    // Moving files in the shared folder is left to the default profile
+    if state.profile.is_none() {
+        migrate_legacy_local_novel_layout(state)?;
+    }
 
     let metadata_root = state.get_novel_metadata_root();
     fs::create_dir_all(&metadata_root)?;
@@ -86,6 +129,9 @@ fn scan_local_novel(state: &NovelState) -> anyhow::Result<()> {
                 continue;
             };
             let id = file_name.to_string_lossy().to_string();
+            if state.db.contains_key(format!("hidden:{}", id))? {
+                continue;
+            }
 
             info!("Found novel directory: {}", id);
 
@@ -93,12 +139,18 @@ fn scan_local_novel(state: &NovelState) -> anyhow::Result<()> {
             let sidecar_data: serde_json::Value = serde_json::from_str(&content)?;
 
             if let Some(metadata) = sidecar_data.get("metadata") {
-                let meta: LNMetadata = serde_json::from_value(metadata.clone())?;
+                let mut meta: LNMetadata = serde_json::from_value(metadata.clone())?;
+                if !state.keeps_reading_sidecars() {
+                    // Category ids in the sidecar are the default profile's
+                    meta.category_ids.clear();
+                }
                 let bytes = serde_json::to_vec(&meta)?;
                 state.db.insert(format!("metadata:{}", id), bytes)?;
             }
 
-            if let Some(progress) = sidecar_data.get("progress") {
+            if let Some(progress) = sidecar_data.get("progress")
+                && state.keeps_reading_sidecars()
+            {
                 let prog: LNProgress = serde_json::from_value(progress.clone())?;
                 let bytes = serde_json::to_vec(&prog)?;
                 state.db.insert(format!("progress:{}", id), bytes)?;
@@ -114,7 +166,7 @@ fn scan_local_novel(state: &NovelState) -> anyhow::Result<()> {
 
     // Scan global categories in root
     let categories_path = local_path.join("categories.json");
-    if categories_path.exists() {
+    if state.keeps_reading_sidecars() && categories_path.exists() {
         if let Ok(content) = fs::read_to_string(&categories_path) {
             if let Ok(sidecar_data) = serde_json::from_str::<serde_json::Value>(&content) {
                 if let Some(categories) = sidecar_data.get("categories") {
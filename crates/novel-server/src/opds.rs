@@ -0,0 +1,710 @@
+use std::{cmp::Reverse, collections::HashMap, fs};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Value, json};
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use crate::{
+    error::NovelError,
+    formats::{escape_html, media_type},
+    routes::load_categories,
+    state::NovelState,
+    types::{LNMetadata, LnCategory},
+};
+
+// ============================================================================
+// OPDS Catalog
+// ============================================================================
+//
+// The library as OPDS 1.2 Atom feeds under `/opds` and as OPDS 2.0 JSON
+// feeds under `/opds/v2`, so e-ink readers like KOReader can browse and
+// download books. Links are absolute paths under the prefix the catalog was
+// requested with, which keeps them working behind `/api/novel` and profile
+// prefixes alike.
+
+const ATOM_NAVIGATION: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ATOM_ACQUISITION: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const OPDS_JSON: &str = "application/opds+json";
+pub const OPENSEARCH: &str = "application/opensearchdescription+xml";
+
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+const IMAGE_REL: &str = "http://opds-spec.org/image";
+const THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpdsVersion {
+    /// Atom feeds of OPDS 1.2
+    V1,
+    /// JSON feeds of OPDS 2.0
+    V2,
+}
+
+/// Cover image of a book
+#[derive(Debug, Clone)]
+pub struct Cover {
+    /// Path under `/static`, or a URL of its own when `local` is false
+    pub href: String,
+    pub local: bool,
+    pub media_type: Option<&'static str>,
+}
+
+/// Book that can be downloaded from the catalog
+#[derive(Debug, Clone)]
+pub struct Publication {
+    pub metadata: LNMetadata,
+    /// Names of the book's categories
+    pub categories: Vec<String>,
+    /// Media type of the stored book file
+    pub media_type: &'static str,
+    pub cover: Option<Cover>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NavigationEntry {
+    pub id: String,
+    pub title: String,
+    /// Path within the catalog
+    pub path: String,
+    pub summary: String,
+    /// Whether the entry leads to books rather than to more navigation
+    pub acquisition: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum FeedItems {
+    Navigation(Vec<NavigationEntry>),
+    Publications(Vec<Publication>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub id: String,
+    pub title: String,
+    /// Path within the catalog, query included
+    pub path: String,
+    pub items: FeedItems,
+}
+
+// ============================================================================
+// Library
+// ============================================================================
+
+/// Books with a downloadable file, newest first
+pub fn publications(state: &NovelState) -> Result<Vec<Publication>, NovelError> {
+    let category_names: HashMap<String, String> = load_categories(state)?
+        .into_iter()
+        .map(|category| (category.id, category.name))
+        .collect();
+
+    let mut publications = Vec::new();
+    for item in state.db.scan_prefix("metadata:") {
+        let (_, value) = item?;
+        let metadata: LNMetadata = serde_json::from_slice(&value)?;
+        if metadata.is_processing == Some(true) || metadata.is_error == Some(true) {
+            continue;
+        }
+        let Some(path) = state.get_book_path(&metadata.id) else {
+            continue;
+        };
+
+        publications.push(Publication {
+            categories: metadata
+                .category_ids
+                .iter()
+                .filter_map(|id| category_names.get(id).cloned())
+                .collect(),
+            media_type: media_type(
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or_default(),
+            ),
+            cover: cover(state, &metadata),
+            metadata,
+        });
+    }
+    publications.sort_by_key(|book| Reverse(book.metadata.added_at));
+    Ok(publications)
+}
+
+/// Covers are kept as data URLs, which readers cannot fetch; they are
+/// written out under `/static` once per distinct image
+fn cover(state: &NovelState, metadata: &LNMetadata) -> Option<Cover> {
+    let cover = metadata.cover.as_deref()?;
+    if cover.starts_with("http://") || cover.starts_with("https://") {
+        return Some(Cover {
+            href: cover.to_string(),
+            local: false,
+            media_type: None,
+        });
+    }
+
+    let (header, data) = cover.strip_prefix("data:")?.split_once(',')?;
+    let (media_type, extension) = match header.strip_suffix(";base64")? {
+        "image/jpeg" => ("image/jpeg", "jpg"),
+        "image/png" => ("image/png", "png"),
+        "image/gif" => ("image/gif", "gif"),
+        "image/webp" => ("image/webp", "webp"),
+        _ => return None,
+    };
+    let bytes = BASE64.decode(data).ok()?;
+    let digest = format!("{:x}", Sha1::digest(&bytes));
+    let name = format!("cover-{}.{extension}", &digest[..16]);
+
+    let dir = state.get_novel_dir(&metadata.id);
+    let path = dir.join(&name);
+    if !path.exists()
+        && let Err(e) = fs::create_dir_all(&dir).and_then(|()| fs::write(&path, &bytes))
+    {
+        warn!("[NOVEL] Could not write cover of {}: {e}", metadata.id);
+        return None;
+    }
+    Some(Cover {
+        href: format!("{}/{name}", metadata.id),
+        local: true,
+        media_type: Some(media_type),
+    })
+}
+
+// ============================================================================
+// Feeds
+// ============================================================================
+
+pub fn root_feed(books: &[Publication], categories: &[LnCategory]) -> Feed {
+    let mut entries = vec![NavigationEntry {
+        id: "books".to_string(),
+        title: "All Books".to_string(),
+        path: "/books".to_string(),
+        summary: count_summary(books.len()),
+        acquisition: true,
+    }];
+    if !categories.is_empty() {
+        entries.push(NavigationEntry {
+            id: "categories".to_string(),
+            title: "Categories".to_string(),
+            path: "/categories".to_string(),
+            summary: match categories.len() {
+                1 => "1 category".to_string(),
+                count => format!("{count} categories"),
+            },
+            acquisition: false,
+        });
+    }
+    Feed {
+        id: "root".to_string(),
+        title: "Manatan Library".to_string(),
+        path: String::new(),
+        items: FeedItems::Navigation(entries),
+    }
+}
+
+pub fn books_feed(books: Vec<Publication>) -> Feed {
+    Feed {
+        id: "books".to_string(),
+        title: "All Books".to_string(),
+        path: "/books".to_string(),
+        items: FeedItems::Publications(books),
+    }
+}
+
+pub fn categories_feed(books: &[Publication], categories: &[LnCategory]) -> Feed {
+    let entries = categories
+        .iter()
+        .map(|category| NavigationEntry {
+            id: format!("category:{}", category.id),
+            title: category.name.clone(),
+            path: format!("/categories/{}", urlencoding::encode(&category.id)),
+            summary: count_summary(
+                books
+                    .iter()
+                    .filter(|book| book.metadata.category_ids.contains(&category.id))
+                    .count(),
+            ),
+            acquisition: true,
+        })
+        .collect();
+    Feed {
+        id: "categories".to_string(),
+        title: "Categories".to_string(),
+        path: "/categories".to_string(),
+        items: FeedItems::Navigation(entries),
+    }
+}
+
+pub fn category_feed(books: Vec<Publication>, category: &LnCategory) -> Feed {
+    Feed {
+        id: format!("category:{}", category.id),
+        title: category.name.clone(),
+        path: format!("/categories/{}", urlencoding::encode(&category.id)),
+        items: FeedItems::Publications(
+            books
+                .into_iter()
+                .filter(|book| book.metadata.category_ids.contains(&category.id))
+                .collect(),
+        ),
+    }
+}
+
+/// Books whose title or author contains the query, ignoring case
+pub fn search_feed(books: Vec<Publication>, query: &str) -> Feed {
+    let query = query.trim().to_lowercase();
+    Feed {
+        id: "search".to_string(),
+        title: format!("Search: {query}"),
+        path: format!("/search?q={}", urlencoding::encode(&query)),
+        items: FeedItems::Publications(
+            books
+                .into_iter()
+                .filter(|book| {
+                    book.metadata.title.to_lowercase().contains(&query)
+                        || book.metadata.author.to_lowercase().contains(&query)
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn count_summary(count: usize) -> String {
+    match count {
+        1 => "1 book".to_string(),
+        count => format!("{count} books"),
+    }
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+/// Renders feeds for one version of the spec, with links under `base`
+pub struct Catalog {
+    base: String,
+    pub version: OpdsVersion,
+}
+
+impl Catalog {
+    /// Catalog for a request to `path`, which the server saw as
+    /// `original_path` before nesting and profile prefixes were stripped
+    pub fn for_request(original_path: &str, path: &str) -> Self {
+        let base = original_path
+            .strip_suffix(path)
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let version = if path == "/opds/v2" || path.starts_with("/opds/v2/") {
+            OpdsVersion::V2
+        } else {
+            OpdsVersion::V1
+        };
+        Self {
+            base: base.to_string(),
+            version,
+        }
+    }
+
+    fn href(&self, path: &str) -> String {
+        match self.version {
+            OpdsVersion::V1 => format!("{}/opds{path}", self.base),
+            OpdsVersion::V2 => format!("{}/opds/v2{path}", self.base),
+        }
+    }
+
+    fn file_href(&self, book: &Publication) -> String {
+        format!(
+            "{}/file/{}",
+            self.base,
+            urlencoding::encode(&book.metadata.id)
+        )
+    }
+
+    fn cover_href(&self, cover: &Cover) -> String {
+        if cover.local {
+            format!("{}/static/{}", self.base, cover.href)
+        } else {
+            cover.href.clone()
+        }
+    }
+
+    pub fn content_type(&self, feed: &Feed) -> &'static str {
+        match (self.version, &feed.items) {
+            (OpdsVersion::V1, FeedItems::Navigation(_)) => ATOM_NAVIGATION,
+            (OpdsVersion::V1, FeedItems::Publications(_)) => ATOM_ACQUISITION,
+            (OpdsVersion::V2, _) => OPDS_JSON,
+        }
+    }
+
+    pub fn render(&self, feed: &Feed) -> String {
+        match self.version {
+            OpdsVersion::V1 => self.atom(feed),
+            OpdsVersion::V2 => self.json(feed).to_string(),
+        }
+    }
+
+    /// OpenSearch description pointing readers at the Atom search feed
+    pub fn opensearch(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Manatan</ShortName>
+  <Description>Search the Manatan library</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <OutputEncoding>UTF-8</OutputEncoding>
+  <Url type="{}" template="{}?q={{searchTerms}}"/>
+</OpenSearchDescription>
+"#,
+            escape_html(ATOM_ACQUISITION),
+            escape_html(&self.href("/search"))
+        )
+    }
+
+    fn atom(&self, feed: &Feed) -> String {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/" xmlns:opds="http://opds-spec.org/2010/catalog">
+"#,
+        );
+        xml.push_str(&format!(
+            "  <id>urn:manatan:novel:{}</id>\n  <title>{}</title>\n  <updated>{}</updated>\n  <author><name>Manatan</name></author>\n",
+            escape_html(&feed.id),
+            escape_html(&feed.title),
+            timestamp(Utc::now().timestamp_millis())
+        ));
+        xml.push_str(&atom_link(
+            "self",
+            &self.href(&feed.path),
+            self.content_type(feed),
+        ));
+        xml.push_str(&atom_link("start", &self.href(""), ATOM_NAVIGATION));
+        xml.push_str(&atom_link(
+            "search",
+            &self.href("/opensearch.xml"),
+            OPENSEARCH,
+        ));
+
+        match &feed.items {
+            FeedItems::Navigation(entries) => {
+                for entry in entries {
+                    let media_type = if entry.acquisition {
+                        ATOM_ACQUISITION
+                    } else {
+                        ATOM_NAVIGATION
+                    };
+                    xml.push_str(&format!(
+                        "  <entry>\n    <title>{}</title>\n    <id>urn:manatan:novel:{}</id>\n    <updated>{}</updated>\n    <content type=\"text\">{}</content>\n  ",
+                        escape_html(&entry.title),
+                        escape_html(&entry.id),
+                        timestamp(Utc::now().timestamp_millis()),
+                        escape_html(&entry.summary)
+                    ));
+                    xml.push_str(&atom_link(
+                        "subsection",
+                        &self.href(&entry.path),
+                        media_type,
+                    ));
+                    xml.push_str("  </entry>\n");
+                }
+            }
+            FeedItems::Publications(books) => {
+                for book in books {
+                    xml.push_str(&self.atom_entry(book));
+                }
+            }
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    fn atom_entry(&self, book: &Publication) -> String {
+        let metadata = &book.metadata;
+        let mut xml = format!(
+            "  <entry>\n    <title>{}</title>\n    <id>urn:manatan:novel:{}</id>\n    <updated>{}</updated>\n    <author><name>{}</name></author>\n",
+            escape_html(&metadata.title),
+            escape_html(&metadata.id),
+            timestamp(metadata.last_modified.unwrap_or(metadata.added_at)),
+            escape_html(&metadata.author)
+        );
+        if let Some(language) = &metadata.language {
+            xml.push_str(&format!(
+                "    <dc:language>{}</dc:language>\n",
+                escape_html(language)
+            ));
+        }
+        for category in &book.categories {
+            let category = escape_html(category);
+            xml.push_str(&format!(
+                "    <category term=\"{category}\" label=\"{category}\"/>\n"
+            ));
+        }
+        xml.push_str("  ");
+        xml.push_str(&atom_link(
+            ACQUISITION_REL,
+            &self.file_href(book),
+            book.media_type,
+        ));
+        if let Some(cover) = &book.cover {
+            let href = self.cover_href(cover);
+            let media_type = cover.media_type.unwrap_or("image/jpeg");
+            for rel in [IMAGE_REL, THUMBNAIL_REL] {
+                xml.push_str("  ");
+                xml.push_str(&atom_link(rel, &href, media_type));
+            }
+        }
+        xml.push_str("  </entry>\n");
+        xml
+    }
+
+    fn json(&self, feed: &Feed) -> Value {
+        let links = json!([
+            { "rel": "self", "href": self.href(&feed.path), "type": OPDS_JSON },
+            { "rel": "start", "href": self.href(""), "type": OPDS_JSON },
+            {
+                "rel": "search",
+                "href": format!("{}{{?query}}", self.href("/search")),
+                "type": OPDS_JSON,
+                "templated": true
+            },
+        ]);
+
+        match &feed.items {
+            FeedItems::Navigation(entries) => json!({
+                "metadata": { "title": feed.title },
+                "links": links,
+                "navigation": entries
+                    .iter()
+                    .map(|entry| json!({
+                        "href": self.href(&entry.path),
+                        "title": entry.title,
+                        "type": OPDS_JSON,
+                        "rel": "subsection",
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            FeedItems::Publications(books) => json!({
+                "metadata": { "title": feed.title, "numberOfItems": books.len() },
+                "links": links,
+                "publications": books
+                    .iter()
+                    .map(|book| self.json_publication(book))
+                    .collect::<Vec<_>>(),
+            }),
+        }
+    }
+
+    fn json_publication(&self, book: &Publication) -> Value {
+        let metadata = &book.metadata;
+        let mut publication_metadata = json!({
+            "@type": "http://schema.org/Book",
+            "identifier": format!("urn:manatan:novel:{}", metadata.id),
+            "title": metadata.title,
+            "author": metadata.author,
+            "modified": timestamp(metadata.last_modified.unwrap_or(metadata.added_at)),
+        });
+        if let Some(language) = &metadata.language {
+            publication_metadata["language"] = json!(language);
+        }
+        if !book.categories.is_empty() {
+            publication_metadata["subject"] = book
+                .categories
+                .iter()
+                .map(|name| json!({ "name": name }))
+                .collect();
+        }
+
+        let mut publication = json!({
+            "metadata": publication_metadata,
+            "links": [{
+                "rel": ACQUISITION_REL,
+                "href": self.file_href(book),
+                "type": book.media_type,
+            }],
+        });
+        if let Some(cover) = &book.cover {
+            let mut image = json!({ "href": self.cover_href(cover) });
+            if let Some(media_type) = cover.media_type {
+                image["type"] = json!(media_type);
+            }
+            publication["images"] = json!([image]);
+        }
+        publication
+    }
+}
+
+fn atom_link(rel: &str, href: &str, media_type: &str) -> String {
+    format!(
+        "  <link rel=\"{}\" href=\"{}\" type=\"{}\"/>\n",
+        escape_html(rel),
+        escape_html(href),
+        escape_html(media_type)
+    )
+}
+
+fn timestamp(millis: i64) -> String {
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    fn unique_temp_dir(label: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("manatan-novel-opds-{label}-{nanos}"));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        dir
+    }
+
+    /// A library of a categorized EPUB with a cover, a text file, and a
+    /// book whose file is gone
+    fn state() -> NovelState {
+        let root = unique_temp_dir("library");
+        let state = NovelState::new(root.join("data"), root.join("local-novel"));
+        std::fs::create_dir_all(state.get_local_novel_path()).expect("folder should be created");
+        for file in ["neko.epub", "rashomon.txt"] {
+            std::fs::write(state.get_local_novel_path().join(file), b"book")
+                .expect("book should be written");
+        }
+
+        let book = |id: &str, title: &str, added_at: i64, extra: Value| {
+            let mut metadata = json!({
+                "id": id, "title": title, "author": "作者 & Co", "addedAt": added_at,
+                "stats": { "chapterLengths": [], "totalLength": 0 }, "chapterCount": 1,
+                "toc": [],
+            });
+            if let (Some(metadata), Value::Object(extra)) = (metadata.as_object_mut(), extra) {
+                metadata.extend(extra);
+            }
+            (format!("metadata:{id}"), metadata)
+        };
+        let entries = [
+            book(
+                "neko",
+                "吾輩は猫である",
+                1,
+                json!({
+                    "cover": "data:image/png;base64,iVBORw0KGgo=",
+                    "language": "ja",
+                    "categoryIds": ["classics"],
+                }),
+            ),
+            book("rashomon", "Rashomon", 2, json!({})),
+            book("missing", "Missing", 3, json!({})),
+            (
+                "category:classics".to_string(),
+                json!({
+                    "id": "classics", "name": "Classics", "order": 0,
+                    "createdAt": 0, "lastModified": 0,
+                }),
+            ),
+        ];
+        for (key, value) in entries {
+            state
+                .db
+                .insert(
+                    key,
+                    serde_json::to_vec(&value).expect("value should serialize"),
+                )
+                .expect("value should be stored");
+        }
+        state
+    }
+
+    fn categories() -> Vec<LnCategory> {
+        vec![LnCategory {
+            id: "classics".to_string(),
+            name: "Classics".to_string(),
+            order: 0,
+            created_at: 0,
+            last_modified: 0,
+        }]
+    }
+
+    #[test]
+    fn catalog_links_follow_the_request_prefix() {
+        let catalog = Catalog::for_request(
+            "/api/novel/profiles/kid/opds/v2/categories/classics",
+            "/opds/v2/categories/classics",
+        );
+        assert_eq!(catalog.version, OpdsVersion::V2);
+        assert_eq!(
+            catalog.href("/books"),
+            "/api/novel/profiles/kid/opds/v2/books"
+        );
+
+        let catalog = Catalog::for_request("/api/novel/opds", "/opds");
+        assert_eq!(catalog.version, OpdsVersion::V1);
+        assert_eq!(catalog.href(""), "/api/novel/opds");
+        assert!(
+            catalog
+                .opensearch()
+                .contains(r#"template="/api/novel/opds/search?q={searchTerms}""#)
+        );
+    }
+
+    #[test]
+    fn lists_downloadable_books_with_covers() {
+        let state = state();
+        let books = publications(&state).expect("books should load");
+
+        let ids: Vec<&str> = books.iter().map(|book| book.metadata.id.as_str()).collect();
+        assert_eq!(ids, vec!["rashomon", "neko"]);
+        assert_eq!(books[0].media_type, "text/plain");
+        assert_eq!(books[1].media_type, "application/epub+zip");
+        assert_eq!(books[1].categories, vec!["Classics".to_string()]);
+
+        let cover = books[1].cover.as_ref().expect("cover should be written");
+        assert!(cover.local);
+        assert!(cover.href.starts_with("neko/cover-") && cover.href.ends_with(".png"));
+        let path = state.get_novel_metadata_root().join(&cover.href);
+        assert_eq!(
+            std::fs::read(path).expect("cover should exist"),
+            BASE64.decode("iVBORw0KGgo=").expect("cover should decode")
+        );
+    }
+
+    #[test]
+    fn renders_atom_and_json_feeds() {
+        let state = state();
+        let books = publications(&state).expect("books should load");
+        let atom = Catalog::for_request("/api/novel/opds/categories", "/opds/categories");
+        let json_catalog = Catalog::for_request("/api/novel/opds/v2", "/opds/v2");
+
+        let feed = root_feed(&books, &categories());
+        assert_eq!(atom.content_type(&feed), ATOM_NAVIGATION);
+        let json = json_catalog.json(&feed);
+        assert_eq!(json["navigation"][0]["href"], "/api/novel/opds/v2/books");
+        assert_eq!(json["navigation"][1]["title"], "Categories");
+
+        let xml = atom.render(&categories_feed(&books, &categories()));
+        roxmltree::Document::parse(&xml).expect("feed should be well-formed");
+        assert!(xml.contains(r#"href="/api/novel/opds/categories/classics""#));
+        assert!(xml.contains("<content type=\"text\">1 book</content>"));
+
+        let feed = category_feed(books.clone(), &categories()[0]);
+        assert_eq!(atom.content_type(&feed), ATOM_ACQUISITION);
+        let xml = atom.render(&feed);
+        roxmltree::Document::parse(&xml).expect("feed should be well-formed");
+        assert!(xml.contains("<author><name>作者 &amp; Co</name></author>"));
+        assert!(xml.contains(
+            r#"<link rel="http://opds-spec.org/acquisition" href="/api/novel/file/neko" type="application/epub+zip"/>"#
+        ));
+        assert!(xml.contains(r#"href="/api/novel/static/neko/cover-"#));
+        assert!(!xml.contains("rashomon"));
+
+        let feed = search_feed(books, "RASHO");
+        let json = json_catalog.json(&feed);
+        assert_eq!(json["metadata"]["numberOfItems"], 1);
+        let publication = &json["publications"][0];
+        assert_eq!(publication["metadata"]["title"], "Rashomon");
+        assert_eq!(publication["links"][0]["href"], "/api/novel/file/rashomon");
+        assert_eq!(
+            json["links"][2]["href"],
+            "/api/novel/opds/v2/search{?query}"
+        );
+    }
+}
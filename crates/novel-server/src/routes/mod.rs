@@ -1,13 +1,25 @@
 use crate::error::NovelError;
+use crate::export;
+use crate::formats::{self, ParsedBook, SUPPORTED_EXTENSIONS};
+use crate::opds::{self, Catalog};
 use crate::state::NovelState;
+use crate::stats;
 use crate::types::*;
 use axum::{
     Json, Router,
-    extract::{Multipart, Path, State},
+    extract::{Multipart, OriginalUri, Path, Query, State},
+    http::{
+        Uri,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    response::IntoResponse,
     routing::{delete, get, post},
 };
+use manatan_sync_server::types::Tombstone;
 use std::collections::HashMap;
 use std::fs;
+use std::path::Path as FsPath;
+use tracing::{info, warn};
 
 pub fn router() -> Router<NovelState> {
     Router::new()
@@ -29,6 +41,22 @@ pub fn router() -> Router<NovelState> {
         .route("/categories/metadata/{id}", post(update_category_metadata))
         .route("/upload/{id}", post(upload_epub))
         .route("/file/{id}", get(get_epub))
+        .route("/search", get(search_library))
+        .route("/highlights/export", get(export_highlights))
+        .route("/stats", get(get_reading_stats))
+        .route("/stats/sessions", get(get_reading_sessions))
+        .route("/stats/export", get(export_reading_stats))
+        .route("/opds", get(opds_root))
+        .route("/opds/books", get(opds_books))
+        .route("/opds/categories", get(opds_categories))
+        .route("/opds/categories/{id}", get(opds_category))
+        .route("/opds/search", get(opds_search))
+        .route("/opds/opensearch.xml", get(opds_opensearch))
+        .route("/opds/v2", get(opds_root))
+        .route("/opds/v2/books", get(opds_books))
+        .route("/opds/v2/categories", get(opds_categories))
+        .route("/opds/v2/categories/{id}", get(opds_category))
+        .route("/opds/v2/search", get(opds_search))
 }
 
 fn discover_pending_epubs(state: &NovelState) -> Result<Vec<DiscoveredEpub>, NovelError> {
@@ -51,7 +79,10 @@ fn discover_pending_epubs(state: &NovelState) -> Result<Vec<DiscoveredEpub>, Nov
         let Some(extension) = path.extension().and_then(|value| value.to_str()) else {
             continue;
         };
-        if !extension.eq_ignore_ascii_case("epub") {
+        if !SUPPORTED_EXTENSIONS
+            .iter()
+            .any(|supported| extension.eq_ignore_ascii_case(supported))
+        {
             continue;
         }
 
@@ -61,7 +92,9 @@ fn discover_pending_epubs(state: &NovelState) -> Result<Vec<DiscoveredEpub>, Nov
         if id.trim().is_empty() {
             continue;
         }
-        if state.db.get(format!("metadata:{id}"))?.is_some() {
+        if state.db.get(format!("metadata:{id}"))?.is_some()
+            || state.db.contains_key(format!("hidden:{id}"))?
+        {
             continue;
         }
 
@@ -75,10 +108,87 @@ fn discover_pending_epubs(state: &NovelState) -> Result<Vec<DiscoveredEpub>, Nov
     Ok(discovered)
 }
 
+/// Import books dropped into the local novel folder. Only the ones the
+/// server could not parse are returned, for the client to import itself.
 async fn discover_epubs(
     State(state): State<NovelState>,
 ) -> Result<Json<Vec<DiscoveredEpub>>, NovelError> {
-    Ok(Json(discover_pending_epubs(&state)?))
+    let mut remaining = Vec::new();
+    for discovered in discover_pending_epubs(&state)? {
+        let path = state.get_local_novel_path().join(&discovered.file_name);
+        let title = discovered.id.clone();
+        match import_book_blocking(state.clone(), discovered.id.clone(), path, title).await {
+            Ok(metadata) => info!(
+                "[NOVEL] Imported {} as \"{}\"",
+                discovered.file_name, metadata.title
+            ),
+            Err(e) => {
+                warn!("[NOVEL] Could not import {}: {e}", discovered.file_name);
+                remaining.push(discovered);
+            }
+        }
+    }
+    Ok(Json(remaining))
+}
+
+/// Parse a stored book file and save it like a client import would. A book
+/// that is already in the library keeps its added date, categories and
+/// settings. `title` names books whose format has no metadata of its own.
+pub(crate) fn import_book(
+    state: &NovelState,
+    id: &str,
+    path: &FsPath,
+    title: &str,
+) -> Result<LNMetadata, NovelError> {
+    let ParsedBook {
+        mut metadata,
+        content,
+    } = formats::parse_book(&fs::read(path)?, book_extension(path), id, title)?;
+
+    if let Some(bytes) = state.db.get(format!("metadata:{id}"))? {
+        let existing: LNMetadata = serde_json::from_slice(&bytes)?;
+        metadata.added_at = existing.added_at;
+        metadata.category_ids = existing.category_ids;
+        metadata.language_settings = existing.language_settings;
+    }
+
+    store_content(state, id, &content)?;
+    store_metadata(state, id, &metadata)?;
+    Ok(metadata)
+}
+
+/// Parse the file of a book restored from a backup archive, keeping the
+/// metadata that came with the archive
+pub(crate) fn restore_content(
+    state: &NovelState,
+    id: &str,
+    path: &FsPath,
+) -> Result<(), NovelError> {
+    let title = match state.db.get(format!("metadata:{id}"))? {
+        Some(bytes) => serde_json::from_slice::<LNMetadata>(&bytes)?.title,
+        None => id.to_string(),
+    };
+    let ParsedBook { content, .. } =
+        formats::parse_book(&fs::read(path)?, book_extension(path), id, &title)?;
+    store_content(state, id, &content)
+}
+
+fn book_extension(path: &FsPath) -> &str {
+    path.extension()
+        .and_then(|value| value.to_str())
+        .unwrap_or("epub")
+}
+
+/// Parsing is CPU heavy, keep it off the async workers
+async fn import_book_blocking(
+    state: NovelState,
+    id: String,
+    path: std::path::PathBuf,
+    title: String,
+) -> Result<LNMetadata, NovelError> {
+    tokio::task::spawn_blocking(move || import_book(&state, &id, &path, &title))
+        .await
+        .map_err(|e| NovelError::Io(std::io::Error::other(e)))?
 }
 
 async fn get_all_metadata(
@@ -109,24 +219,32 @@ async fn update_metadata(
     Path(id): Path<String>,
     Json(req): Json<UpdateMetadataRequest>,
 ) -> Result<(), NovelError> {
+    store_metadata(&state, &id, &req.metadata)
+}
+
+fn store_metadata(state: &NovelState, id: &str, metadata: &LNMetadata) -> Result<(), NovelError> {
     let key = format!("metadata:{}", id);
-    let bytes = serde_json::to_vec(&req.metadata)?;
+    let bytes = serde_json::to_vec(metadata)?;
     state.db.insert(key, bytes)?;
+    state.db.remove(format!("hidden:{}", id))?;
+    state.db.remove(format!("tombstone:{}", id))?;
 
     // Sidecar save
-    let novel_dir = state.get_novel_dir(&id);
-    fs::create_dir_all(&novel_dir)?;
-    let sidecar_path = novel_dir.join("metadata.json");
-
-    let mut sidecar_data = if sidecar_path.exists() {
-        let content = fs::read_to_string(&sidecar_path)?;
-        serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
-    } else {
-        serde_json::json!({})
-    };
+    if state.keeps_reading_sidecars() {
+        let novel_dir = state.get_novel_dir(id);
+        fs::create_dir_all(&novel_dir)?;
+        let sidecar_path = novel_dir.join("metadata.json");
+
+        let mut sidecar_data = if sidecar_path.exists() {
+            let content = fs::read_to_string(&sidecar_path)?;
+            serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
+        } else {
+            serde_json::json!({})
+        };
 
-    sidecar_data["metadata"] = serde_json::to_value(&req.metadata)?;
-    fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+        sidecar_data["metadata"] = serde_json::to_value(metadata)?;
+        fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+    }
 
     state.db.flush()?;
     Ok(())
@@ -139,6 +257,26 @@ async fn delete_book(
     state.db.remove(format!("metadata:{}", id))?;
     state.db.remove(format!("progress:{}", id))?;
     state.db.remove(format!("content:{}", id))?;
+    state.search.remove_book(&id)?;
+    // Past sessions stay in the reading history
+    state.db.remove(format!("session_cursor:{}", id))?;
+    // Background syncs carry the deletion to the other devices instead of
+    // bringing the book back
+    let tombstone = Tombstone {
+        deleted_at: chrono::Utc::now().timestamp_millis(),
+        device_id: None,
+    };
+    state
+        .db
+        .insert(format!("tombstone:{}", id), serde_json::to_vec(&tombstone)?)?;
+
+    // Files are shared between profiles; only the default profile removes
+    // them. Other profiles hide the book so a rescan does not bring it back.
+    if !state.keeps_reading_sidecars() {
+        state.db.insert(format!("hidden:{}", id), &[])?;
+        state.db.flush()?;
+        return Ok(());
+    }
 
     let novel_dir = state.get_novel_dir(&id);
     if novel_dir.exists() {
@@ -150,9 +288,8 @@ async fn delete_book(
         fs::remove_dir_all(legacy_novel_dir)?;
     }
 
-    let epub_path = state.get_epub_path(&id);
-    if epub_path.exists() {
-        fs::remove_file(epub_path)?;
+    while let Some(book_path) = state.get_book_path(&id) {
+        fs::remove_file(book_path)?;
     }
 
     state.db.flush()?;
@@ -178,27 +315,34 @@ async fn save_content(
     Path(id): Path<String>,
     Json(content): Json<LNParsedBook>,
 ) -> Result<(), NovelError> {
+    store_content(&state, &id, &content)
+}
+
+fn store_content(state: &NovelState, id: &str, content: &LNParsedBook) -> Result<(), NovelError> {
     let key = format!("content:{}", id);
 
     // Save to DB for sync compatibility
-    let bytes = serde_json::to_vec(&content)?;
+    let bytes = serde_json::to_vec(content)?;
     state.db.insert(key, bytes)?;
+    state.search.index_book(id, content)?;
 
     // Novel directory structure
-    let novel_dir = state.get_novel_dir(&id);
+    let novel_dir = state.get_novel_dir(id);
     fs::create_dir_all(&novel_dir)?;
 
     // Sidecar save for portability
-    let sidecar_path = novel_dir.join("metadata.json");
-    let mut sidecar_data = if sidecar_path.exists() {
-        let content = fs::read_to_string(&sidecar_path)?;
-        serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
-    } else {
-        serde_json::json!({})
-    };
+    if state.keeps_reading_sidecars() {
+        let sidecar_path = novel_dir.join("metadata.json");
+        let mut sidecar_data = if sidecar_path.exists() {
+            let content = fs::read_to_string(&sidecar_path)?;
+            serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
+        } else {
+            serde_json::json!({})
+        };
 
-    sidecar_data["content"] = serde_json::to_value(&content)?;
-    fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+        sidecar_data["content"] = serde_json::to_value(content)?;
+        fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+    }
 
     // Static extraction for speed
     let extracted_dir = novel_dir.join("extracted");
@@ -210,14 +354,14 @@ async fn save_content(
     // Save images as files
     let img_dir = extracted_dir.join("images");
     fs::create_dir_all(&img_dir)?;
-    for (path, base64) in content.image_blobs {
-        let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &base64)
+    for (path, base64) in &content.image_blobs {
+        let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, base64)
             .map_err(|e| NovelError::BadRequest(format!("Invalid base64 image: {}", e)))?;
 
         let normalized_path = if path.starts_with('/') {
             &path[1..]
         } else {
-            &path
+            path
         };
         let img_path = img_dir.join(normalized_path);
         if let Some(parent) = img_path.parent() {
@@ -260,21 +404,29 @@ async fn update_progress(
     let key = format!("progress:{}", id);
     let bytes = serde_json::to_vec(&req.progress)?;
     state.db.insert(key, bytes)?;
+    stats::record_progress(
+        &state,
+        &id,
+        &req.progress,
+        chrono::Utc::now().timestamp_millis(),
+    )?;
 
     // Sidecar save
-    let novel_dir = state.get_novel_dir(&id);
-    fs::create_dir_all(&novel_dir)?;
-    let sidecar_path = novel_dir.join("metadata.json");
-
-    let mut sidecar_data = if sidecar_path.exists() {
-        let content = fs::read_to_string(&sidecar_path)?;
-        serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
-    } else {
-        serde_json::json!({})
-    };
+    if state.keeps_reading_sidecars() {
+        let novel_dir = state.get_novel_dir(&id);
+        fs::create_dir_all(&novel_dir)?;
+        let sidecar_path = novel_dir.join("metadata.json");
+
+        let mut sidecar_data = if sidecar_path.exists() {
+            let content = fs::read_to_string(&sidecar_path)?;
+            serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
+        } else {
+            serde_json::json!({})
+        };
 
-    sidecar_data["progress"] = serde_json::to_value(&req.progress)?;
-    fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+        sidecar_data["progress"] = serde_json::to_value(&req.progress)?;
+        fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+    }
 
     state.db.flush()?;
     Ok(())
@@ -283,24 +435,16 @@ async fn update_progress(
 async fn get_categories(
     State(state): State<NovelState>,
 ) -> Result<Json<Vec<LnCategory>>, NovelError> {
-    let mut categories = Vec::new();
-    for item in state.db.scan_prefix("category:") {
-        let (_, v) = item?;
-        let category: LnCategory = serde_json::from_slice(&v)?;
-        categories.push(category);
-    }
-    categories.sort_by(|a, b| a.order.cmp(&b.order));
-    Ok(Json(categories))
+    Ok(Json(load_categories(&state)?))
 }
 
 async fn save_global_categories(state: &NovelState) -> Result<(), NovelError> {
-    let mut categories = Vec::new();
-    for item in state.db.scan_prefix("category:") {
-        let (_, v) = item?;
-        let category: LnCategory = serde_json::from_slice(&v)?;
-        categories.push(category);
+    if !state.keeps_reading_sidecars() {
+        return Ok(());
     }
 
+    let categories = load_categories(state)?;
+
     let mut meta_map = HashMap::new();
     for item in state.db.scan_prefix("category_metadata:") {
         let (k, v) = item?;
@@ -417,21 +561,57 @@ async fn update_category_metadata(
     Ok(())
 }
 
+/// Store a book file and import it, with its format taken from the uploaded
+/// file name. The book's metadata is returned, or `null` when the server
+/// could not parse it and the client has to.
 async fn upload_epub(
     State(state): State<NovelState>,
     Path(id): Path<String>,
     mut multipart: Multipart,
-) -> Result<(), NovelError> {
+) -> Result<Json<Option<LNMetadata>>, NovelError> {
     while let Some(field) = multipart.next_field().await? {
         if let Some(name) = field.name() {
             if name == "file" {
+                // Files without a known extension, such as bare blobs, are
+                // EPUBs as before
+                let extension = field
+                    .file_name()
+                    .and_then(|file_name| FsPath::new(file_name).extension())
+                    .and_then(|value| value.to_str())
+                    .map(str::to_ascii_lowercase)
+                    .filter(|extension| SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+                    .unwrap_or_else(|| "epub".to_string());
+                let title = field
+                    .file_name()
+                    .and_then(|file_name| FsPath::new(file_name).file_stem())
+                    .and_then(|value| value.to_str())
+                    .unwrap_or(&id)
+                    .to_string();
                 let data = field.bytes().await?;
-                let path = state.get_epub_path(&id);
+
+                // A book is stored in one format only
+                for extension in SUPPORTED_EXTENSIONS {
+                    let path = state
+                        .get_local_novel_path()
+                        .join(format!("{id}.{extension}"));
+                    if path.exists() {
+                        fs::remove_file(path)?;
+                    }
+                }
+                let path = state
+                    .get_local_novel_path()
+                    .join(format!("{id}.{extension}"));
                 if let Some(parent) = path.parent() {
                     fs::create_dir_all(parent)?;
                 }
-                fs::write(path, data)?;
-                return Ok(());
+                fs::write(&path, data)?;
+                return match import_book_blocking(state, id.clone(), path, title).await {
+                    Ok(metadata) => Ok(Json(Some(metadata))),
+                    Err(e) => {
+                        warn!("[NOVEL] Could not parse uploaded book {id}: {e}");
+                        Ok(Json(None))
+                    }
+                };
             }
         }
     }
@@ -441,24 +621,246 @@ async fn upload_epub(
 async fn get_epub(
     State(state): State<NovelState>,
     Path(id): Path<String>,
-) -> Result<Vec<u8>, NovelError> {
-    let primary_path = state.get_epub_path(&id);
-    if primary_path.exists() {
-        return Ok(fs::read(primary_path)?);
+) -> Result<impl IntoResponse, NovelError> {
+    // Includes the pre-migration layout for backward compatibility.
+    let path = state.get_book_path(&id).ok_or(NovelError::NotFound)?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let data = fs::read(path)?;
+    Ok((
+        [
+            (CONTENT_TYPE, formats::media_type(&extension).to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{id}.{extension}\""),
+            ),
+        ],
+        data,
+    ))
+}
+
+const DEFAULT_SEARCH_RESULTS: usize = 50;
+const MAX_SEARCH_RESULTS: usize = 500;
+
+/// Find a word or sentence in the library. Hits come in reading order with
+/// the chapter and character offsets the reader restores progress from.
+async fn search_library(
+    State(state): State<NovelState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, NovelError> {
+    let text = query.q.trim().to_string();
+    if text.is_empty() {
+        return Err(NovelError::BadRequest("Missing search query".into()));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_RESULTS)
+        .clamp(1, MAX_SEARCH_RESULTS);
+
+    tokio::task::spawn_blocking(move || state.search.search(&text, query.book_id.as_deref(), limit))
+        .await
+        .map_err(|e| NovelError::Io(std::io::Error::other(e)))?
+        .map(Json)
+}
+
+/// Download highlights with their sentences as CSV, TSV, Markdown or an
+/// Anki deck
+async fn export_highlights(
+    State(state): State<NovelState>,
+    Query(query): Query<HighlightExportQuery>,
+) -> Result<impl IntoResponse, NovelError> {
+    let format = query.format;
+    let filename = match &query.book_id {
+        Some(id) => format!("manatan-highlights-{id}.{}", format.extension()),
+        None => format!("manatan-highlights.{}", format.extension()),
+    };
+
+    let data = tokio::task::spawn_blocking(move || {
+        export::export_highlights(&state, query.book_id.as_deref(), format)
+    })
+    .await
+    .map_err(|e| NovelError::Io(std::io::Error::other(e)))??;
+
+    Ok((
+        [
+            (CONTENT_TYPE, format.content_type().to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        data,
+    ))
+}
+
+/// Categories in their display order
+pub(crate) fn load_categories(state: &NovelState) -> Result<Vec<LnCategory>, NovelError> {
+    let mut categories = Vec::new();
+    for item in state.db.scan_prefix("category:") {
+        let (_, v) = item?;
+        let category: LnCategory = serde_json::from_slice(&v)?;
+        categories.push(category);
     }
+    categories.sort_by_key(|category| category.order);
+    Ok(categories)
+}
+
+fn opds_response(catalog: &Catalog, feed: &opds::Feed) -> impl IntoResponse + use<> {
+    (
+        [(CONTENT_TYPE, catalog.content_type(feed))],
+        catalog.render(feed),
+    )
+}
 
-    // Backward compatibility for pre-migration layout.
-    let legacy_path = state.get_legacy_epub_path(&id);
-    if legacy_path.exists() {
-        return Ok(fs::read(legacy_path)?);
+// OPDS feeds answer under both `/opds` and `/opds/v2`; the request path
+// picks the version.
+
+async fn opds_root(
+    State(state): State<NovelState>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+) -> Result<impl IntoResponse, NovelError> {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    let feed = opds::root_feed(&opds::publications(&state)?, &load_categories(&state)?);
+    Ok(opds_response(&catalog, &feed))
+}
+
+async fn opds_books(
+    State(state): State<NovelState>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+) -> Result<impl IntoResponse, NovelError> {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    let feed = opds::books_feed(opds::publications(&state)?);
+    Ok(opds_response(&catalog, &feed))
+}
+
+async fn opds_categories(
+    State(state): State<NovelState>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+) -> Result<impl IntoResponse, NovelError> {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    let feed = opds::categories_feed(&opds::publications(&state)?, &load_categories(&state)?);
+    Ok(opds_response(&catalog, &feed))
+}
+
+async fn opds_category(
+    State(state): State<NovelState>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, NovelError> {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    let v = state
+        .db
+        .get(format!("category:{}", id))?
+        .ok_or(NovelError::NotFound)?;
+    let category: LnCategory = serde_json::from_slice(&v)?;
+    let feed = opds::category_feed(opds::publications(&state)?, &category);
+    Ok(opds_response(&catalog, &feed))
+}
+
+async fn opds_search(
+    State(state): State<NovelState>,
+    OriginalUri(original): OriginalUri,
+    uri: Uri,
+    Query(query): Query<OpdsSearchQuery>,
+) -> Result<impl IntoResponse, NovelError> {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    let feed = opds::search_feed(opds::publications(&state)?, &query.q);
+    Ok(opds_response(&catalog, &feed))
+}
+
+async fn opds_opensearch(OriginalUri(original): OriginalUri, uri: Uri) -> impl IntoResponse {
+    let catalog = Catalog::for_request(original.path(), uri.path());
+    ([(CONTENT_TYPE, opds::OPENSEARCH)], catalog.opensearch())
+}
+
+/// Sessions in the query's range, and every session of its book for streaks
+fn query_sessions(
+    state: &NovelState,
+    query: &ReadingStatsQuery,
+) -> Result<(Vec<ReadingSession>, Vec<ReadingSession>), NovelError> {
+    let mut history = stats::load_sessions(state, None, None)?;
+    if let Some(book_id) = &query.book_id {
+        history.retain(|session| &session.book_id == book_id);
     }
+    let sessions = history
+        .iter()
+        .filter(|session| {
+            query.from.is_none_or(|from| session.started_at >= from)
+                && query.to.is_none_or(|to| session.started_at < to)
+        })
+        .cloned()
+        .collect();
+    Ok((sessions, history))
+}
 
-    Err(NovelError::NotFound)
+async fn get_reading_stats(
+    State(state): State<NovelState>,
+    Query(query): Query<ReadingStatsQuery>,
+) -> Result<Json<ReadingStats>, NovelError> {
+    let (sessions, history) = query_sessions(&state, &query)?;
+    let titles = stats::book_titles(&state)?;
+    Ok(Json(stats::compute_stats(
+        &sessions,
+        &history,
+        &titles,
+        query.utc_offset_minutes,
+        chrono::Utc::now().timestamp_millis(),
+    )))
+}
+
+async fn get_reading_sessions(
+    State(state): State<NovelState>,
+    Query(query): Query<ReadingStatsQuery>,
+) -> Result<Json<Vec<ReadingSession>>, NovelError> {
+    let (sessions, _) = query_sessions(&state, &query)?;
+    Ok(Json(sessions))
+}
+
+async fn export_reading_stats(
+    State(state): State<NovelState>,
+    Query(query): Query<ReadingStatsQuery>,
+) -> Result<impl IntoResponse, NovelError> {
+    let (sessions, history) = query_sessions(&state, &query)?;
+    let titles = stats::book_titles(&state)?;
+    let reading_stats = stats::compute_stats(
+        &sessions,
+        &history,
+        &titles,
+        query.utc_offset_minutes,
+        chrono::Utc::now().timestamp_millis(),
+    );
+    let csv = stats::to_csv(
+        query.by,
+        &sessions,
+        &reading_stats,
+        &titles,
+        query.utc_offset_minutes,
+    );
+
+    let filename = format!("manatan-reading-{}.csv", query.by.name());
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        csv,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use manatan_sync_server::LocalLibrary;
     use std::time::{SystemTime, UNIX_EPOCH};
 
     fn unique_temp_dir(label: &str) -> std::path::PathBuf {
@@ -483,7 +885,7 @@ mod tests {
 
         fs::write(local_novel_dir.join("indexed.epub"), b"epub").expect("epub should be written");
         fs::write(local_novel_dir.join("pending.EPUB"), b"epub").expect("epub should be written");
-        fs::write(local_novel_dir.join("readme.txt"), b"text").expect("txt should be written");
+        fs::write(local_novel_dir.join("readme.pdf"), b"pdf").expect("pdf should be written");
 
         let state = NovelState::new(data_dir, local_novel_dir);
         state
@@ -527,4 +929,232 @@ mod tests {
         let discovered = discover_pending_epubs(&state).expect("discovery should succeed");
         assert!(discovered.is_empty());
     }
+
+    #[tokio::test]
+    async fn discover_imports_epubs_on_the_server() {
+        let root = unique_temp_dir("discover-import");
+        let local_novel_dir = root.join("local-novel");
+        fs::create_dir_all(&local_novel_dir).expect("local dir should be created");
+        fs::write(
+            local_novel_dir.join("book.epub"),
+            crate::formats::sample_epub(),
+        )
+        .expect("epub should be written");
+        fs::write(local_novel_dir.join("broken.epub"), b"not a zip")
+            .expect("epub should be written");
+        let state = NovelState::new(root.join("data"), local_novel_dir);
+
+        let Json(remaining) = discover_epubs(State(state.clone()))
+            .await
+            .expect("discovery should succeed");
+        let remaining: Vec<&str> = remaining.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(remaining, vec!["broken"]);
+
+        let Json(metadata) = get_metadata(State(state.clone()), Path("book".to_string()))
+            .await
+            .expect("book should be imported");
+        assert_eq!(metadata.title, "Test Book");
+        assert_eq!(metadata.chapter_count, 2);
+        let Json(content) = get_content(State(state.clone()), Path("book".to_string()))
+            .await
+            .expect("content should be stored");
+        assert_eq!(content.chapters.len(), 2);
+        assert!(
+            state
+                .get_novel_dir("book")
+                .join("extracted/images/OEBPS/images/cover.png")
+                .exists()
+        );
+    }
+
+    #[tokio::test]
+    async fn search_covers_stored_and_new_content() {
+        let root = unique_temp_dir("search");
+        let state = NovelState::new(root.join("data"), root.join("local-novel"));
+        let chapter = |text: &str| LNParsedBook {
+            chapters: vec![format!(r#"<p data-block-id="ch0-b0">{text}</p>"#)],
+            image_blobs: HashMap::new(),
+            chapter_filenames: vec!["chapter.html".to_string()],
+            css: None,
+        };
+        // Stored before the index existed, picked up at startup
+        state
+            .db
+            .insert(
+                "content:old",
+                serde_json::to_vec(&chapter("国境の長いトンネルを抜けると雪国であった。"))
+                    .expect("content should serialize"),
+            )
+            .expect("content should be stored");
+        crate::index_missing_books(&state).expect("backfill should succeed");
+        save_content(
+            State(state.clone()),
+            Path("new".to_string()),
+            Json(chapter("トンネルの向こうは雪だった。")),
+        )
+        .await
+        .expect("content should be saved");
+
+        let search = |q: &str| SearchQuery {
+            q: q.to_string(),
+            book_id: None,
+            limit: None,
+        };
+        let Json(hits) = search_library(State(state.clone()), Query(search("トンネル")))
+            .await
+            .expect("search should succeed");
+        let found: Vec<(&str, i32)> = hits
+            .iter()
+            .map(|hit| (hit.book_id.as_str(), hit.chapter_char_offset))
+            .collect();
+        assert_eq!(found, vec![("new", 0), ("old", 5)]);
+
+        delete_book(State(state.clone()), Path("new".to_string()))
+            .await
+            .expect("book should be deleted");
+        let Json(hits) = search_library(State(state.clone()), Query(search("雪")))
+            .await
+            .expect("search should succeed");
+        let books: Vec<&str> = hits.iter().map(|hit| hit.book_id.as_str()).collect();
+        assert_eq!(books, vec!["old"]);
+
+        assert!(
+            search_library(State(state), Query(search("  ")))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn profiles_keep_their_own_progress_and_share_epubs() {
+        let root = unique_temp_dir("profiles");
+        let local_novel_dir = root.join("local-novel");
+        fs::create_dir_all(&local_novel_dir).expect("local dir should be created");
+        let state = NovelState::new(root.join("data"), local_novel_dir);
+        let alice = state.profile("alice");
+        fs::write(state.get_epub_path("book"), b"epub").expect("epub should be written");
+
+        let progress = LNProgress {
+            total_progress: 0.5,
+            ..LNProgress::default()
+        };
+        update_progress(
+            State(alice.clone()),
+            Path("book".to_string()),
+            Json(UpdateProgressRequest { progress }),
+        )
+        .await
+        .expect("progress should be saved");
+
+        assert!(alice.db.contains_key("progress:book").expect("db readable"));
+        assert!(!state.db.contains_key("progress:book").expect("db readable"));
+        assert!(!state.get_novel_dir("book").join("metadata.json").exists());
+        assert_eq!(alice.get_epub_path("book"), state.get_epub_path("book"));
+        // The same database is handed out again
+        assert!(
+            state
+                .profile("alice")
+                .db
+                .contains_key("progress:book")
+                .expect("db readable")
+        );
+
+        delete_book(State(alice.clone()), Path("book".to_string()))
+            .await
+            .expect("book should be removed from the profile");
+        assert!(state.get_epub_path("book").exists());
+    }
+
+    #[tokio::test]
+    async fn profile_metadata_edits_stay_out_of_the_shared_sidecars() {
+        use manatan_sync_server::{LocalLibrary, SyncPayload};
+
+        let root = unique_temp_dir("profile-sidecars");
+        let local_novel_dir = root.join("local-novel");
+        fs::create_dir_all(&local_novel_dir).expect("local dir should be created");
+        let state = NovelState::new(root.join("data"), local_novel_dir);
+        let alice = state.profile("alice");
+        let epub = state.get_epub_path("book");
+        fs::write(&epub, crate::formats::sample_epub()).expect("epub should be written");
+        import_book(&state, "book", &epub, "book").expect("book imported");
+        import_book(&alice, "book", &epub, "book").expect("book imported");
+
+        let Json(mut metadata) = get_metadata(State(alice.clone()), Path("book".to_string()))
+            .await
+            .expect("metadata stored");
+        metadata.title = "Alice's title".to_string();
+        update_metadata(
+            State(alice.clone()),
+            Path("book".to_string()),
+            Json(UpdateMetadataRequest {
+                metadata: metadata.clone(),
+            }),
+        )
+        .await
+        .expect("metadata updated");
+
+        let mut payload = SyncPayload::new("alice-phone".to_string());
+        metadata.title = "Synced title".to_string();
+        payload.ln_metadata.insert("book".to_string(), metadata);
+        alice
+            .apply_payload(&payload)
+            .await
+            .expect("payload applied");
+
+        crate::scan_local_novel(&state).expect("rescan succeeds");
+        let Json(default_metadata) = get_metadata(State(state.clone()), Path("book".to_string()))
+            .await
+            .expect("metadata stored");
+        assert_eq!(default_metadata.title, "Test Book");
+        let sidecar = fs::read_to_string(state.get_novel_dir("book").join("metadata.json"))
+            .expect("sidecar written by the default profile");
+        assert!(!sidecar.contains("Alice's title"));
+        assert!(!sidecar.contains("Synced title"));
+    }
+
+    #[tokio::test]
+    async fn deleted_books_are_synced_as_tombstones() {
+        let root = unique_temp_dir("delete");
+        let state = NovelState::new(root.join("data"), root.join("local-novel"));
+        delete_book(State(state.clone()), Path("book".to_string()))
+            .await
+            .expect("book deleted");
+
+        let payload = state
+            .export_payload("device")
+            .await
+            .expect("payload exported");
+        assert_eq!(
+            payload.tombstones.ln_metadata["book"].device_id.as_deref(),
+            Some("device")
+        );
+        assert!(payload.tombstones.ln_progress.contains_key("book"));
+
+        // Adding the book again takes the deletion back
+        let metadata = LNMetadata {
+            id: "book".to_string(),
+            title: "Book".to_string(),
+            author: String::new(),
+            cover: None,
+            added_at: 0,
+            is_processing: None,
+            is_error: None,
+            error_msg: None,
+            stats: BookStats::default(),
+            chapter_count: 1,
+            toc: Vec::new(),
+            has_progress: None,
+            last_modified: Some(0),
+            sync_version: Some(1),
+            language: None,
+            category_ids: Vec::new(),
+            language_settings: HashMap::new(),
+        };
+        store_metadata(&state, "book", &metadata).expect("metadata stored");
+        let payload = state
+            .export_payload("device")
+            .await
+            .expect("payload exported");
+        assert!(payload.tombstones.is_empty());
+    }
 }
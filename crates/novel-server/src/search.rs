@@ -0,0 +1,422 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, LazyLock, Mutex, MutexGuard},
+};
+
+use rusqlite::{Connection, params};
+use scraper::{Html, Selector};
+
+use crate::{
+    error::NovelError,
+    formats::{character_count, clean_text},
+    types::{LNParsedBook, SearchHit},
+};
+
+// ============================================================================
+// Full-Text Search
+// ============================================================================
+//
+// Chapter text is indexed block by block in an SQLite FTS5 table using the
+// trigram tokenizer, which needs no word segmentation and so works for
+// Japanese as well as for spaced languages. Offsets are counted the way the
+// block maps count them, so a hit maps straight onto `chapterCharOffset`.
+
+static BLOCK_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("[data-block-id]").expect("block selector should parse"));
+
+/// Trigram queries need at least three characters; shorter ones scan
+const MIN_TRIGRAM_CHARS: usize = 3;
+/// Characters of context on each side of a hit
+const CONTEXT_CHARS: usize = 30;
+
+/// Text of one reader block
+pub(crate) struct Block {
+    pub(crate) id: Option<String>,
+    pub(crate) start_offset: i32,
+    pub(crate) text: String,
+}
+
+#[derive(Clone)]
+pub struct SearchIndex {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SearchIndex {
+    pub fn open(path: &Path) -> Result<Self, NovelError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+
+             CREATE VIRTUAL TABLE IF NOT EXISTS blocks USING fts5(
+                book_id UNINDEXED,
+                chapter_index UNINDEXED,
+                block_id UNINDEXED,
+                start_offset UNINDEXED,
+                text,
+                tokenize = 'trigram'
+             );
+
+             CREATE TABLE IF NOT EXISTS books (
+                book_id TEXT PRIMARY KEY
+             );",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Replace the indexed text of a book
+    pub fn index_book(&self, book_id: &str, content: &LNParsedBook) -> Result<(), NovelError> {
+        let chapters: Vec<Vec<Block>> = content
+            .chapters
+            .iter()
+            .map(|html| chapter_blocks(html))
+            .collect();
+
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM blocks WHERE book_id = ?1", [book_id])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO blocks (book_id, chapter_index, block_id, start_offset, text)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (chapter_index, blocks) in chapters.iter().enumerate() {
+                for block in blocks {
+                    insert.execute(params![
+                        book_id,
+                        chapter_index as i64,
+                        block.id,
+                        block.start_offset,
+                        block.text
+                    ])?;
+                }
+            }
+        }
+        transaction.execute(
+            "INSERT OR IGNORE INTO books (book_id) VALUES (?1)",
+            [book_id],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_book(&self, book_id: &str) -> Result<(), NovelError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM blocks WHERE book_id = ?1", [book_id])?;
+        transaction.execute("DELETE FROM books WHERE book_id = ?1", [book_id])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn indexed_books(&self) -> Result<HashSet<String>, NovelError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT book_id FROM books")?;
+        let books = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(books)
+    }
+
+    /// Occurrences of `query` in the library, or in one book, in reading
+    /// order. Case is ignored.
+    pub fn search(
+        &self,
+        query: &str,
+        book_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, NovelError> {
+        // The trigram index answers phrase queries of three characters or
+        // more; shorter ones fall back to `LIKE`
+        let (condition, pattern) = if query.chars().count() >= MIN_TRIGRAM_CHARS {
+            (
+                "blocks MATCH ?1",
+                format!("\"{}\"", query.replace('"', "\"\"")),
+            )
+        } else {
+            (
+                r"text LIKE ?1 ESCAPE '\'",
+                format!("%{}%", escape_like(query)),
+            )
+        };
+        let sql = format!(
+            "SELECT book_id, chapter_index, block_id, start_offset, text FROM blocks
+             WHERE {condition} AND (?2 IS NULL OR book_id = ?2)
+             ORDER BY book_id, chapter_index, start_offset"
+        );
+
+        let connection = self.connection();
+        let mut statement = connection.prepare(&sql)?;
+        let mut rows = statement.query(params![pattern, book_id])?;
+
+        let mut hits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let book_id: String = row.get(0)?;
+            let chapter_index: i32 = row.get(1)?;
+            let block_id: Option<String> = row.get(2)?;
+            let start_offset: i32 = row.get(3)?;
+            let text: String = row.get(4)?;
+
+            for (start, end) in find_matches(&text, query) {
+                if hits.len() >= limit {
+                    return Ok(hits);
+                }
+                let block_local_offset = character_count(&text[..start]);
+                hits.push(SearchHit {
+                    book_id: book_id.clone(),
+                    chapter_index,
+                    block_id: block_id.clone(),
+                    block_local_offset,
+                    chapter_char_offset: start_offset + block_local_offset,
+                    length: character_count(&text[start..end]),
+                    context_before: context_before(&text[..start]),
+                    matched_text: text[start..end].to_string(),
+                    context_after: context_after(&text[end..]),
+                });
+            }
+        }
+        Ok(hits)
+    }
+}
+
+/// Blocks of stored chapter HTML with the offsets of the book's block maps
+pub(crate) fn chapter_blocks(html: &str) -> Vec<Block> {
+    let fragment = Html::parse_fragment(html);
+
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    for element in fragment.select(&BLOCK_SELECTOR) {
+        let text = clean_text(element);
+        let length = character_count(&text);
+        if length > 0 {
+            blocks.push(Block {
+                id: element.value().attr("data-block-id").map(str::to_string),
+                start_offset: offset,
+                text,
+            });
+        }
+        offset += length;
+    }
+
+    // Content from before block maps
+    if offset == 0 {
+        let text = clean_text(fragment.root_element());
+        if character_count(&text) > 0 {
+            blocks.push(Block {
+                id: None,
+                start_offset: 0,
+                text,
+            });
+        }
+    }
+    blocks
+}
+
+/// Byte ranges of the non-overlapping occurrences of `query`, ignoring case
+fn find_matches(text: &str, query: &str) -> Vec<(usize, usize)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    let mut search_from = 0;
+    'starts: for (start, _) in text.char_indices() {
+        if start < search_from {
+            continue;
+        }
+        let mut position = 0;
+        for (index, c) in text[start..].char_indices() {
+            for folded in c.to_lowercase() {
+                if query.get(position) != Some(&folded) {
+                    continue 'starts;
+                }
+                position += 1;
+            }
+            if position == query.len() {
+                search_from = start + index + c.len_utf8();
+                matches.push((start, search_from));
+                continue 'starts;
+            }
+        }
+    }
+    matches
+}
+
+fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn context_before(text: &str) -> String {
+    let chars: Vec<char> = text.chars().rev().take(CONTEXT_CHARS).collect();
+    chars
+        .into_iter()
+        .rev()
+        .collect::<String>()
+        .trim_start()
+        .to_string()
+}
+
+fn context_after(text: &str) -> String {
+    text.chars()
+        .take(CONTEXT_CHARS)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::*;
+
+    fn temp_index(label: &str) -> SearchIndex {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("manatan-novel-search-{label}-{nanos}"));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        SearchIndex::open(&dir.join("search.db")).expect("index should open")
+    }
+
+    fn book(chapters: &[&str]) -> LNParsedBook {
+        LNParsedBook {
+            chapters: chapters.iter().map(|html| html.to_string()).collect(),
+            image_blobs: HashMap::new(),
+            chapter_filenames: Vec::new(),
+            css: None,
+        }
+    }
+
+    #[test]
+    fn finds_offsets_matching_block_maps() {
+        let index = temp_index("offsets");
+        index
+            .index_book(
+                "neko",
+                &book(&[
+                    r#"<h1 data-block-id="ch0-b0">第一章</h1>"#,
+                    r#"<p data-block-id="ch1-b0">吾輩は<ruby>猫<rt>ねこ</rt></ruby>である。</p>
+<p data-block-id="ch1-b1">名前はまだ無い。猫</p>"#,
+                ]),
+            )
+            .expect("book should be indexed");
+        index
+            .index_book(
+                "english",
+                &book(&[r#"<p data-block-id="ch0-b0">Hello, World! Hello again.</p>"#]),
+            )
+            .expect("book should be indexed");
+
+        let hits = index.search("猫", None, 10).expect("search should run");
+        let positions: Vec<(i32, Option<&str>, i32, i32)> = hits
+            .iter()
+            .map(|hit| {
+                (
+                    hit.chapter_index,
+                    hit.block_id.as_deref(),
+                    hit.block_local_offset,
+                    hit.chapter_char_offset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![(1, Some("ch1-b0"), 3, 3), (1, Some("ch1-b1"), 7, 14)]
+        );
+        assert_eq!(hits[0].context_before, "吾輩は");
+        assert_eq!(hits[0].context_after, "である。");
+
+        let hits = index
+            .search("まだ無い", None, 10)
+            .expect("search should run");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chapter_char_offset, 10);
+        assert_eq!(hits[0].length, 4);
+
+        // Furigana is not part of the text
+        assert!(
+            index
+                .search("ねこ", None, 10)
+                .expect("search should run")
+                .is_empty()
+        );
+
+        let hits = index
+            .search("hello", Some("english"), 10)
+            .expect("search should run");
+        let offsets: Vec<i32> = hits.iter().map(|hit| hit.chapter_char_offset).collect();
+        assert_eq!(offsets, vec![0, 10]);
+        assert_eq!(hits[0].matched_text, "Hello");
+        assert!(
+            index
+                .search("hello", Some("neko"), 10)
+                .expect("search should run")
+                .is_empty()
+        );
+        assert_eq!(
+            index
+                .search("hello", None, 1)
+                .expect("search should run")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn reindexing_and_removing_replace_old_text() {
+        let index = temp_index("replace");
+        index
+            .index_book("book", &book(&["<p>古い本文です。</p>"]))
+            .expect("book should be indexed");
+        index
+            .index_book("book", &book(&["<p>新しい本文です。</p>"]))
+            .expect("book should be indexed");
+
+        assert!(
+            index
+                .search("古い", None, 10)
+                .expect("search should run")
+                .is_empty()
+        );
+        assert_eq!(
+            index
+                .search("新しい", None, 10)
+                .expect("search should run")
+                .len(),
+            1
+        );
+        assert!(
+            index
+                .indexed_books()
+                .expect("lookup should run")
+                .contains("book")
+        );
+
+        index.remove_book("book").expect("book should be removed");
+        assert!(
+            index
+                .search("本文", None, 10)
+                .expect("search should run")
+                .is_empty()
+        );
+        assert!(index.indexed_books().expect("lookup should run").is_empty());
+    }
+}
@@ -1,13 +1,25 @@
+use crate::search::SearchIndex;
 use sled::Db;
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 pub const NOVEL_METADATA_DIR_NAME: &str = ".manatan-metadata";
 
 #[derive(Clone)]
 pub struct NovelState {
     pub db: Db,
+    pub search: SearchIndex,
     pub storage_dir: PathBuf,
     pub local_novel_path: PathBuf,
+    /// Named profile this state belongs to, `None` for the default profile
+    pub profile: Option<String>,
+    pub(crate) data_dir: PathBuf,
+    /// States of named profiles, shared by all clones so each database is
+    /// opened once
+    profiles: Arc<Mutex<HashMap<String, NovelState>>>,
 }
 
 impl NovelState {
@@ -17,14 +29,47 @@ impl NovelState {
 
         let db_path = novel_dir.join("novel.db");
         let db = sled::open(db_path).expect("Failed to open novel database");
+        let search = SearchIndex::open(&novel_dir.join("search.db"))
+            .expect("Failed to open novel search index");
 
         Self {
             db,
+            search,
             storage_dir: novel_dir,
             local_novel_path,
+            profile: None,
+            data_dir,
+            profiles: Arc::default(),
         }
     }
 
+    /// State of a named profile: its own database, the same local novel folder
+    pub fn profile(&self, name: &str) -> NovelState {
+        let mut profiles = self
+            .profiles
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        profiles
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let profile_dir = manatan_sync_server::profile::profile_dir(&self.data_dir, name);
+                Self {
+                    profile: Some(name.to_string()),
+                    data_dir: self.data_dir.clone(),
+                    profiles: self.profiles.clone(),
+                    ..Self::new(profile_dir, self.local_novel_path.clone())
+                }
+            })
+            .clone()
+    }
+
+    /// Metadata, progress and categories belong to a profile, so only the
+    /// default profile keeps them in the sidecars of the shared local novel
+    /// folder
+    pub fn keeps_reading_sidecars(&self) -> bool {
+        self.profile.is_none()
+    }
+
     pub fn get_local_novel_path(&self) -> PathBuf {
         self.local_novel_path.clone()
     }
@@ -41,6 +86,16 @@ impl NovelState {
         self.local_novel_path.join(format!("{id}.epub"))
     }
 
+    /// Stored file of a book in any supported format, the legacy EPUB
+    /// location last
+    pub fn get_book_path(&self, id: &str) -> Option<PathBuf> {
+        crate::formats::SUPPORTED_EXTENSIONS
+            .iter()
+            .map(|extension| self.local_novel_path.join(format!("{id}.{extension}")))
+            .chain([self.get_legacy_epub_path(id)])
+            .find(|path| path.exists())
+    }
+
     pub fn get_legacy_novel_dir(&self, id: &str) -> PathBuf {
         self.local_novel_path.join(id)
     }
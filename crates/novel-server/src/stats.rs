@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::NovelError,
+    export::quote_field,
+    state::NovelState,
+    types::{
+        BookReadingStats, LNMetadata, LNProgress, ReadingPeriodStats, ReadingSession, ReadingStats,
+        ReadingStatsGrouping, ReadingTotals,
+    },
+};
+
+// ============================================================================
+// Reading Sessions
+// ============================================================================
+//
+// Sessions are derived from progress updates: updates of a book that follow
+// each other within `IDLE_GAP_MS` extend one session, and the growth of
+// `total_chars_read` between them counts as read. Jumps faster than anyone
+// reads, like skipping ahead through the TOC, move the position without
+// counting.
+
+/// Longest pause that still continues a session
+const IDLE_GAP_MS: i64 = 10 * 60 * 1000;
+/// Fastest plausible reading speed
+const MAX_CHARS_PER_SECOND: i64 = 30;
+
+/// Where a book's current session stands
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionCursor {
+    started_at: i64,
+    updated_at: i64,
+    total_chars_read: i32,
+    chars_read: i64,
+}
+
+fn session_key(started_at: i64, book_id: &str) -> String {
+    format!("session:{started_at:013}:{book_id}")
+}
+
+/// Extend or start the book's reading session with a progress update
+/// received at `now`
+pub fn record_progress(
+    state: &NovelState,
+    book_id: &str,
+    progress: &LNProgress,
+    now: i64,
+) -> Result<(), NovelError> {
+    let cursor_key = format!("session_cursor:{book_id}");
+    let cursor: Option<SessionCursor> = state
+        .db
+        .get(&cursor_key)?
+        .map(|bytes| serde_json::from_slice(&bytes))
+        .transpose()?;
+
+    let cursor = match cursor {
+        Some(cursor) if now >= cursor.updated_at && now - cursor.updated_at <= IDLE_GAP_MS => {
+            let elapsed_secs = ((now - cursor.updated_at) / 1000).max(1);
+            let delta = i64::from(progress.total_chars_read - cursor.total_chars_read);
+            let counted = if delta > 0 && delta <= elapsed_secs * MAX_CHARS_PER_SECOND {
+                delta
+            } else {
+                0
+            };
+            let cursor = SessionCursor {
+                updated_at: now,
+                total_chars_read: progress.total_chars_read,
+                chars_read: cursor.chars_read + counted,
+                ..cursor
+            };
+
+            // Sessions are stored once they last beyond their first update
+            let session = ReadingSession {
+                book_id: book_id.to_string(),
+                started_at: cursor.started_at,
+                ended_at: now,
+                chars_read: cursor.chars_read,
+            };
+            state.db.insert(
+                session_key(session.started_at, book_id),
+                serde_json::to_vec(&session)?,
+            )?;
+            cursor
+        }
+        _ => SessionCursor {
+            started_at: now,
+            updated_at: now,
+            total_chars_read: progress.total_chars_read,
+            chars_read: 0,
+        },
+    };
+    state.db.insert(cursor_key, serde_json::to_vec(&cursor)?)?;
+    Ok(())
+}
+
+/// Sessions started within `[from, to)`, oldest first
+pub fn load_sessions(
+    state: &NovelState,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<ReadingSession>, NovelError> {
+    let mut sessions = Vec::new();
+    for item in state.db.scan_prefix("session:") {
+        let (_, value) = item?;
+        let session: ReadingSession = serde_json::from_slice(&value)?;
+        if from.is_some_and(|from| session.started_at < from)
+            || to.is_some_and(|to| session.started_at >= to)
+        {
+            continue;
+        }
+        sessions.push(session);
+    }
+    Ok(sessions)
+}
+
+/// Titles of the books in the library, by ID
+pub fn book_titles(state: &NovelState) -> Result<HashMap<String, String>, NovelError> {
+    let mut titles = HashMap::new();
+    for item in state.db.scan_prefix("metadata:") {
+        let (_, value) = item?;
+        let metadata: LNMetadata = serde_json::from_slice(&value)?;
+        titles.insert(metadata.id, metadata.title);
+    }
+    Ok(titles)
+}
+
+// ============================================================================
+// Aggregates
+// ============================================================================
+
+fn totals<'a>(sessions: impl IntoIterator<Item = &'a ReadingSession>) -> ReadingTotals {
+    let mut totals = ReadingTotals::default();
+    for session in sessions {
+        totals.chars_read += session.chars_read;
+        totals.seconds += (session.ended_at - session.started_at).max(0) / 1000;
+        totals.sessions += 1;
+    }
+    if totals.seconds > 0 {
+        totals.chars_per_hour = totals.chars_read as f64 * 3600.0 / totals.seconds as f64;
+    }
+    totals
+}
+
+fn local_date(millis: i64, offset: FixedOffset) -> NaiveDate {
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .with_timezone(&offset)
+        .date_naive()
+}
+
+fn time_zone(utc_offset_minutes: i32) -> FixedOffset {
+    FixedOffset::east_opt(utc_offset_minutes * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).expect("UTC offset should be valid"))
+}
+
+/// Stats of `sessions`, with days and weeks in the reader's time zone.
+/// Streaks count days with characters read, up to `now`, across all of
+/// `history`.
+pub fn compute_stats(
+    sessions: &[ReadingSession],
+    history: &[ReadingSession],
+    titles: &HashMap<String, String>,
+    utc_offset_minutes: i32,
+    now: i64,
+) -> ReadingStats {
+    let offset = time_zone(utc_offset_minutes);
+
+    let mut days: BTreeMap<NaiveDate, Vec<&ReadingSession>> = BTreeMap::new();
+    let mut weeks: BTreeMap<(i32, u32), Vec<&ReadingSession>> = BTreeMap::new();
+    let mut books: HashMap<&str, Vec<&ReadingSession>> = HashMap::new();
+    for session in sessions {
+        let date = local_date(session.started_at, offset);
+        let week = date.iso_week();
+        days.entry(date).or_default().push(session);
+        weeks
+            .entry((week.year(), week.week()))
+            .or_default()
+            .push(session);
+        books.entry(&session.book_id).or_default().push(session);
+    }
+
+    let mut books: Vec<BookReadingStats> = books
+        .into_iter()
+        .map(|(book_id, sessions)| BookReadingStats {
+            book_id: book_id.to_string(),
+            title: titles
+                .get(book_id)
+                .cloned()
+                .unwrap_or_else(|| book_id.to_string()),
+            last_read: sessions
+                .iter()
+                .map(|session| session.ended_at)
+                .max()
+                .unwrap_or_default(),
+            totals: totals(sessions),
+        })
+        .collect();
+    books.sort_by(|a, b| {
+        b.totals
+            .chars_read
+            .cmp(&a.totals.chars_read)
+            .then_with(|| a.title.cmp(&b.title))
+    });
+
+    let reading_days: BTreeSet<NaiveDate> = history
+        .iter()
+        .filter(|session| session.chars_read > 0 && session.started_at <= now)
+        .map(|session| local_date(session.started_at, offset))
+        .collect();
+    let (current_streak, longest_streak) = streaks(&reading_days, local_date(now, offset));
+
+    ReadingStats {
+        totals: totals(sessions),
+        current_streak,
+        longest_streak,
+        days: days
+            .into_iter()
+            .map(|(date, sessions)| ReadingPeriodStats {
+                period: date.format("%Y-%m-%d").to_string(),
+                totals: totals(sessions),
+            })
+            .collect(),
+        weeks: weeks
+            .into_iter()
+            .map(|((year, week), sessions)| ReadingPeriodStats {
+                period: format!("{year}-W{week:02}"),
+                totals: totals(sessions),
+            })
+            .collect(),
+        books,
+    }
+}
+
+/// Current and longest run of consecutive reading days. A streak is still
+/// current when today has no reading yet but yesterday did.
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &day in days {
+        run = match previous {
+            Some(previous) if previous.checked_add_days(Days::new(1)) == Some(day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+
+    let mut day = if days.contains(&today) {
+        Some(today)
+    } else {
+        today.checked_sub_days(Days::new(1))
+    };
+    let mut current = 0;
+    while let Some(date) = day.filter(|date| days.contains(date)) {
+        current += 1;
+        day = date.checked_sub_days(Days::new(1));
+    }
+    (current, longest)
+}
+
+/// Sessions or aggregates as CSV
+pub fn to_csv(
+    grouping: ReadingStatsGrouping,
+    sessions: &[ReadingSession],
+    stats: &ReadingStats,
+    titles: &HashMap<String, String>,
+    utc_offset_minutes: i32,
+) -> String {
+    let offset = time_zone(utc_offset_minutes);
+    let format_time = |millis: i64| {
+        DateTime::from_timestamp_millis(millis)
+            .map(|time| time.with_timezone(&offset).to_rfc3339())
+            .unwrap_or_default()
+    };
+    let totals_fields = |totals: &ReadingTotals| {
+        vec![
+            totals.chars_read.to_string(),
+            totals.seconds.to_string(),
+            format!("{:.0}", totals.chars_per_hour),
+            totals.sessions.to_string(),
+        ]
+    };
+
+    let (header, rows): (&[&str], Vec<Vec<String>>) = match grouping {
+        ReadingStatsGrouping::Session => (
+            &[
+                "Book ID",
+                "Book",
+                "Started",
+                "Ended",
+                "Seconds",
+                "Characters",
+                "Characters per hour",
+            ],
+            sessions
+                .iter()
+                .map(|session| {
+                    let session_totals = totals([session]);
+                    vec![
+                        session.book_id.clone(),
+                        titles
+                            .get(&session.book_id)
+                            .cloned()
+                            .unwrap_or_else(|| session.book_id.clone()),
+                        format_time(session.started_at),
+                        format_time(session.ended_at),
+                        session_totals.seconds.to_string(),
+                        session.chars_read.to_string(),
+                        format!("{:.0}", session_totals.chars_per_hour),
+                    ]
+                })
+                .collect(),
+        ),
+        ReadingStatsGrouping::Day | ReadingStatsGrouping::Week => (
+            &[
+                "Period",
+                "Characters",
+                "Seconds",
+                "Characters per hour",
+                "Sessions",
+            ],
+            if matches!(grouping, ReadingStatsGrouping::Day) {
+                &stats.days
+            } else {
+                &stats.weeks
+            }
+            .iter()
+            .map(|period| {
+                let mut fields = vec![period.period.clone()];
+                fields.extend(totals_fields(&period.totals));
+                fields
+            })
+            .collect(),
+        ),
+        ReadingStatsGrouping::Book => (
+            &[
+                "Book ID",
+                "Book",
+                "Characters",
+                "Seconds",
+                "Characters per hour",
+                "Sessions",
+                "Last read",
+            ],
+            stats
+                .books
+                .iter()
+                .map(|book| {
+                    let mut fields = vec![book.book_id.clone(), book.title.clone()];
+                    fields.extend(totals_fields(&book.totals));
+                    fields.push(format_time(book.last_read));
+                    fields
+                })
+                .collect(),
+        ),
+    };
+
+    let mut output = String::new();
+    let header: Vec<String> = header.iter().map(|field| field.to_string()).collect();
+    for fields in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = fields.iter().map(|field| quote_field(field, ',')).collect();
+        output.push_str(&line.join(","));
+        output.push_str("\r\n");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    const MINUTE: i64 = 60 * 1000;
+    /// 2024-01-01 00:00 UTC, a Monday
+    const JAN_1: i64 = 1_704_067_200_000;
+    const DAY: i64 = 24 * 60 * MINUTE;
+
+    fn state() -> NovelState {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let root = std::env::temp_dir().join(format!("manatan-novel-stats-{nanos}"));
+        NovelState::new(root.join("data"), root.join("local-novel"))
+    }
+
+    fn progress(total_chars_read: i32) -> LNProgress {
+        LNProgress {
+            total_chars_read,
+            ..LNProgress::default()
+        }
+    }
+
+    fn session(book_id: &str, started_at: i64, minutes: i64, chars_read: i64) -> ReadingSession {
+        ReadingSession {
+            book_id: book_id.to_string(),
+            started_at,
+            ended_at: started_at + minutes * MINUTE,
+            chars_read,
+        }
+    }
+
+    #[test]
+    fn progress_updates_become_sessions() {
+        let state = state();
+        let updates = [
+            (JAN_1, 100),
+            (JAN_1 + MINUTE, 400),
+            // Skipping ahead through the TOC
+            (JAN_1 + 2 * MINUTE, 20_000),
+            (JAN_1 + 5 * MINUTE, 20_500),
+            // After a break
+            (JAN_1 + 60 * MINUTE, 20_600),
+            (JAN_1 + 70 * MINUTE, 21_600),
+            // Opened once and closed again
+            (JAN_1 + 3 * 60 * MINUTE, 21_600),
+        ];
+        for (now, chars) in updates {
+            record_progress(&state, "book", &progress(chars), now).expect("progress should record");
+        }
+
+        let sessions = load_sessions(&state, None, None).expect("sessions should load");
+        let summary: Vec<(i64, i64, i64)> = sessions
+            .iter()
+            .map(|session| (session.started_at, session.ended_at, session.chars_read))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (JAN_1, JAN_1 + 5 * MINUTE, 800),
+                (JAN_1 + 60 * MINUTE, JAN_1 + 70 * MINUTE, 1000),
+            ]
+        );
+        assert_eq!(
+            load_sessions(&state, Some(JAN_1 + MINUTE), None)
+                .expect("sessions should load")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn aggregates_days_weeks_books_and_streaks() {
+        let sessions = vec![
+            // 2023-12-31 in UTC, but 2024-01-01 in Japan
+            session("a", JAN_1 - 2 * 60 * MINUTE, 30, 1000),
+            session("a", JAN_1 + 10 * 60 * MINUTE, 30, 500),
+            session("b", JAN_1 + DAY, 60, 3000),
+            session("b", JAN_1 + 3 * DAY, 60, 1200),
+            session("b", JAN_1 + 8 * DAY, 10, 0),
+        ];
+        let titles = HashMap::from([("a".to_string(), "Book A".to_string())]);
+        let japan = 9 * 60;
+
+        let stats = compute_stats(&sessions, &sessions, &titles, japan, JAN_1 + 3 * DAY);
+        assert_eq!(stats.totals.chars_read, 5700);
+        assert_eq!(stats.totals.seconds, (30 + 30 + 60 + 60 + 10) * 60);
+        assert_eq!(stats.totals.sessions, 5);
+
+        let days: Vec<(&str, i64)> = stats
+            .days
+            .iter()
+            .map(|day| (day.period.as_str(), day.totals.chars_read))
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                ("2024-01-01", 1500),
+                ("2024-01-02", 3000),
+                ("2024-01-04", 1200),
+                ("2024-01-09", 0),
+            ]
+        );
+        assert_eq!(stats.days[0].totals.chars_per_hour, 1500.0);
+        let weeks: Vec<(&str, i64)> = stats
+            .weeks
+            .iter()
+            .map(|week| (week.period.as_str(), week.totals.chars_read))
+            .collect();
+        assert_eq!(weeks, vec![("2024-W01", 5700), ("2024-W02", 0)]);
+
+        let books: Vec<(&str, &str, i64)> = stats
+            .books
+            .iter()
+            .map(|book| {
+                (
+                    book.book_id.as_str(),
+                    book.title.as_str(),
+                    book.totals.chars_read,
+                )
+            })
+            .collect();
+        assert_eq!(books, vec![("b", "b", 4200), ("a", "Book A", 1500)]);
+
+        assert_eq!((stats.current_streak, stats.longest_streak), (1, 2));
+        // Yesterday's reading keeps the streak going
+        let stats = compute_stats(
+            &sessions,
+            &sessions,
+            &titles,
+            japan,
+            JAN_1 + DAY + 20 * 60 * MINUTE,
+        );
+        assert_eq!(stats.current_streak, 2);
+        let stats = compute_stats(&sessions, &sessions, &titles, japan, JAN_1 + 6 * DAY);
+        assert_eq!(stats.current_streak, 0);
+
+        let csv = to_csv(
+            ReadingStatsGrouping::Book,
+            &sessions,
+            &stats,
+            &titles,
+            japan,
+        );
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Book ID,Book,Characters,Seconds,Characters per hour,Sessions,Last read"
+        );
+        assert!(lines[2].starts_with("a,Book A,1500,3600,1500,2,2024-01-01T"));
+    }
+}
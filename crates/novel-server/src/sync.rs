@@ -0,0 +1,293 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use async_trait::async_trait;
+use manatan_sync_server::{
+    LocalLibrary, SyncError, SyncPayload, SyncSection, Tombstone,
+    library::{BookFile, is_valid_book_id},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::info;
+
+use crate::{error::NovelError, formats::SUPPORTED_EXTENSIONS, state::NovelState};
+
+/// Background syncs cover reading state: metadata, progress and categories.
+/// Book content and files are large and still go through the frontend; book
+/// files are only read and written here for backup archives.
+#[async_trait]
+impl LocalLibrary for NovelState {
+    async fn export_payload(&self, device_id: &str) -> Result<SyncPayload, SyncError> {
+        let mut payload = SyncPayload::new(device_id.to_string());
+        payload.ln_metadata = read_prefix(self, "metadata:")?;
+        payload.ln_progress = read_prefix(self, "progress:")?;
+        payload.ln_categories = read_prefix(self, "category:")?;
+        payload.ln_category_metadata = read_prefix(self, "category_metadata:")?;
+
+        // Books deleted here, see `delete_book`
+        let deleted: HashMap<String, Tombstone> = read_prefix(self, "tombstone:")?;
+        for (id, mut tombstone) in deleted {
+            tombstone.device_id = Some(device_id.to_string());
+            for section in [
+                SyncSection::LnProgress,
+                SyncSection::LnMetadata,
+                SyncSection::LnContent,
+                SyncSection::LnFiles,
+            ] {
+                payload
+                    .tombstones
+                    .section_mut(section)
+                    .insert(id.clone(), tombstone.clone());
+            }
+        }
+        Ok(payload)
+    }
+
+    async fn apply_payload(&self, payload: &SyncPayload) -> Result<(), SyncError> {
+        apply(self, payload).map_err(|e| SyncError::Other(e.into()))
+    }
+
+    fn for_profile(&self, name: &str) -> Option<Arc<dyn LocalLibrary>> {
+        Some(Arc::new(self.profile(name)))
+    }
+
+    async fn read_book_file(&self, book_id: &str) -> Result<Option<BookFile>, SyncError> {
+        let Some(path) = self.get_book_path(book_id) else {
+            return Ok(None);
+        };
+        let extension = path
+            .extension()
+            .and_then(|value| value.to_str())
+            .unwrap_or("epub")
+            .to_ascii_lowercase();
+        Ok(Some(BookFile {
+            extension,
+            data: fs::read(path)?,
+        }))
+    }
+
+    async fn write_book_file(&self, book_id: &str, file: &BookFile) -> Result<(), SyncError> {
+        if !SUPPORTED_EXTENSIONS.contains(&file.extension.as_str()) {
+            return Err(SyncError::BadRequest(format!(
+                "Unsupported book format: .{}",
+                file.extension
+            )));
+        }
+        let root = self.get_local_novel_path();
+        let path = root.join(format!("{book_id}.{}", file.extension));
+        if !is_valid_book_id(book_id) || path.parent() != Some(root.as_path()) {
+            return Err(SyncError::BadRequest(format!(
+                "Invalid book id: {book_id:?}"
+            )));
+        }
+        fs::create_dir_all(&root)?;
+        fs::write(&path, &file.data)?;
+
+        // The reader needs the parsed content, which archives do not carry
+        let state = self.clone();
+        let book_id = book_id.to_string();
+        tokio::task::spawn_blocking(move || crate::routes::restore_content(&state, &book_id, &path))
+            .await
+            .map_err(|e| SyncError::Other(e.into()))?
+            .map_err(|e| SyncError::Other(e.into()))
+    }
+}
+
+fn apply(state: &NovelState, payload: &SyncPayload) -> Result<(), NovelError> {
+    for (id, metadata) in &payload.ln_metadata {
+        state
+            .db
+            .insert(format!("metadata:{id}"), serde_json::to_vec(metadata)?)?;
+        // Kept elsewhere or added again after the deletion
+        state.db.remove(format!("tombstone:{id}"))?;
+        write_sidecar(state, id, "metadata", metadata)?;
+    }
+    for (id, progress) in &payload.ln_progress {
+        state
+            .db
+            .insert(format!("progress:{id}"), serde_json::to_vec(progress)?)?;
+        write_sidecar(state, id, "progress", progress)?;
+    }
+    for id in payload.tombstones.ln_progress.keys() {
+        state.db.remove(format!("progress:{id}"))?;
+    }
+    for id in payload.tombstones.ln_metadata.keys() {
+        state.db.remove(format!("metadata:{id}"))?;
+    }
+
+    for (id, category) in &payload.ln_categories {
+        state
+            .db
+            .insert(format!("category:{id}"), serde_json::to_vec(category)?)?;
+    }
+    for (id, metadata) in &payload.ln_category_metadata {
+        state.db.insert(
+            format!("category_metadata:{id}"),
+            serde_json::to_vec(metadata)?,
+        )?;
+    }
+    for id in payload.tombstones.ln_categories.keys() {
+        state.db.remove(format!("category:{id}"))?;
+        state.db.remove(format!("category_metadata:{id}"))?;
+    }
+
+    state.db.flush()?;
+    info!(
+        "[SYNC] Applied {} progress, {} metadata entries from background sync",
+        payload.ln_progress.len(),
+        payload.ln_metadata.len()
+    );
+    Ok(())
+}
+
+fn read_prefix<T: DeserializeOwned>(
+    state: &NovelState,
+    prefix: &str,
+) -> Result<HashMap<String, T>, SyncError> {
+    let mut entries = HashMap::new();
+    for item in state.db.scan_prefix(prefix) {
+        let (k, v) = item?;
+        let key = String::from_utf8_lossy(&k);
+        let id = key.strip_prefix(prefix).unwrap_or(&key).to_string();
+        entries.insert(id, serde_json::from_slice(&v)?);
+    }
+    Ok(entries)
+}
+
+/// Keep the sidecar in step, so a rescan of local-novel does not bring back
+/// the pre-sync state. Named profiles leave the shared sidecars alone.
+fn write_sidecar<T: Serialize>(
+    state: &NovelState,
+    id: &str,
+    field: &str,
+    value: &T,
+) -> Result<(), NovelError> {
+    if !state.keeps_reading_sidecars() {
+        return Ok(());
+    }
+    let novel_dir = state.get_novel_dir(id);
+    fs::create_dir_all(&novel_dir)?;
+    let sidecar_path = novel_dir.join("metadata.json");
+
+    let mut sidecar_data = if sidecar_path.exists() {
+        let content = fs::read_to_string(&sidecar_path)?;
+        serde_json::from_str::<serde_json::Value>(&content).unwrap_or(serde_json::json!({}))
+    } else {
+        serde_json::json!({})
+    };
+
+    sidecar_data[field] = serde_json::to_value(value)?;
+    fs::write(sidecar_path, serde_json::to_string_pretty(&sidecar_data)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use manatan_sync_server::{SyncState, archive};
+
+    use super::*;
+    use crate::types::LNParsedBook;
+
+    fn unique_temp_dir(label: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("manatan-novel-sync-{label}-{nanos}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("temp dir should be created");
+        dir
+    }
+
+    #[tokio::test]
+    async fn book_files_stay_inside_the_novel_folder() {
+        let root = unique_temp_dir("escape");
+        let state = NovelState::new(root.join("data"), root.join("library/local-novel"));
+
+        for book_id in [
+            "../escaped",
+            "../../escaped",
+            "nested/book",
+            "/tmp/escaped",
+            "",
+        ] {
+            let file = BookFile {
+                extension: "epub".to_string(),
+                data: b"epub".to_vec(),
+            };
+            let written = state.write_book_file(book_id, &file).await;
+            assert!(
+                matches!(written, Err(SyncError::BadRequest(_))),
+                "{book_id:?} was written"
+            );
+        }
+        let unsupported = BookFile {
+            extension: "sh".to_string(),
+            data: b"echo".to_vec(),
+        };
+        assert!(matches!(
+            state.write_book_file("book", &unsupported).await,
+            Err(SyncError::BadRequest(_))
+        ));
+        assert!(!root.join("library/escaped.epub").exists());
+        assert!(!root.join("escaped.epub").exists());
+    }
+
+    #[tokio::test]
+    async fn archived_books_can_be_read_after_a_restore() {
+        let root = unique_temp_dir("archive");
+        let source = NovelState::new(root.join("source/data"), root.join("source/local-novel"));
+        fs::create_dir_all(source.get_local_novel_path()).expect("local dir should be created");
+        let epub = source.get_epub_path("book");
+        fs::write(&epub, crate::formats::sample_epub()).expect("epub should be written");
+        crate::routes::import_book(&source, "book", &epub, "book").expect("book imported");
+        let text = source.get_local_novel_path().join("notes.txt");
+        fs::write(&text, "第一章\n\n｜漢字《かんじ》の本文。\n").expect("txt should be written");
+        crate::routes::import_book(&source, "notes", &text, "Notes").expect("book imported");
+
+        let mut backup = Cursor::new(Vec::new());
+        let source_sync =
+            SyncState::new(root.join("source/sync")).with_library(Arc::new(source.clone()));
+        archive::export(&source_sync, &mut backup)
+            .await
+            .expect("archive exported");
+
+        // A fresh install with nothing but the archive
+        let target = NovelState::new(root.join("target/data"), root.join("target/local-novel"));
+        let target_sync =
+            SyncState::new(root.join("target/sync")).with_library(Arc::new(target.clone()));
+        let imported = archive::import(&target_sync, Cursor::new(backup.into_inner()))
+            .await
+            .expect("archive imported");
+        assert_eq!(imported.books_restored, 2);
+        assert!(target.get_epub_path("book").exists());
+        assert!(target.get_local_novel_path().join("notes.txt").exists());
+        assert!(
+            target
+                .db
+                .get("content:notes")
+                .expect("db readable")
+                .is_some()
+        );
+
+        let bytes = target
+            .db
+            .get("content:book")
+            .expect("db readable")
+            .expect("content should be restored");
+        let content: LNParsedBook = serde_json::from_slice(&bytes).expect("content parses");
+        assert_eq!(content.chapters.len(), 2);
+        let metadata: serde_json::Value = serde_json::from_slice(
+            &target
+                .db
+                .get("metadata:book")
+                .expect("db readable")
+                .expect("metadata should be restored"),
+        )
+        .expect("metadata parses");
+        assert_eq!(metadata["title"], "Test Book");
+    }
+}
@@ -28,3 +28,162 @@ pub struct DiscoveredEpub {
     pub id: String,
     pub file_name: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    pub q: String,
+    /// Search a single book instead of the whole library
+    pub book_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// A search match, with offsets counted like `LNProgress.chapter_char_offset`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub book_id: String,
+    pub chapter_index: i32,
+    pub block_id: Option<String>,
+    pub block_local_offset: i32,
+    pub chapter_char_offset: i32,
+    /// Counted characters of the match
+    pub length: i32,
+    pub context_before: String,
+    pub matched_text: String,
+    pub context_after: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightExportFormat {
+    #[default]
+    Csv,
+    Tsv,
+    #[serde(alias = "md")]
+    Markdown,
+    Apkg,
+}
+
+impl HighlightExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+            Self::Markdown => "md",
+            Self::Apkg => "apkg",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Tsv => "text/tab-separated-values; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Apkg => "application/octet-stream",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightExportQuery {
+    #[serde(default)]
+    pub format: HighlightExportFormat,
+    /// Export a single book instead of the whole library
+    pub book_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingSession {
+    pub book_id: String,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub chars_read: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingTotals {
+    pub chars_read: i64,
+    pub seconds: i64,
+    pub chars_per_hour: f64,
+    pub sessions: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingPeriodStats {
+    /// `2024-01-31` for days, `2024-W05` for ISO weeks
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: ReadingTotals,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookReadingStats {
+    pub book_id: String,
+    pub title: String,
+    pub last_read: i64,
+    #[serde(flatten)]
+    pub totals: ReadingTotals,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingStats {
+    #[serde(flatten)]
+    pub totals: ReadingTotals,
+    /// Consecutive days with reading, up to today or yesterday
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub days: Vec<ReadingPeriodStats>,
+    pub weeks: Vec<ReadingPeriodStats>,
+    pub books: Vec<BookReadingStats>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadingStatsGrouping {
+    #[default]
+    Session,
+    Day,
+    Week,
+    Book,
+}
+
+impl ReadingStatsGrouping {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Session => "sessions",
+            Self::Day => "days",
+            Self::Week => "weeks",
+            Self::Book => "books",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingStatsQuery {
+    /// Sessions started at or after this time, in milliseconds
+    pub from: Option<i64>,
+    /// Sessions started before this time, in milliseconds
+    pub to: Option<i64>,
+    /// Offset of the reader's time zone from UTC, for day boundaries
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    pub book_id: Option<String>,
+    /// Rows of the CSV export
+    #[serde(default)]
+    pub by: ReadingStatsGrouping,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OpdsSearchQuery {
+    /// `q` from the OpenSearch template of OPDS 1.2, `query` in OPDS 2.0
+    #[serde(default, alias = "query")]
+    pub q: String,
+}
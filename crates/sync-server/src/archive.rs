@@ -0,0 +1,449 @@
+use std::io::{Read, Seek, Write};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    error::SyncError,
+    library::{BookFile, LocalLibrary, is_valid_book_id, local_library},
+    merge::{DeletionPolicy, merge_payloads},
+    migration,
+    state::SyncState,
+    types::{ConflictInfo, PendingDeletion, SyncPayload},
+};
+
+// ============================================================================
+// Backup Archives
+// ============================================================================
+//
+// A zip holding the complete payload and the book files of the library, for
+// offline backups and for moving a library between machines without a cloud
+// backend. `manifest.json` lists every other entry with its size and SHA-256,
+// and an archive that does not match it is rejected as a whole. Restoring
+// merges the archive into the local data like a remote payload.
+//
+// Layout:
+//   manifest.json
+//   payload.json
+//   books/<book id>.<extension>
+
+/// Bumped when the archive layout changes; the payload has its own schema version
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+const PAYLOAD_PATH: &str = "payload.json";
+const BOOKS_DIR: &str = "books/";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveManifest {
+    pub format_version: u32,
+    pub schema_version: u32,
+    pub created_at: i64,
+    pub device_id: String,
+    pub files: Vec<ArchiveFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    /// Set for book files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub book_id: Option<String>,
+}
+
+/// What restoring an archive changed
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveImport {
+    pub progress: usize,
+    pub metadata: usize,
+    pub books_restored: usize,
+    pub conflicts: Vec<ConflictInfo>,
+    pub pending_deletions: Vec<PendingDeletion>,
+}
+
+/// Write the local data and book files as an archive
+pub async fn export<W: Write + Seek + Send>(
+    state: &SyncState,
+    writer: W,
+) -> Result<ArchiveManifest, SyncError> {
+    let library = local_library(state)?;
+    let payload = local_payload(state, library).await?;
+
+    let mut zip = ZipWriter::new(writer);
+    let mut files = Vec::new();
+
+    let json = serde_json::to_vec_pretty(&payload)?;
+    files.push(write_entry(&mut zip, PAYLOAD_PATH, &json, None)?);
+
+    let mut book_ids: Vec<&String> = payload.ln_metadata.keys().collect();
+    book_ids.sort();
+    for book_id in book_ids {
+        let Some(file) = library.read_book_file(book_id).await? else {
+            continue;
+        };
+        let path = format!("{BOOKS_DIR}{book_id}.{}", file.extension);
+        files.push(write_entry(&mut zip, &path, &file.data, Some(book_id))?);
+    }
+
+    let manifest = ArchiveManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        schema_version: payload.schema_version,
+        created_at: chrono::Utc::now().timestamp_millis(),
+        device_id: state.get_device_id(),
+        files,
+    };
+    zip.start_file(MANIFEST_PATH, SimpleFileOptions::default())
+        .map_err(archive_error)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish().map_err(archive_error)?;
+
+    info!(
+        "[BACKUP] Exported {} progress, {} metadata entries and {} book files",
+        payload.ln_progress.len(),
+        payload.ln_metadata.len(),
+        manifest.files.len() - 1
+    );
+    Ok(manifest)
+}
+
+/// Check an archive against its manifest and merge it into the local data.
+/// Book files are only written for books the library has no file for.
+pub async fn import<R: Read + Seek>(
+    state: &SyncState,
+    reader: R,
+) -> Result<ArchiveImport, SyncError> {
+    let library = local_library(state)?;
+    let mut zip = ZipArchive::new(reader).map_err(archive_error)?;
+
+    let manifest: ArchiveManifest = serde_json::from_slice(&read_entry(&mut zip, MANIFEST_PATH)?)?;
+    if manifest.format_version > ARCHIVE_FORMAT_VERSION {
+        return Err(SyncError::ArchiveError(format!(
+            "format v{} is newer than the supported v{ARCHIVE_FORMAT_VERSION}",
+            manifest.format_version
+        )));
+    }
+
+    // Verify everything before anything is written
+    let mut payload = None;
+    let mut books = Vec::new();
+    for file in &manifest.files {
+        let data = read_entry(&mut zip, &file.path)?;
+        if data.len() as u64 != file.size || sha256(&data) != file.sha256 {
+            return Err(SyncError::ArchiveError(format!(
+                "{} does not match the manifest",
+                file.path
+            )));
+        }
+        match &file.book_id {
+            Some(book_id) => {
+                let extension = book_extension(&file.path, book_id).ok_or_else(|| {
+                    SyncError::ArchiveError(format!("{} names an invalid book file", file.path))
+                })?;
+                books.push((book_id.clone(), BookFile { extension, data }));
+            }
+            None if file.path == PAYLOAD_PATH => payload = Some(migration::parse_payload(&data)?),
+            None => {}
+        }
+    }
+    let archived =
+        payload.ok_or_else(|| SyncError::ArchiveError(format!("{PAYLOAD_PATH} is missing")))?;
+
+    let device_id = state.get_device_id();
+    let config = state.get_sync_config();
+    let local = local_payload(state, library).await?;
+    let policy = DeletionPolicy::new(config.deletion_behavior, Vec::new());
+    let mut outcome = merge_payloads(local, archived, &device_id, &policy);
+
+    // Deletions waiting for confirmation are reported, not applied
+    outcome
+        .payload
+        .tombstones
+        .remove_pending(&outcome.pending_deletions);
+    library.apply_payload(&outcome.payload).await?;
+    state.set_media_library(&outcome.payload.take_media())?;
+    if let Some(settings) = &outcome.payload.reader_settings {
+        state.set_reader_settings(settings)?;
+    }
+
+    let mut books_restored = 0;
+    for (book_id, file) in books {
+        if !outcome.payload.ln_metadata.contains_key(&book_id)
+            || library.read_book_file(&book_id).await?.is_some()
+        {
+            continue;
+        }
+        library.write_book_file(&book_id, &file).await?;
+        books_restored += 1;
+    }
+
+    info!(
+        "[BACKUP] Restored archive from {}: {} conflicts, {} book files",
+        manifest.device_id,
+        outcome.conflicts.len(),
+        books_restored
+    );
+    Ok(ArchiveImport {
+        progress: outcome.payload.ln_progress.len(),
+        metadata: outcome.payload.ln_metadata.len(),
+        books_restored,
+        conflicts: outcome.conflicts,
+        pending_deletions: outcome.pending_deletions,
+    })
+}
+
+/// Everything this device has: the library plus the media library and reader
+/// settings kept by the sync server
+async fn local_payload(
+    state: &SyncState,
+    library: &dyn LocalLibrary,
+) -> Result<SyncPayload, SyncError> {
+    let mut payload = library.export_payload(&state.get_device_id()).await?;
+    payload.set_media(state.get_media_library());
+    payload.reader_settings = state.get_reader_settings();
+    Ok(payload)
+}
+
+/// Extension of a book entry, `books/<book id>.<extension>`, if the entry
+/// names a valid id and a plain extension
+fn book_extension(path: &str, book_id: &str) -> Option<String> {
+    if !is_valid_book_id(book_id) {
+        return None;
+    }
+    let extension = path
+        .strip_prefix(BOOKS_DIR)?
+        .strip_prefix(book_id)?
+        .strip_prefix('.')?;
+    (!extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then(|| extension.to_ascii_lowercase())
+}
+
+fn write_entry<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    path: &str,
+    data: &[u8],
+    book_id: Option<&str>,
+) -> Result<ArchiveFile, SyncError> {
+    // EPUBs are zips already
+    let method = match book_id {
+        Some(_) if path.ends_with(".epub") => CompressionMethod::Stored,
+        _ => CompressionMethod::Deflated,
+    };
+    zip.start_file(
+        path,
+        SimpleFileOptions::default().compression_method(method),
+    )
+    .map_err(archive_error)?;
+    zip.write_all(data)?;
+    Ok(ArchiveFile {
+        path: path.to_string(),
+        size: data.len() as u64,
+        sha256: sha256(data),
+        book_id: book_id.map(str::to_string),
+    })
+}
+
+fn read_entry<R: Read + Seek>(zip: &mut ZipArchive<R>, path: &str) -> Result<Vec<u8>, SyncError> {
+    let mut entry = zip
+        .by_name(path)
+        .map_err(|e| SyncError::ArchiveError(format!("{path}: {e}")))?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn archive_error(e: zip::result::ZipError) -> SyncError {
+    SyncError::ArchiveError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        test_util::unique_temp_dir,
+        types::{BookStats, LNMetadata, LNProgress},
+    };
+
+    /// Library holding its payload and book files in memory
+    #[derive(Default)]
+    struct MemoryLibrary {
+        payload: Mutex<Option<SyncPayload>>,
+        files: Mutex<HashMap<String, BookFile>>,
+    }
+
+    #[async_trait]
+    impl LocalLibrary for MemoryLibrary {
+        async fn export_payload(&self, device_id: &str) -> Result<SyncPayload, SyncError> {
+            let stored = self.payload.lock().expect("payload lock").clone();
+            Ok(stored.unwrap_or_else(|| SyncPayload::new(device_id.to_string())))
+        }
+
+        async fn apply_payload(&self, payload: &SyncPayload) -> Result<(), SyncError> {
+            *self.payload.lock().expect("payload lock") = Some(payload.clone());
+            Ok(())
+        }
+
+        async fn read_book_file(&self, book_id: &str) -> Result<Option<BookFile>, SyncError> {
+            Ok(self.files.lock().expect("files lock").get(book_id).cloned())
+        }
+
+        async fn write_book_file(&self, book_id: &str, file: &BookFile) -> Result<(), SyncError> {
+            self.files
+                .lock()
+                .expect("files lock")
+                .insert(book_id.to_string(), file.clone());
+            Ok(())
+        }
+    }
+
+    fn state_with(root: &std::path::Path, name: &str, library: Arc<MemoryLibrary>) -> SyncState {
+        SyncState::new(root.join(name)).with_library(library)
+    }
+
+    #[tokio::test]
+    async fn archive_moves_a_library_to_another_machine() {
+        let root = unique_temp_dir("archive");
+
+        let source = Arc::new(MemoryLibrary::default());
+        let mut payload = SyncPayload::new("source".to_string());
+        payload.ln_metadata.insert(
+            "book".to_string(),
+            LNMetadata {
+                id: "book".to_string(),
+                title: "Book".to_string(),
+                author: String::new(),
+                cover: None,
+                added_at: 0,
+                is_processing: None,
+                is_error: None,
+                error_msg: None,
+                stats: BookStats::default(),
+                chapter_count: 1,
+                toc: Vec::new(),
+                has_progress: None,
+                last_modified: Some(0),
+                sync_version: Some(1),
+                language: None,
+                category_ids: Vec::new(),
+                language_settings: HashMap::new(),
+            },
+        );
+        payload.ln_progress.insert(
+            "book".to_string(),
+            LNProgress {
+                total_progress: 0.4,
+                ..LNProgress::default()
+            },
+        );
+        *source.payload.lock().expect("payload lock") = Some(payload);
+        source.files.lock().expect("files lock").insert(
+            "book".to_string(),
+            BookFile {
+                extension: "txt".to_string(),
+                data: "｜漢字《かんじ》".as_bytes().to_vec(),
+            },
+        );
+
+        let mut archive = Cursor::new(Vec::new());
+        let manifest = export(&state_with(&root, "source", source), &mut archive)
+            .await
+            .expect("archive exported");
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.files[1].path, "books/book.txt");
+
+        let target = Arc::new(MemoryLibrary::default());
+        let target_state = state_with(&root, "target", target.clone());
+        let imported = import(&target_state, Cursor::new(archive.get_ref().clone()))
+            .await
+            .expect("archive imported");
+        assert_eq!(imported.metadata, 1);
+        assert_eq!(imported.books_restored, 1);
+        let restored = target.payload.lock().expect("payload lock").clone();
+        assert_eq!(
+            restored.expect("payload applied").ln_progress["book"].total_progress,
+            0.4
+        );
+        let restored_file = target.files.lock().expect("files lock")["book"].clone();
+        assert_eq!(restored_file.extension, "txt");
+        assert_eq!(restored_file.data, "｜漢字《かんじ》".as_bytes());
+
+        // A file that no longer matches its hash rejects the whole archive
+        let mut tampered = Cursor::new(Vec::new());
+        {
+            let mut original =
+                ZipArchive::new(Cursor::new(archive.get_ref().clone())).expect("archive readable");
+            let mut zip = ZipWriter::new(&mut tampered);
+            for path in [MANIFEST_PATH, PAYLOAD_PATH] {
+                let data = read_entry(&mut original, path).expect("entry readable");
+                zip.start_file(path, SimpleFileOptions::default())
+                    .expect("entry started");
+                zip.write_all(&data).expect("entry written");
+            }
+            zip.start_file("books/book.txt", SimpleFileOptions::default())
+                .expect("entry started");
+            zip.write_all(b"other-bytes").expect("entry written");
+            zip.finish().expect("archive finished");
+        }
+        let rejected = import(&target_state, Cursor::new(tampered.into_inner())).await;
+        assert!(matches!(rejected, Err(SyncError::ArchiveError(_))));
+    }
+
+    #[tokio::test]
+    async fn book_ids_that_leave_the_library_reject_the_archive() {
+        let root = unique_temp_dir("archive-evil");
+
+        for book_id in ["../../escaped", "nested/book", "C:\\book", "bo\0ok", ".."] {
+            let mut archive = Cursor::new(Vec::new());
+            {
+                let mut zip = ZipWriter::new(&mut archive);
+                let payload = serde_json::to_vec(&SyncPayload::new("evil".to_string()))
+                    .expect("payload serialized");
+                let files = vec![
+                    write_entry(&mut zip, PAYLOAD_PATH, &payload, None).expect("entry written"),
+                    write_entry(&mut zip, "books/book.epub", b"epub", Some(book_id))
+                        .expect("entry written"),
+                ];
+                let manifest = ArchiveManifest {
+                    format_version: ARCHIVE_FORMAT_VERSION,
+                    schema_version: 1,
+                    created_at: 0,
+                    device_id: "evil".to_string(),
+                    files,
+                };
+                zip.start_file(MANIFEST_PATH, SimpleFileOptions::default())
+                    .expect("entry started");
+                zip.write_all(&serde_json::to_vec(&manifest).expect("manifest serialized"))
+                    .expect("entry written");
+                zip.finish().expect("archive finished");
+            }
+
+            let target = Arc::new(MemoryLibrary::default());
+            let state = state_with(&root, "target", target.clone());
+            let rejected = import(&state, Cursor::new(archive.into_inner())).await;
+            assert!(
+                matches!(rejected, Err(SyncError::ArchiveError(_))),
+                "{book_id:?} was accepted"
+            );
+            assert!(target.files.lock().expect("files lock").is_empty());
+            assert!(target.payload.lock().expect("payload lock").is_none());
+        }
+    }
+}
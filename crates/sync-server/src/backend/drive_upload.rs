@@ -0,0 +1,507 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use reqwest::{StatusCode, header};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    error::SyncError,
+    state::{SyncState, UploadState},
+};
+
+// ============================================================================
+// Google Drive Resumable Uploads
+// ============================================================================
+//
+// google-drive3 uploads in chunks but keeps the session in memory, so a sync
+// interrupted on a flaky connection starts over. Here the session URI and the
+// confirmed offset are stored as an `UploadState`, and the next attempt asks
+// Drive where it stopped and continues from there.
+//
+// Every push encodes its data afresh, and the encoding differs each time
+// (merge timestamps, encryption nonces), so the bytes of a session are
+// spooled to disk and a retry finishes those exact bytes. Changes made in
+// between go up with the next sync.
+//
+// Protocol: https://developers.google.com/drive/api/guides/manage-uploads#resumable
+
+const UPLOAD_ENDPOINT: &str = "https://www.googleapis.com/upload/drive/v3/files";
+
+/// Uploads smaller than this go through a single request
+pub const RESUMABLE_THRESHOLD: usize = 5 * 1024 * 1024;
+
+/// Chunk size; Drive requires a multiple of 256 KiB
+const CHUNK_SIZE: usize = 8 * 256 * 1024;
+
+/// Drive drops upload sessions after a week
+const SESSION_TTL_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// What a resumable upload writes to
+pub enum UploadTarget {
+    /// New file with this metadata (name, parents, appProperties, ...)
+    Create(serde_json::Value),
+    /// New content, and optionally metadata, for an existing file
+    Update {
+        file_id: String,
+        metadata: serde_json::Value,
+    },
+}
+
+/// The uploaded file as returned by Drive
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    pub id: String,
+    #[serde(default)]
+    pub md5_checksum: String,
+}
+
+/// What is being uploaded, for the stored `UploadState`
+pub struct UploadItem<'a> {
+    /// Stable key, so a retry of the same upload finds its session
+    pub upload_id: &'a str,
+    pub book_id: &'a str,
+    pub file_type: &'a str,
+    pub mime_type: &'a str,
+    /// Version of the remote file being replaced, empty when creating one
+    pub base_version: &'a str,
+}
+
+/// Upload `data` through a resumable session. A stored session for the
+/// same item and remote version is finished with its spooled bytes instead.
+pub async fn upload(
+    state: &SyncState,
+    access_token: &str,
+    item: &UploadItem<'_>,
+    target: UploadTarget,
+    data: &[u8],
+) -> Result<UploadedFile, SyncError> {
+    upload_to(state, UPLOAD_ENDPOINT, access_token, item, target, data).await
+}
+
+async fn upload_to(
+    state: &SyncState,
+    endpoint: &str,
+    access_token: &str,
+    item: &UploadItem<'_>,
+    target: UploadTarget,
+    data: &[u8],
+) -> Result<UploadedFile, SyncError> {
+    let client = reqwest::Client::new();
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let stored = state
+        .get_upload_state(item.upload_id)
+        .filter(|upload| can_resume(upload, item.base_version, now))
+        .and_then(|upload| {
+            let spooled = read_spool(&upload)?;
+            Some((upload, spooled))
+        });
+
+    let (mut upload, data) = match stored {
+        Some((mut upload, spooled)) => {
+            let uri = upload.resumable_uri.clone().unwrap_or_default();
+            match query_offset(&client, access_token, &uri, upload.total_size).await? {
+                SessionStatus::Complete(file) => {
+                    finish(state, &upload)?;
+                    return Ok(file);
+                }
+                SessionStatus::Offset(offset) => {
+                    info!(
+                        "[DRIVE] Resuming upload {} at {}/{} bytes",
+                        item.upload_id, offset, upload.total_size
+                    );
+                    upload.uploaded_bytes = offset;
+                    (upload, Cow::Owned(spooled))
+                }
+                SessionStatus::Expired => {
+                    warn!(
+                        "[DRIVE] Upload session for {} expired, starting over",
+                        item.upload_id
+                    );
+                    remove_spool(&upload);
+                    let upload =
+                        start_session(state, &client, endpoint, access_token, item, target, data)
+                            .await?;
+                    (upload, Cow::Borrowed(data))
+                }
+            }
+        }
+        None => {
+            let upload =
+                start_session(state, &client, endpoint, access_token, item, target, data).await?;
+            (upload, Cow::Borrowed(data))
+        }
+    };
+    state.set_upload_state(item.upload_id, &upload)?;
+
+    let total_size = upload.total_size;
+    let uri = upload.resumable_uri.clone().unwrap_or_default();
+    loop {
+        let (start, end) = next_chunk(upload.uploaded_bytes, total_size);
+        let response = client
+            .put(&uri)
+            .bearer_auth(access_token)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{}/{total_size}", end - 1),
+            )
+            .body(data[start as usize..end as usize].to_vec())
+            .send()
+            .await
+            .map_err(|e| SyncError::DriveError(format!("Upload interrupted: {e}")))?;
+
+        match response.status() {
+            StatusCode::OK | StatusCode::CREATED => {
+                finish(state, &upload)?;
+                return response
+                    .json()
+                    .await
+                    .map_err(|e| SyncError::DriveError(e.to_string()));
+            }
+            StatusCode::PERMANENT_REDIRECT => {
+                upload.uploaded_bytes = confirmed_offset(response.headers());
+                upload.last_chunk_at = chrono::Utc::now().timestamp_millis();
+                state.set_upload_state(item.upload_id, &upload)?;
+            }
+            status => {
+                return Err(SyncError::DriveError(format!(
+                    "Upload chunk failed ({status}): {}",
+                    response.text().await.unwrap_or_default()
+                )));
+            }
+        }
+    }
+}
+
+/// Where the bytes of an upload are kept until it completes
+fn spool_path(state: &SyncState, upload_id: &str) -> PathBuf {
+    state
+        .data_dir
+        .join("uploads")
+        .join(format!("{:x}.bin", Sha256::digest(upload_id.as_bytes())))
+}
+
+/// Spooled bytes of a stored session, if they are still intact
+fn read_spool(upload: &UploadState) -> Option<Vec<u8>> {
+    let bytes = std::fs::read(upload.spool_path.as_ref()?).ok()?;
+    (format!("{:x}", Sha256::digest(&bytes)) == upload.file_hash).then_some(bytes)
+}
+
+fn remove_spool(upload: &UploadState) {
+    if let Some(path) = &upload.spool_path
+        && let Err(e) = std::fs::remove_file(path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("[DRIVE] Could not remove {}: {}", path.display(), e);
+    }
+}
+
+fn finish(state: &SyncState, upload: &UploadState) -> Result<(), SyncError> {
+    remove_spool(upload);
+    state.clear_upload_state(&upload.upload_id)?;
+    Ok(())
+}
+
+enum SessionStatus {
+    Complete(UploadedFile),
+    Offset(u64),
+    Expired,
+}
+
+async fn start_session(
+    state: &SyncState,
+    client: &reqwest::Client,
+    endpoint: &str,
+    access_token: &str,
+    item: &UploadItem<'_>,
+    target: UploadTarget,
+    data: &[u8],
+) -> Result<UploadState, SyncError> {
+    let request = match target {
+        UploadTarget::Create(metadata) => client.post(endpoint).json(&metadata),
+        UploadTarget::Update { file_id, metadata } => client
+            .patch(format!("{endpoint}/{file_id}"))
+            .json(&metadata),
+    };
+    let response = request
+        .bearer_auth(access_token)
+        .query(&[("uploadType", "resumable"), ("fields", "id,md5Checksum")])
+        .header("X-Upload-Content-Type", item.mime_type)
+        .header("X-Upload-Content-Length", data.len())
+        .send()
+        .await
+        .map_err(|e| SyncError::DriveError(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(SyncError::DriveError(format!(
+            "Failed to start upload session ({}): {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        )));
+    }
+    let uri = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| SyncError::DriveError("Upload session has no URI".to_string()))?
+        .to_string();
+
+    let spool_path = spool_path(state, item.upload_id);
+    if let Some(parent) = spool_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&spool_path, data)?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    info!(
+        "[DRIVE] Started upload session for {} ({} bytes)",
+        item.upload_id,
+        data.len()
+    );
+    Ok(UploadState {
+        upload_id: item.upload_id.to_string(),
+        book_id: item.book_id.to_string(),
+        file_type: item.file_type.to_string(),
+        file_hash: format!("{:x}", Sha256::digest(data)),
+        total_size: data.len() as u64,
+        uploaded_bytes: 0,
+        resumable_uri: Some(uri),
+        started_at: now,
+        last_chunk_at: now,
+        spool_path: Some(spool_path),
+        base_version: item.base_version.to_string(),
+    })
+}
+
+/// Ask Drive how much of an interrupted upload it has
+async fn query_offset(
+    client: &reqwest::Client,
+    access_token: &str,
+    uri: &str,
+    total_size: u64,
+) -> Result<SessionStatus, SyncError> {
+    let response = client
+        .put(uri)
+        .bearer_auth(access_token)
+        .header(header::CONTENT_RANGE, format!("bytes */{total_size}"))
+        .header(header::CONTENT_LENGTH, 0)
+        .send()
+        .await
+        .map_err(|e| SyncError::DriveError(e.to_string()))?;
+
+    match response.status() {
+        StatusCode::OK | StatusCode::CREATED => Ok(SessionStatus::Complete(
+            response
+                .json()
+                .await
+                .map_err(|e| SyncError::DriveError(e.to_string()))?,
+        )),
+        StatusCode::PERMANENT_REDIRECT => {
+            Ok(SessionStatus::Offset(confirmed_offset(response.headers())))
+        }
+        _ => Ok(SessionStatus::Expired),
+    }
+}
+
+/// A stored session can be continued if it replaces the same remote version
+/// and Drive still knows it
+fn can_resume(upload: &UploadState, base_version: &str, now: i64) -> bool {
+    upload.resumable_uri.is_some()
+        && upload.spool_path.is_some()
+        && upload.base_version == base_version
+        && now - upload.started_at < SESSION_TTL_MS
+}
+
+/// Bytes Drive has confirmed, from the `Range: bytes=0-N` header of a 308
+fn confirmed_offset(headers: &header::HeaderMap) -> u64 {
+    headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|range| range.rsplit('-').next())
+        .and_then(|end| end.parse::<u64>().ok())
+        .map_or(0, |end| end + 1)
+}
+
+/// Byte range `[start, end)` of the next chunk
+fn next_chunk(offset: u64, total_size: u64) -> (u64, u64) {
+    let start = offset.min(total_size);
+    (start, (start + CHUNK_SIZE as u64).min(total_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode as AxumStatus},
+        response::{IntoResponse, Response},
+        routing::{post, put},
+    };
+
+    use super::*;
+    use crate::test_util::unique_temp_dir;
+
+    fn upload_state(started_at: i64) -> UploadState {
+        UploadState {
+            upload_id: "payload".to_string(),
+            book_id: String::new(),
+            file_type: "payload".to_string(),
+            file_hash: "abc".to_string(),
+            total_size: 100,
+            uploaded_bytes: 40,
+            resumable_uri: Some("https://upload.example/session".to_string()),
+            started_at,
+            last_chunk_at: started_at,
+            spool_path: Some(PathBuf::from("payload.bin")),
+            base_version: "v1".to_string(),
+        }
+    }
+
+    #[test]
+    fn resumes_only_a_live_session_on_the_same_version() {
+        let upload = upload_state(0);
+        assert!(can_resume(&upload, "v1", 1_000));
+        assert!(!can_resume(&upload, "v2", 1_000));
+        assert!(!can_resume(&upload, "v1", SESSION_TTL_MS));
+        let unspooled = UploadState {
+            spool_path: None,
+            ..upload_state(0)
+        };
+        assert!(!can_resume(&unspooled, "v1", 1_000));
+    }
+
+    /// Drive's side of one resumable session, failing one chunk on request
+    #[derive(Clone, Default)]
+    struct StandIn {
+        base_url: Arc<Mutex<String>>,
+        received: Arc<Mutex<Vec<u8>>>,
+        sessions: Arc<Mutex<usize>>,
+        fail_next_chunk_at: Arc<Mutex<Option<u64>>>,
+    }
+
+    async fn start(State(server): State<StandIn>) -> Response {
+        *server.sessions.lock().expect("lock") += 1;
+        server.received.lock().expect("lock").clear();
+        let location = format!("{}/session", server.base_url.lock().expect("lock"));
+        (AxumStatus::OK, [("location", location)]).into_response()
+    }
+
+    async fn chunk(State(server): State<StandIn>, headers: HeaderMap, body: Bytes) -> Response {
+        let range = headers
+            .get("content-range")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .trim_start_matches("bytes ")
+            .to_string();
+        let (span, total) = range.split_once('/').expect("range has a total");
+        let total: u64 = total.parse().expect("total is a number");
+
+        let mut received = server.received.lock().expect("lock");
+        if span != "*" {
+            let start: u64 = span
+                .split('-')
+                .next()
+                .and_then(|start| start.parse().ok())
+                .expect("range has a start");
+            let mut fail_at = server.fail_next_chunk_at.lock().expect("lock");
+            if *fail_at == Some(start) {
+                *fail_at = None;
+                return AxumStatus::SERVICE_UNAVAILABLE.into_response();
+            }
+            assert_eq!(start, received.len() as u64, "chunks arrive in order");
+            received.extend_from_slice(&body);
+        }
+
+        if received.len() as u64 == total {
+            let file = serde_json::json!({ "id": "file-1", "md5Checksum": "done" });
+            return (AxumStatus::OK, axum::Json(file)).into_response();
+        }
+        match received.len() {
+            0 => AxumStatus::PERMANENT_REDIRECT.into_response(),
+            length => (
+                AxumStatus::PERMANENT_REDIRECT,
+                [("range", format!("bytes=0-{}", length - 1))],
+            )
+                .into_response(),
+        }
+    }
+
+    async fn spawn_stand_in(server: StandIn) -> String {
+        let router = Router::new()
+            .route("/upload", post(start))
+            .route("/session", put(chunk))
+            .with_state(server.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let base_url = format!("http://{}", listener.local_addr().expect("address"));
+        *server.base_url.lock().expect("lock") = base_url.clone();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("server runs");
+        });
+        format!("{base_url}/upload")
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_upload_resumes_with_its_own_bytes() {
+        let state = SyncState::new(unique_temp_dir("drive-upload"));
+        let server = StandIn::default();
+        *server.fail_next_chunk_at.lock().expect("lock") = Some(CHUNK_SIZE as u64);
+        let endpoint = spawn_stand_in(server.clone()).await;
+
+        let item = UploadItem {
+            upload_id: "drive:payload",
+            book_id: "",
+            file_type: "payload",
+            mime_type: "application/gzip",
+            base_version: "v1",
+        };
+        let target = || UploadTarget::Create(serde_json::json!({ "name": "payload" }));
+        // Each push encodes the payload anew, with different bytes
+        let first = vec![1u8; CHUNK_SIZE + 10];
+        let second = vec![2u8; CHUNK_SIZE + 20];
+
+        let interrupted = upload_to(&state, &endpoint, "token", &item, target(), &first).await;
+        assert!(interrupted.is_err(), "{interrupted:?}");
+        let stored = state
+            .get_upload_state(item.upload_id)
+            .expect("session is stored");
+        assert_eq!(stored.uploaded_bytes, CHUNK_SIZE as u64);
+
+        let file = upload_to(&state, &endpoint, "token", &item, target(), &second)
+            .await
+            .expect("upload resumes");
+        assert_eq!(file.id, "file-1");
+        assert_eq!(*server.sessions.lock().expect("lock"), 1);
+        assert_eq!(*server.received.lock().expect("lock"), first);
+        assert!(state.get_upload_state(item.upload_id).is_none());
+        assert!(stored.spool_path.is_some_and(|path| !path.exists()));
+    }
+
+    #[test]
+    fn reads_the_confirmed_offset_from_the_range_header() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(confirmed_offset(&headers), 0);
+        headers.insert(
+            header::RANGE,
+            header::HeaderValue::from_static("bytes=0-524287"),
+        );
+        assert_eq!(confirmed_offset(&headers), 524_288);
+    }
+
+    #[test]
+    fn chunks_stop_at_the_end_of_the_data() {
+        assert_eq!(next_chunk(0, 10), (0, 10));
+        let total = CHUNK_SIZE as u64 * 2 + 5;
+        assert_eq!(next_chunk(0, total), (0, CHUNK_SIZE as u64));
+        assert_eq!(
+            next_chunk(CHUNK_SIZE as u64 * 2, total),
+            (CHUNK_SIZE as u64 * 2, total)
+        );
+    }
+}
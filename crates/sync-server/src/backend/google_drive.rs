@@ -1,8 +1,5 @@
-use std::io::{Read, Write};
-
 use async_trait::async_trait;
 use base64::Engine as _;
-use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 // Re-exports from google-drive3
 use google_drive3::api::File;
 use google_drive3::{
@@ -18,10 +15,14 @@ use sha2::{Digest, Sha256};
 use tracing::{error, info, warn};
 
 use crate::{
-    backend::{AuthFlow, PushResult, SyncBackend},
+    backend::{
+        AuthFlow, PushResult, SYNC_FILE_NAME, SyncBackend, decode_payload,
+        drive_upload::{self, RESUMABLE_THRESHOLD, UploadItem, UploadTarget},
+        encode_payload,
+    },
     error::SyncError,
     state::SyncState,
-    types::SyncPayload,
+    types::{SyncBackendType, SyncPayload},
 };
 
 // ============================================================================
@@ -67,7 +68,6 @@ const GOOGLE_OAUTH_TOKEN_ENDPOINT: &str = "https://oauth2.googleapis.com/token";
 const DEFAULT_GOOGLE_OAUTH_BROKER_ENDPOINT: &str = "https://manatan.com/auth/google";
 const GOOGLE_OAUTH_BROKER_ENDPOINT_ENV: &str = "MANATAN_GOOGLE_OAUTH_BROKER_ENDPOINT";
 const GOOGLE_OAUTH_BROKER_TOKEN_ENV: &str = "MANATAN_GOOGLE_OAUTH_BROKER_TOKEN";
-const SYNC_FILE_NAME: &str = "manatan_sync.proto.gz";
 const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
 
 fn oauth_token_endpoint() -> String {
@@ -132,14 +132,6 @@ impl GoogleDriveBackend {
         }
     }
 
-    pub async fn initialize(&mut self) -> Result<(), SyncError> {
-        if self.state.get_access_token().is_none() || self.state.get_refresh_token().is_none() {
-            return Err(SyncError::NotAuthenticated);
-        }
-        self.setup_hub().await?;
-        Ok(())
-    }
-
     async fn setup_hub(&mut self) -> Result<(), SyncError> {
         let Some(access_token) = self.state.get_access_token() else {
             return Err(SyncError::NotAuthenticated);
@@ -263,6 +255,15 @@ impl GoogleDriveBackend {
     }
 
     async fn find_sync_file(&self, folder_id: &str) -> Result<Option<(String, String)>, SyncError> {
+        self.find_file(folder_id, SYNC_FILE_NAME).await
+    }
+
+    /// Look up a file by name in the sync folder, returning (file ID, md5)
+    async fn find_file(
+        &self,
+        folder_id: &str,
+        name: &str,
+    ) -> Result<Option<(String, String)>, SyncError> {
         let hub = self.get_hub()?;
         let config = self.state.get_sync_config();
         let spaces =
@@ -271,10 +272,11 @@ impl GoogleDriveBackend {
             } else {
                 "drive"
             };
+        let name = name.replace('\\', "\\\\").replace('\'', "\\'");
         let query = if folder_id == "appDataFolder" {
-            format!("name = '{SYNC_FILE_NAME}' and trashed = false")
+            format!("name = '{name}' and trashed = false")
         } else {
-            format!("name = '{SYNC_FILE_NAME}' and '{folder_id}' in parents and trashed = false")
+            format!("name = '{name}' and '{folder_id}' in parents and trashed = false")
         };
 
         let (_, file_list) = hub
@@ -373,6 +375,71 @@ impl GoogleDriveBackend {
         Ok((token_response.access_token, refresh_token))
     }
 
+    /// Upload through a session stored in `UploadState`, so an interrupted
+    /// upload continues on the next attempt instead of starting over
+    async fn upload_resumable(
+        &self,
+        item: &UploadItem<'_>,
+        target: UploadTarget,
+        data: &[u8],
+    ) -> Result<drive_upload::UploadedFile, SyncError> {
+        let access_token = self
+            .state
+            .get_access_token()
+            .ok_or(SyncError::NotAuthenticated)?;
+        drive_upload::upload(&self.state, &access_token, item, target, data).await
+    }
+
+    async fn push_large_payload(
+        &self,
+        compressed: Vec<u8>,
+        existing_file: Option<(String, String)>,
+        etag: Option<&str>,
+        folder_id: String,
+    ) -> Result<PushResult, SyncError> {
+        let app_properties = serde_json::json!({ "deviceId": self.state.get_device_id() });
+        let base_version = existing_file
+            .as_ref()
+            .map(|(_, current_etag)| current_etag.clone())
+            .unwrap_or_default();
+        let target = match existing_file {
+            Some((file_id, current_etag)) => {
+                if let Some(expected_etag) = etag
+                    && expected_etag != current_etag
+                {
+                    return Ok(PushResult::Conflict {
+                        remote_etag: current_etag,
+                    });
+                }
+                UploadTarget::Update {
+                    file_id,
+                    metadata: serde_json::json!({ "appProperties": app_properties }),
+                }
+            }
+            None => UploadTarget::Create(serde_json::json!({
+                "name": SYNC_FILE_NAME,
+                "parents": [folder_id],
+                "appProperties": app_properties,
+            })),
+        };
+
+        info!(
+            "[DRIVE] Uploading {} bytes via stored resumable session...",
+            compressed.len()
+        );
+        let item = UploadItem {
+            upload_id: "drive:payload",
+            book_id: "",
+            file_type: "payload",
+            mime_type: "application/gzip",
+            base_version: &base_version,
+        };
+        let uploaded = self.upload_resumable(&item, target, &compressed).await?;
+        Ok(PushResult::Success {
+            etag: uploaded.md5_checksum,
+        })
+    }
+
     async fn do_refresh_token(&mut self) -> Result<(), SyncError> {
         self.refresh_access_token().await?;
         self.setup_hub().await?;
@@ -382,6 +449,18 @@ impl GoogleDriveBackend {
 
 #[async_trait]
 impl SyncBackend for GoogleDriveBackend {
+    fn backend_type(&self) -> SyncBackendType {
+        SyncBackendType::GoogleDrive
+    }
+
+    async fn initialize(&mut self) -> Result<(), SyncError> {
+        if self.state.get_access_token().is_none() || self.state.get_refresh_token().is_none() {
+            return Err(SyncError::NotAuthenticated);
+        }
+        self.setup_hub().await?;
+        Ok(())
+    }
+
     async fn pull(&self) -> Result<Option<(SyncPayload, String)>, SyncError> {
         let folder_id = self.get_or_create_folder().await?;
         info!("[DRIVE] Using folder: {}", folder_id);
@@ -394,18 +473,9 @@ impl SyncBackend for GoogleDriveBackend {
         info!("[DRIVE] Found sync file: {}, etag: {}", file_id, etag);
         let body_bytes = self.download_file(&file_id).await?;
 
-        let mut decoder = GzDecoder::new(&body_bytes[..]);
-        let mut decompressed = Vec::new();
-        match decoder.read_to_end(&mut decompressed) {
-            Ok(size) => info!("[DRIVE] Decompressed to: {} bytes", size),
-            Err(e) => {
-                error!("[DRIVE] Failed to decompress: {}", e);
-                return Err(SyncError::IoError(e));
-            }
-        };
-
-        let payload: SyncPayload =
-            serde_json::from_slice(&decompressed).map_err(SyncError::SerializationError)?;
+        let payload = decode_payload(&self.state, &body_bytes).inspect_err(|e| {
+            error!("[DRIVE] Failed to decode sync file: {}", e);
+        })?;
         Ok(Some((payload, etag)))
     }
 
@@ -414,23 +484,12 @@ impl SyncBackend for GoogleDriveBackend {
         let existing_file = self.find_sync_file(&folder_id).await?;
         let config = self.state.get_sync_config();
 
-        let json_bytes = serde_json::to_vec(data).map_err(SyncError::SerializationError)?;
-
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(&json_bytes).map_err(SyncError::IoError)?;
-        let compressed = encoder.finish().map_err(SyncError::IoError)?;
-
-        let reduction = if !json_bytes.is_empty() {
-            (100.0 - (compressed.len() as f64 / json_bytes.len() as f64 * 100.0)) as i32
-        } else {
-            0
-        };
-        info!(
-            "[DRIVE] Compressed: {} -> {} bytes ({}% reduction)",
-            json_bytes.len(),
-            compressed.len(),
-            reduction
-        );
+        let compressed = encode_payload(&self.state, data)?;
+        if compressed.len() >= RESUMABLE_THRESHOLD {
+            return self
+                .push_large_payload(compressed, existing_file, etag, folder_id)
+                .await;
+        }
 
         let hub = self.get_hub()?;
         let cursor = std::io::Cursor::new(compressed);
@@ -570,4 +629,86 @@ impl SyncBackend for GoogleDriveBackend {
     async fn refresh_token(&mut self) -> Result<(), SyncError> {
         self.do_refresh_token().await
     }
+
+    // Drive names may contain `/`, so objects live flat in the sync folder
+    // under their full name instead of in real subfolders.
+    fn supports_objects(&self) -> bool {
+        true
+    }
+
+    async fn pull_object(&self, name: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        let folder_id = self.get_or_create_folder().await?;
+        match self.find_file(&folder_id, name).await? {
+            Some((file_id, _)) => Ok(Some(self.download_file(&file_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn push_object(&self, name: &str, data: &[u8]) -> Result<(), SyncError> {
+        let folder_id = self.get_or_create_folder().await?;
+        let existing_file = self.find_file(&folder_id, name).await?;
+
+        if data.len() >= RESUMABLE_THRESHOLD {
+            let base_version = existing_file
+                .as_ref()
+                .map(|(file_id, _)| file_id.clone())
+                .unwrap_or_default();
+            let target = match existing_file {
+                Some((file_id, _)) => UploadTarget::Update {
+                    file_id,
+                    metadata: serde_json::json!({}),
+                },
+                None => UploadTarget::Create(serde_json::json!({
+                    "name": name,
+                    "parents": [folder_id],
+                })),
+            };
+            let item = UploadItem {
+                upload_id: &format!("drive:{name}"),
+                book_id: name,
+                file_type: "object",
+                mime_type: "application/gzip",
+                base_version: &base_version,
+            };
+            self.upload_resumable(&item, target, data).await?;
+            return Ok(());
+        }
+
+        let hub = self.get_hub()?;
+        let cursor = std::io::Cursor::new(data.to_vec());
+        let mime: mime::Mime = "application/gzip".parse().expect("valid gzip mime type");
+
+        if let Some((file_id, _)) = existing_file {
+            hub.files()
+                .update(File::default(), &file_id)
+                .upload_resumable(cursor, mime)
+                .await
+                .map_err(|e| SyncError::DriveError(e.to_string()))?;
+        } else {
+            let file_metadata = File {
+                name: Some(name.to_string()),
+                parents: Some(vec![folder_id]),
+                ..Default::default()
+            };
+            hub.files()
+                .create(file_metadata)
+                .upload_resumable(cursor, mime)
+                .await
+                .map_err(|e| SyncError::DriveError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<(), SyncError> {
+        let folder_id = self.get_or_create_folder().await?;
+        if let Some((file_id, _)) = self.find_file(&folder_id, name).await? {
+            self.get_hub()?
+                .files()
+                .delete(&file_id)
+                .doit()
+                .await
+                .map_err(|e| SyncError::DriveError(e.to_string()))?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,360 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::{
+    backend::{AuthFlow, PushResult, SYNC_FILE_NAME, SyncBackend, decode_payload, encode_payload},
+    error::SyncError,
+    state::SyncState,
+    types::{SyncBackendType, SyncPayload},
+};
+
+// ============================================================================
+// Local Folder Backend
+// ============================================================================
+
+/// Sync backend that keeps the payload in a plain directory.
+///
+/// Meant for folders that something else replicates (Syncthing, a USB stick,
+/// an NFS share). The SHA-256 of the file doubles as its ETag, and writes go
+/// through a temporary file plus rename so readers never see a partial file.
+pub struct LocalFolderBackend {
+    state: SyncState,
+}
+
+impl LocalFolderBackend {
+    pub fn new(state: SyncState) -> Self {
+        Self { state }
+    }
+
+    fn folder(&self) -> Result<PathBuf, SyncError> {
+        let path = self.state.get_sync_config().local_folder_path;
+        let path = path.trim();
+        if path.is_empty() {
+            return Err(SyncError::BadRequest(
+                "Local sync folder is not configured".to_string(),
+            ));
+        }
+        Ok(PathBuf::from(path))
+    }
+
+    fn sync_file_path(&self) -> Result<PathBuf, SyncError> {
+        Ok(self.folder()?.join(SYNC_FILE_NAME))
+    }
+
+    /// Read the sync file and its content hash, or None if it does not exist
+    async fn read_sync_file(&self) -> Result<Option<(Vec<u8>, String)>, SyncError> {
+        match tokio::fs::read(self.sync_file_path()?).await {
+            Ok(bytes) => {
+                let etag = content_hash(&bytes);
+                Ok(Some((bytes, etag)))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SyncError::IoError(e)),
+        }
+    }
+
+    /// Check that the folder exists and is writable.
+    ///
+    /// The folder is not created on purpose: a missing path usually means an
+    /// unmounted drive, and syncing into an empty mount point would fork the data.
+    pub async fn verify(&self) -> Result<(), SyncError> {
+        let folder = self.folder()?;
+        if !tokio::fs::metadata(&folder)
+            .await
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false)
+        {
+            return Err(SyncError::FileNotFound(folder.display().to_string()));
+        }
+
+        let probe = folder.join(format!(".manatan-probe-{}", uuid::Uuid::new_v4().simple()));
+        tokio::fs::write(&probe, b"").await?;
+        tokio::fs::remove_file(&probe).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SyncBackend for LocalFolderBackend {
+    fn backend_type(&self) -> SyncBackendType {
+        SyncBackendType::LocalFolder
+    }
+
+    async fn pull(&self) -> Result<Option<(SyncPayload, String)>, SyncError> {
+        let Some((bytes, etag)) = self.read_sync_file().await? else {
+            info!("[LOCAL] No sync file found");
+            return Ok(None);
+        };
+
+        info!("[LOCAL] Read {} bytes, etag: {}", bytes.len(), etag);
+        let payload = decode_payload(&self.state, &bytes)?;
+        Ok(Some((payload, etag)))
+    }
+
+    async fn push(&self, data: &SyncPayload, etag: Option<&str>) -> Result<PushResult, SyncError> {
+        let folder = self.folder()?;
+        let target = self.sync_file_path()?;
+
+        let current_etag = self.read_sync_file().await?.map(|(_, etag)| etag);
+        let conflict = match (etag, &current_etag) {
+            (Some(expected), Some(current)) => expected != current,
+            // Someone created the file since our last pull
+            (None, Some(_)) => true,
+            // The file vanished; writing it again is safe
+            (_, None) => false,
+        };
+        if conflict {
+            let remote_etag = current_etag.unwrap_or_default();
+            warn!(
+                "[LOCAL] Conflict, expected {:?}, found {}",
+                etag, remote_etag
+            );
+            return Ok(PushResult::Conflict { remote_etag });
+        }
+
+        let compressed = encode_payload(&self.state, data)?;
+        let new_etag = content_hash(&compressed);
+        write_atomic(&folder, &target, &compressed).await?;
+
+        info!(
+            "[LOCAL] Wrote {} bytes to {}, etag: {}",
+            compressed.len(),
+            target.display(),
+            new_etag
+        );
+        Ok(PushResult::Success { etag: new_etag })
+    }
+
+    async fn is_authenticated(&self) -> bool {
+        match self.folder() {
+            Ok(folder) => tokio::fs::metadata(folder)
+                .await
+                .map(|metadata| metadata.is_dir())
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    async fn get_user_info(&self) -> Result<Option<String>, SyncError> {
+        Ok(self
+            .folder()
+            .ok()
+            .map(|folder| folder.display().to_string()))
+    }
+
+    fn start_auth(&self, _redirect_uri: &str) -> Result<AuthFlow, SyncError> {
+        Err(SyncError::BadRequest(
+            "The local folder backend does not need authentication".to_string(),
+        ))
+    }
+
+    async fn complete_auth(&mut self, _code: &str, _redirect_uri: &str) -> Result<(), SyncError> {
+        Err(SyncError::BadRequest(
+            "The local folder backend does not need authentication".to_string(),
+        ))
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SyncError> {
+        Ok(())
+    }
+
+    async fn refresh_token(&mut self) -> Result<(), SyncError> {
+        Ok(())
+    }
+
+    fn supports_objects(&self) -> bool {
+        true
+    }
+
+    async fn pull_object(&self, name: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        match tokio::fs::read(self.folder()?.join(name)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SyncError::IoError(e)),
+        }
+    }
+
+    async fn push_object(&self, name: &str, data: &[u8]) -> Result<(), SyncError> {
+        let target = self.folder()?.join(name);
+        let Some(parent) = target.parent() else {
+            return Err(SyncError::BadRequest(format!(
+                "Invalid object name: {name}"
+            )));
+        };
+        tokio::fs::create_dir_all(parent).await?;
+        write_atomic(parent, &target, data).await
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<(), SyncError> {
+        match tokio::fs::remove_file(self.folder()?.join(name)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SyncError::IoError(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Write through a temporary file in `folder` and rename it over `target`.
+/// The temp file lives next to the target so the rename stays on the same filesystem.
+async fn write_atomic(folder: &Path, target: &Path, bytes: &[u8]) -> Result<(), SyncError> {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = folder.join(format!(
+        ".{file_name}.{}.tmp",
+        uuid::Uuid::new_v4().simple()
+    ));
+    let write_result = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        file.write_all(bytes).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&temp_path, target).await
+    }
+    .await;
+
+    if let Err(e) = write_result {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(SyncError::IoError(e));
+    }
+    Ok(())
+}
+
+fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{local_folder_state, unique_temp_dir},
+        types::ConflictResolution,
+    };
+
+    #[tokio::test]
+    async fn push_then_pull_round_trips_and_uses_content_hash_as_etag() {
+        let root = unique_temp_dir("local-roundtrip");
+        let folder = root.join("shared");
+        let backend = LocalFolderBackend::new(local_folder_state(
+            root.join("data"),
+            &folder,
+            ConflictResolution::Automatic,
+        ));
+
+        backend.verify().await.expect("folder is writable");
+        assert!(backend.pull().await.expect("pull succeeds").is_none());
+
+        let PushResult::Success { etag } = backend
+            .push(&SyncPayload::new("device-a".to_string()), None)
+            .await
+            .expect("push succeeds")
+        else {
+            panic!("expected a successful push");
+        };
+
+        let on_disk = std::fs::read(folder.join(SYNC_FILE_NAME)).expect("sync file exists");
+        assert_eq!(etag, content_hash(&on_disk));
+
+        let (payload, pulled_etag) = backend
+            .pull()
+            .await
+            .expect("pull succeeds")
+            .expect("payload exists");
+        assert_eq!(payload.device_id, "device-a");
+        assert_eq!(pulled_etag, etag);
+
+        // No temp files are left behind after the rename
+        let leftovers = std::fs::read_dir(&folder)
+            .expect("folder is readable")
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn push_detects_concurrent_writes() {
+        let root = unique_temp_dir("local-conflict");
+        let folder = root.join("shared");
+        let backend = LocalFolderBackend::new(local_folder_state(
+            root.join("data"),
+            &folder,
+            ConflictResolution::Automatic,
+        ));
+
+        let PushResult::Success { etag } = backend
+            .push(&SyncPayload::new("device-a".to_string()), None)
+            .await
+            .expect("push succeeds")
+        else {
+            panic!("expected a successful push");
+        };
+
+        // Another device overwrites the file through the shared folder
+        let other = encode_payload(&backend.state, &SyncPayload::new("device-b".to_string()))
+            .expect("payload encodes");
+        std::fs::write(folder.join(SYNC_FILE_NAME), &other).expect("file overwritten");
+
+        let result = backend
+            .push(&SyncPayload::new("device-a".to_string()), Some(&etag))
+            .await
+            .expect("push returns a result");
+        assert!(
+            matches!(result, PushResult::Conflict { remote_etag } if remote_etag == content_hash(&other))
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_missing_folder() {
+        let root = unique_temp_dir("local-missing");
+        let folder = root.join("unmounted");
+        let state = local_folder_state(root.join("data"), &folder, ConflictResolution::Automatic);
+        std::fs::remove_dir(&folder).expect("folder removed");
+        let backend = LocalFolderBackend::new(state);
+
+        assert!(matches!(
+            backend.verify().await,
+            Err(SyncError::FileNotFound(_))
+        ));
+        assert!(!backend.is_authenticated().await);
+    }
+
+    #[tokio::test]
+    async fn objects_are_stored_in_nested_folders() {
+        let root = unique_temp_dir("local-objects");
+        let folder = root.join("shared");
+        let backend = LocalFolderBackend::new(local_folder_state(
+            root.join("data"),
+            &folder,
+            ConflictResolution::Automatic,
+        ));
+
+        assert!(
+            backend
+                .pull_object("books/a.gz")
+                .await
+                .expect("read")
+                .is_none()
+        );
+        backend
+            .push_object("books/a.gz", b"data")
+            .await
+            .expect("object written");
+        assert_eq!(
+            backend.pull_object("books/a.gz").await.expect("read"),
+            Some(b"data".to_vec())
+        );
+
+        backend.delete_object("books/a.gz").await.expect("deleted");
+        backend
+            .delete_object("books/a.gz")
+            .await
+            .expect("deleting twice is fine");
+        assert!(!folder.join("books/a.gz").exists());
+    }
+}
@@ -1,8 +1,34 @@
+pub mod drive_upload;
 pub mod google_drive;
+pub mod local_folder;
+pub mod syncyomi;
+pub mod webdav;
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::{
+    backend::{
+        google_drive::GoogleDriveBackend, local_folder::LocalFolderBackend,
+        syncyomi::SyncYomiBackend, webdav::WebDavBackend,
+    },
+    crypto, delta,
+    error::SyncError,
+    merge::{DeletionPolicy, merge_payloads},
+    migration,
+    state::SyncState,
+    types::{SyncBackendType, SyncPayload},
+};
 
-use crate::{error::SyncError, types::SyncPayload};
+/// Name of the remote sync file, shared by every file-based backend
+pub const SYNC_FILE_NAME: &str = "manatan_sync.proto.gz";
 
 /// Result of a push operation
 #[derive(Debug)]
@@ -32,6 +58,14 @@ pub struct AuthStatus {
 /// Trait for sync storage backends
 #[async_trait]
 pub trait SyncBackend: Send + Sync {
+    /// Which `SyncConfig.backend` value this implementation serves
+    fn backend_type(&self) -> SyncBackendType;
+
+    /// Prepare clients from stored credentials (called once before first use)
+    async fn initialize(&mut self) -> Result<(), SyncError> {
+        Ok(())
+    }
+
     /// Pull sync data from remote storage
     /// Returns (payload, etag) or None if no data exists
     async fn pull(&self) -> Result<Option<(SyncPayload, String)>, SyncError>;
@@ -57,4 +91,359 @@ pub trait SyncBackend: Send + Sync {
 
     /// Refresh access token
     async fn refresh_token(&mut self) -> Result<(), SyncError>;
+
+    /// Whether per-book objects can be stored next to the sync file.
+    /// Backends without object storage always sync the whole payload.
+    fn supports_objects(&self) -> bool {
+        false
+    }
+
+    /// Read an object written by `push_object`, or None if it does not exist
+    async fn pull_object(&self, _name: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        Err(objects_unsupported(self.backend_type()))
+    }
+
+    /// Write an object, creating or replacing it.
+    /// `name` is relative to the sync folder and may contain `/`.
+    async fn push_object(&self, _name: &str, _data: &[u8]) -> Result<(), SyncError> {
+        Err(objects_unsupported(self.backend_type()))
+    }
+
+    /// Delete an object; deleting a missing object is not an error
+    async fn delete_object(&self, _name: &str) -> Result<(), SyncError> {
+        Err(objects_unsupported(self.backend_type()))
+    }
+}
+
+fn objects_unsupported(backend_type: SyncBackendType) -> SyncError {
+    SyncError::BadRequest(format!(
+        "{backend_type:?} backend does not support per-book objects"
+    ))
+}
+
+/// The active backend, shared across requests and rebuilt when the config changes
+pub type ActiveBackend = Arc<RwLock<Option<Box<dyn SyncBackend>>>>;
+
+/// Build an uninitialized backend for the given type
+pub fn create_backend(
+    state: &SyncState,
+    backend_type: &SyncBackendType,
+) -> Option<Box<dyn SyncBackend>> {
+    match backend_type {
+        SyncBackendType::None => None,
+        SyncBackendType::GoogleDrive => Some(Box::new(GoogleDriveBackend::new(state.clone()))),
+        SyncBackendType::WebDav => Some(Box::new(WebDavBackend::new(state.clone()))),
+        SyncBackendType::SyncYomi => Some(Box::new(SyncYomiBackend::new(state.clone()))),
+        SyncBackendType::LocalFolder => Some(Box::new(LocalFolderBackend::new(state.clone()))),
+    }
+}
+
+/// Backend selected in the config.
+///
+/// Installs that connected Google Drive before `SyncConfig.backend` existed
+/// still have tokens but no selection, so those keep using Google Drive.
+pub fn active_backend_type(state: &SyncState) -> SyncBackendType {
+    match state.get_sync_config().backend {
+        SyncBackendType::None
+            if state.get_access_token().is_some() && state.get_refresh_token().is_some() =>
+        {
+            SyncBackendType::GoogleDrive
+        }
+        backend_type => backend_type,
+    }
+}
+
+/// Make sure `state.backend` holds an initialized instance of the configured backend
+pub async fn load_backend(state: &SyncState) -> Result<(), SyncError> {
+    let backend_type = active_backend_type(state);
+    let mut active = state.backend.write().await;
+
+    if active
+        .as_ref()
+        .is_some_and(|backend| backend.backend_type() == backend_type)
+    {
+        return Ok(());
+    }
+
+    let Some(mut backend) = create_backend(state, &backend_type) else {
+        *active = None;
+        return Err(SyncError::NotAuthenticated);
+    };
+    if !backend.is_authenticated().await {
+        return Err(SyncError::NotAuthenticated);
+    }
+
+    info!("[SYNC] Initializing {:?} backend", backend_type);
+    backend.initialize().await?;
+    *active = Some(backend);
+    Ok(())
+}
+
+/// Load the configured backend and refresh its credentials before an operation
+pub async fn ensure_backend(state: &SyncState) -> Result<(), SyncError> {
+    load_backend(state).await?;
+
+    let mut active = state.backend.write().await;
+    if let Some(backend) = active.as_mut()
+        && let Err(e) = backend.refresh_token().await
+    {
+        debug!("Token refresh failed (may be okay): {}", e);
+    }
+
+    Ok(())
+}
+
+/// Outcome of switching the active backend
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendSwitch {
+    pub from: SyncBackendType,
+    pub to: SyncBackendType,
+    /// Whether a remote payload was copied to the new backend
+    pub migrated: bool,
+    pub etag: Option<String>,
+}
+
+/// Make `target` the active backend, optionally copying the current remote
+/// payload over first.
+///
+/// If the target already holds a payload (e.g. another device migrated
+/// earlier) the two are merged instead of overwriting it. The source backend
+/// is left untouched, so switching back is always possible.
+pub async fn switch_backend(
+    state: &SyncState,
+    target: SyncBackendType,
+    migrate: bool,
+) -> Result<BackendSwitch, SyncError> {
+    let source_type = active_backend_type(state);
+
+    let mut target_backend = match create_backend(state, &target) {
+        Some(mut backend) => {
+            if !backend.is_authenticated().await {
+                return Err(SyncError::NotAuthenticated);
+            }
+            backend.initialize().await?;
+            Some(backend)
+        }
+        None => None,
+    };
+
+    let mut migrated = false;
+    let mut etag = None;
+
+    if migrate
+        && source_type != target
+        && let Some(target_backend) = target_backend.as_mut()
+        && let Some(mut source) = create_backend(state, &source_type)
+        && source.is_authenticated().await
+    {
+        source.initialize().await?;
+        if let Err(e) = source.refresh_token().await {
+            debug!("Token refresh failed (may be okay): {}", e);
+        }
+
+        if let Some((payload, _)) = source.pull().await? {
+            info!(
+                "[SYNC] Migrating remote payload from {:?} to {:?}",
+                source_type, target
+            );
+            let (to_push, expected_etag) = match target_backend.pull().await? {
+                Some((existing, existing_etag)) => {
+                    warn!(
+                        "[SYNC] {:?} already has sync data, merging instead of overwriting",
+                        target
+                    );
+                    let policy =
+                        DeletionPolicy::new(state.get_sync_config().deletion_behavior, Vec::new());
+                    let merged =
+                        merge_payloads(payload, existing, &state.get_device_id(), &policy).payload;
+                    (merged, Some(existing_etag))
+                }
+                None => (payload, None),
+            };
+
+            match target_backend
+                .push(&to_push, expected_etag.as_deref())
+                .await?
+            {
+                PushResult::Success { etag: new_etag } => {
+                    migrated = true;
+                    etag = Some(new_etag);
+                }
+                PushResult::Conflict { remote_etag } => {
+                    return Err(SyncError::Conflict(format!(
+                        "Migration target changed during copy, remote etag: {remote_etag}"
+                    )));
+                }
+            }
+        }
+    }
+
+    let mut config = state.get_sync_config();
+    config.backend = target.clone();
+    state.set_sync_config(&config)?;
+    if let Some(etag) = &etag {
+        state.set_last_etag(etag)?;
+    }
+    *state.backend.write().await = target_backend;
+
+    info!(
+        "[SYNC] Switched backend {:?} -> {:?} (migrated: {})",
+        source_type, target, migrated
+    );
+    Ok(BackendSwitch {
+        from: source_type,
+        to: target,
+        migrated,
+        etag,
+    })
+}
+
+/// Set (or with None, remove) the end-to-end encryption passphrase and
+/// rewrite the remote copy to match.
+///
+/// The remote payload is read with the new passphrase first, so a device
+/// joining an already encrypted library just has its passphrase verified. If
+/// that fails the old passphrase is tried, which re-encrypts the library under
+/// the new one. The new passphrase is only stored once the remote has been
+/// rewritten; until then the old one stays in place, and a failed rewrite
+/// returns its error (`WrongPassphrase` or `PassphraseRequired` when neither
+/// passphrase can read the remote).
+pub async fn change_passphrase(state: &SyncState, new: Option<&str>) -> Result<(), SyncError> {
+    let old = state.get_encryption_passphrase();
+    let store = |passphrase: Option<&str>| match passphrase {
+        Some(passphrase) => state.set_encryption_passphrase(passphrase),
+        None => state.clear_encryption_passphrase(),
+    };
+
+    if ensure_backend(state).await.is_err() {
+        // Nothing remote to verify against yet
+        store(new)?;
+        return Ok(());
+    }
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
+
+    // A second instance of the backend that reads and writes with the new
+    // passphrase, leaving the stored one untouched
+    let mut rewriter = create_backend(&state.with_passphrase(new), &backend.backend_type())
+        .ok_or(SyncError::NotAuthenticated)?;
+    rewriter.initialize().await?;
+    if let Err(e) = rewriter.refresh_token().await {
+        debug!("Token refresh failed (may be okay): {}", e);
+    }
+
+    let remote = match rewriter.pull().await {
+        Ok(remote) => remote,
+        Err(e @ (SyncError::WrongPassphrase | SyncError::PassphraseRequired)) => {
+            backend.pull().await.map_err(|_| e)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    let Some((payload, etag)) = remote else {
+        store(new)?;
+        return Ok(());
+    };
+
+    // Objects rewritten so far with their old bytes, to put back if the
+    // payload cannot be pushed and the old passphrase stays in use
+    let mut rewritten = Vec::new();
+    let rewrite = async {
+        if rewriter.supports_objects() {
+            for reference in payload.file_manifest.values() {
+                let name = delta::object_name(reference);
+                let Some(bytes) = rewriter.pull_object(&name).await? else {
+                    continue;
+                };
+                if crypto::is_encrypted(&bytes) == new.is_some()
+                    && crypto::open_with(state, new, &bytes).is_ok()
+                {
+                    continue;
+                }
+                let plain = crypto::open_with(state, old.as_deref(), &bytes)?;
+                rewriter
+                    .push_object(&name, &crypto::seal_with(state, new, &plain)?)
+                    .await?;
+                rewritten.push((name, bytes));
+            }
+        }
+
+        match rewriter.push(&payload, Some(&etag)).await? {
+            PushResult::Success { etag } => Ok(etag),
+            PushResult::Conflict { remote_etag } => Err(SyncError::Conflict(format!(
+                "Remote changed while re-encrypting, remote etag: {remote_etag}"
+            ))),
+        }
+    };
+
+    match rewrite.await {
+        Ok(etag) => {
+            store(new)?;
+            state.set_last_etag(&etag)?;
+        }
+        Err(e) => {
+            for (name, bytes) in &rewritten {
+                if let Err(e) = backend.push_object(name, bytes).await {
+                    warn!(
+                        "[SYNC] Could not restore {} after a failed rewrite: {}",
+                        name, e
+                    );
+                }
+            }
+            return Err(e);
+        }
+    }
+    info!(
+        "[SYNC] Remote data rewritten {}",
+        if new.is_some() {
+            "with encryption"
+        } else {
+            "without encryption"
+        }
+    );
+    Ok(())
+}
+
+/// Serialize, gzip and (if a passphrase is set) encrypt a payload for upload
+pub fn encode_payload(state: &SyncState, data: &SyncPayload) -> Result<Vec<u8>, SyncError> {
+    let json_bytes = serde_json::to_vec(data)?;
+    let compressed = compress(&json_bytes)?;
+
+    let reduction = if !json_bytes.is_empty() {
+        (100.0 - (compressed.len() as f64 / json_bytes.len() as f64 * 100.0)) as i32
+    } else {
+        0
+    };
+    info!(
+        "[SYNC] Compressed: {} -> {} bytes ({}% reduction)",
+        json_bytes.len(),
+        compressed.len(),
+        reduction
+    );
+
+    crypto::seal(state, &compressed)
+}
+
+/// Decrypt, decompress and deserialize a downloaded payload
+pub fn decode_payload(state: &SyncState, bytes: &[u8]) -> Result<SyncPayload, SyncError> {
+    let decompressed = decompress(&crypto::open(state, bytes)?)?;
+    info!("[SYNC] Decompressed to: {} bytes", decompressed.len());
+
+    migration::parse_payload(&decompressed)
+}
+
+/// Gzip raw bytes (used for the sync file and per-book objects)
+pub fn compress(bytes: &[u8]) -> Result<Vec<u8>, SyncError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// Inverse of `compress`
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, SyncError> {
+    let mut decoder = GzDecoder::new(bytes);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
 }
@@ -0,0 +1,358 @@
+use async_trait::async_trait;
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH},
+};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{AuthFlow, PushResult, SyncBackend, decode_payload, encode_payload},
+    error::SyncError,
+    state::SyncState,
+    types::{SyncBackendType, SyncPayload},
+};
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+const API_TOKEN_HEADER: &str = "X-API-Token";
+const CONTENT_ENDPOINT: &str = "/api/sync/content";
+
+// ============================================================================
+// SyncYomi Backend
+// ============================================================================
+
+/// Sync backend for a self-hosted SyncYomi server.
+///
+/// SyncYomi stores a single opaque blob per API key and guards writes with
+/// ETags, so it maps directly onto `pull`/`push` with `If-Match`.
+pub struct SyncYomiBackend {
+    state: SyncState,
+    client: reqwest::Client,
+}
+
+impl SyncYomiBackend {
+    pub fn new(state: SyncState) -> Self {
+        Self {
+            state,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn api_key(&self) -> Result<String, SyncError> {
+        self.state
+            .get_syncyomi_api_key()
+            .ok_or(SyncError::NotAuthenticated)
+    }
+
+    fn content_url(&self) -> Result<String, SyncError> {
+        let url = self.state.get_sync_config().syncyomi_url;
+        let url = url.trim().trim_end_matches('/');
+        if url.is_empty() {
+            return Err(SyncError::BadRequest(
+                "SyncYomi server URL is not configured".to_string(),
+            ));
+        }
+        Ok(format!("{url}{CONTENT_ENDPOINT}"))
+    }
+
+    async fn get_content(
+        &self,
+        if_none_match: Option<&str>,
+    ) -> Result<reqwest::Response, SyncError> {
+        let mut request = self
+            .client
+            .get(self.content_url()?)
+            .header(API_TOKEN_HEADER, self.api_key()?);
+        if let Some(etag) = if_none_match {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SyncError::SyncYomiError(e.to_string()))?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(SyncError::NotAuthenticated),
+            _ => Ok(response),
+        }
+    }
+
+    /// Check that the server is reachable and accepts the API key
+    pub async fn verify(&self) -> Result<(), SyncError> {
+        let response = self.get_content(None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::NOT_MODIFIED => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => Err(SyncError::SyncYomiError(format!(
+                "Server responded with status {status}"
+            ))),
+        }
+    }
+
+    /// Current remote ETag, used to report the winner of a rejected push
+    async fn remote_etag(&self) -> Result<String, SyncError> {
+        let response = self.get_content(None).await?;
+        Ok(response_etag(&response).unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl SyncBackend for SyncYomiBackend {
+    fn backend_type(&self) -> SyncBackendType {
+        SyncBackendType::SyncYomi
+    }
+
+    async fn pull(&self) -> Result<Option<(SyncPayload, String)>, SyncError> {
+        info!("[SYNCYOMI] Downloading sync content...");
+        let response = self.get_content(None).await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                info!("[SYNCYOMI] No sync content found");
+                return Ok(None);
+            }
+            status if !status.is_success() => {
+                return Err(SyncError::SyncYomiError(format!(
+                    "Download failed with status {status}"
+                )));
+            }
+            _ => {}
+        }
+
+        let etag = response_etag(&response).unwrap_or_default();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| SyncError::SyncYomiError(e.to_string()))?;
+        info!("[SYNCYOMI] Downloaded {} bytes, etag: {}", body.len(), etag);
+
+        // An empty body means the key exists on the server but nothing was uploaded yet
+        if body.is_empty() {
+            return Ok(None);
+        }
+
+        let payload = decode_payload(&self.state, &body)?;
+        Ok(Some((payload, etag)))
+    }
+
+    async fn push(&self, data: &SyncPayload, etag: Option<&str>) -> Result<PushResult, SyncError> {
+        let compressed = encode_payload(&self.state, data)?;
+
+        let mut request = self
+            .client
+            .put(self.content_url()?)
+            .header(API_TOKEN_HEADER, self.api_key()?)
+            .header(CONTENT_TYPE, "application/octet-stream");
+        // SyncYomi mirrors HTTP semantics: If-Match for updates, If-None-Match: * for
+        // the first upload, 412 Precondition Failed when someone else wrote first.
+        request = match etag {
+            Some(expected) if !expected.is_empty() => request.header(IF_MATCH, expected),
+            _ => request.header(IF_NONE_MATCH, "*"),
+        };
+
+        info!("[SYNCYOMI] Uploading {} bytes...", compressed.len());
+        let response = request
+            .body(compressed)
+            .send()
+            .await
+            .map_err(|e| SyncError::SyncYomiError(e.to_string()))?;
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(SyncError::NotAuthenticated),
+            StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT => {
+                let remote_etag = self.remote_etag().await?;
+                warn!(
+                    "[SYNCYOMI] Upload rejected, expected {:?}, remote {}",
+                    etag, remote_etag
+                );
+                Ok(PushResult::Conflict { remote_etag })
+            }
+            status if status.is_success() => {
+                let new_etag = match response_etag(&response) {
+                    Some(etag) => etag,
+                    None => self.remote_etag().await?,
+                };
+                info!("[SYNCYOMI] Upload successful, etag: {}", new_etag);
+                Ok(PushResult::Success { etag: new_etag })
+            }
+            status => Err(SyncError::SyncYomiError(format!(
+                "Upload failed with status {status}"
+            ))),
+        }
+    }
+
+    async fn is_authenticated(&self) -> bool {
+        self.state.get_syncyomi_api_key().is_some() && self.content_url().is_ok()
+    }
+
+    async fn get_user_info(&self) -> Result<Option<String>, SyncError> {
+        let url = self.state.get_sync_config().syncyomi_url;
+        Ok(reqwest::Url::parse(url.trim())
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_string)))
+    }
+
+    fn start_auth(&self, _redirect_uri: &str) -> Result<AuthFlow, SyncError> {
+        Err(SyncError::BadRequest(
+            "SyncYomi does not use OAuth; connect with an API key instead".to_string(),
+        ))
+    }
+
+    async fn complete_auth(&mut self, _code: &str, _redirect_uri: &str) -> Result<(), SyncError> {
+        Err(SyncError::BadRequest(
+            "SyncYomi does not use OAuth; connect with an API key instead".to_string(),
+        ))
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SyncError> {
+        self.state.clear_syncyomi_api_key()?;
+        info!("Disconnected from SyncYomi");
+        Ok(())
+    }
+
+    async fn refresh_token(&mut self) -> Result<(), SyncError> {
+        Ok(())
+    }
+}
+
+fn response_etag(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode as AxumStatus},
+        response::{IntoResponse, Response},
+        routing::get,
+    };
+
+    use super::*;
+    use crate::test_util::unique_temp_dir;
+
+    const API_KEY: &str = "test-key";
+
+    /// (body, etag) of the single blob SyncYomi stores per API key
+    type StoredContent = Arc<Mutex<Option<(Vec<u8>, String)>>>;
+
+    fn authorized(headers: &HeaderMap) -> bool {
+        headers
+            .get(API_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            == Some(API_KEY)
+    }
+
+    async fn get_content(State(stored): State<StoredContent>, headers: HeaderMap) -> Response {
+        if !authorized(&headers) {
+            return AxumStatus::UNAUTHORIZED.into_response();
+        }
+        match stored.lock().expect("lock").as_ref() {
+            Some((body, etag)) => ([("etag", etag.clone())], body.clone()).into_response(),
+            None => AxumStatus::NOT_FOUND.into_response(),
+        }
+    }
+
+    async fn put_content(
+        State(stored): State<StoredContent>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        if !authorized(&headers) {
+            return AxumStatus::UNAUTHORIZED.into_response();
+        }
+        let mut stored = stored.lock().expect("lock");
+        let current = stored.as_ref().map(|(_, etag)| etag.clone());
+        let if_match = headers.get("if-match").and_then(|v| v.to_str().ok());
+        let accepted = match if_match {
+            Some(expected) => current.as_deref() == Some(expected),
+            None => current.is_none(),
+        };
+        if !accepted {
+            return AxumStatus::PRECONDITION_FAILED.into_response();
+        }
+
+        let etag = uuid::Uuid::new_v4().simple().to_string();
+        *stored = Some((body.to_vec(), etag.clone()));
+        ([("etag", etag)], AxumStatus::OK).into_response()
+    }
+
+    async fn spawn_stand_in() -> String {
+        let router = Router::new()
+            .route(CONTENT_ENDPOINT, get(get_content).put(put_content))
+            .with_state(StoredContent::default());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let addr = listener.local_addr().expect("listener has an address");
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("server runs");
+        });
+        format!("http://{addr}")
+    }
+
+    fn test_state(label: &str, url: &str) -> SyncState {
+        let dir = unique_temp_dir(&format!("syncyomi-{label}"));
+        let state = SyncState::new(dir);
+
+        let mut config = state.get_sync_config();
+        config.backend = SyncBackendType::SyncYomi;
+        config.syncyomi_url = url.to_string();
+        state.set_sync_config(&config).expect("config saved");
+        state.set_syncyomi_api_key(API_KEY).expect("api key saved");
+        state
+    }
+
+    #[tokio::test]
+    async fn push_and_pull_follow_etag_preconditions() {
+        let url = spawn_stand_in().await;
+        let backend = SyncYomiBackend::new(test_state("etag", &url));
+
+        assert!(backend.pull().await.expect("pull succeeds").is_none());
+
+        let PushResult::Success { etag } = backend
+            .push(&SyncPayload::new("device-a".to_string()), None)
+            .await
+            .expect("first push succeeds")
+        else {
+            panic!("expected a successful push");
+        };
+
+        let (payload, pulled_etag) = backend
+            .pull()
+            .await
+            .expect("pull succeeds")
+            .expect("remote payload exists");
+        assert_eq!(payload.device_id, "device-a");
+        assert_eq!(pulled_etag, etag);
+
+        let stale = backend
+            .push(&SyncPayload::new("device-b".to_string()), Some("stale"))
+            .await
+            .expect("push returns a result");
+        assert!(matches!(stale, PushResult::Conflict { remote_etag } if remote_etag == etag));
+    }
+
+    #[tokio::test]
+    async fn wrong_api_key_is_not_authenticated() {
+        let url = spawn_stand_in().await;
+        let state = test_state("badkey", &url);
+        state.set_syncyomi_api_key("wrong").expect("api key saved");
+
+        assert!(matches!(
+            SyncYomiBackend::new(state).verify().await,
+            Err(SyncError::NotAuthenticated)
+        ));
+    }
+}
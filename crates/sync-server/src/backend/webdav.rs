@@ -0,0 +1,877 @@
+use async_trait::async_trait;
+use reqwest::{
+    Method, RequestBuilder, Response, StatusCode,
+    header::{AUTHORIZATION, ETAG, HeaderMap, IF_MATCH, IF_NONE_MATCH, WWW_AUTHENTICATE},
+};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{AuthFlow, PushResult, SYNC_FILE_NAME, SyncBackend, decode_payload, encode_payload},
+    error::SyncError,
+    state::{SyncState, WebDavAuthType, WebDavCredentials},
+    types::{SyncBackendType, SyncPayload},
+};
+
+// ============================================================================
+// WebDAV Backend
+// ============================================================================
+
+/// Sync backend for Nextcloud, ownCloud and any other WebDAV server.
+///
+/// The server URL and remote folder come from `SyncConfig`, while the
+/// credentials live in `SyncState` alongside the OAuth tokens.
+pub struct WebDavBackend {
+    state: SyncState,
+    client: reqwest::Client,
+}
+
+impl WebDavBackend {
+    pub fn new(state: SyncState) -> Self {
+        Self {
+            state,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn credentials(&self) -> Result<WebDavCredentials, SyncError> {
+        self.state
+            .get_webdav_credentials()
+            .ok_or(SyncError::NotAuthenticated)
+    }
+
+    fn base_url(&self) -> Result<String, SyncError> {
+        let url = self.state.get_sync_config().webdav_url;
+        let url = url.trim().trim_end_matches('/');
+        if url.is_empty() {
+            return Err(SyncError::BadRequest(
+                "WebDAV server URL is not configured".to_string(),
+            ));
+        }
+        Ok(url.to_string())
+    }
+
+    /// Remote folder segments, e.g. `"Apps/Manatan"` -> `["Apps", "Manatan"]`
+    fn folder_segments(&self) -> Vec<String> {
+        self.state
+            .get_sync_config()
+            .webdav_path
+            .split('/')
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect()
+    }
+
+    fn folder_url(&self) -> Result<String, SyncError> {
+        let mut url = self.base_url()?;
+        for segment in self.folder_segments() {
+            url.push('/');
+            url.push_str(&segment);
+        }
+        Ok(url)
+    }
+
+    fn sync_file_url(&self) -> Result<String, SyncError> {
+        Ok(format!("{}/{SYNC_FILE_NAME}", self.folder_url()?))
+    }
+
+    /// Send a request, answering a Digest challenge if the server asks for one
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        headers: HeaderMap,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response, SyncError> {
+        let credentials = self.credentials()?;
+
+        let build = |authorization: Option<String>| -> RequestBuilder {
+            let mut request = self
+                .client
+                .request(method.clone(), url)
+                .headers(headers.clone());
+            if let Some(body) = &body {
+                request = request.body(body.clone());
+            }
+            match (&credentials.auth_type, authorization) {
+                (_, Some(value)) => request.header(AUTHORIZATION, value),
+                (WebDavAuthType::Basic, None) => {
+                    request.basic_auth(&credentials.username, Some(&credentials.password))
+                }
+                (WebDavAuthType::Digest, None) => request,
+            }
+        };
+
+        let response = build(None)
+            .send()
+            .await
+            .map_err(|e| SyncError::WebDavError(e.to_string()))?;
+
+        if response.status() != StatusCode::UNAUTHORIZED
+            || credentials.auth_type != WebDavAuthType::Digest
+        {
+            return check_auth(response);
+        }
+
+        let Some(challenge) = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(DigestChallenge::parse)
+        else {
+            return Err(SyncError::NotAuthenticated);
+        };
+
+        let uri = reqwest::Url::parse(url)
+            .map(|parsed| match parsed.query() {
+                Some(query) => format!("{}?{query}", parsed.path()),
+                None => parsed.path().to_string(),
+            })
+            .map_err(|e| SyncError::BadRequest(format!("Invalid WebDAV URL: {e}")))?;
+        let cnonce = uuid::Uuid::new_v4().simple().to_string();
+        let authorization = challenge.authorization(
+            method.as_str(),
+            &uri,
+            &credentials.username,
+            &credentials.password,
+            &cnonce,
+        );
+
+        let response = build(Some(authorization))
+            .send()
+            .await
+            .map_err(|e| SyncError::WebDavError(e.to_string()))?;
+        check_auth(response)
+    }
+
+    /// Fetch the current ETag of the sync file without downloading it
+    async fn head_etag(&self) -> Result<Option<String>, SyncError> {
+        let response = self
+            .send(Method::HEAD, &self.sync_file_url()?, HeaderMap::new(), None)
+            .await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(response_etag(&response)),
+            status => Err(SyncError::WebDavError(format!(
+                "HEAD {SYNC_FILE_NAME} failed with status {status}"
+            ))),
+        }
+    }
+
+    /// Current ETag of the sync file. Servers that send none get a hash of
+    /// the file's content instead, which costs a download.
+    async fn remote_etag(&self) -> Result<Option<String>, SyncError> {
+        if let Some(etag) = self.head_etag().await? {
+            return Ok(Some(etag));
+        }
+
+        let response = self
+            .send(Method::GET, &self.sync_file_url()?, HeaderMap::new(), None)
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let body = response
+                    .bytes()
+                    .await
+                    .map_err(|e| SyncError::WebDavError(e.to_string()))?;
+                Ok(Some(content_etag(&body)))
+            }
+            status => Err(SyncError::WebDavError(format!(
+                "GET {SYNC_FILE_NAME} failed with status {status}"
+            ))),
+        }
+    }
+
+    /// URL of an object; each `/`-separated part of the name is encoded separately
+    fn object_url(&self, name: &str) -> Result<String, SyncError> {
+        let mut url = self.folder_url()?;
+        for segment in name.split('/') {
+            url.push('/');
+            url.push_str(&urlencoding::encode(segment));
+        }
+        Ok(url)
+    }
+
+    /// Create every folder on the configured remote path plus `subfolders`
+    /// below it (MKCOL is not recursive)
+    async fn ensure_folder(&self, subfolders: &[&str]) -> Result<(), SyncError> {
+        let mkcol = Method::from_bytes(b"MKCOL").expect("valid WebDAV method");
+        let mut url = self.base_url()?;
+
+        let extra = subfolders
+            .iter()
+            .map(|segment| urlencoding::encode(segment).into_owned());
+        for segment in self.folder_segments().into_iter().chain(extra) {
+            url.push('/');
+            url.push_str(&segment);
+
+            let response = self
+                .send(mkcol.clone(), &url, HeaderMap::new(), None)
+                .await?;
+            match response.status() {
+                // 405 Method Not Allowed means the collection already exists
+                StatusCode::CREATED | StatusCode::METHOD_NOT_ALLOWED => {}
+                status if status.is_success() => {}
+                status => {
+                    return Err(SyncError::WebDavError(format!(
+                        "Failed to create folder {url}: status {status}"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the server is reachable and the credentials are accepted
+    pub async fn verify(&self) -> Result<(), SyncError> {
+        let propfind = Method::from_bytes(b"PROPFIND").expect("valid WebDAV method");
+        let mut headers = HeaderMap::new();
+        headers.insert("Depth", "0".parse().expect("valid header value"));
+
+        let response = self
+            .send(propfind, &self.base_url()?, headers, None)
+            .await?;
+        let status = response.status();
+        if status.is_success() || status == StatusCode::MULTI_STATUS {
+            Ok(())
+        } else {
+            Err(SyncError::WebDavError(format!(
+                "Server rejected PROPFIND with status {status}"
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl SyncBackend for WebDavBackend {
+    fn backend_type(&self) -> SyncBackendType {
+        SyncBackendType::WebDav
+    }
+
+    async fn pull(&self) -> Result<Option<(SyncPayload, String)>, SyncError> {
+        let url = self.sync_file_url()?;
+        info!("[WEBDAV] Downloading {}", url);
+
+        let response = self.send(Method::GET, &url, HeaderMap::new(), None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                info!("[WEBDAV] No sync file found");
+                return Ok(None);
+            }
+            status if !status.is_success() => {
+                return Err(SyncError::WebDavError(format!(
+                    "GET {SYNC_FILE_NAME} failed with status {status}"
+                )));
+            }
+            _ => {}
+        }
+
+        let etag = response_etag(&response);
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| SyncError::WebDavError(e.to_string()))?;
+        info!("[WEBDAV] Downloaded {} bytes", body.len());
+
+        // Some servers omit the ETag on GET; fall back to a content hash so that
+        // the next push still has something to compare against.
+        let etag = match etag {
+            Some(etag) => etag,
+            None => self
+                .head_etag()
+                .await?
+                .unwrap_or_else(|| content_etag(&body)),
+        };
+
+        let payload = decode_payload(&self.state, &body)?;
+        Ok(Some((payload, etag)))
+    }
+
+    async fn push(&self, data: &SyncPayload, etag: Option<&str>) -> Result<PushResult, SyncError> {
+        self.ensure_folder(&[]).await?;
+
+        let compressed = encode_payload(&self.state, data)?;
+        let url = self.sync_file_url()?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            "application/gzip".parse().expect("valid header value"),
+        );
+        match etag {
+            // A server without ETags rejects any `If-Match`, so the content is
+            // compared here instead. Another device may still write between
+            // this check and the PUT; such servers offer nothing better.
+            Some(expected) if is_content_etag(expected) => {
+                let current = self.remote_etag().await?;
+                if current.as_deref() != Some(expected) {
+                    let remote_etag = current.unwrap_or_default();
+                    warn!(
+                        "[WEBDAV] Content changed, expected {}, remote {}",
+                        expected, remote_etag
+                    );
+                    return Ok(PushResult::Conflict { remote_etag });
+                }
+            }
+            Some(expected) => {
+                let value = quote_etag(expected)
+                    .parse()
+                    .map_err(|_| SyncError::BadRequest(format!("Invalid etag: {expected}")))?;
+                headers.insert(IF_MATCH, value);
+            }
+            None => {
+                headers.insert(IF_NONE_MATCH, "*".parse().expect("valid header value"));
+            }
+        }
+
+        info!("[WEBDAV] Uploading {} bytes to {}", compressed.len(), url);
+        let uploaded_etag = content_etag(&compressed);
+        let response = self
+            .send(Method::PUT, &url, headers, Some(compressed))
+            .await?;
+
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED => {
+                let remote_etag = self.remote_etag().await?.unwrap_or_default();
+                warn!(
+                    "[WEBDAV] Precondition failed, expected {:?}, remote {}",
+                    etag, remote_etag
+                );
+                Ok(PushResult::Conflict { remote_etag })
+            }
+            status if status.is_success() => {
+                let new_etag = match response_etag(&response) {
+                    Some(etag) => etag,
+                    None => self.head_etag().await?.unwrap_or(uploaded_etag),
+                };
+                Ok(PushResult::Success { etag: new_etag })
+            }
+            status => Err(SyncError::WebDavError(format!(
+                "PUT {SYNC_FILE_NAME} failed with status {status}"
+            ))),
+        }
+    }
+
+    async fn is_authenticated(&self) -> bool {
+        self.state.get_webdav_credentials().is_some() && self.base_url().is_ok()
+    }
+
+    async fn get_user_info(&self) -> Result<Option<String>, SyncError> {
+        Ok(self
+            .state
+            .get_webdav_credentials()
+            .map(|credentials| credentials.username))
+    }
+
+    fn start_auth(&self, _redirect_uri: &str) -> Result<AuthFlow, SyncError> {
+        Err(SyncError::BadRequest(
+            "WebDAV does not use OAuth; connect with a username and password instead".to_string(),
+        ))
+    }
+
+    async fn complete_auth(&mut self, _code: &str, _redirect_uri: &str) -> Result<(), SyncError> {
+        Err(SyncError::BadRequest(
+            "WebDAV does not use OAuth; connect with a username and password instead".to_string(),
+        ))
+    }
+
+    async fn disconnect(&mut self) -> Result<(), SyncError> {
+        self.state.clear_webdav_credentials()?;
+        info!("Disconnected from WebDAV");
+        Ok(())
+    }
+
+    async fn refresh_token(&mut self) -> Result<(), SyncError> {
+        Ok(())
+    }
+
+    fn supports_objects(&self) -> bool {
+        true
+    }
+
+    async fn pull_object(&self, name: &str) -> Result<Option<Vec<u8>>, SyncError> {
+        let response = self
+            .send(Method::GET, &self.object_url(name)?, HeaderMap::new(), None)
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(
+                response
+                    .bytes()
+                    .await
+                    .map_err(|e| SyncError::WebDavError(e.to_string()))?
+                    .to_vec(),
+            )),
+            status => Err(SyncError::WebDavError(format!(
+                "GET {name} failed with status {status}"
+            ))),
+        }
+    }
+
+    async fn push_object(&self, name: &str, data: &[u8]) -> Result<(), SyncError> {
+        let mut subfolders: Vec<&str> = name.split('/').collect();
+        subfolders.pop();
+        self.ensure_folder(&subfolders).await?;
+
+        let response = self
+            .send(
+                Method::PUT,
+                &self.object_url(name)?,
+                HeaderMap::new(),
+                Some(data.to_vec()),
+            )
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(SyncError::WebDavError(format!(
+                "PUT {name} failed with status {status}"
+            ))),
+        }
+    }
+
+    async fn delete_object(&self, name: &str) -> Result<(), SyncError> {
+        let response = self
+            .send(
+                Method::DELETE,
+                &self.object_url(name)?,
+                HeaderMap::new(),
+                None,
+            )
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => Err(SyncError::WebDavError(format!(
+                "DELETE {name} failed with status {status}"
+            ))),
+        }
+    }
+}
+
+fn check_auth(response: Response) -> Result<Response, SyncError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(SyncError::NotAuthenticated),
+        _ => Ok(response),
+    }
+}
+
+/// Marks ETags made up from a content hash, for servers that send none
+const CONTENT_ETAG_PREFIX: &str = "sha256:";
+
+fn content_etag(body: &[u8]) -> String {
+    format!("{CONTENT_ETAG_PREFIX}{:x}", Sha256::digest(body))
+}
+
+fn is_content_etag(etag: &str) -> bool {
+    etag.starts_with(CONTENT_ETAG_PREFIX)
+}
+
+fn response_etag(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(unquote_etag)
+        .filter(|value| !value.is_empty())
+}
+
+/// Strip the weak prefix and quotes so ETags compare equal across requests
+fn unquote_etag(value: &str) -> String {
+    value
+        .trim()
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .to_string()
+}
+
+fn quote_etag(value: &str) -> String {
+    format!("\"{}\"", unquote_etag(value))
+}
+
+// ============================================================================
+// Digest Authentication (RFC 2617)
+// ============================================================================
+
+#[derive(Debug, Default)]
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Option<String>,
+    qop: Option<String>,
+}
+
+impl DigestChallenge {
+    fn parse(header: &str) -> Option<Self> {
+        let params = header.trim().strip_prefix("Digest")?;
+        let mut challenge = Self::default();
+
+        for (key, value) in split_auth_params(params) {
+            match key.to_ascii_lowercase().as_str() {
+                "realm" => challenge.realm = value,
+                "nonce" => challenge.nonce = value,
+                "opaque" => challenge.opaque = Some(value),
+                "algorithm" => challenge.algorithm = Some(value),
+                // Servers may offer "auth,auth-int"; only "auth" is supported
+                "qop" if value.split(',').any(|option| option.trim() == "auth") => {
+                    challenge.qop = Some("auth".to_string());
+                }
+                _ => {}
+            }
+        }
+
+        (!challenge.nonce.is_empty()).then_some(challenge)
+    }
+
+    fn authorization(
+        &self,
+        method: &str,
+        uri: &str,
+        username: &str,
+        password: &str,
+        cnonce: &str,
+    ) -> String {
+        const NONCE_COUNT: &str = "00000001";
+
+        let ha1 = md5_hex(&format!("{username}:{}:{password}", self.realm));
+        let ha2 = md5_hex(&format!("{method}:{uri}"));
+        let response = match &self.qop {
+            Some(qop) => md5_hex(&format!(
+                "{ha1}:{}:{NONCE_COUNT}:{cnonce}:{qop}:{ha2}",
+                self.nonce
+            )),
+            None => md5_hex(&format!("{ha1}:{}:{ha2}", self.nonce)),
+        };
+
+        let mut header = format!(
+            "Digest username=\"{username}\", realm=\"{}\", nonce=\"{}\", uri=\"{uri}\", response=\"{response}\"",
+            self.realm, self.nonce
+        );
+        if let Some(qop) = &self.qop {
+            header.push_str(&format!(
+                ", qop={qop}, nc={NONCE_COUNT}, cnonce=\"{cnonce}\""
+            ));
+        }
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(", opaque=\"{opaque}\""));
+        }
+        if let Some(algorithm) = &self.algorithm {
+            header.push_str(&format!(", algorithm={algorithm}"));
+        }
+        header
+    }
+}
+
+/// Split `key="value", key2=value2` pairs, honouring commas inside quotes
+fn split_auth_params(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for ch in params.chars().chain(std::iter::once(',')) {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            ',' if !in_quotes => {
+                if let Some((key, value)) = current.split_once('=') {
+                    pairs.push((
+                        key.trim().to_string(),
+                        value.trim().trim_matches('"').to_string(),
+                    ));
+                }
+                current.clear();
+            }
+            _ => current.push(ch),
+        }
+    }
+
+    pairs
+}
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", md5::Md5::digest(input.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        Router,
+        body::Bytes,
+        extract::{Request, State},
+        http::{HeaderMap as AxumHeaderMap, Method as AxumMethod, StatusCode as AxumStatus},
+        response::{IntoResponse, Response as AxumResponse},
+    };
+
+    use super::*;
+    use crate::{test_util::unique_temp_dir, types::LNProgress};
+
+    /// Path -> (body, etag)
+    type StoredFiles = HashMap<String, (Vec<u8>, String)>;
+
+    /// Minimal in-memory WebDAV server: GET/HEAD/PUT with ETags, MKCOL and PROPFIND
+    #[derive(Clone, Default)]
+    struct StandIn {
+        files: Arc<Mutex<StoredFiles>>,
+        folders: Arc<Mutex<Vec<String>>>,
+        /// Servers without ETag support send none and fail every `If-Match`
+        without_etags: bool,
+    }
+
+    const USERNAME: &str = "alice";
+    const PASSWORD: &str = "secret";
+
+    async fn handle(State(server): State<StandIn>, request: Request) -> AxumResponse {
+        let expected = format!(
+            "Basic {}",
+            base64::Engine::encode(
+                &base64::engine::general_purpose::STANDARD,
+                format!("{USERNAME}:{PASSWORD}")
+            )
+        );
+        let authorized = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            == Some(expected.as_str());
+        if !authorized {
+            return AxumStatus::UNAUTHORIZED.into_response();
+        }
+
+        let path = request.uri().path().to_string();
+        let method = request.method().clone();
+        let headers = request.headers().clone();
+        let body: Bytes = axum::body::to_bytes(request.into_body(), usize::MAX)
+            .await
+            .unwrap_or_default();
+
+        let mut files = server.files.lock().expect("lock");
+        match method.as_str() {
+            "PROPFIND" => AxumStatus::MULTI_STATUS.into_response(),
+            "MKCOL" => {
+                let mut folders = server.folders.lock().expect("lock");
+                if folders.contains(&path) {
+                    AxumStatus::METHOD_NOT_ALLOWED.into_response()
+                } else {
+                    folders.push(path);
+                    AxumStatus::CREATED.into_response()
+                }
+            }
+            _ if method == AxumMethod::GET || method == AxumMethod::HEAD => {
+                match files.get(&path) {
+                    Some((data, etag)) => {
+                        let mut response_headers = AxumHeaderMap::new();
+                        if !server.without_etags {
+                            response_headers
+                                .insert("etag", format!("\"{etag}\"").parse().expect("etag"));
+                        }
+                        (response_headers, data.clone()).into_response()
+                    }
+                    None => AxumStatus::NOT_FOUND.into_response(),
+                }
+            }
+            _ if method == AxumMethod::PUT => {
+                let current = files.get(&path).map(|(_, etag)| format!("\"{etag}\""));
+                let if_match = headers.get("if-match").and_then(|v| v.to_str().ok());
+                let if_none_match = headers.get("if-none-match").and_then(|v| v.to_str().ok());
+                let precondition_ok = match (if_match, if_none_match) {
+                    (Some(_), _) if server.without_etags => false,
+                    (Some(expected), _) => current.as_deref() == Some(expected),
+                    (None, Some("*")) => current.is_none(),
+                    _ => true,
+                };
+                if !precondition_ok {
+                    return AxumStatus::PRECONDITION_FAILED.into_response();
+                }
+
+                let etag = format!("{:x}", Sha256::digest(&body));
+                files.insert(path, (body.to_vec(), etag.clone()));
+                let mut response_headers = AxumHeaderMap::new();
+                if !server.without_etags {
+                    response_headers.insert("etag", format!("\"{etag}\"").parse().expect("etag"));
+                }
+                (AxumStatus::CREATED, response_headers).into_response()
+            }
+            _ => AxumStatus::METHOD_NOT_ALLOWED.into_response(),
+        }
+    }
+
+    async fn spawn_stand_in() -> String {
+        spawn_server(StandIn::default()).await
+    }
+
+    async fn spawn_server(server: StandIn) -> String {
+        let router = Router::new().fallback(handle).with_state(server);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let addr = listener.local_addr().expect("listener has an address");
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("server runs");
+        });
+        format!("http://{addr}/dav")
+    }
+
+    fn test_state(label: &str, url: &str, password: &str) -> SyncState {
+        let dir = unique_temp_dir(&format!("webdav-{label}"));
+        let state = SyncState::new(dir);
+
+        let mut config = state.get_sync_config();
+        config.backend = SyncBackendType::WebDav;
+        config.webdav_url = url.to_string();
+        config.webdav_path = "Apps/Manatan".to_string();
+        state.set_sync_config(&config).expect("config saved");
+        state
+            .set_webdav_credentials(&WebDavCredentials {
+                username: USERNAME.to_string(),
+                password: password.to_string(),
+                auth_type: WebDavAuthType::Basic,
+            })
+            .expect("credentials saved");
+        state
+    }
+
+    fn sample_payload(device_id: &str) -> SyncPayload {
+        let mut payload = SyncPayload::new(device_id.to_string());
+        payload.ln_progress.insert(
+            "book-1".to_string(),
+            LNProgress {
+                chapter_index: 3,
+                total_progress: 0.42,
+                ..Default::default()
+            },
+        );
+        payload
+    }
+
+    #[tokio::test]
+    async fn push_then_pull_round_trips_payload() {
+        let url = spawn_stand_in().await;
+        let backend = WebDavBackend::new(test_state("roundtrip", &url, PASSWORD));
+
+        assert!(backend.pull().await.expect("pull succeeds").is_none());
+
+        let pushed = backend
+            .push(&sample_payload("device-a"), None)
+            .await
+            .expect("push succeeds");
+        let PushResult::Success { etag } = pushed else {
+            panic!("expected a successful push");
+        };
+
+        let (payload, pulled_etag) = backend
+            .pull()
+            .await
+            .expect("pull succeeds")
+            .expect("remote payload exists");
+        assert_eq!(pulled_etag, etag);
+        assert_eq!(payload.device_id, "device-a");
+        assert_eq!(payload.ln_progress["book-1"].chapter_index, 3);
+    }
+
+    #[tokio::test]
+    async fn push_with_stale_etag_reports_conflict() {
+        let url = spawn_stand_in().await;
+        let backend = WebDavBackend::new(test_state("conflict", &url, PASSWORD));
+
+        backend
+            .push(&sample_payload("device-a"), None)
+            .await
+            .expect("first push succeeds");
+
+        let result = backend
+            .push(&sample_payload("device-b"), Some("stale"))
+            .await
+            .expect("push returns a result");
+        assert!(matches!(result, PushResult::Conflict { remote_etag } if !remote_etag.is_empty()));
+
+        // Creating the file again without an etag must not clobber it either
+        let result = backend
+            .push(&sample_payload("device-b"), None)
+            .await
+            .expect("push returns a result");
+        assert!(matches!(result, PushResult::Conflict { .. }));
+    }
+
+    #[tokio::test]
+    async fn servers_without_etags_compare_content_instead() {
+        let url = spawn_server(StandIn {
+            without_etags: true,
+            ..StandIn::default()
+        })
+        .await;
+        let device_a = WebDavBackend::new(test_state("no-etag-a", &url, PASSWORD));
+        let device_b = WebDavBackend::new(test_state("no-etag-b", &url, PASSWORD));
+
+        let PushResult::Success { etag } = device_a
+            .push(&sample_payload("device-a"), None)
+            .await
+            .expect("push succeeds")
+        else {
+            panic!("expected a successful push");
+        };
+        let (_, pulled_etag) = device_b
+            .pull()
+            .await
+            .expect("pull succeeds")
+            .expect("remote payload exists");
+        assert_eq!(pulled_etag, etag);
+        assert!(is_content_etag(&etag));
+
+        // Pushing on top of the version that was pulled goes through
+        let PushResult::Success { etag: newer } = device_b
+            .push(&sample_payload("device-b"), Some(&pulled_etag))
+            .await
+            .expect("push succeeds")
+        else {
+            panic!("expected a successful push");
+        };
+        assert_ne!(newer, etag);
+
+        // Device A still holds the old version
+        let result = device_a
+            .push(&sample_payload("device-a"), Some(&etag))
+            .await
+            .expect("push returns a result");
+        assert!(matches!(result, PushResult::Conflict { remote_etag } if remote_etag == newer));
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_not_authenticated() {
+        let url = spawn_stand_in().await;
+        let backend = WebDavBackend::new(test_state("badauth", &url, "wrong"));
+
+        assert!(matches!(
+            backend.verify().await,
+            Err(SyncError::NotAuthenticated)
+        ));
+    }
+
+    #[test]
+    fn digest_response_matches_rfc_2617_example() {
+        let challenge = DigestChallenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .expect("challenge parses");
+
+        let header = challenge.authorization(
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        );
+        assert!(header.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(header.contains("qop=auth, nc=00000001"));
+    }
+}
@@ -0,0 +1,200 @@
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    backend::{
+        AuthFlow, SyncBackend, active_backend_type, create_backend, ensure_backend,
+        google_drive::GoogleDriveBackend,
+    },
+    conflicts,
+    error::SyncError,
+    library::local_library,
+    migration,
+    routes::{
+        auth::handle_callback,
+        sync::{self, PushResponse},
+    },
+    scheduler::sync_library,
+    state::SyncState,
+    types::{PendingDeletion, SyncBackendType, SyncPayload},
+};
+
+// ============================================================================
+// Command Line Sync
+// ============================================================================
+//
+// The operations behind `manatan sync`, for headless installs and scripted
+// backups. They work on the same `SyncState` as the server, so the server
+// must not be running on the same data directory (sled locks the database).
+//
+// Google's device-code flow only allows limited-input OAuth clients, which
+// the embedded Drive client is not, so `login` uses the paste-the-code flow:
+// the user opens the consent URL anywhere and pastes back the URL they were
+// redirected to, or just its `code` parameter.
+
+/// Where the sync stands, as printed by `manatan sync status`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub backend: SyncBackendType,
+    pub connected: bool,
+    pub email: Option<String>,
+    pub device_id: String,
+    pub last_sync: Option<i64>,
+    pub pending_conflicts: usize,
+}
+
+pub async fn status(state: &SyncState) -> Result<SyncStatus, SyncError> {
+    let backend_type = active_backend_type(state);
+    let connected = match create_backend(state, &backend_type) {
+        Some(backend) => backend.is_authenticated().await,
+        None => false,
+    };
+
+    let mut email = None;
+    if connected {
+        match ensure_backend(state).await {
+            Ok(()) => {
+                let active = state.backend.read().await;
+                if let Some(backend) = active.as_ref() {
+                    email = backend.get_user_info().await.ok().flatten();
+                }
+            }
+            Err(e) => debug!("[CLI] {:?} backend not ready: {e}", backend_type),
+        }
+    }
+
+    Ok(SyncStatus {
+        backend: backend_type,
+        connected,
+        email,
+        device_id: state.get_device_id(),
+        last_sync: state.get_last_sync(),
+        pending_conflicts: conflicts::list(state)?.len(),
+    })
+}
+
+/// Start a Google Drive login; the returned URL is opened in any browser
+pub fn google_login_start(state: &SyncState, redirect_uri: &str) -> Result<AuthFlow, SyncError> {
+    state.set_auth_redirect_uri(redirect_uri)?;
+    GoogleDriveBackend::new(state.clone()).start_auth(redirect_uri)
+}
+
+/// Finish a Google Drive login with what the user pasted back: the full
+/// redirect URL, its query string, or the bare code
+pub async fn google_login_finish(state: &SyncState, pasted: &str) -> Result<(), SyncError> {
+    let (code, received_state) = parse_pasted_code(pasted)?;
+    handle_callback(state.clone(), code, received_state).await
+}
+
+/// Replace the local library with the remote payload
+pub async fn pull(state: &SyncState) -> Result<Option<SyncPayload>, SyncError> {
+    let Some((payload, _)) = sync::pull(state).await? else {
+        return Ok(None);
+    };
+    local_library(state)?.apply_payload(&payload).await?;
+    Ok(Some(payload))
+}
+
+/// Replace the remote payload with the local library. Without `force` this
+/// fails if the remote changed since the last sync.
+pub async fn push(state: &SyncState, force: bool) -> Result<PushResponse, SyncError> {
+    let payload = local_library(state)?
+        .export_payload(&state.get_device_id())
+        .await?;
+    let etag = match force {
+        true => None,
+        false => state.get_last_etag(),
+    };
+    sync::push(state, payload, etag.as_deref()).await
+}
+
+/// Merge the local library with the remote, as a background sync does.
+/// Deletions waiting for confirmation are returned instead of applied.
+pub async fn merge(state: &SyncState) -> Result<Vec<PendingDeletion>, SyncError> {
+    sync_library(state).await
+}
+
+/// The local library, or with `remote` the remote payload, for a backup
+pub async fn export(state: &SyncState, remote: bool) -> Result<Option<SyncPayload>, SyncError> {
+    if remote {
+        return Ok(sync::pull(state).await?.map(|(payload, _)| payload));
+    }
+    local_library(state)?
+        .export_payload(&state.get_device_id())
+        .await
+        .map(Some)
+}
+
+/// Write an exported payload into the local library, upgrading older schemas
+pub async fn import(state: &SyncState, bytes: &[u8]) -> Result<SyncPayload, SyncError> {
+    let payload = migration::parse_payload(bytes)?;
+    local_library(state)?.apply_payload(&payload).await?;
+    Ok(payload)
+}
+
+/// `code` and `state` from a pasted redirect URL or query string, or a bare code
+fn parse_pasted_code(pasted: &str) -> Result<(String, Option<String>), SyncError> {
+    let pasted = pasted.trim();
+    if !pasted.contains("code=") {
+        if pasted.is_empty() || pasted.contains(char::is_whitespace) {
+            return Err(SyncError::BadRequest(
+                "Paste the redirect URL or its code parameter".to_string(),
+            ));
+        }
+        return Ok((pasted.to_string(), None));
+    }
+
+    let query = pasted.split_once('?').map_or(pasted, |(_, query)| query);
+    let query = query.split('#').next().unwrap_or_default();
+    let mut code = None;
+    let mut received_state = None;
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = urlencoding::decode(value)
+            .map_err(|e| SyncError::BadRequest(format!("Invalid redirect URL: {e}")))?
+            .into_owned();
+        match key {
+            "code" => code = Some(value),
+            "state" => received_state = Some(value),
+            "error" => {
+                return Err(SyncError::OAuthError(format!(
+                    "Google declined the login: {value}"
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    match code.filter(|code| !code.is_empty()) {
+        Some(code) => Ok((code, received_state)),
+        None => Err(SyncError::BadRequest(
+            "The redirect URL has no code".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_code_from_what_was_pasted() {
+        let (code, received_state) = parse_pasted_code(
+            " http://127.0.0.1:4568/api/sync/auth/google/callback?state=abc&code=4%2F0Ab&scope=email ",
+        )
+        .expect("redirect URL parsed");
+        assert_eq!(code, "4/0Ab");
+        assert_eq!(received_state.as_deref(), Some("abc"));
+
+        let (code, received_state) = parse_pasted_code("4/0Ab").expect("bare code parsed");
+        assert_eq!(code, "4/0Ab");
+        assert_eq!(received_state, None);
+
+        assert!(matches!(
+            parse_pasted_code("http://127.0.0.1/?error=access_denied&code="),
+            Err(SyncError::OAuthError(_))
+        ));
+        assert!(parse_pasted_code("").is_err());
+    }
+}
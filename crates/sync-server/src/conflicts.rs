@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::{
+    error::SyncError,
+    state::SyncState,
+    types::{ConflictInfo, LNMetadata, LNProgress, SyncPayload, SyncSection},
+};
+
+// ============================================================================
+// Manual Conflict Resolution
+// ============================================================================
+//
+// With `ConflictResolution::Manual`, progress and metadata that conflict in a
+// merge are not decided automatically. Both sides are kept here and the
+// remote value stays in place until the user picks one; the choice is then
+// written on the next merge or push.
+
+const CONFLICT_PREFIX: &str = "conflict:";
+
+/// Which side of a held conflict to keep
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictChoice {
+    KeepLocal,
+    KeepRemote,
+    /// The side with more reading progress; for metadata the newer side
+    KeepFurthest,
+}
+
+/// A conflict waiting for the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingConflict {
+    /// `<section>:<entry id>`
+    pub id: String,
+    pub section: SyncSection,
+    pub entry_id: String,
+    pub local: Value,
+    pub remote: Value,
+    pub detected_at: i64,
+    /// Set once the user has chosen; applied on the next sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<ConflictChoice>,
+}
+
+/// Progress and metadata of both sides, taken before they are merged
+pub struct MergeSides {
+    local_progress: HashMap<String, LNProgress>,
+    remote_progress: HashMap<String, LNProgress>,
+    local_metadata: HashMap<String, LNMetadata>,
+    remote_metadata: HashMap<String, LNMetadata>,
+}
+
+impl MergeSides {
+    pub fn new(local: &SyncPayload, remote: &SyncPayload) -> Self {
+        Self {
+            local_progress: local.ln_progress.clone(),
+            remote_progress: remote.ln_progress.clone(),
+            local_metadata: local.ln_metadata.clone(),
+            remote_metadata: remote.ln_metadata.clone(),
+        }
+    }
+}
+
+/// Store the progress and metadata conflicts of a merge and put the remote
+/// value back in the merged payload. Conflicts the user already resolved are
+/// left to `apply_resolutions`.
+pub fn hold(
+    state: &SyncState,
+    sides: MergeSides,
+    conflicts: &mut [ConflictInfo],
+    merged: &mut SyncPayload,
+) -> Result<(), SyncError> {
+    for pending in held(state, sides, conflicts, merged)? {
+        state.db.insert(
+            format!("{CONFLICT_PREFIX}{}", pending.id).as_bytes(),
+            serde_json::to_vec(&pending)?,
+        )?;
+    }
+    state.db.flush()?;
+    Ok(())
+}
+
+/// Like `hold`, but stores nothing, for previewing a merge
+pub fn preview_hold(
+    state: &SyncState,
+    sides: MergeSides,
+    conflicts: &mut [ConflictInfo],
+    merged: &mut SyncPayload,
+) -> Result<(), SyncError> {
+    held(state, sides, conflicts, merged).map(drop)
+}
+
+fn held(
+    state: &SyncState,
+    sides: MergeSides,
+    conflicts: &mut [ConflictInfo],
+    merged: &mut SyncPayload,
+) -> Result<Vec<PendingConflict>, SyncError> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut held = Vec::new();
+    for conflict in conflicts.iter_mut() {
+        let id = &conflict.book_id;
+        let section = match conflict.field.as_str() {
+            "progress" => SyncSection::LnProgress,
+            "metadata" => SyncSection::LnMetadata,
+            _ => continue,
+        };
+        let key = conflict_id(section, id);
+        if get(state, &key)?.is_some_and(|held| held.resolution.is_some()) {
+            continue;
+        }
+
+        let (local, remote) = match section {
+            SyncSection::LnProgress => {
+                let (Some(local), Some(remote)) =
+                    (sides.local_progress.get(id), sides.remote_progress.get(id))
+                else {
+                    continue;
+                };
+                if let Some(entry) = merged.ln_progress.get_mut(id) {
+                    // Highlights are merged by id and never conflict
+                    *entry = LNProgress {
+                        highlights: std::mem::take(&mut entry.highlights),
+                        deleted_highlights: std::mem::take(&mut entry.deleted_highlights),
+                        ..remote.clone()
+                    };
+                }
+                (serde_json::to_value(local)?, serde_json::to_value(remote)?)
+            }
+            _ => {
+                let (Some(local), Some(remote)) =
+                    (sides.local_metadata.get(id), sides.remote_metadata.get(id))
+                else {
+                    continue;
+                };
+                if let Some(entry) = merged.ln_metadata.get_mut(id) {
+                    *entry = remote.clone();
+                }
+                (serde_json::to_value(local)?, serde_json::to_value(remote)?)
+            }
+        };
+
+        held.push(PendingConflict {
+            id: key,
+            section,
+            entry_id: id.clone(),
+            local,
+            remote,
+            detected_at: now,
+            resolution: None,
+        });
+        conflict.resolution = "pending".to_string();
+    }
+    Ok(held)
+}
+
+/// Write resolved conflicts into `payload`, returning them so they can be
+/// cleared once the payload is pushed
+pub fn apply_resolutions(
+    state: &SyncState,
+    payload: &mut SyncPayload,
+) -> Result<Vec<PendingConflict>, SyncError> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut applied = Vec::new();
+    for conflict in list(state)? {
+        let Some(choice) = conflict.resolution else {
+            continue;
+        };
+        match conflict.section {
+            SyncSection::LnProgress => {
+                let local: LNProgress = serde_json::from_value(conflict.local.clone())?;
+                let remote: LNProgress = serde_json::from_value(conflict.remote.clone())?;
+                let keep_local = match choice {
+                    ConflictChoice::KeepLocal => true,
+                    ConflictChoice::KeepRemote => false,
+                    ConflictChoice::KeepFurthest => !remote.is_further_than(&local),
+                };
+                let mut chosen = if keep_local { local } else { remote };
+                // Newer than either side, so other devices take it
+                chosen.last_modified = Some(now);
+                if let Some(entry) = payload.ln_progress.get_mut(&conflict.entry_id) {
+                    chosen.highlights = std::mem::take(&mut entry.highlights);
+                    chosen.deleted_highlights = std::mem::take(&mut entry.deleted_highlights);
+                }
+                payload
+                    .ln_progress
+                    .insert(conflict.entry_id.clone(), chosen);
+            }
+            SyncSection::LnMetadata => {
+                let local: LNMetadata = serde_json::from_value(conflict.local.clone())?;
+                let remote: LNMetadata = serde_json::from_value(conflict.remote.clone())?;
+                let keep_local = match choice {
+                    ConflictChoice::KeepLocal => true,
+                    ConflictChoice::KeepRemote => false,
+                    ConflictChoice::KeepFurthest => local.last_modified >= remote.last_modified,
+                };
+                let mut chosen = if keep_local { local } else { remote };
+                chosen.sync_version = Some(chosen.sync_version.unwrap_or_default() + 1);
+                chosen.last_modified = Some(now);
+                payload
+                    .ln_metadata
+                    .insert(conflict.entry_id.clone(), chosen);
+            }
+            _ => {}
+        }
+        info!("[CONFLICT] Applied {:?} to {}", choice, conflict.id);
+        applied.push(conflict);
+    }
+    Ok(applied)
+}
+
+/// Drop conflicts whose resolution has been pushed
+pub fn clear(state: &SyncState, conflicts: &[PendingConflict]) -> Result<(), SyncError> {
+    for conflict in conflicts {
+        state
+            .db
+            .remove(format!("{CONFLICT_PREFIX}{}", conflict.id).as_bytes())?;
+    }
+    state.db.flush()?;
+    Ok(())
+}
+
+/// All held conflicts, oldest first
+pub fn list(state: &SyncState) -> Result<Vec<PendingConflict>, SyncError> {
+    let mut conflicts = Vec::new();
+    for entry in state.db.scan_prefix(CONFLICT_PREFIX.as_bytes()) {
+        let (_, bytes) = entry?;
+        conflicts.push(serde_json::from_slice::<PendingConflict>(&bytes)?);
+    }
+    conflicts.sort_by_key(|conflict| conflict.detected_at);
+    Ok(conflicts)
+}
+
+/// Record the user's choice for a held conflict
+pub fn resolve(
+    state: &SyncState,
+    id: &str,
+    choice: ConflictChoice,
+) -> Result<PendingConflict, SyncError> {
+    let mut conflict =
+        get(state, id)?.ok_or_else(|| SyncError::ConflictNotFound(id.to_string()))?;
+    conflict.resolution = Some(choice);
+    state.db.insert(
+        format!("{CONFLICT_PREFIX}{id}").as_bytes(),
+        serde_json::to_vec(&conflict)?,
+    )?;
+    state.db.flush()?;
+    Ok(conflict)
+}
+
+fn get(state: &SyncState, id: &str) -> Result<Option<PendingConflict>, SyncError> {
+    match state.db.get(format!("{CONFLICT_PREFIX}{id}").as_bytes())? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+fn conflict_id(section: SyncSection, entry_id: &str) -> String {
+    let section = serde_json::to_value(section)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default();
+    format!("{section}:{entry_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes::sync::merge,
+        test_util::{local_folder_state, unique_temp_dir},
+        types::{ConflictResolution, MergeRequest},
+    };
+
+    fn request(state: &SyncState, total_progress: f64) -> MergeRequest {
+        let mut payload = SyncPayload::new(state.get_device_id());
+        payload.ln_progress.insert(
+            "book".to_string(),
+            LNProgress {
+                total_progress,
+                device_id: Some(state.get_device_id()),
+                last_modified: Some(chrono::Utc::now().timestamp_millis()),
+                ..LNProgress::default()
+            },
+        );
+        MergeRequest {
+            payload,
+            config: None,
+            deletion_decisions: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn held_conflict_is_pushed_once_resolved() {
+        let root = unique_temp_dir("conflicts");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Manual);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Manual);
+
+        merge(device_a.clone(), request(&device_a, 0.3))
+            .await
+            .expect("first merge succeeds");
+
+        // B re-read the book from the start; the remote keeps A's progress
+        let held = merge(device_b.clone(), request(&device_b, 0.1))
+            .await
+            .expect("second merge succeeds");
+        assert_eq!(held.payload.ln_progress["book"].total_progress, 0.3);
+        assert_eq!(held.conflicts[0].resolution, "pending");
+        let pending = list(&device_b).expect("conflicts listed");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "lnProgress:book");
+
+        resolve(&device_b, &pending[0].id, ConflictChoice::KeepLocal).expect("conflict resolved");
+        let resolved = merge(device_b.clone(), request(&device_b, 0.1))
+            .await
+            .expect("third merge succeeds");
+        assert_eq!(resolved.payload.ln_progress["book"].total_progress, 0.1);
+        assert!(list(&device_b).expect("conflicts listed").is_empty());
+        assert!(matches!(
+            resolve(&device_b, "lnProgress:book", ConflictChoice::KeepRemote),
+            Err(SyncError::ConflictNotFound(_))
+        ));
+    }
+}
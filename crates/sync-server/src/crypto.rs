@@ -0,0 +1,251 @@
+use std::sync::{Arc, Mutex};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore},
+};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{error::SyncError, state::SyncState};
+
+// ============================================================================
+// Envelope Format
+// ============================================================================
+//
+// MAGIC (8) | salt (16) | key check (16) | nonce (24) | ciphertext + tag
+//
+// The salt travels with the data so any device with the passphrase can derive
+// the key. The key check lets us tell a wrong passphrase apart from corrupted
+// data before touching the ciphertext.
+
+const MAGIC: &[u8; 8] = b"MNTNENC1";
+const SALT_LEN: usize = 16;
+const KEY_CHECK_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + KEY_CHECK_LEN + NONCE_LEN;
+
+/// Key derived from the passphrase for one salt
+#[derive(Clone)]
+pub(crate) struct DerivedKey {
+    passphrase_digest: [u8; 32],
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+}
+
+/// Single-entry cache of the last derived key.
+///
+/// Argon2 is deliberately slow, and a delta sync seals one object per changed
+/// book. All devices converge on the salt of whatever they last opened, so one
+/// entry is enough in practice.
+pub(crate) type KeyCache = Arc<Mutex<Option<DerivedKey>>>;
+
+/// Whether `bytes` were produced by `seal`
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypt `plaintext` if a passphrase is configured, otherwise pass it through
+pub fn seal(state: &SyncState, plaintext: &[u8]) -> Result<Vec<u8>, SyncError> {
+    seal_with(
+        state,
+        state.get_encryption_passphrase().as_deref(),
+        plaintext,
+    )
+}
+
+/// Decrypt data written by `seal`. Plain data is returned unchanged, so
+/// enabling encryption does not lock out the existing remote copy.
+pub fn open(state: &SyncState, bytes: &[u8]) -> Result<Vec<u8>, SyncError> {
+    open_with(state, state.get_encryption_passphrase().as_deref(), bytes)
+}
+
+/// `seal` with an explicit passphrase instead of the stored one
+pub fn seal_with(
+    state: &SyncState,
+    passphrase: Option<&str>,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, SyncError> {
+    let Some(passphrase) = passphrase else {
+        return Ok(plaintext.to_vec());
+    };
+    let derived = key_for(state, passphrase, None)?;
+
+    let cipher = XChaCha20Poly1305::new(&derived.key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| SyncError::EncryptionError("Encryption failed".to_string()))?;
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&derived.salt);
+    sealed.extend_from_slice(&key_check(&derived.key));
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// `open` with an explicit passphrase instead of the stored one
+pub fn open_with(
+    state: &SyncState,
+    passphrase: Option<&str>,
+    bytes: &[u8],
+) -> Result<Vec<u8>, SyncError> {
+    if !is_encrypted(bytes) {
+        return Ok(bytes.to_vec());
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(SyncError::EncryptionError(
+            "Encrypted data is truncated".to_string(),
+        ));
+    }
+    let Some(passphrase) = passphrase else {
+        return Err(SyncError::PassphraseRequired);
+    };
+
+    let (salt, rest) = bytes[MAGIC.len()..].split_at(SALT_LEN);
+    let (check, rest) = rest.split_at(KEY_CHECK_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let salt: [u8; SALT_LEN] = salt.try_into().expect("salt slice has SALT_LEN bytes");
+    let derived = key_for(state, passphrase, Some(salt))?;
+    if key_check(&derived.key) != check {
+        return Err(SyncError::WrongPassphrase);
+    }
+
+    XChaCha20Poly1305::new(&derived.key.into())
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SyncError::EncryptionError("Encrypted data failed authentication".to_string()))
+}
+
+/// Look up or derive the key for `passphrase`. With `salt` None any cached
+/// salt is reused, otherwise a fresh one is generated.
+fn key_for(
+    state: &SyncState,
+    passphrase: &str,
+    salt: Option<[u8; SALT_LEN]>,
+) -> Result<DerivedKey, SyncError> {
+    let passphrase_digest: [u8; 32] = Sha256::digest(passphrase.as_bytes()).into();
+    let mut cache = state
+        .key_cache
+        .lock()
+        .map_err(|_| SyncError::EncryptionError("Key cache poisoned".to_string()))?;
+
+    if let Some(cached) = cache.as_ref()
+        && cached.passphrase_digest == passphrase_digest
+        && salt.is_none_or(|salt| salt == cached.salt)
+    {
+        return Ok(cached.clone());
+    }
+
+    let salt = salt.unwrap_or_else(|| {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    });
+    debug!("[CRYPTO] Deriving encryption key");
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| SyncError::EncryptionError(e.to_string()))?;
+
+    let derived = DerivedKey {
+        passphrase_digest,
+        salt,
+        key,
+    };
+    *cache = Some(derived.clone());
+    Ok(derived)
+}
+
+fn key_check(key: &[u8; 32]) -> [u8; KEY_CHECK_LEN] {
+    let digest = Sha256::new()
+        .chain_update(b"manatan-sync-key-check")
+        .chain_update(key)
+        .finalize();
+    let mut check = [0u8; KEY_CHECK_LEN];
+    check.copy_from_slice(&digest[..KEY_CHECK_LEN]);
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::unique_temp_dir;
+
+    fn test_state(label: &str) -> SyncState {
+        SyncState::new(unique_temp_dir(&format!("crypto-{label}")))
+    }
+
+    #[test]
+    fn seal_and_open_round_trip_across_devices() {
+        let device_a = test_state("a");
+        let device_b = test_state("b");
+        for state in [&device_a, &device_b] {
+            state
+                .set_encryption_passphrase("correct horse")
+                .expect("passphrase saved");
+        }
+
+        let sealed = seal(&device_a, b"reading history").expect("seal succeeds");
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(7).any(|window| window == b"reading"));
+
+        assert_eq!(
+            open(&device_b, &sealed).expect("open succeeds"),
+            b"reading history"
+        );
+    }
+
+    #[test]
+    fn wrong_or_missing_passphrase_is_reported() {
+        let writer = test_state("writer");
+        writer
+            .set_encryption_passphrase("correct horse")
+            .expect("passphrase saved");
+        let sealed = seal(&writer, b"data").expect("seal succeeds");
+
+        let reader = test_state("reader");
+        assert!(matches!(
+            open(&reader, &sealed),
+            Err(SyncError::PassphraseRequired)
+        ));
+
+        reader
+            .set_encryption_passphrase("battery staple")
+            .expect("passphrase saved");
+        assert!(matches!(
+            open(&reader, &sealed),
+            Err(SyncError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let state = test_state("tamper");
+        state
+            .set_encryption_passphrase("correct horse")
+            .expect("passphrase saved");
+        let mut sealed = seal(&state, b"data").expect("seal succeeds");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+
+        assert!(matches!(
+            open(&state, &sealed),
+            Err(SyncError::EncryptionError(_))
+        ));
+    }
+
+    #[test]
+    fn plain_data_passes_through() {
+        let state = test_state("plain");
+        assert_eq!(seal(&state, b"data").expect("seal succeeds"), b"data");
+
+        state
+            .set_encryption_passphrase("correct horse")
+            .expect("passphrase saved");
+        assert_eq!(open(&state, b"data").expect("open succeeds"), b"data");
+    }
+}
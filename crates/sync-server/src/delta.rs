@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet};
+
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    backend::{SyncBackend, compress, decompress},
+    crypto,
+    error::SyncError,
+    state::SyncState,
+    types::{FileReference, FileType, PendingDeletion, SyncPayload, SyncSection, Tombstones},
+};
+
+/// Remote folder (relative to the sync file) holding the per-book objects
+pub const OBJECT_FOLDER: &str = "books";
+
+/// Uncompressed JSON of a single book's content or file, keyed by manifest key
+pub type BookObjects = HashMap<String, Vec<u8>>;
+
+/// Manifest key for a book object, e.g. `content:<bookId>`
+pub fn manifest_key(book_id: &str, file_type: &FileType) -> String {
+    format!("{}:{book_id}", file_type_name(file_type))
+}
+
+/// Remote object name for a manifest entry, relative to the sync folder.
+///
+/// The hash is part of the name so an object referenced by a manifest is never
+/// overwritten: a push that loses the race on the sync file leaves the winning
+/// device's objects intact.
+pub fn object_name(reference: &FileReference) -> String {
+    let book_id = urlencoding::encode(&reference.book_id).replace('.', "%2E");
+    let hash = reference
+        .file_hash
+        .get(..16)
+        .unwrap_or(&reference.file_hash);
+    format!(
+        "{OBJECT_FOLDER}/{book_id}.{}.{hash}.json.gz",
+        file_type_name(&reference.file_type)
+    )
+}
+
+fn file_type_name(file_type: &FileType) -> &'static str {
+    match file_type {
+        FileType::Epub => "epub",
+        FileType::Content => "content",
+    }
+}
+
+/// Move inline `ln_content`/`ln_files` out of the payload, recording each one
+/// in `file_manifest`.
+///
+/// An existing manifest entry with the same hash keeps its timestamp, so data
+/// that did not change is never considered newer than the remote copy.
+pub fn extract_objects(payload: &mut SyncPayload) -> Result<BookObjects, SyncError> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut objects = BookObjects::new();
+
+    let content = std::mem::take(&mut payload.ln_content)
+        .into_iter()
+        .map(|(book_id, book)| Ok((book_id, FileType::Content, serde_json::to_vec(&book)?)));
+    let files = std::mem::take(&mut payload.ln_files)
+        .into_iter()
+        .map(|(book_id, data)| Ok((book_id, FileType::Epub, serde_json::to_vec(&data)?)));
+
+    for entry in content.chain(files) {
+        let (book_id, file_type, bytes) = entry.map_err(SyncError::SerializationError)?;
+        let key = manifest_key(&book_id, &file_type);
+        let file_hash = format!("{:x}", Sha256::digest(&bytes));
+
+        let last_modified = match payload.file_manifest.get(&key) {
+            Some(existing) if existing.file_hash == file_hash => existing.last_modified,
+            _ => now,
+        };
+        payload.file_manifest.insert(
+            key.clone(),
+            FileReference {
+                book_id,
+                file_type,
+                file_hash,
+                file_size: bytes.len() as u64,
+                last_modified,
+                drive_file_id: None,
+            },
+        );
+        objects.insert(key, bytes);
+    }
+
+    Ok(objects)
+}
+
+/// Put downloaded objects back into `ln_content`/`ln_files`
+pub fn restore_objects(payload: &mut SyncPayload, objects: BookObjects) -> Result<(), SyncError> {
+    for (key, bytes) in objects {
+        let Some(reference) = payload.file_manifest.get(&key) else {
+            continue;
+        };
+        match reference.file_type {
+            FileType::Content => {
+                payload
+                    .ln_content
+                    .insert(reference.book_id.clone(), serde_json::from_slice(&bytes)?);
+            }
+            FileType::Epub => {
+                payload
+                    .ln_files
+                    .insert(reference.book_id.clone(), serde_json::from_slice(&bytes)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove manifest entries whose book content or file was deleted.
+///
+/// Deletions still waiting for confirmation are left alone, as are entries
+/// changed after their tombstone (the book was added again).
+pub fn drop_deleted(
+    manifest: &mut HashMap<String, FileReference>,
+    tombstones: &Tombstones,
+    pending: &[PendingDeletion],
+) {
+    for (section, file_type, section_tombstones) in [
+        (
+            SyncSection::LnContent,
+            FileType::Content,
+            &tombstones.ln_content,
+        ),
+        (SyncSection::LnFiles, FileType::Epub, &tombstones.ln_files),
+    ] {
+        for (book_id, tombstone) in section_tombstones {
+            let is_pending = pending
+                .iter()
+                .any(|deletion| deletion.section == section && &deletion.id == book_id);
+            let key = manifest_key(book_id, &file_type);
+            if !is_pending
+                && manifest
+                    .get(&key)
+                    .is_some_and(|reference| reference.last_modified <= tombstone.deleted_at)
+            {
+                manifest.remove(&key);
+            }
+        }
+    }
+}
+
+/// What a merge has to transfer to bring both sides up to date
+#[derive(Debug, Default)]
+pub struct DeltaPlan {
+    /// Keys whose local data is newer and must be written to the backend
+    pub upload: Vec<String>,
+    /// Keys whose remote copy is newer than (or missing from) the frontend
+    pub download: Vec<String>,
+    /// Keys the frontend has newer data for but did not include in the request
+    pub request: Vec<String>,
+    /// Manifest describing the objects on the backend after the transfer
+    pub manifest: HashMap<String, FileReference>,
+}
+
+/// Compare the local and remote manifests.
+///
+/// `local_data` lists the keys the frontend sent data for; entries without
+/// data only tell us what version the frontend already has. Ties go to the
+/// local side, matching `merge_simple_maps`.
+pub fn plan(
+    local: &HashMap<String, FileReference>,
+    local_data: &HashSet<String>,
+    remote: &HashMap<String, FileReference>,
+) -> DeltaPlan {
+    let mut plan = DeltaPlan::default();
+
+    for (key, local_ref) in local {
+        let has_data = local_data.contains(key);
+        match remote.get(key) {
+            Some(remote_ref) if remote_ref.file_hash == local_ref.file_hash => {
+                plan.manifest.insert(key.clone(), remote_ref.clone());
+            }
+            Some(remote_ref) if remote_ref.last_modified > local_ref.last_modified => {
+                plan.download.push(key.clone());
+                plan.manifest.insert(key.clone(), remote_ref.clone());
+            }
+            remote_ref => {
+                if has_data {
+                    plan.upload.push(key.clone());
+                    plan.manifest.insert(key.clone(), local_ref.clone());
+                } else {
+                    plan.request.push(key.clone());
+                    if let Some(remote_ref) = remote_ref {
+                        plan.manifest.insert(key.clone(), remote_ref.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    for (key, remote_ref) in remote {
+        if !local.contains_key(key) {
+            plan.download.push(key.clone());
+            plan.manifest.insert(key.clone(), remote_ref.clone());
+        }
+    }
+
+    plan.upload.sort();
+    plan.download.sort();
+    plan.request.sort();
+    plan
+}
+
+/// Upload and download the objects a merge needs.
+///
+/// Objects are compressed and, if a passphrase is set, encrypted just like the
+/// sync file. `remote_objects` holds content found inline in a payload written before
+/// delta sync; it is moved into objects so the next sync can skip it. Returns
+/// the plan (with `download` trimmed to what was actually found) and the
+/// downloaded objects.
+pub async fn transfer(
+    state: &SyncState,
+    backend: &dyn SyncBackend,
+    local_manifest: &HashMap<String, FileReference>,
+    mut local_objects: BookObjects,
+    remote_manifest: &HashMap<String, FileReference>,
+    mut remote_objects: BookObjects,
+) -> Result<(DeltaPlan, BookObjects), SyncError> {
+    if !remote_objects.is_empty() {
+        info!(
+            "[DELTA] Moving {} inline entries of the remote payload into objects",
+            remote_objects.len()
+        );
+    }
+    for (key, bytes) in &remote_objects {
+        if let Some(reference) = remote_manifest.get(key) {
+            backend
+                .push_object(
+                    &object_name(reference),
+                    &crypto::seal(state, &compress(bytes)?)?,
+                )
+                .await?;
+        }
+    }
+
+    let local_keys = local_objects.keys().cloned().collect();
+    let mut plan = plan(local_manifest, &local_keys, remote_manifest);
+    info!(
+        "[DELTA] {} to upload, {} to download, {} unchanged, {} requested from frontend",
+        plan.upload.len(),
+        plan.download.len(),
+        plan.manifest.len() - plan.upload.len() - plan.download.len(),
+        plan.request.len()
+    );
+
+    for key in &plan.upload {
+        if let (Some(reference), Some(bytes)) = (plan.manifest.get(key), local_objects.remove(key))
+        {
+            backend
+                .push_object(
+                    &object_name(reference),
+                    &crypto::seal(state, &compress(&bytes)?)?,
+                )
+                .await?;
+        }
+    }
+
+    let mut downloaded = BookObjects::new();
+    for key in &plan.download {
+        if let Some(bytes) = remote_objects.remove(key) {
+            downloaded.insert(key.clone(), bytes);
+            continue;
+        }
+        let Some(reference) = plan.manifest.get(key) else {
+            continue;
+        };
+        match backend.pull_object(&object_name(reference)).await? {
+            Some(bytes) => {
+                downloaded.insert(key.clone(), decompress(&crypto::open(state, &bytes)?)?);
+            }
+            None => warn!("[DELTA] Object for {} is missing on the backend", key),
+        }
+    }
+    plan.download.retain(|key| downloaded.contains_key(key));
+
+    Ok((plan, downloaded))
+}
+
+/// Delete objects that `current` no longer references.
+/// Only call this after the manifest in `current` has been pushed.
+pub async fn remove_replaced(
+    backend: &dyn SyncBackend,
+    previous: &HashMap<String, FileReference>,
+    current: &HashMap<String, FileReference>,
+) {
+    for (key, old) in previous {
+        let replaced = current
+            .get(key)
+            .is_none_or(|new| new.file_hash != old.file_hash);
+        if replaced && let Err(e) = backend.delete_object(&object_name(old)).await {
+            warn!(
+                "[DELTA] Failed to delete replaced object for {}: {}",
+                key, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LNParsedBook;
+
+    fn reference(book_id: &str, hash: &str, last_modified: i64) -> FileReference {
+        FileReference {
+            book_id: book_id.to_string(),
+            file_type: FileType::Content,
+            file_hash: hash.to_string(),
+            file_size: 1,
+            last_modified,
+            drive_file_id: None,
+        }
+    }
+
+    #[test]
+    fn extract_and_restore_round_trip_content() {
+        let mut payload = SyncPayload::new("device".to_string());
+        payload.ln_content.insert(
+            "book".to_string(),
+            LNParsedBook {
+                chapters: vec!["<p>本文</p>".to_string()],
+                image_blobs: HashMap::new(),
+                chapter_filenames: vec!["ch1.xhtml".to_string()],
+                css: None,
+            },
+        );
+        payload
+            .ln_files
+            .insert("book".to_string(), "ZXB1Yg==".to_string());
+
+        let objects = extract_objects(&mut payload).expect("objects extract");
+        assert!(payload.ln_content.is_empty() && payload.ln_files.is_empty());
+        assert_eq!(objects.len(), 2);
+        assert!(payload.file_manifest.contains_key("content:book"));
+        assert!(payload.file_manifest.contains_key("epub:book"));
+
+        restore_objects(&mut payload, objects).expect("objects restore");
+        assert!(payload.ln_content.contains_key("book"));
+        assert_eq!(payload.ln_files["book"], "ZXB1Yg==");
+    }
+
+    #[test]
+    fn unchanged_data_keeps_its_manifest_timestamp() {
+        let mut payload = SyncPayload::new("device".to_string());
+        payload
+            .ln_files
+            .insert("book".to_string(), "ZXB1Yg==".to_string());
+        extract_objects(&mut payload).expect("objects extract");
+        let first = payload.file_manifest["epub:book"].clone();
+
+        payload
+            .file_manifest
+            .get_mut("epub:book")
+            .expect("manifest entry")
+            .last_modified = 1;
+        payload
+            .ln_files
+            .insert("book".to_string(), "ZXB1Yg==".to_string());
+        extract_objects(&mut payload).expect("objects extract");
+
+        assert_eq!(
+            payload.file_manifest["epub:book"].file_hash,
+            first.file_hash
+        );
+        assert_eq!(payload.file_manifest["epub:book"].last_modified, 1);
+    }
+
+    #[test]
+    fn plan_transfers_only_changed_books() {
+        let local = HashMap::from([
+            ("content:same".to_string(), reference("same", "aaa", 5)),
+            ("content:edited".to_string(), reference("edited", "new", 9)),
+            ("content:stale".to_string(), reference("stale", "old", 1)),
+            ("content:unsent".to_string(), reference("unsent", "mine", 9)),
+        ]);
+        let local_data = HashSet::from(["content:edited".to_string()]);
+        let remote = HashMap::from([
+            ("content:same".to_string(), reference("same", "aaa", 5)),
+            ("content:edited".to_string(), reference("edited", "prev", 4)),
+            ("content:stale".to_string(), reference("stale", "fresh", 8)),
+            (
+                "content:unsent".to_string(),
+                reference("unsent", "theirs", 2),
+            ),
+            ("content:other".to_string(), reference("other", "bbb", 3)),
+        ]);
+
+        let plan = plan(&local, &local_data, &remote);
+
+        assert_eq!(plan.upload, vec!["content:edited"]);
+        assert_eq!(plan.download, vec!["content:other", "content:stale"]);
+        assert_eq!(plan.request, vec!["content:unsent"]);
+        assert_eq!(plan.manifest["content:edited"].file_hash, "new");
+        assert_eq!(plan.manifest["content:stale"].file_hash, "fresh");
+        // Nothing is on the backend for the unsent version yet
+        assert_eq!(plan.manifest["content:unsent"].file_hash, "theirs");
+        assert_eq!(plan.manifest.len(), 5);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    delta::manifest_key,
+    types::{ConflictInfo, FileType, PendingDeletion, SyncPayload},
+};
+
+/// Ids added, removed or changed in one payload section
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionDiff {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<String>,
+    /// Changed id → the fields that differ
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+impl SectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Entry-level differences between two payloads, going from `before` to `after`
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadDiff {
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub ln_progress: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub ln_metadata: SectionDiff,
+    /// Book content and files, by manifest key
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub files: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub ln_categories: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub manga: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub anime: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub manga_categories: SectionDiff,
+    #[serde(default, skip_serializing_if = "SectionDiff::is_empty")]
+    pub anime_categories: SectionDiff,
+}
+
+impl PayloadDiff {
+    pub fn is_empty(&self) -> bool {
+        self.ln_progress.is_empty()
+            && self.ln_metadata.is_empty()
+            && self.files.is_empty()
+            && self.ln_categories.is_empty()
+            && self.manga.is_empty()
+            && self.anime.is_empty()
+            && self.manga_categories.is_empty()
+            && self.anime_categories.is_empty()
+    }
+}
+
+/// What a merge would do, returned by `POST /merge?dry_run=true`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MergePreview {
+    /// Changes this device would apply, from its payload to the merged one
+    pub local: PayloadDiff,
+    /// Changes the push would make, from the remote payload to the merged one
+    pub remote: PayloadDiff,
+    #[serde(default)]
+    pub conflicts: Vec<ConflictInfo>,
+    #[serde(default)]
+    pub pending_deletions: Vec<PendingDeletion>,
+}
+
+/// Compare two payloads entry by entry
+pub fn diff_payloads(before: &SyncPayload, after: &SyncPayload) -> PayloadDiff {
+    PayloadDiff {
+        ln_progress: diff_maps(&before.ln_progress, &after.ln_progress),
+        ln_metadata: diff_maps(&before.ln_metadata, &after.ln_metadata),
+        files: diff_maps(&file_hashes(before), &file_hashes(after)),
+        ln_categories: diff_maps(&before.ln_categories, &after.ln_categories),
+        manga: diff_maps(&before.manga, &after.manga),
+        anime: diff_maps(&before.anime, &after.anime),
+        manga_categories: diff_maps(&before.manga_categories, &after.manga_categories),
+        anime_categories: diff_maps(&before.anime_categories, &after.anime_categories),
+    }
+}
+
+/// Content hashes by manifest key. Inline content that has not been moved
+/// into the manifest yet is compared by its serialized form.
+fn file_hashes(payload: &SyncPayload) -> HashMap<String, String> {
+    let mut hashes: HashMap<String, String> = payload
+        .file_manifest
+        .iter()
+        .map(|(key, reference)| (key.clone(), reference.file_hash.clone()))
+        .collect();
+    for (book_id, book) in &payload.ln_content {
+        hashes
+            .entry(manifest_key(book_id, &FileType::Content))
+            .or_insert_with(|| serde_json::to_string(book).unwrap_or_default());
+    }
+    for (book_id, file) in &payload.ln_files {
+        hashes
+            .entry(manifest_key(book_id, &FileType::Epub))
+            .or_insert_with(|| file.clone());
+    }
+    hashes
+}
+
+/// Entries are compared by their JSON form, since not every payload type
+/// implements `PartialEq`
+fn diff_maps<V: Serialize>(before: &HashMap<String, V>, after: &HashMap<String, V>) -> SectionDiff {
+    let ids: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let mut diff = SectionDiff::default();
+    for id in ids {
+        match (before.get(id), after.get(id)) {
+            (None, Some(_)) => diff.added.push(id.clone()),
+            (Some(_), None) => diff.removed.push(id.clone()),
+            (Some(old), Some(new)) => {
+                let old = serde_json::to_value(old).ok();
+                let new = serde_json::to_value(new).ok();
+                if old != new {
+                    diff.changed.push(id.clone());
+                    let fields = changed_fields(old.as_ref(), new.as_ref());
+                    if !fields.is_empty() {
+                        diff.fields.insert(id.clone(), fields);
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    diff
+}
+
+/// Top-level fields that differ between two JSON objects
+fn changed_fields(old: Option<&Value>, new: Option<&Value>) -> Vec<String> {
+    let (Some(Value::Object(old)), Some(Value::Object(new))) = (old, new) else {
+        return Vec::new();
+    };
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LNProgress;
+
+    #[test]
+    fn reports_added_removed_and_changed_entries() {
+        let mut before = SyncPayload::new("device".to_string());
+        before
+            .ln_progress
+            .insert("kept".to_string(), LNProgress::default());
+        before
+            .ln_progress
+            .insert("removed".to_string(), LNProgress::default());
+        before
+            .ln_progress
+            .insert("changed".to_string(), LNProgress::default());
+
+        let mut after = before.clone();
+        after.ln_progress.remove("removed");
+        after.ln_progress.insert(
+            "changed".to_string(),
+            LNProgress {
+                chapter_index: 4,
+                ..LNProgress::default()
+            },
+        );
+        after
+            .ln_progress
+            .insert("added".to_string(), LNProgress::default());
+
+        let diff = diff_payloads(&before, &after);
+        assert_eq!(diff.ln_progress.added, vec!["added"]);
+        assert_eq!(diff.ln_progress.removed, vec!["removed"]);
+        assert_eq!(diff.ln_progress.changed, vec!["changed"]);
+        assert_eq!(diff.ln_progress.fields["changed"], vec!["chapterIndex"]);
+        assert!(diff.ln_metadata.is_empty());
+        assert!(diff_payloads(&after, &after).is_empty());
+    }
+}
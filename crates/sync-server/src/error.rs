@@ -17,6 +17,24 @@ pub enum SyncError {
     #[error("Google Drive error: {0}")]
     DriveError(String),
 
+    #[error("WebDAV error: {0}")]
+    WebDavError(String),
+
+    #[error("SyncYomi error: {0}")]
+    SyncYomiError(String),
+
+    #[error("Remote sync data is encrypted but no passphrase is set")]
+    PassphraseRequired,
+
+    #[error("Wrong encryption passphrase")]
+    WrongPassphrase,
+
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+
+    #[error("Sync data uses schema v{found}, this version supports up to v{supported}")]
+    SchemaTooNew { found: u32, supported: u32 },
+
     #[error("Database error: {0}")]
     DatabaseError(#[from] sled::Error),
 
@@ -38,6 +56,18 @@ pub enum SyncError {
     #[error("Invalid request: {0}")]
     BadRequest(String),
 
+    #[error("Backup archive error: {0}")]
+    ArchiveError(String),
+
+    #[error("Profile not found: {0}")]
+    ProfileNotFound(String),
+
+    #[error("Conflict not found: {0}")]
+    ConflictNotFound(String),
+
+    #[error("Snapshot needs book data that is no longer stored: {0}")]
+    SnapshotIncomplete(String),
+
     #[error("{0}")]
     Other(#[from] anyhow::Error),
 }
@@ -116,6 +146,20 @@ impl SyncError {
             SyncError::DriveError(_) => {
                 "Google Drive request failed. Please try again later.".to_string()
             }
+            SyncError::WebDavError(message) => format!("WebDAV request failed: {message}"),
+            SyncError::SyncYomiError(message) => format!("SyncYomi request failed: {message}"),
+            SyncError::PassphraseRequired => {
+                "Your sync data is encrypted. Enter the encryption passphrase to continue syncing."
+                    .to_string()
+            }
+            SyncError::WrongPassphrase => {
+                "The encryption passphrase does not match the one your sync data was encrypted with."
+                    .to_string()
+            }
+            SyncError::SchemaTooNew { .. } => {
+                "Your sync data was written by a newer version of Manatan. Update Manatan on this device to keep syncing."
+                    .to_string()
+            }
             _ => self.to_string(),
         }
     }
@@ -127,16 +171,34 @@ impl IntoResponse for SyncError {
             SyncError::NotAuthenticated => (StatusCode::UNAUTHORIZED, "not_authenticated"),
             SyncError::OAuthError(_) => (StatusCode::BAD_REQUEST, "oauth_error"),
             SyncError::DriveError(_) => (StatusCode::BAD_GATEWAY, "drive_error"),
+            SyncError::WebDavError(_) => (StatusCode::BAD_GATEWAY, "webdav_error"),
+            SyncError::SyncYomiError(_) => (StatusCode::BAD_GATEWAY, "syncyomi_error"),
+            SyncError::PassphraseRequired => {
+                (StatusCode::PRECONDITION_REQUIRED, "passphrase_required")
+            }
+            SyncError::WrongPassphrase => (StatusCode::FORBIDDEN, "wrong_passphrase"),
+            SyncError::EncryptionError(_) => (StatusCode::UNPROCESSABLE_ENTITY, "encryption_error"),
+            SyncError::SchemaTooNew { .. } => (StatusCode::CONFLICT, "schema_too_new"),
             SyncError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
             SyncError::UploadIncomplete { .. } => {
                 (StatusCode::PARTIAL_CONTENT, "upload_incomplete")
             }
             SyncError::FileNotFound(_) => (StatusCode::NOT_FOUND, "file_not_found"),
             SyncError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            SyncError::ArchiveError(_) => (StatusCode::BAD_REQUEST, "archive_error"),
+            SyncError::ProfileNotFound(_) => (StatusCode::NOT_FOUND, "profile_not_found"),
+            SyncError::ConflictNotFound(_) => (StatusCode::NOT_FOUND, "conflict_not_found"),
+            SyncError::SnapshotIncomplete(_) => (StatusCode::CONFLICT, "snapshot_incomplete"),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
         };
 
-        if matches!(&self, SyncError::OAuthError(_) | SyncError::DriveError(_)) {
+        if matches!(
+            &self,
+            SyncError::OAuthError(_)
+                | SyncError::DriveError(_)
+                | SyncError::WebDavError(_)
+                | SyncError::SyncYomiError(_)
+        ) {
             warn!("Sync request failed [{}]: {}", error_type, self);
         }
 
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::{
+    backend::{SyncBackend, compress, decompress},
+    delta::{manifest_key, object_name},
+    error::SyncError,
+    migration,
+    state::SyncState,
+    types::{FileType, SyncPayload},
+};
+
+// ============================================================================
+// Sync History
+// ============================================================================
+//
+// Before the remote payload is overwritten, a copy is kept in the local
+// database so a bad merge can be undone. Inline book content and files are
+// left out to keep the database small; with delta sync the manifest still
+// references the per-book objects, which are not versioned. A restore takes
+// book data from the current remote and is refused once some of it is gone.
+
+const SNAPSHOT_PREFIX: &str = "snapshot:";
+
+/// What replaced the remote payload a snapshot was taken of
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SnapshotReason {
+    Merge,
+    Push,
+    Restore,
+}
+
+/// Summary of a stored snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub id: String,
+    /// When the snapshot was taken
+    pub taken_at: i64,
+    pub reason: SnapshotReason,
+    /// Device that wrote the snapshotted payload
+    pub device_id: String,
+    /// When the snapshotted payload was written
+    pub last_modified: i64,
+    pub schema_version: u32,
+    pub progress_count: usize,
+    pub metadata_count: usize,
+    pub manga_count: usize,
+    pub anime_count: usize,
+}
+
+#[derive(Serialize)]
+struct StoredSnapshot {
+    info: SnapshotInfo,
+    payload: SyncPayload,
+    /// Books whose inline content was left out of `payload`
+    content_ids: Vec<String>,
+    /// Books whose inline files were left out of `payload`
+    file_ids: Vec<String>,
+}
+
+/// Store a snapshot of `payload`, dropping the oldest ones beyond the
+/// configured `history_limit`
+pub fn record(
+    state: &SyncState,
+    payload: &SyncPayload,
+    reason: SnapshotReason,
+) -> Result<Option<SnapshotInfo>, SyncError> {
+    let limit = state.get_sync_config().history_limit;
+    if limit == 0 {
+        return Ok(None);
+    }
+
+    // Merging against an unchanged remote would otherwise fill the history
+    // with copies of the same payload
+    if let Some(latest) = list(state)?.first()
+        && latest.device_id == payload.device_id
+        && latest.last_modified == payload.last_modified
+    {
+        debug!(
+            "[HISTORY] Remote payload unchanged since snapshot {}",
+            latest.id
+        );
+        return Ok(None);
+    }
+
+    let taken_at = chrono::Utc::now().timestamp_millis();
+    // Monotonic and fixed-width, so keys sort oldest first
+    let id = format!("{:016x}", state.db.generate_id()?);
+    let info = SnapshotInfo {
+        id: id.clone(),
+        taken_at,
+        reason,
+        device_id: payload.device_id.clone(),
+        last_modified: payload.last_modified,
+        schema_version: payload.schema_version,
+        progress_count: payload.ln_progress.len(),
+        metadata_count: payload.ln_metadata.len(),
+        manga_count: payload.manga.len(),
+        anime_count: payload.anime.len(),
+    };
+
+    let mut payload = payload.clone();
+    let content_ids = std::mem::take(&mut payload.ln_content)
+        .into_keys()
+        .collect();
+    let file_ids = std::mem::take(&mut payload.ln_files).into_keys().collect();
+    let stored = StoredSnapshot {
+        info: info.clone(),
+        payload,
+        content_ids,
+        file_ids,
+    };
+    let bytes = compress(&serde_json::to_vec(&stored)?)?;
+    state
+        .db
+        .insert(format!("{SNAPSHOT_PREFIX}{id}").as_bytes(), bytes)?;
+
+    let keys: Vec<_> = state
+        .db
+        .scan_prefix(SNAPSHOT_PREFIX.as_bytes())
+        .keys()
+        .collect::<Result<_, _>>()?;
+    for key in keys.iter().take(keys.len().saturating_sub(limit)) {
+        state.db.remove(key)?;
+    }
+    state.db.flush()?;
+
+    info!("[HISTORY] Stored snapshot {} ({:?})", id, reason);
+    Ok(Some(info))
+}
+
+/// All snapshots, newest first
+pub fn list(state: &SyncState) -> Result<Vec<SnapshotInfo>, SyncError> {
+    let mut snapshots = Vec::new();
+    for entry in state.db.scan_prefix(SNAPSHOT_PREFIX.as_bytes()) {
+        let (key, bytes) = entry?;
+        match decode(&bytes) {
+            Ok(stored) => snapshots.push(stored.info),
+            Err(e) => debug!(
+                "[HISTORY] Skipping unreadable snapshot {}: {}",
+                String::from_utf8_lossy(&key),
+                e
+            ),
+        }
+    }
+    snapshots.reverse();
+    Ok(snapshots)
+}
+
+/// Load the payload of a snapshot
+pub fn load(state: &SyncState, id: &str) -> Result<SyncPayload, SyncError> {
+    Ok(load_stored(state, id)?.payload)
+}
+
+/// Load the payload of a snapshot to write back to `backend`, whose current
+/// payload is `remote`. Left out content and files are taken from `remote`,
+/// and the objects the manifest references must still exist.
+pub async fn load_restorable(
+    state: &SyncState,
+    id: &str,
+    backend: &dyn SyncBackend,
+    remote: Option<&SyncPayload>,
+) -> Result<SyncPayload, SyncError> {
+    let stored = load_stored(state, id)?;
+    let mut payload = stored.payload;
+    let mut missing = Vec::new();
+
+    for book_id in stored.content_ids {
+        match remote.and_then(|remote| remote.ln_content.get(&book_id)) {
+            Some(content) => {
+                payload.ln_content.insert(book_id, content.clone());
+            }
+            None => missing.push(manifest_key(&book_id, &FileType::Content)),
+        }
+    }
+    for book_id in stored.file_ids {
+        match remote.and_then(|remote| remote.ln_files.get(&book_id)) {
+            Some(file) => {
+                payload.ln_files.insert(book_id, file.clone());
+            }
+            None => missing.push(manifest_key(&book_id, &FileType::Epub)),
+        }
+    }
+
+    // Objects the remote still references are there; others may have been
+    // deleted after being replaced
+    for (key, reference) in &payload.file_manifest {
+        let referenced = remote
+            .and_then(|remote| remote.file_manifest.get(key))
+            .is_some_and(|current| current.file_hash == reference.file_hash);
+        if referenced {
+            continue;
+        }
+        if backend
+            .pull_object(&object_name(reference))
+            .await?
+            .is_none()
+        {
+            missing.push(key.clone());
+        }
+    }
+
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(SyncError::SnapshotIncomplete(missing.join(", ")));
+    }
+    Ok(payload)
+}
+
+fn load_stored(state: &SyncState, id: &str) -> Result<StoredSnapshot, SyncError> {
+    let bytes = state
+        .db
+        .get(format!("{SNAPSHOT_PREFIX}{id}").as_bytes())?
+        .ok_or_else(|| SyncError::FileNotFound(format!("Snapshot {id}")))?;
+    decode(&bytes)
+}
+
+/// Snapshots outlive upgrades, so the payload goes through the migrations
+fn decode(bytes: &[u8]) -> Result<StoredSnapshot, SyncError> {
+    let mut value: Value = serde_json::from_slice(&decompress(bytes)?)?;
+    let payload = migration::upgrade(value["payload"].take())?;
+    Ok(StoredSnapshot {
+        info: serde_json::from_value(value["info"].take())?,
+        payload: serde_json::from_value(payload)?,
+        content_ids: serde_json::from_value(value["content_ids"].take())?,
+        file_ids: serde_json::from_value(value["file_ids"].take())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::unique_temp_dir;
+
+    #[test]
+    fn keeps_only_the_newest_snapshots() {
+        let state = SyncState::new(unique_temp_dir("history-limit"));
+        let mut config = state.get_sync_config();
+        config.history_limit = 2;
+        state.set_sync_config(&config).expect("config saved");
+
+        for device in ["first", "second", "third"] {
+            record(
+                &state,
+                &SyncPayload::new(device.to_string()),
+                SnapshotReason::Merge,
+            )
+            .expect("snapshot recorded");
+        }
+
+        let snapshots = list(&state).expect("snapshots listed");
+        let devices: Vec<&str> = snapshots.iter().map(|s| s.device_id.as_str()).collect();
+        assert_eq!(devices, vec!["third", "second"]);
+
+        let payload = load(&state, &snapshots[1].id).expect("snapshot loads");
+        assert_eq!(payload.device_id, "second");
+        assert!(matches!(
+            load(&state, "missing"),
+            Err(SyncError::FileNotFound(_))
+        ));
+    }
+}
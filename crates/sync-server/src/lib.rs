@@ -1,29 +1,67 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use axum::{Router, extract::DefaultBodyLimit};
 use tower_http::cors::{Any, CorsLayer};
 
+pub mod archive;
 pub mod backend;
+pub mod cli;
+pub mod conflicts;
+pub mod crypto;
+pub mod delta;
+pub mod diff;
 pub mod error;
+pub mod history;
+pub mod library;
 pub mod merge;
+pub mod migration;
+pub mod profile;
 pub mod routes;
+pub mod scheduler;
 pub mod state;
+#[cfg(test)]
+mod test_util;
 pub mod types;
 
 pub use error::SyncError;
+pub use library::LocalLibrary;
 pub use state::SyncState;
 pub use types::*;
 
 pub fn create_router(data_dir: PathBuf) -> Router {
-    let state = SyncState::new(data_dir);
+    router_for(data_dir, None)
+}
+
+/// Like `create_router`, with a local library so the scheduler can sync
+/// in the background
+pub fn create_router_with_library(data_dir: PathBuf, library: Arc<dyn LocalLibrary>) -> Router {
+    router_for(data_dir, Some(library))
+}
+
+/// Router for the default profile, dispatching to the other profiles' own
+/// states (see `profile`)
+fn router_for(data_dir: PathBuf, library: Option<Arc<dyn LocalLibrary>>) -> Router {
+    let mut default_state = SyncState::new(data_dir.clone());
+    default_state.library = library.clone();
+    let default_router = router_with_state(default_state)
+        .nest("/profiles", routes::profiles::router(data_dir.clone()));
+    let router = profile::dispatch(data_dir.clone(), default_router, move |name| {
+        let mut state = SyncState::new(profile::profile_dir(&data_dir, name));
+        state.library = library
+            .as_ref()
+            .and_then(|library| library.for_profile(name));
+        router_with_state(state)
+    });
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    routes::router()
-        .layer(cors)
-        .layer(DefaultBodyLimit::disable())
-        .with_state(state)
+    router.layer(cors).layer(DefaultBodyLimit::disable())
+}
+
+fn router_with_state(state: SyncState) -> Router {
+    scheduler::spawn(state.clone());
+    routes::router().with_state(state)
 }
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{error::SyncError, state::SyncState, types::SyncPayload};
+
+/// The file a book was imported from, in whichever format it came
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookFile {
+    /// Lowercase file extension without the dot, e.g. `epub` or `txt`
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+/// Server-side store of the light novel library.
+///
+/// The frontend normally sends its payload to `/merge` and applies the
+/// result itself. Syncs started by the server (see `scheduler`) have no
+/// frontend to ask, so they read and write the library through this trait.
+#[async_trait]
+pub trait LocalLibrary: Send + Sync {
+    /// Light novel sections of the local library as a payload. Manga and
+    /// anime state is kept by the sync server and must be left empty.
+    async fn export_payload(&self, device_id: &str) -> Result<SyncPayload, SyncError>;
+
+    /// Write the light novel sections of a merged payload back
+    async fn apply_payload(&self, payload: &SyncPayload) -> Result<(), SyncError>;
+
+    /// Stored file of a book, for backup archives
+    async fn read_book_file(&self, _book_id: &str) -> Result<Option<BookFile>, SyncError> {
+        Ok(None)
+    }
+
+    /// The library of a named profile (see `profile`), if the host keeps
+    /// profiles apart
+    fn for_profile(&self, _name: &str) -> Option<Arc<dyn LocalLibrary>> {
+        None
+    }
+
+    /// Store the file of a book restored from a backup archive and make it
+    /// readable; archives carry the file, not the parsed content
+    async fn write_book_file(&self, book_id: &str, _file: &BookFile) -> Result<(), SyncError> {
+        Err(SyncError::Other(anyhow::anyhow!(
+            "This library cannot store the file of {book_id}"
+        )))
+    }
+}
+
+/// Whether a book id from another device can name a file: no path
+/// separators, parent references or NUL bytes
+pub fn is_valid_book_id(id: &str) -> bool {
+    !id.is_empty() && id != "." && !id.contains("..") && !id.contains(['/', '\\', '\0'])
+}
+
+/// The library attached to `state`, for operations that need one
+pub(crate) fn local_library(state: &SyncState) -> Result<&dyn LocalLibrary, SyncError> {
+    state
+        .library
+        .as_deref()
+        .ok_or_else(|| SyncError::Other(anyhow::anyhow!("No local library is available")))
+}
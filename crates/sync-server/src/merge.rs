@@ -2,42 +2,164 @@ use std::collections::HashMap;
 
 use tracing::debug;
 
-use crate::types::{ConflictInfo, LNMetadata, LNProgress, LnCategory, SyncPayload};
+use crate::{
+    delta::manifest_key,
+    types::{
+        AnimeEntry, AnimeEpisodeState, ConflictInfo, DeletionBehavior, DeletionDecision,
+        FileReference, FileType, LNHighlight, LNMetadata, LNProgress, LnCategory,
+        MangaChapterState, MangaEntry, PendingDeletion, ReaderSettingsSync, SyncPayload,
+        SyncSection, Tombstone, Tombstones,
+    },
+};
+
+/// Tombstones older than this are dropped. A device that stays offline longer
+/// than this may bring a deleted entry back.
+const TOMBSTONE_TTL_MS: i64 = 180 * 24 * 60 * 60 * 1000;
+
+/// How tombstones are applied during a merge
+#[derive(Debug, Clone, Default)]
+pub struct DeletionPolicy {
+    pub behavior: DeletionBehavior,
+    /// Answers to earlier `PendingDeletion`s; they override `behavior`
+    pub decisions: Vec<DeletionDecision>,
+}
+
+impl DeletionPolicy {
+    pub fn new(behavior: DeletionBehavior, decisions: Vec<DeletionDecision>) -> Self {
+        Self {
+            behavior,
+            decisions,
+        }
+    }
+
+    fn decision(&self, section: SyncSection, id: &str) -> Option<bool> {
+        self.decisions
+            .iter()
+            .find(|decision| decision.section == section && decision.id == id)
+            .map(|decision| decision.delete)
+    }
+}
+
+/// Result of merging two payloads
+#[derive(Debug)]
+pub struct MergeOutcome {
+    pub payload: SyncPayload,
+    pub conflicts: Vec<ConflictInfo>,
+    pub pending_deletions: Vec<PendingDeletion>,
+}
 
 /// Merge two sync payloads, returning the merged result
 pub fn merge_payloads(
     local: SyncPayload,
     remote: SyncPayload,
     local_device_id: &str,
-) -> (SyncPayload, Vec<ConflictInfo>) {
+    policy: &DeletionPolicy,
+) -> MergeOutcome {
     let mut conflicts = Vec::new();
+    let mut pending = Vec::new();
+    let mut tombstones = merge_tombstones(local.tombstones, remote.tombstones);
 
     // Merge progress
-    let (merged_progress, progress_conflicts) =
-        merge_progress_maps(local.ln_progress, remote.ln_progress, local_device_id);
+    let (merged_progress, progress_conflicts) = merge_progress_maps(
+        local.ln_progress,
+        remote.ln_progress,
+        local_device_id,
+        &mut tombstones.ln_progress,
+        policy,
+        &mut pending,
+    );
     conflicts.extend(progress_conflicts);
 
     // Merge metadata
-    let (merged_metadata, metadata_conflicts) =
-        merge_metadata_maps(local.ln_metadata, remote.ln_metadata);
+    let (merged_metadata, metadata_conflicts) = merge_metadata_maps(
+        local.ln_metadata,
+        remote.ln_metadata,
+        &mut tombstones.ln_metadata,
+        policy,
+        &mut pending,
+    );
     conflicts.extend(metadata_conflicts);
 
     // Merge content (simple: prefer local if exists, else remote)
-    let merged_content = merge_simple_maps(local.ln_content, remote.ln_content);
+    let mut merged_content = merge_simple_maps(local.ln_content, remote.ln_content);
 
     // Merge files (simple: prefer local if exists, else remote)
-    let merged_files = merge_simple_maps(local.ln_files, remote.ln_files);
+    let mut merged_files = merge_simple_maps(local.ln_files, remote.ln_files);
 
     // Merge file manifest
-    let merged_manifest = merge_simple_maps(local.file_manifest, remote.file_manifest);
+    let mut merged_manifest = merge_simple_maps(local.file_manifest, remote.file_manifest);
+
+    resolve_file_tombstones(
+        SyncSection::LnContent,
+        FileType::Content,
+        &mut merged_content,
+        &mut merged_manifest,
+        &mut tombstones.ln_content,
+        policy,
+        &mut pending,
+    );
+    resolve_file_tombstones(
+        SyncSection::LnFiles,
+        FileType::Epub,
+        &mut merged_files,
+        &mut merged_manifest,
+        &mut tombstones.ln_files,
+        policy,
+        &mut pending,
+    );
 
     // Merge categories (simple merge - both sides preserved)
-    let merged_categories = merge_categories(local.ln_categories, remote.ln_categories);
+    let merged_categories = merge_categories(
+        SyncSection::LnCategories,
+        local.ln_categories,
+        remote.ln_categories,
+        &mut tombstones.ln_categories,
+        policy,
+        &mut pending,
+    );
 
     // Merge category metadata (simple: prefer remote)
     let merged_category_metadata =
         merge_simple_maps(local.ln_category_metadata, remote.ln_category_metadata);
 
+    // Merge manga and anime: the newer entry wins, chapters and episodes are
+    // merged one by one so read state from both devices is kept
+    let merged_manga = merge_media_entries(
+        SyncSection::Manga,
+        local.manga,
+        remote.manga,
+        &mut tombstones.manga,
+        policy,
+        &mut pending,
+    );
+    let merged_anime = merge_media_entries(
+        SyncSection::Anime,
+        local.anime,
+        remote.anime,
+        &mut tombstones.anime,
+        policy,
+        &mut pending,
+    );
+    let merged_manga_categories = merge_categories(
+        SyncSection::MangaCategories,
+        local.manga_categories,
+        remote.manga_categories,
+        &mut tombstones.manga_categories,
+        policy,
+        &mut pending,
+    );
+    let merged_anime_categories = merge_categories(
+        SyncSection::AnimeCategories,
+        local.anime_categories,
+        remote.anime_categories,
+        &mut tombstones.anime_categories,
+        policy,
+        &mut pending,
+    );
+
+    let merged_reader_settings =
+        merge_reader_settings(local.reader_settings, remote.reader_settings);
+
     let merged = SyncPayload {
         schema_version: SyncPayload::CURRENT_SCHEMA_VERSION,
         device_id: local_device_id.to_string(),
@@ -49,15 +171,172 @@ pub fn merge_payloads(
         file_manifest: merged_manifest,
         ln_categories: merged_categories,
         ln_category_metadata: merged_category_metadata,
+        manga: merged_manga,
+        anime: merged_anime,
+        manga_categories: merged_manga_categories,
+        anime_categories: merged_anime_categories,
+        reader_settings: merged_reader_settings,
+        tombstones,
     };
 
-    (merged, conflicts)
+    MergeOutcome {
+        payload: merged,
+        conflicts,
+        pending_deletions: pending,
+    }
+}
+
+/// Global settings and each device's override are replaced as a whole by
+/// the newer side
+pub fn merge_reader_settings(
+    local: Option<ReaderSettingsSync>,
+    remote: Option<ReaderSettingsSync>,
+) -> Option<ReaderSettingsSync> {
+    let (local, remote) = match (local, remote) {
+        (Some(local), Some(remote)) => (local, remote),
+        (local, remote) => return local.or(remote),
+    };
+
+    let (mut merged, other) = if remote.global_modified > local.global_modified {
+        (remote, local)
+    } else {
+        (local, remote)
+    };
+    for (device_id, device) in other.device_overrides {
+        match merged.device_overrides.get(&device_id) {
+            Some(existing) if existing.last_modified >= device.last_modified => {}
+            _ => {
+                merged.device_overrides.insert(device_id, device);
+            }
+        }
+    }
+    Some(merged)
+}
+
+/// Union both sides' tombstones, keeping the latest deletion of each entry
+fn merge_tombstones(local: Tombstones, remote: Tombstones) -> Tombstones {
+    let cutoff = chrono::Utc::now().timestamp_millis() - TOMBSTONE_TTL_MS;
+    let merge = |local: HashMap<String, Tombstone>, remote: HashMap<String, Tombstone>| {
+        let mut merged = remote;
+        for (id, tombstone) in local {
+            match merged.get(&id) {
+                Some(existing) if existing.deleted_at >= tombstone.deleted_at => {}
+                _ => {
+                    merged.insert(id, tombstone);
+                }
+            }
+        }
+        merged.retain(|_, tombstone| tombstone.deleted_at >= cutoff);
+        merged
+    };
+
+    Tombstones {
+        ln_progress: merge(local.ln_progress, remote.ln_progress),
+        ln_metadata: merge(local.ln_metadata, remote.ln_metadata),
+        ln_content: merge(local.ln_content, remote.ln_content),
+        ln_files: merge(local.ln_files, remote.ln_files),
+        ln_categories: merge(local.ln_categories, remote.ln_categories),
+        manga: merge(local.manga, remote.manga),
+        anime: merge(local.anime, remote.anime),
+        manga_categories: merge(local.manga_categories, remote.manga_categories),
+        anime_categories: merge(local.anime_categories, remote.anime_categories),
+    }
+}
+
+/// Decide what happens to entries that have a tombstone, returning the ids
+/// to remove from the merged map.
+///
+/// `modified_at` gives the last change of an entry that still exists. An
+/// entry changed after its deletion was re-added, so its tombstone is
+/// dropped. With `KeepEverywhere` deletions never leave the device, so all
+/// tombstones are dropped.
+fn resolve_tombstones(
+    section: SyncSection,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    modified_at: impl Fn(&str) -> Option<i64>,
+    pending: &mut Vec<PendingDeletion>,
+) -> Vec<String> {
+    let mut deleted = Vec::new();
+    if policy.behavior == DeletionBehavior::KeepEverywhere {
+        tombstones.clear();
+        return deleted;
+    }
+
+    tombstones.retain(|id, tombstone| {
+        let Some(modified) = modified_at(id) else {
+            // Already gone on both sides; keep the tombstone for other devices
+            return true;
+        };
+        if modified > tombstone.deleted_at {
+            debug!("{:?} {}: re-added after deletion", section, id);
+            return false;
+        }
+
+        match policy.decision(section, id) {
+            Some(true) => {
+                deleted.push(id.clone());
+                true
+            }
+            Some(false) => {
+                debug!("{:?} {}: deletion rejected by user", section, id);
+                false
+            }
+            None if policy.behavior == DeletionBehavior::DeleteEverywhere => {
+                deleted.push(id.clone());
+                true
+            }
+            None => {
+                pending.push(PendingDeletion {
+                    section,
+                    id: id.clone(),
+                    deleted_at: tombstone.deleted_at,
+                    device_id: tombstone.device_id.clone(),
+                });
+                true
+            }
+        }
+    });
+
+    deleted
+}
+
+/// Apply content/files tombstones. These entries carry no timestamp of their
+/// own, and with delta sync they may only exist as manifest entries, so both
+/// places are checked and cleaned up.
+fn resolve_file_tombstones<V>(
+    section: SyncSection,
+    file_type: FileType,
+    entries: &mut HashMap<String, V>,
+    manifest: &mut HashMap<String, FileReference>,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    pending: &mut Vec<PendingDeletion>,
+) {
+    let deleted = resolve_tombstones(
+        section,
+        tombstones,
+        policy,
+        |id| match manifest.get(&manifest_key(id, &file_type)) {
+            Some(reference) => Some(reference.last_modified),
+            None => entries.contains_key(id).then_some(0),
+        },
+        pending,
+    );
+    for id in deleted {
+        entries.remove(&id);
+        manifest.remove(&manifest_key(&id, &file_type));
+    }
 }
 
 /// Merge categories - keep all categories from both sides
 fn merge_categories(
+    section: SyncSection,
     local: HashMap<String, LnCategory>,
     remote: HashMap<String, LnCategory>,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    pending: &mut Vec<PendingDeletion>,
 ) -> HashMap<String, LnCategory> {
     let mut merged = remote;
 
@@ -75,6 +354,128 @@ fn merge_categories(
         }
     }
 
+    let deleted = resolve_tombstones(
+        section,
+        tombstones,
+        policy,
+        |id| merged.get(id).map(|category| category.last_modified),
+        pending,
+    );
+    for id in deleted {
+        merged.remove(&id);
+    }
+
+    merged
+}
+
+/// Library entry whose units (chapters or episodes) are merged one by one
+trait MediaEntry {
+    type Unit: Clone;
+
+    fn last_modified(&self) -> i64;
+    fn units(&self) -> &HashMap<String, Self::Unit>;
+    fn units_mut(&mut self) -> &mut HashMap<String, Self::Unit>;
+    fn unit_modified(unit: &Self::Unit) -> i64;
+
+    /// Last change to the entry or any of its units
+    fn modified_at(&self) -> i64 {
+        let units = self
+            .units()
+            .values()
+            .map(Self::unit_modified)
+            .max()
+            .unwrap_or(i64::MIN);
+        self.last_modified().max(units)
+    }
+}
+
+impl MediaEntry for MangaEntry {
+    type Unit = MangaChapterState;
+
+    fn last_modified(&self) -> i64 {
+        self.last_modified
+    }
+
+    fn units(&self) -> &HashMap<String, MangaChapterState> {
+        &self.chapters
+    }
+
+    fn units_mut(&mut self) -> &mut HashMap<String, MangaChapterState> {
+        &mut self.chapters
+    }
+
+    fn unit_modified(unit: &MangaChapterState) -> i64 {
+        unit.last_modified
+    }
+}
+
+impl MediaEntry for AnimeEntry {
+    type Unit = AnimeEpisodeState;
+
+    fn last_modified(&self) -> i64 {
+        self.last_modified
+    }
+
+    fn units(&self) -> &HashMap<String, AnimeEpisodeState> {
+        &self.episodes
+    }
+
+    fn units_mut(&mut self) -> &mut HashMap<String, AnimeEpisodeState> {
+        &mut self.episodes
+    }
+
+    fn unit_modified(unit: &AnimeEpisodeState) -> i64 {
+        unit.last_modified
+    }
+}
+
+/// Merge manga or anime entries. Library fields (title, categories, in
+/// library) come from the newer entry; each chapter or episode keeps its own
+/// newest state, so reading on two devices never loses read marks.
+fn merge_media_entries<E: MediaEntry>(
+    section: SyncSection,
+    local: HashMap<String, E>,
+    remote: HashMap<String, E>,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    pending: &mut Vec<PendingDeletion>,
+) -> HashMap<String, E> {
+    let mut merged = remote;
+
+    for (id, mut entry) in local {
+        let Some(existing) = merged.get_mut(&id) else {
+            merged.insert(id, entry);
+            continue;
+        };
+
+        let mut units = std::mem::take(existing.units_mut());
+        for (key, unit) in std::mem::take(entry.units_mut()) {
+            match units.get(&key) {
+                Some(current) if E::unit_modified(current) >= E::unit_modified(&unit) => {}
+                _ => {
+                    units.insert(key, unit);
+                }
+            }
+        }
+
+        if entry.last_modified() > existing.last_modified() {
+            debug!("{:?} {}: local entry newer", section, id);
+            *existing = entry;
+        }
+        *existing.units_mut() = units;
+    }
+
+    let deleted = resolve_tombstones(
+        section,
+        tombstones,
+        policy,
+        |id| merged.get(id).map(E::modified_at),
+        pending,
+    );
+    for id in deleted {
+        merged.remove(&id);
+    }
+
     merged
 }
 
@@ -82,6 +483,9 @@ fn merge_progress_maps(
     local: HashMap<String, LNProgress>,
     remote: HashMap<String, LNProgress>,
     local_device_id: &str,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    pending: &mut Vec<PendingDeletion>,
 ) -> (HashMap<String, LNProgress>, Vec<ConflictInfo>) {
     let mut merged = HashMap::new();
     let mut conflicts = Vec::new();
@@ -164,18 +568,155 @@ fn merge_progress_maps(
             (None, None) => unreachable!(),
         };
 
+        let chosen = match (local_progress, remote_progress) {
+            (Some(l), Some(r)) => {
+                let (highlights, deleted_highlights) =
+                    merge_highlights(&book_id, l, r, &mut conflicts);
+                LNProgress {
+                    highlights,
+                    deleted_highlights,
+                    ..chosen
+                }
+            }
+            _ => chosen,
+        };
+
         merged.insert(book_id, chosen);
     }
 
+    let deleted = resolve_tombstones(
+        SyncSection::LnProgress,
+        tombstones,
+        policy,
+        |id| {
+            merged
+                .get(id)
+                .map(|progress| progress.last_modified.or(progress.last_read).unwrap_or(0))
+        },
+        pending,
+    );
+    for id in deleted {
+        merged.remove(&id);
+    }
+
     (merged, conflicts)
 }
 
+/// Merge highlights as a set keyed by id, independent of which progress
+/// record won. The latest edit of each highlight wins, and a deletion wins
+/// over edits made before it.
+fn merge_highlights(
+    book_id: &str,
+    local: &LNProgress,
+    remote: &LNProgress,
+    conflicts: &mut Vec<ConflictInfo>,
+) -> (Vec<LNHighlight>, HashMap<String, i64>) {
+    let cutoff = chrono::Utc::now().timestamp_millis() - TOMBSTONE_TTL_MS;
+    let mut deleted: HashMap<String, i64> = HashMap::new();
+    for (id, deleted_at) in local
+        .deleted_highlights
+        .iter()
+        .chain(&remote.deleted_highlights)
+    {
+        if *deleted_at >= cutoff {
+            let entry = deleted.entry(id.clone()).or_insert(*deleted_at);
+            *entry = (*entry).max(*deleted_at);
+        }
+    }
+
+    let mut highlights: HashMap<String, LNHighlight> = remote
+        .highlights
+        .iter()
+        .map(|highlight| (highlight.id.clone(), highlight.clone()))
+        .collect();
+
+    for highlight in &local.highlights {
+        match highlights.get(&highlight.id) {
+            Some(existing) if existing == highlight => {}
+            Some(existing) => {
+                let local_wins = highlight.modified_at() >= existing.modified_at();
+                debug!(
+                    "Highlight {} in {}: edited on both devices, {} newer",
+                    highlight.id,
+                    book_id,
+                    if local_wins { "local" } else { "remote" }
+                );
+                conflicts.push(ConflictInfo {
+                    book_id: book_id.to_string(),
+                    field: format!("highlight:{}", highlight.id),
+                    local_value: highlight.text.clone(),
+                    remote_value: existing.text.clone(),
+                    resolution: if local_wins {
+                        "local (newer)".to_string()
+                    } else {
+                        "remote (newer)".to_string()
+                    },
+                });
+                if local_wins {
+                    highlights.insert(highlight.id.clone(), highlight.clone());
+                }
+            }
+            None => {
+                highlights.insert(highlight.id.clone(), highlight.clone());
+            }
+        }
+    }
+
+    let mut revived = Vec::new();
+    highlights.retain(|id, highlight| match deleted.get(id) {
+        Some(deleted_at) if *deleted_at >= highlight.modified_at() => {
+            debug!("Highlight {} in {}: deleted", id, book_id);
+            false
+        }
+        Some(deleted_at) => {
+            debug!(
+                "Highlight {} in {}: edited after deletion, kept",
+                id, book_id
+            );
+            let side_value = |progress: &LNProgress| {
+                if progress.deleted_highlights.contains_key(id) {
+                    "deleted".to_string()
+                } else {
+                    highlight.text.clone()
+                }
+            };
+            conflicts.push(ConflictInfo {
+                book_id: book_id.to_string(),
+                field: format!("highlight:{id}"),
+                local_value: side_value(local),
+                remote_value: side_value(remote),
+                resolution: format!("kept (edited after deletion at {deleted_at})"),
+            });
+            revived.push(id.clone());
+            true
+        }
+        None => true,
+    });
+    for id in revived {
+        deleted.remove(&id);
+    }
+
+    let mut highlights: Vec<LNHighlight> = highlights.into_values().collect();
+    highlights.sort_by(|a, b| {
+        (a.chapter_index, a.start_offset, a.created_at, &a.id).cmp(&(
+            b.chapter_index,
+            b.start_offset,
+            b.created_at,
+            &b.id,
+        ))
+    });
+    (highlights, deleted)
+}
+
 fn merge_metadata_maps(
     local: HashMap<String, LNMetadata>,
     remote: HashMap<String, LNMetadata>,
+    tombstones: &mut HashMap<String, Tombstone>,
+    policy: &DeletionPolicy,
+    pending: &mut Vec<PendingDeletion>,
 ) -> (HashMap<String, LNMetadata>, Vec<ConflictInfo>) {
     let mut merged = HashMap::new();
-    let conflicts = Vec::new();
+    let mut conflicts = Vec::new();
 
     let all_keys: std::collections::HashSet<_> =
         local.keys().chain(remote.keys()).cloned().collect();
@@ -184,6 +725,31 @@ fn merge_metadata_maps(
         let local_meta = local.get(&book_id);
         let remote_meta = remote.get(&book_id);
 
+        // Edits made from the same version on two devices
+        if let (Some(l), Some(r)) = (local_meta, remote_meta)
+            && l.sync_version == r.sync_version
+            && serde_json::to_value(l).ok() != serde_json::to_value(r).ok()
+        {
+            // Same choice as below
+            let local_wins = l.sync_version.is_some()
+                || match (l.last_modified, r.last_modified) {
+                    (Some(lt), Some(rt)) => lt >= rt,
+                    _ => true,
+                };
+            conflicts.push(ConflictInfo {
+                book_id: book_id.clone(),
+                field: "metadata".to_string(),
+                local_value: l.last_modified.unwrap_or_default().to_string(),
+                remote_value: r.last_modified.unwrap_or_default().to_string(),
+                resolution: if local_wins {
+                    "local (newer)"
+                } else {
+                    "remote (newer)"
+                }
+                .to_string(),
+            });
+        }
+
         let chosen = match (local_meta, remote_meta) {
             (Some(l), None) => l.clone(),
             (None, Some(r)) => r.clone(),
@@ -210,6 +776,21 @@ fn merge_metadata_maps(
         merged.insert(book_id, chosen);
     }
 
+    let deleted = resolve_tombstones(
+        SyncSection::LnMetadata,
+        tombstones,
+        policy,
+        |id| {
+            merged
+                .get(id)
+                .map(|metadata| metadata.last_modified.unwrap_or(metadata.added_at))
+        },
+        pending,
+    );
+    for id in deleted {
+        merged.remove(&id);
+    }
+
     (merged, conflicts)
 }
 
@@ -223,3 +804,401 @@ fn merge_simple_maps<V: Clone>(
     }
     merged
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ReaderSettingsOverride;
+
+    fn progress(last_modified: i64) -> LNProgress {
+        LNProgress {
+            last_modified: Some(last_modified),
+            ..LNProgress::default()
+        }
+    }
+
+    fn tombstone(deleted_at: i64) -> Tombstone {
+        Tombstone {
+            deleted_at,
+            device_id: Some("device-a".to_string()),
+        }
+    }
+
+    /// Device A deleted `book` at `deleted_at`; device B still has it
+    fn payloads(deleted_at: i64, remote_modified: i64) -> (SyncPayload, SyncPayload) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut local = SyncPayload::new("device-a".to_string());
+        local
+            .tombstones
+            .ln_progress
+            .insert("book".to_string(), tombstone(now + deleted_at));
+
+        let mut remote = SyncPayload::new("device-b".to_string());
+        remote
+            .ln_progress
+            .insert("book".to_string(), progress(now + remote_modified));
+        remote
+            .ln_progress
+            .insert("other".to_string(), progress(now));
+        (local, remote)
+    }
+
+    fn policy(behavior: DeletionBehavior) -> DeletionPolicy {
+        DeletionPolicy::new(behavior, Vec::new())
+    }
+
+    #[test]
+    fn delete_everywhere_removes_entry_and_keeps_tombstone() {
+        let (local, remote) = payloads(0, -1000);
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::DeleteEverywhere),
+        );
+
+        assert!(!outcome.payload.ln_progress.contains_key("book"));
+        assert!(outcome.payload.ln_progress.contains_key("other"));
+        assert!(outcome.payload.tombstones.ln_progress.contains_key("book"));
+        assert!(outcome.pending_deletions.is_empty());
+    }
+
+    #[test]
+    fn entry_changed_after_deletion_survives() {
+        let (local, remote) = payloads(-1000, 0);
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::DeleteEverywhere),
+        );
+
+        assert!(outcome.payload.ln_progress.contains_key("book"));
+        assert!(outcome.payload.tombstones.is_empty());
+    }
+
+    #[test]
+    fn keep_everywhere_ignores_tombstones() {
+        let (local, remote) = payloads(0, -1000);
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::KeepEverywhere),
+        );
+
+        assert!(outcome.payload.ln_progress.contains_key("book"));
+        assert!(outcome.payload.tombstones.is_empty());
+    }
+
+    #[test]
+    fn ask_each_time_waits_for_a_decision() {
+        let (local, remote) = payloads(0, -1000);
+        let outcome = merge_payloads(
+            local.clone(),
+            remote.clone(),
+            "device-a",
+            &policy(DeletionBehavior::AskEachTime),
+        );
+        assert!(outcome.payload.ln_progress.contains_key("book"));
+        assert_eq!(outcome.pending_deletions.len(), 1);
+        assert_eq!(
+            outcome.pending_deletions[0].section,
+            SyncSection::LnProgress
+        );
+        assert_eq!(outcome.pending_deletions[0].id, "book");
+
+        let decide = |delete| {
+            DeletionPolicy::new(
+                DeletionBehavior::AskEachTime,
+                vec![DeletionDecision {
+                    section: SyncSection::LnProgress,
+                    id: "book".to_string(),
+                    delete,
+                }],
+            )
+        };
+
+        let confirmed = merge_payloads(local.clone(), remote.clone(), "device-a", &decide(true));
+        assert!(!confirmed.payload.ln_progress.contains_key("book"));
+        assert!(confirmed.pending_deletions.is_empty());
+
+        let rejected = merge_payloads(local, remote, "device-a", &decide(false));
+        assert!(rejected.payload.ln_progress.contains_key("book"));
+        assert!(rejected.payload.tombstones.is_empty());
+    }
+
+    #[test]
+    fn content_tombstone_removes_manifest_entry() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut local = SyncPayload::new("device-a".to_string());
+        local
+            .tombstones
+            .ln_content
+            .insert("book".to_string(), tombstone(now));
+
+        let mut remote = SyncPayload::new("device-b".to_string());
+        remote.file_manifest.insert(
+            manifest_key("book", &FileType::Content),
+            FileReference {
+                book_id: "book".to_string(),
+                file_type: FileType::Content,
+                file_hash: "abc".to_string(),
+                file_size: 3,
+                last_modified: now - 1000,
+                drive_file_id: None,
+            },
+        );
+
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::DeleteEverywhere),
+        );
+        assert!(outcome.payload.file_manifest.is_empty());
+    }
+
+    #[test]
+    fn expired_tombstones_are_pruned() {
+        let mut local = SyncPayload::new("device-a".to_string());
+        local.tombstones.ln_metadata.insert(
+            "book".to_string(),
+            tombstone(chrono::Utc::now().timestamp_millis() - TOMBSTONE_TTL_MS - 1),
+        );
+        let outcome = merge_payloads(
+            local,
+            SyncPayload::new("device-b".to_string()),
+            "device-a",
+            &policy(DeletionBehavior::DeleteEverywhere),
+        );
+        assert!(outcome.payload.tombstones.is_empty());
+    }
+
+    fn highlight(id: &str, text: &str, updated_at: i64) -> LNHighlight {
+        LNHighlight {
+            id: id.to_string(),
+            chapter_index: 0,
+            block_id: "block".to_string(),
+            text: text.to_string(),
+            start_offset: 0,
+            end_offset: text.len() as i32,
+            created_at: 0,
+            updated_at: Some(updated_at),
+        }
+    }
+
+    fn merge_book_progress(local: LNProgress, remote: LNProgress) -> MergeOutcome {
+        let mut local_payload = SyncPayload::new("device-a".to_string());
+        local_payload.ln_progress.insert("book".to_string(), local);
+        let mut remote_payload = SyncPayload::new("device-b".to_string());
+        remote_payload
+            .ln_progress
+            .insert("book".to_string(), remote);
+        merge_payloads(
+            local_payload,
+            remote_payload,
+            "device-a",
+            &policy(DeletionBehavior::KeepEverywhere),
+        )
+    }
+
+    fn highlight_texts(outcome: &MergeOutcome) -> Vec<&str> {
+        let mut texts: Vec<&str> = outcome.payload.ln_progress["book"]
+            .highlights
+            .iter()
+            .map(|highlight| highlight.text.as_str())
+            .collect();
+        texts.sort_unstable();
+        texts
+    }
+
+    #[test]
+    fn highlights_from_both_devices_survive_whichever_progress_wins() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let local = LNProgress {
+            total_progress: 0.2,
+            highlights: vec![highlight("a", "from a", now)],
+            ..progress(now)
+        };
+        let remote = LNProgress {
+            total_progress: 0.8,
+            highlights: vec![highlight("b", "from b", now)],
+            ..progress(now)
+        };
+
+        let outcome = merge_book_progress(local, remote);
+        assert_eq!(outcome.payload.ln_progress["book"].total_progress, 0.8);
+        assert_eq!(highlight_texts(&outcome), vec!["from a", "from b"]);
+    }
+
+    #[test]
+    fn newer_highlight_edit_wins_and_is_reported() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let local = LNProgress {
+            total_progress: 0.8,
+            highlights: vec![highlight("a", "old edit", now - 1000)],
+            ..progress(now)
+        };
+        let remote = LNProgress {
+            total_progress: 0.2,
+            highlights: vec![highlight("a", "new edit", now)],
+            ..progress(now)
+        };
+
+        let outcome = merge_book_progress(local, remote);
+        assert_eq!(highlight_texts(&outcome), vec!["new edit"]);
+        assert!(outcome.conflicts.iter().any(|conflict| {
+            conflict.field == "highlight:a" && conflict.resolution == "remote (newer)"
+        }));
+    }
+
+    #[test]
+    fn highlight_deletion_wins_over_older_edits_only() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let local = LNProgress {
+            deleted_highlights: HashMap::from([
+                ("a".to_string(), now),
+                ("b".to_string(), now - 1000),
+            ]),
+            ..progress(now)
+        };
+        let remote = LNProgress {
+            highlights: vec![
+                highlight("a", "edited before deletion", now - 500),
+                highlight("b", "edited after deletion", now - 500),
+            ],
+            ..progress(now)
+        };
+
+        let outcome = merge_book_progress(local, remote);
+        let merged = &outcome.payload.ln_progress["book"];
+        assert_eq!(highlight_texts(&outcome), vec!["edited after deletion"]);
+        assert!(merged.deleted_highlights.contains_key("a"));
+        assert!(!merged.deleted_highlights.contains_key("b"));
+        assert!(outcome.conflicts.iter().any(|conflict| {
+            conflict.field == "highlight:b" && conflict.local_value == "deleted"
+        }));
+    }
+
+    fn manga(title: &str, last_modified: i64, chapters: &[(&str, bool, i64)]) -> MangaEntry {
+        MangaEntry {
+            source_id: "1".to_string(),
+            url: "/manga/1".to_string(),
+            title: title.to_string(),
+            in_library: true,
+            category_ids: Vec::new(),
+            chapters: chapters
+                .iter()
+                .map(|(url, read, modified)| {
+                    (
+                        url.to_string(),
+                        MangaChapterState {
+                            read: *read,
+                            last_modified: *modified,
+                            ..MangaChapterState::default()
+                        },
+                    )
+                })
+                .collect(),
+            last_modified,
+        }
+    }
+
+    #[test]
+    fn manga_chapters_merge_independently_of_the_entry() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut local = SyncPayload::new("device-a".to_string());
+        local.manga.insert(
+            "1:/manga/1".to_string(),
+            manga(
+                "old title",
+                now - 1000,
+                &[("/c/1", true, now), ("/c/2", true, now - 500)],
+            ),
+        );
+        let mut remote = SyncPayload::new("device-b".to_string());
+        remote.manga.insert(
+            "1:/manga/1".to_string(),
+            manga(
+                "new title",
+                now,
+                &[("/c/2", false, now), ("/c/3", true, now)],
+            ),
+        );
+
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::KeepEverywhere),
+        );
+        let entry = &outcome.payload.manga["1:/manga/1"];
+        assert_eq!(entry.title, "new title");
+        assert!(entry.chapters["/c/1"].read);
+        assert!(!entry.chapters["/c/2"].read);
+        assert!(entry.chapters["/c/3"].read);
+    }
+
+    #[test]
+    fn manga_deleted_on_one_device_is_removed_everywhere() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut local = SyncPayload::new("device-a".to_string());
+        local
+            .tombstones
+            .manga
+            .insert("1:/manga/1".to_string(), tombstone(now));
+        let mut remote = SyncPayload::new("device-b".to_string());
+        remote.manga.insert(
+            "1:/manga/1".to_string(),
+            manga("title", now - 2000, &[("/c/1", true, now - 1000)]),
+        );
+
+        let outcome = merge_payloads(
+            local,
+            remote,
+            "device-a",
+            &policy(DeletionBehavior::DeleteEverywhere),
+        );
+        assert!(outcome.payload.manga.is_empty());
+        assert!(outcome.payload.tombstones.manga.contains_key("1:/manga/1"));
+    }
+
+    #[test]
+    fn reader_settings_take_the_newer_global_and_each_device_override() {
+        let device_override = |font_size: f64, last_modified: i64| ReaderSettingsOverride {
+            settings: serde_json::Map::from_iter([(
+                "lnFontSize".to_string(),
+                serde_json::json!(font_size),
+            )]),
+            last_modified,
+        };
+
+        let mut local = ReaderSettingsSync {
+            global_modified: 200,
+            ..ReaderSettingsSync::default()
+        };
+        local.global.ln_theme = "light".to_string();
+        local
+            .device_overrides
+            .insert("phone".to_string(), device_override(14.0, 100));
+
+        let mut remote = ReaderSettingsSync {
+            global_modified: 100,
+            ..ReaderSettingsSync::default()
+        };
+        remote
+            .device_overrides
+            .insert("phone".to_string(), device_override(12.0, 300));
+        remote
+            .device_overrides
+            .insert("tablet".to_string(), device_override(16.0, 50));
+
+        let merged = merge_reader_settings(Some(local), Some(remote)).expect("settings kept");
+        assert_eq!(merged.global.ln_theme, "light");
+        assert_eq!(merged.device_overrides.len(), 2);
+        assert_eq!(merged.device_overrides["phone"], device_override(12.0, 300));
+        assert_eq!(merged.effective("tablet").ln_font_size, 16.0);
+        assert!(merge_reader_settings(None, None).is_none());
+    }
+}
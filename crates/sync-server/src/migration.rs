@@ -0,0 +1,250 @@
+use serde_json::Value;
+use tracing::info;
+
+use crate::{error::SyncError, types::SyncPayload};
+
+// ============================================================================
+// Schema Migrations
+// ============================================================================
+//
+// Remote payloads are upgraded as raw JSON before they are deserialized, one
+// version at a time, so a device that skipped several releases still reads
+// data written by any older client.
+//
+// When a change to the payload types is not backwards compatible (a new
+// required field, a renamed field, a changed meaning), bump
+// `SyncPayload::CURRENT_SCHEMA_VERSION`, append a step here and add a golden
+// fixture for the new version under `tests/fixtures/schema`.
+
+/// One upgrade step from `from` to `from + 1`
+struct Migration {
+    from: u32,
+    apply: fn(&mut Value),
+}
+
+/// Steps in order, one per schema version bump
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        apply: v1_highlight_edit_times,
+    },
+    Migration {
+        from: 2,
+        apply: v2_media_sections,
+    },
+    Migration {
+        from: 3,
+        apply: v3_reader_settings,
+    },
+];
+
+/// Payloads written before the version field existed are treated as v1
+const UNVERSIONED_SCHEMA: u32 = 1;
+
+/// Schema version a raw payload was written with
+pub fn schema_version(value: &Value) -> u32 {
+    value
+        .get("schemaVersion")
+        .and_then(Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(UNVERSIONED_SCHEMA)
+}
+
+/// Upgrade a raw payload to `SyncPayload::CURRENT_SCHEMA_VERSION`.
+///
+/// Data written by a newer client is refused instead of being read with the
+/// unknown parts silently dropped, since the next push would erase them for
+/// every device.
+pub fn upgrade(value: Value) -> Result<Value, SyncError> {
+    upgrade_with(value, MIGRATIONS, SyncPayload::CURRENT_SCHEMA_VERSION)
+}
+
+fn upgrade_with(
+    mut value: Value,
+    migrations: &[Migration],
+    target: u32,
+) -> Result<Value, SyncError> {
+    let found = schema_version(&value);
+    if found > target {
+        return Err(SyncError::SchemaTooNew {
+            found,
+            supported: target,
+        });
+    }
+
+    let mut version = found;
+    while version < target {
+        let step = migrations
+            .iter()
+            .find(|step| step.from == version)
+            .ok_or_else(|| {
+                SyncError::Other(anyhow::anyhow!(
+                    "No migration from schema version {version}"
+                ))
+            })?;
+        (step.apply)(&mut value);
+        version += 1;
+    }
+
+    if found != target {
+        info!("[SYNC] Migrated payload from schema v{found} to v{target}");
+    }
+    if let Value::Object(map) = &mut value {
+        map.insert("schemaVersion".to_string(), Value::from(target));
+    }
+    Ok(value)
+}
+
+/// v2 merges highlights by id and tracks edits, so every highlight gets an
+/// explicit `updatedAt`. v1 clients rewrote the whole list with the winning
+/// progress record, which would drop edits and deletions made by v2 clients.
+fn v1_highlight_edit_times(value: &mut Value) {
+    let Some(progress) = value.get_mut("lnProgress").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let highlights = progress
+        .values_mut()
+        .filter_map(|entry| entry.get_mut("highlights"))
+        .filter_map(Value::as_array_mut)
+        .flatten()
+        .filter_map(Value::as_object_mut);
+    for highlight in highlights {
+        if !highlight.contains_key("updatedAt")
+            && let Some(created_at) = highlight.get("createdAt").cloned()
+        {
+            highlight.insert("updatedAt".to_string(), created_at);
+        }
+    }
+}
+
+/// v3 adds the manga and anime sections. There is nothing to convert; the
+/// bump keeps v2 clients, which would drop those sections on push, from
+/// writing over v3 data.
+fn v2_media_sections(_value: &mut Value) {}
+
+/// v4 adds global reader settings. Nothing to convert; as with v3, the bump
+/// keeps older clients from pushing payloads without them.
+fn v3_reader_settings(_value: &mut Value) {}
+
+/// Upgrade and deserialize a raw payload
+pub fn parse_payload(bytes: &[u8]) -> Result<SyncPayload, SyncError> {
+    let value: Value = serde_json::from_slice(bytes)?;
+    Ok(serde_json::from_value(upgrade(value)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Golden payloads, one per schema version. Each one holds the previous
+    /// version's library plus the sections its version added.
+    const FIXTURES: &[(u32, &str)] = &[
+        (1, include_str!("../tests/fixtures/schema/v1.json")),
+        (2, include_str!("../tests/fixtures/schema/v2.json")),
+        (3, include_str!("../tests/fixtures/schema/v3.json")),
+        (4, include_str!("../tests/fixtures/schema/v4.json")),
+    ];
+
+    fn fixture(version: u32) -> Value {
+        let (_, raw) = FIXTURES
+            .iter()
+            .find(|(fixture_version, _)| *fixture_version == version)
+            .expect("fixture exists for version");
+        serde_json::from_str(raw).expect("fixture is valid JSON")
+    }
+
+    #[test]
+    fn every_schema_version_has_a_fixture() {
+        let versions: Vec<u32> = FIXTURES.iter().map(|(version, _)| *version).collect();
+        let expected: Vec<u32> = (1..=SyncPayload::CURRENT_SCHEMA_VERSION).collect();
+        assert_eq!(versions, expected);
+    }
+
+    #[test]
+    fn older_fixtures_upgrade_to_the_current_fixture() {
+        let current = fixture(SyncPayload::CURRENT_SCHEMA_VERSION);
+        for (version, _) in FIXTURES {
+            let upgraded = upgrade(fixture(*version)).expect("fixture upgrades");
+            let payload: SyncPayload =
+                serde_json::from_value(upgraded).expect("upgraded fixture parses");
+            assert_eq!(payload.schema_version, SyncPayload::CURRENT_SCHEMA_VERSION);
+
+            // Sections added after `version` can't be in the older fixture
+            let upgraded = serde_json::to_value(&payload).expect("payload serializes");
+            let mut expected = current.clone();
+            if let (Some(expected), Some(upgraded)) =
+                (expected.as_object_mut(), upgraded.as_object())
+            {
+                expected.retain(|key, _| upgraded.contains_key(key));
+            }
+            assert_eq!(
+                upgraded, expected,
+                "schema v{version} fixture does not upgrade to the current fixture"
+            );
+        }
+    }
+
+    #[test]
+    fn current_fixture_round_trips_unchanged() {
+        // Fails when a type change alters the wire format; bump the schema
+        // version and add a migration if older devices can't read the result
+        let current = fixture(SyncPayload::CURRENT_SCHEMA_VERSION);
+        let payload: SyncPayload =
+            serde_json::from_value(current.clone()).expect("current fixture parses");
+        assert_eq!(
+            serde_json::to_value(&payload).expect("payload serializes"),
+            current
+        );
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let mut newer = fixture(SyncPayload::CURRENT_SCHEMA_VERSION);
+        newer["schemaVersion"] = json!(SyncPayload::CURRENT_SCHEMA_VERSION + 1);
+        newer["someFutureField"] = json!({ "kept": true });
+
+        assert!(matches!(
+            upgrade(newer),
+            Err(SyncError::SchemaTooNew { found, supported })
+                if found == SyncPayload::CURRENT_SCHEMA_VERSION + 1
+                    && supported == SyncPayload::CURRENT_SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn steps_run_in_order_up_to_the_target() {
+        fn rename_total(value: &mut Value) {
+            if let Some(total) = value.get("total").cloned() {
+                value["sum"] = total;
+            }
+        }
+        fn double_sum(value: &mut Value) {
+            if let Some(sum) = value.get("sum").and_then(Value::as_i64) {
+                value["sum"] = json!(sum * 2);
+            }
+        }
+        let migrations = [
+            Migration {
+                from: 2,
+                apply: double_sum,
+            },
+            Migration {
+                from: 1,
+                apply: rename_total,
+            },
+        ];
+
+        let upgraded =
+            upgrade_with(json!({ "total": 3 }), &migrations, 3).expect("upgrade succeeds");
+        assert_eq!(upgraded["sum"], json!(6));
+        assert_eq!(upgraded["schemaVersion"], json!(3));
+
+        let partial = upgrade_with(json!({ "schemaVersion": 2, "sum": 5 }), &migrations, 3)
+            .expect("upgrade succeeds");
+        assert_eq!(partial["sum"], json!(10));
+
+        assert!(upgrade_with(json!({ "schemaVersion": 1 }), &migrations[..1], 3).is_err());
+    }
+}
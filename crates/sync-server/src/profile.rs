@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    Router,
+    extract::Request,
+    http::{Uri, uri::PathAndQuery},
+    response::IntoResponse,
+};
+use tower::ServiceExt;
+use tracing::warn;
+
+use crate::error::SyncError;
+
+// ============================================================================
+// Profiles
+// ============================================================================
+//
+// People sharing one server each get a named profile with its own sync
+// tokens, reading progress, highlights and categories. A request picks its
+// profile with the `X-Manatan-Profile` header or a `/profiles/<name>/` path
+// prefix; requests with neither use the default profile, which keeps the
+// data layout from before profiles existed.
+//
+// Profiles are created explicitly (`POST /api/sync/profiles` or `manatan
+// sync create-profile`) and listed in `<data dir>/profiles/profiles.json`.
+// Requests for any other name get a 404, so a typo in a header does not
+// leave a new database and background scheduler behind.
+//
+// Each profile keeps its databases under `<data dir>/profiles/<name>/`.
+// EPUB files stay in the shared local novel folder.
+
+pub const PROFILE_HEADER: &str = "x-manatan-profile";
+const PROFILE_PATH_PREFIX: &str = "/profiles/";
+const PROFILES_DIR: &str = "profiles";
+const DEFAULT_PROFILE: &str = "default";
+const REGISTRY_FILE: &str = "profiles.json";
+
+/// Keeps registry updates within this process from overwriting each other
+static REGISTRY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Data directory of a named profile
+pub fn profile_dir(data_dir: &Path, name: &str) -> PathBuf {
+    data_dir.join(PROFILES_DIR).join(name)
+}
+
+/// Profile names end up in paths, so only short ASCII names are allowed
+pub fn validate_name(name: &str) -> Result<(), SyncError> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(SyncError::BadRequest(format!(
+            "Invalid profile name \"{name}\": use up to 32 letters, digits, '-' or '_'"
+        )))
+    }
+}
+
+/// Names of the created profiles, sorted
+pub fn list(data_dir: &Path) -> Result<Vec<String>, SyncError> {
+    match fs::read(data_dir.join(PROFILES_DIR).join(REGISTRY_FILE)) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn exists(data_dir: &Path, name: &str) -> Result<bool, SyncError> {
+    Ok(list(data_dir)?.iter().any(|profile| profile == name))
+}
+
+/// Register a profile so requests for it are served. Returns `false` if
+/// it already existed.
+pub fn create(data_dir: &Path, name: &str) -> Result<bool, SyncError> {
+    validate_name(name)?;
+    if name == DEFAULT_PROFILE {
+        return Err(SyncError::BadRequest(format!(
+            "\"{DEFAULT_PROFILE}\" is the profile used without a name"
+        )));
+    }
+
+    let _guard = REGISTRY_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut names = list(data_dir)?;
+    if names.iter().any(|profile| profile == name) {
+        return Ok(false);
+    }
+    names.push(name.to_string());
+    names.sort();
+
+    let dir = data_dir.join(PROFILES_DIR);
+    fs::create_dir_all(&dir)?;
+    let staging = dir.join(format!("{REGISTRY_FILE}.tmp"));
+    fs::write(&staging, serde_json::to_vec_pretty(&names)?)?;
+    fs::rename(staging, dir.join(REGISTRY_FILE))?;
+    Ok(true)
+}
+
+/// The profile a request asks for, `None` for the default profile. A path
+/// prefix is stripped so the profile's router sees the usual paths.
+pub fn select(req: &mut Request) -> Result<Option<String>, SyncError> {
+    let name = match strip_path_prefix(req.uri())? {
+        Some((name, uri)) => {
+            *req.uri_mut() = uri;
+            name
+        }
+        None => match req.headers().get(PROFILE_HEADER) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| SyncError::BadRequest("Invalid profile header".to_string()))?
+                .trim()
+                .to_string(),
+            None => return Ok(None),
+        },
+    };
+
+    if name.is_empty() || name == DEFAULT_PROFILE {
+        return Ok(None);
+    }
+    validate_name(&name)?;
+    Ok(Some(name))
+}
+
+fn strip_path_prefix(uri: &Uri) -> Result<Option<(String, Uri)>, SyncError> {
+    let Some(rest) = uri.path().strip_prefix(PROFILE_PATH_PREFIX) else {
+        return Ok(None);
+    };
+    let (name, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(
+        PathAndQuery::try_from(path_and_query)
+            .map_err(|e| SyncError::BadRequest(format!("Invalid path: {e}")))?,
+    );
+    let uri =
+        Uri::from_parts(parts).map_err(|e| SyncError::BadRequest(format!("Invalid path: {e}")))?;
+    Ok(Some((name.to_string(), uri)))
+}
+
+/// Route each request to its profile's router. Routers of the profiles
+/// created under `data_dir` are built by `build` right away, those of
+/// profiles created later on first use, and kept for the life of the server.
+pub fn dispatch<F>(data_dir: PathBuf, default: Router, build: F) -> Router
+where
+    F: Fn(&str) -> Router + Send + Sync + 'static,
+{
+    let names = list(&data_dir).unwrap_or_else(|e| {
+        warn!("[PROFILE] Could not read the profile list: {e}");
+        Vec::new()
+    });
+    let routers: HashMap<String, Router> = names
+        .into_iter()
+        .map(|name| {
+            let router = build(&name);
+            (name, router)
+        })
+        .collect();
+    let routers = Arc::new(Mutex::new(routers));
+    let build = Arc::new(build);
+    let data_dir = Arc::new(data_dir);
+
+    Router::new().fallback(move |mut req: Request| {
+        let default = default.clone();
+        let routers = routers.clone();
+        let build = build.clone();
+        let data_dir = data_dir.clone();
+        async move {
+            let router = match select(&mut req) {
+                Ok(None) => default,
+                Ok(Some(name)) => match profile_router(&routers, &data_dir, &name, &*build) {
+                    Ok(router) => router,
+                    Err(e) => return e.into_response(),
+                },
+                Err(e) => return e.into_response(),
+            };
+            match router.oneshot(req).await {
+                Ok(response) => response,
+                Err(never) => match never {},
+            }
+        }
+    })
+}
+
+fn profile_router(
+    routers: &Mutex<HashMap<String, Router>>,
+    data_dir: &Path,
+    name: &str,
+    build: &dyn Fn(&str) -> Router,
+) -> Result<Router, SyncError> {
+    let mut routers = routers
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(router) = routers.get(name) {
+        return Ok(router.clone());
+    }
+    if !exists(data_dir, name)? {
+        return Err(SyncError::ProfileNotFound(name.to_string()));
+    }
+    let router = build(name);
+    routers.insert(name.to_string(), router.clone());
+    Ok(router)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{body::Body, extract::State, routing::get};
+
+    use super::*;
+    use crate::test_util::unique_temp_dir;
+
+    fn named(name: &'static str) -> Router {
+        Router::new()
+            .route(
+                "/whoami",
+                get(|State(name): State<&'static str>| async move { name }),
+            )
+            .with_state(name)
+    }
+
+    async fn call(router: &Router, req: Request) -> String {
+        let response = router
+            .clone()
+            .oneshot(req)
+            .await
+            .expect("router is infallible");
+        let bytes = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .expect("body readable");
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[test]
+    fn profiles_are_created_once() {
+        let data_dir = unique_temp_dir("profile-create");
+        assert!(list(&data_dir).expect("list readable").is_empty());
+        assert!(create(&data_dir, "bob").expect("profile created"));
+        assert!(create(&data_dir, "alice").expect("profile created"));
+        assert!(!create(&data_dir, "alice").expect("profile exists"));
+        assert_eq!(list(&data_dir).expect("list readable"), ["alice", "bob"]);
+        assert!(create(&data_dir, "default").is_err());
+        assert!(create(&data_dir, "../evil").is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_reach_the_selected_profile() {
+        let data_dir = unique_temp_dir("profile-dispatch");
+        create(&data_dir, "alice").expect("profile created");
+        create(&data_dir, "bob").expect("profile created");
+        let built = Arc::new(AtomicUsize::new(0));
+        let counter = built.clone();
+        let router = Router::new().nest(
+            "/api/sync",
+            dispatch(data_dir.clone(), named("default"), move |name| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                match name {
+                    "alice" => named("alice"),
+                    _ => named("other"),
+                }
+            }),
+        );
+        // Routers of existing profiles are built up front
+        assert_eq!(built.load(Ordering::SeqCst), 2);
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .body(Body::empty())
+                .expect("request built")
+        };
+
+        assert_eq!(call(&router, request("/api/sync/whoami")).await, "default");
+        assert_eq!(
+            call(&router, request("/api/sync/profiles/alice/whoami?x=1")).await,
+            "alice"
+        );
+        let mut with_header = request("/api/sync/whoami");
+        with_header
+            .headers_mut()
+            .insert(PROFILE_HEADER, "bob".parse().expect("header value"));
+        assert_eq!(call(&router, with_header).await, "other");
+        assert_eq!(
+            call(&router, request("/api/sync/profiles/default/whoami")).await,
+            "default"
+        );
+        assert!(
+            call(&router, request("/api/sync/profiles/..%2F/whoami"))
+                .await
+                .contains("bad_request")
+        );
+
+        // Unknown names are not created on the fly
+        let mut unknown = request("/api/sync/whoami");
+        unknown
+            .headers_mut()
+            .insert(PROFILE_HEADER, "carol".parse().expect("header value"));
+        assert!(call(&router, unknown).await.contains("profile_not_found"));
+        assert!(
+            call(&router, request("/api/sync/profiles/carol/whoami"))
+                .await
+                .contains("profile_not_found")
+        );
+        assert!(!profile_dir(&data_dir, "carol").exists());
+
+        // Profiles created later are served from their first request
+        create(&data_dir, "carol").expect("profile created");
+        assert_eq!(
+            call(&router, request("/api/sync/profiles/carol/whoami")).await,
+            "other"
+        );
+        assert_eq!(built.load(Ordering::SeqCst), 3);
+    }
+}
@@ -8,9 +8,14 @@ use axum::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    backend::{AuthFlow, SyncBackend, google_drive::GoogleDriveBackend},
+    backend::{
+        AuthFlow, BackendSwitch, SyncBackend, active_backend_type, create_backend,
+        google_drive::GoogleDriveBackend, load_backend, local_folder::LocalFolderBackend,
+        switch_backend, syncyomi::SyncYomiBackend, webdav::WebDavBackend,
+    },
     error::SyncError,
-    state::SyncState,
+    state::{SyncState, WebDavAuthType, WebDavCredentials},
+    types::SyncBackendType,
 };
 
 pub fn router() -> Router<SyncState> {
@@ -19,6 +24,9 @@ pub fn router() -> Router<SyncState> {
         .route("/google/start", post(google_start))
         .route("/google/callback", get(google_callback))
         .route("/google/callback", post(google_callback_post))
+        .route("/webdav/connect", post(webdav_connect))
+        .route("/syncyomi/connect", post(syncyomi_connect))
+        .route("/local/connect", post(local_folder_connect))
         .route("/disconnect", get(disconnect).post(disconnect))
 }
 
@@ -33,27 +41,20 @@ pub struct AuthStatusResponse {
 }
 
 async fn auth_status(State(state): State<SyncState>) -> Result<impl IntoResponse, SyncError> {
-    // 1. Get a WRITE lock so we can modify the backend state and refresh tokens
-    let mut gdrive = state.google_drive.write().await;
-
-    // 2. If backend is not initialized (e.g., server restarted), try to initialize it from DB
-    if gdrive.is_none() {
-        let access_token = state.get_access_token();
-        let refresh_token = state.get_refresh_token();
-
-        if access_token.is_some() && refresh_token.is_some() {
-            tracing::info!("[AUTH] Found existing tokens, initializing backend");
-            let mut backend = GoogleDriveBackend::new(state.clone());
-            // If initialization fails, we just don't set the backend
-            if backend.initialize().await.is_ok() {
-                *gdrive = Some(backend);
-            }
-        }
+    let backend_type = active_backend_type(&state);
+
+    // 1. If backend is not initialized (e.g., server restarted), try to initialize it from DB.
+    // If initialization fails, we just don't set the backend
+    if let Err(e) = load_backend(&state).await {
+        tracing::debug!("[AUTH] {:?} backend not ready: {e}", backend_type);
     }
 
+    // 2. Get a WRITE lock so we can refresh tokens
+    let mut active = state.backend.write().await;
+
     // 3. Check authentication status and get email
     let mut did_refresh = false;
-    let (connected, email) = if let Some(backend) = gdrive.as_mut() {
+    let (connected, email) = if let Some(backend) = active.as_mut() {
         let is_auth = backend.is_authenticated().await;
 
         let mut user_email = if is_auth {
@@ -80,16 +81,16 @@ async fn auth_status(State(state): State<SyncState>) -> Result<impl IntoResponse
 
         (is_auth, user_email)
     } else {
-        // Fallback: Check if tokens exist in DB even if backend isn't ready
-        let has_tokens = state.get_access_token().is_some() && state.get_refresh_token().is_some();
+        // Fallback: Check if Google tokens exist in DB even if backend isn't ready
+        let has_tokens = backend_type == SyncBackendType::GoogleDrive
+            && state.get_access_token().is_some()
+            && state.get_refresh_token().is_some();
         (has_tokens, None)
     };
 
-    let config = state.get_sync_config();
-
     let response = Json(AuthStatusResponse {
         connected,
-        backend: format!("{:?}", config.backend).to_lowercase(),
+        backend: format!("{backend_type:?}").to_lowercase(),
         email,
         last_sync: state.get_last_sync(),
         device_id: state.get_device_id(),
@@ -123,12 +124,11 @@ async fn google_start(
     // Store the redirect_uri to use in the callback (it must match exactly)
     state.set_auth_redirect_uri(&req.redirect_uri)?;
 
+    // The PKCE verifier and CSRF state are persisted, so the callback can use a
+    // fresh instance and the currently active backend keeps working meanwhile.
     let backend = GoogleDriveBackend::new(state.clone());
     let auth_flow = backend.start_auth(&req.redirect_uri)?;
 
-    // Store backend for later (write lock)
-    *state.google_drive.write().await = Some(backend);
-
     Ok(Json(auth_flow))
 }
 
@@ -206,16 +206,12 @@ async fn google_callback_post(
         return Err(SyncError::OAuthError("State mismatch".to_string()));
     }
 
-    let mut gdrive = state.google_drive.write().await;
-    let backend = gdrive.get_or_insert_with(|| GoogleDriveBackend::new(state.clone()));
-
+    let mut backend = GoogleDriveBackend::new(state.clone());
     backend
         .complete_auth(&body.code, &body.redirect_uri)
         .await?;
 
-    let mut config = state.get_sync_config();
-    config.backend = crate::types::SyncBackendType::GoogleDrive;
-    state.set_sync_config(&config)?;
+    activate_backend(&state, SyncBackendType::GoogleDrive).await?;
 
     Ok(Json(CallbackResponse {
         success: true,
@@ -223,7 +219,7 @@ async fn google_callback_post(
     }))
 }
 
-async fn handle_callback(
+pub(crate) async fn handle_callback(
     state: SyncState,
     code: String,
     received_state: Option<String>,
@@ -241,38 +237,185 @@ async fn handle_callback(
         ));
     };
 
-    let mut gdrive = state.google_drive.write().await;
-    let backend = gdrive.get_or_insert_with(|| GoogleDriveBackend::new(state.clone()));
-
+    let mut backend = GoogleDriveBackend::new(state.clone());
     backend.complete_auth(&code, &redirect_uri).await?;
 
     let _ = state.clear_auth_redirect_uri();
 
-    let mut config = state.get_sync_config();
-    config.backend = crate::types::SyncBackendType::GoogleDrive;
-    state.set_sync_config(&config)?;
+    activate_backend(&state, SyncBackendType::GoogleDrive).await?;
 
     Ok(())
 }
 
+/// Make a freshly connected backend the active one, copying the previous
+/// backend's remote data over. A failed copy does not block the switch: the
+/// next merge uploads everything the frontend has locally anyway.
+async fn activate_backend(
+    state: &SyncState,
+    backend_type: SyncBackendType,
+) -> Result<BackendSwitch, SyncError> {
+    match switch_backend(state, backend_type.clone(), true).await {
+        Ok(switch) => Ok(switch),
+        Err(e) => {
+            tracing::warn!(
+                "[AUTH] Copying sync data to {:?} failed, switching without it: {e}",
+                backend_type
+            );
+            switch_backend(state, backend_type, false).await
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavConnectRequest {
+    pub url: String,
+    pub path: Option<String>,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub auth_type: WebDavAuthType,
+}
+
+async fn webdav_connect(
+    State(state): State<SyncState>,
+    Json(req): Json<WebDavConnectRequest>,
+) -> Result<Json<CallbackResponse>, SyncError> {
+    if req.url.trim().is_empty() {
+        return Err(SyncError::BadRequest("WebDAV URL is required".to_string()));
+    }
+
+    let previous_config = state.get_sync_config();
+    let previous_credentials = state.get_webdav_credentials();
+
+    let mut config = previous_config.clone();
+    config.webdav_url = req.url.trim().to_string();
+    if let Some(path) = req.path {
+        config.webdav_path = path;
+    }
+    state.set_sync_config(&config)?;
+    state.set_webdav_credentials(&WebDavCredentials {
+        username: req.username,
+        password: req.password,
+        auth_type: req.auth_type,
+    })?;
+
+    // Roll back to the previous settings if the server rejects the new ones
+    if let Err(e) = WebDavBackend::new(state.clone()).verify().await {
+        tracing::warn!("[AUTH] WebDAV verification failed: {e}");
+        state.set_sync_config(&previous_config)?;
+        match previous_credentials {
+            Some(credentials) => state.set_webdav_credentials(&credentials)?,
+            None => state.clear_webdav_credentials()?,
+        }
+        return Err(e);
+    }
+
+    activate_backend(&state, SyncBackendType::WebDav).await?;
+
+    Ok(Json(CallbackResponse {
+        success: true,
+        message: "Successfully connected to WebDAV".to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncYomiConnectRequest {
+    pub url: String,
+    pub api_key: String,
+}
+
+async fn syncyomi_connect(
+    State(state): State<SyncState>,
+    Json(req): Json<SyncYomiConnectRequest>,
+) -> Result<Json<CallbackResponse>, SyncError> {
+    if req.url.trim().is_empty() || req.api_key.trim().is_empty() {
+        return Err(SyncError::BadRequest(
+            "SyncYomi URL and API key are required".to_string(),
+        ));
+    }
+
+    let previous_config = state.get_sync_config();
+    let previous_api_key = state.get_syncyomi_api_key();
+
+    let mut config = previous_config.clone();
+    config.syncyomi_url = req.url.trim().to_string();
+    state.set_sync_config(&config)?;
+    state.set_syncyomi_api_key(req.api_key.trim())?;
+
+    // Roll back to the previous settings if the server rejects the new ones
+    if let Err(e) = SyncYomiBackend::new(state.clone()).verify().await {
+        tracing::warn!("[AUTH] SyncYomi verification failed: {e}");
+        state.set_sync_config(&previous_config)?;
+        match previous_api_key {
+            Some(api_key) => state.set_syncyomi_api_key(&api_key)?,
+            None => state.clear_syncyomi_api_key()?,
+        }
+        return Err(e);
+    }
+
+    activate_backend(&state, SyncBackendType::SyncYomi).await?;
+
+    Ok(Json(CallbackResponse {
+        success: true,
+        message: "Successfully connected to SyncYomi".to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalFolderConnectRequest {
+    pub path: String,
+}
+
+async fn local_folder_connect(
+    State(state): State<SyncState>,
+    Json(req): Json<LocalFolderConnectRequest>,
+) -> Result<Json<CallbackResponse>, SyncError> {
+    if req.path.trim().is_empty() {
+        return Err(SyncError::BadRequest("Folder path is required".to_string()));
+    }
+
+    let previous_config = state.get_sync_config();
+    let mut config = previous_config.clone();
+    config.local_folder_path = req.path.trim().to_string();
+    state.set_sync_config(&config)?;
+
+    if let Err(e) = LocalFolderBackend::new(state.clone()).verify().await {
+        tracing::warn!("[AUTH] Local folder verification failed: {e}");
+        state.set_sync_config(&previous_config)?;
+        return Err(e);
+    }
+
+    activate_backend(&state, SyncBackendType::LocalFolder).await?;
+
+    Ok(Json(CallbackResponse {
+        success: true,
+        message: format!("Syncing to {}", config.local_folder_path),
+    }))
+}
+
 async fn disconnect(State(state): State<SyncState>) -> Result<Json<CallbackResponse>, SyncError> {
-    let mut gdrive = state.google_drive.write().await;
+    let backend_type = active_backend_type(&state);
+    let mut active = state.backend.write().await;
 
-    if let Some(backend) = gdrive.as_mut() {
-        backend.disconnect().await?;
-    } else {
+    let backend = match active.take() {
+        Some(backend) if backend.backend_type() == backend_type => Some(backend),
         // If backend is not currently initialized, we still need to clear any
-        // persisted OAuth tokens from the sync state database.
-        state.clear_tokens()?;
+        // persisted credentials from the sync state database.
+        _ => create_backend(&state, &backend_type),
+    };
+    if let Some(mut backend) = backend {
+        backend.disconnect().await?;
     }
 
-    *gdrive = None;
     let _ = state.clear_auth_state();
     let _ = state.clear_auth_code_verifier();
     let _ = state.clear_auth_redirect_uri();
 
     let mut config = state.get_sync_config();
-    config.backend = crate::types::SyncBackendType::None;
+    config.backend = SyncBackendType::None;
     state.set_sync_config(&config)?;
 
     Ok(Json(CallbackResponse {
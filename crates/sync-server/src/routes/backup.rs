@@ -0,0 +1,49 @@
+use std::io::Cursor;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::State,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+    routing::get,
+};
+
+use crate::{
+    archive::{self, ArchiveImport},
+    error::SyncError,
+    state::SyncState,
+};
+
+pub fn router() -> Router<SyncState> {
+    Router::new().route("/", get(export_archive).post(import_archive))
+}
+
+/// Download the local data and book files as a backup archive
+async fn export_archive(State(state): State<SyncState>) -> Result<impl IntoResponse, SyncError> {
+    let mut data = Cursor::new(Vec::new());
+    archive::export(&state, &mut data).await?;
+
+    let filename = format!(
+        "manatan-backup-{}.zip",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    );
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        data.into_inner(),
+    ))
+}
+
+/// Merge an uploaded backup archive into the local data
+async fn import_archive(
+    State(state): State<SyncState>,
+    body: Bytes,
+) -> Result<Json<ArchiveImport>, SyncError> {
+    Ok(Json(archive::import(&state, Cursor::new(body)).await?))
+}
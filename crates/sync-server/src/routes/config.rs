@@ -3,14 +3,27 @@ use axum::{
     extract::State,
     routing::{get, put},
 };
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::{error::SyncError, state::SyncState, types::SyncConfig};
+use crate::{
+    backend::{BackendSwitch, active_backend_type, change_passphrase, switch_backend},
+    error::SyncError,
+    state::SyncState,
+    types::{SyncBackendType, SyncConfig},
+};
 
 pub fn router() -> Router<SyncState> {
     Router::new()
         .route("/", get(get_config))
         .route("/", put(set_config))
+        .route("/backend", put(set_backend))
+        .route(
+            "/encryption",
+            get(get_encryption)
+                .put(set_encryption)
+                .delete(clear_encryption),
+        )
 }
 
 async fn get_config(State(state): State<SyncState>) -> Json<SyncConfig> {
@@ -26,6 +39,81 @@ async fn set_config(
         "[CONFIG] Config updated - sync settings: progress={}, metadata={}, content={}, files={}",
         config.ln_progress, config.ln_metadata, config.ln_content, config.ln_files
     );
-    state.set_sync_config(&config)?;
+    // Backend changes go through the switch so the cached instance is rebuilt
+    let backend = config.backend.clone();
+    let mut stored = config.clone();
+    stored.backend = state.get_sync_config().backend;
+    state.set_sync_config(&stored)?;
+
+    if backend != active_backend_type(&state) {
+        switch_backend(&state, backend, false).await?;
+    }
+    let config = state.get_sync_config();
+    state.scheduler.reschedule(&config);
     Ok(Json(config))
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetBackendRequest {
+    backend: SyncBackendType,
+    /// Copy the current remote data to the new backend before switching
+    #[serde(default = "default_migrate")]
+    migrate: bool,
+}
+
+fn default_migrate() -> bool {
+    true
+}
+
+async fn set_backend(
+    State(state): State<SyncState>,
+    Json(req): Json<SetBackendRequest>,
+) -> Result<Json<BackendSwitch>, SyncError> {
+    info!(
+        "[CONFIG] Switching backend to {:?} (migrate: {})",
+        req.backend, req.migrate
+    );
+    let switch = switch_backend(&state, req.backend, req.migrate).await?;
+    Ok(Json(switch))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptionStatus {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetEncryptionRequest {
+    passphrase: String,
+}
+
+async fn get_encryption(State(state): State<SyncState>) -> Json<EncryptionStatus> {
+    Json(EncryptionStatus {
+        enabled: state.get_encryption_passphrase().is_some(),
+    })
+}
+
+async fn set_encryption(
+    State(state): State<SyncState>,
+    Json(req): Json<SetEncryptionRequest>,
+) -> Result<Json<EncryptionStatus>, SyncError> {
+    if req.passphrase.is_empty() {
+        return Err(SyncError::BadRequest(
+            "Passphrase must not be empty".to_string(),
+        ));
+    }
+    info!("[CONFIG] Enabling end-to-end encryption");
+    change_passphrase(&state, Some(&req.passphrase)).await?;
+    Ok(Json(EncryptionStatus { enabled: true }))
+}
+
+async fn clear_encryption(
+    State(state): State<SyncState>,
+) -> Result<Json<EncryptionStatus>, SyncError> {
+    info!("[CONFIG] Disabling end-to-end encryption");
+    change_passphrase(&state, None).await?;
+    Ok(Json(EncryptionStatus { enabled: false }))
+}
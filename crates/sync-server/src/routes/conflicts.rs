@@ -0,0 +1,104 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    conflicts::{self, ConflictChoice, PendingConflict},
+    error::SyncError,
+    state::SyncState,
+};
+
+pub fn router() -> Router<SyncState> {
+    Router::new()
+        .route("/", get(list_conflicts))
+        .route("/{*target}", post(resolve_conflict))
+}
+
+async fn list_conflicts(
+    State(state): State<SyncState>,
+) -> Result<Json<Vec<PendingConflict>>, SyncError> {
+    Ok(Json(conflicts::list(&state)?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolveRequest {
+    choice: ConflictChoice,
+}
+
+/// `POST /{id}/resolve`: record a choice; it is written to the remote on the
+/// next sync. Book ids may contain slashes, so the id is everything before
+/// the final `/resolve`, percent-encoded or not.
+async fn resolve_conflict(
+    State(state): State<SyncState>,
+    Path(target): Path<String>,
+    Json(req): Json<ResolveRequest>,
+) -> Result<Json<PendingConflict>, SyncError> {
+    let id = target
+        .strip_suffix("/resolve")
+        .ok_or_else(|| SyncError::ConflictNotFound(target.clone()))?;
+    info!("[CONFLICT] Resolving {} with {:?}", id, req.choice);
+    Ok(Json(conflicts::resolve(&state, id, req.choice)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{test_util::unique_temp_dir, types::SyncSection};
+
+    #[tokio::test]
+    async fn ids_with_slashes_can_be_resolved() {
+        let state = SyncState::new(unique_temp_dir("conflict-routes"));
+        let conflict = PendingConflict {
+            id: "lnProgress:site/book".to_string(),
+            section: SyncSection::LnProgress,
+            entry_id: "site/book".to_string(),
+            local: serde_json::json!({}),
+            remote: serde_json::json!({}),
+            detected_at: 0,
+            resolution: None,
+        };
+        state
+            .db
+            .insert(
+                "conflict:lnProgress:site/book",
+                serde_json::to_vec(&conflict).expect("conflict serialized"),
+            )
+            .expect("conflict stored");
+        let router = router().with_state(state);
+        let resolve = |uri: &str| {
+            Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"choice":"keepLocal"}"#))
+                .expect("request built")
+        };
+
+        for uri in [
+            "/lnProgress:site/book/resolve",
+            "/lnProgress%3Asite%2Fbook/resolve",
+        ] {
+            let response = router
+                .clone()
+                .oneshot(resolve(uri))
+                .await
+                .expect("router is infallible");
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+        }
+
+        let missing = router
+            .oneshot(resolve("/lnProgress:other/resolve"))
+            .await
+            .expect("router is infallible");
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,195 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    backend::{PushResult, ensure_backend},
+    diff::{PayloadDiff, diff_payloads},
+    error::SyncError,
+    history::{self, SnapshotInfo, SnapshotReason},
+    state::SyncState,
+    types::SyncPayload,
+};
+
+pub fn router() -> Router<SyncState> {
+    Router::new()
+        .route("/", get(list_snapshots))
+        .route("/diff", get(diff_snapshots))
+        .route("/{id}/restore", post(restore_snapshot))
+}
+
+async fn list_snapshots(
+    State(state): State<SyncState>,
+) -> Result<Json<Vec<SnapshotInfo>>, SyncError> {
+    Ok(Json(history::list(&state)?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffQuery {
+    from: String,
+    to: String,
+}
+
+async fn diff_snapshots(
+    State(state): State<SyncState>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<PayloadDiff>, SyncError> {
+    let from = history::load(&state, &query.from)?;
+    let to = history::load(&state, &query.to)?;
+    Ok(Json(diff_payloads(&from, &to)))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RestoreResponse {
+    /// The restored payload, to apply locally so the next merge keeps it
+    payload: SyncPayload,
+    etag: String,
+    sync_timestamp: i64,
+}
+
+/// Write a snapshot back as the remote payload. The current remote is
+/// snapshotted first, so a restore can itself be undone.
+async fn restore_snapshot(
+    State(state): State<SyncState>,
+    Path(id): Path<String>,
+) -> Result<Json<RestoreResponse>, SyncError> {
+    info!("[HISTORY] Restoring snapshot {}", id);
+    ensure_backend(&state).await?;
+
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
+
+    let (remote, etag) = backend.pull().await?.unzip();
+    let mut payload =
+        history::load_restorable(&state, &id, backend.as_ref(), remote.as_ref()).await?;
+    if let Some(remote) = &remote {
+        history::record(&state, remote, SnapshotReason::Restore)?;
+    }
+
+    // The restore is a write from this device
+    let now = chrono::Utc::now().timestamp_millis();
+    payload.device_id = state.get_device_id();
+    payload.schema_version = SyncPayload::CURRENT_SCHEMA_VERSION;
+    payload.last_modified = now;
+
+    match backend.push(&payload, etag.as_deref()).await? {
+        PushResult::Success { etag } => {
+            state.set_last_etag(&etag)?;
+            state.set_last_sync(now)?;
+            info!("[HISTORY] Snapshot {} restored, etag: {}", id, etag);
+            Ok(Json(RestoreResponse {
+                payload,
+                etag,
+                sync_timestamp: now,
+            }))
+        }
+        PushResult::Conflict { remote_etag } => Err(SyncError::Conflict(format!(
+            "[HISTORY] Remote changed during restore, etag: {remote_etag}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{local_folder_state, unique_temp_dir},
+        types::{ConflictResolution, FileReference, FileType, LNProgress},
+    };
+
+    async fn push(state: &SyncState, payload: &SyncPayload) {
+        ensure_backend(state).await.expect("backend ready");
+        let active = state.backend.read().await;
+        let backend = active.as_ref().expect("backend active");
+        let etag = match backend.pull().await.expect("pull succeeds") {
+            Some((remote, etag)) => {
+                history::record(state, &remote, SnapshotReason::Push).expect("snapshot recorded");
+                Some(etag)
+            }
+            None => None,
+        };
+        let result = backend
+            .push(payload, etag.as_deref())
+            .await
+            .expect("push succeeds");
+        assert!(matches!(result, PushResult::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn restore_brings_back_an_overwritten_payload() {
+        let root = unique_temp_dir("history-restore");
+        let state = local_folder_state(
+            root.join("data"),
+            &root.join("shared"),
+            ConflictResolution::Automatic,
+        );
+        let mut good = SyncPayload::new("device-a".to_string());
+        good.ln_progress
+            .insert("book".to_string(), LNProgress::default());
+        push(&state, &good).await;
+        push(&state, &SyncPayload::new("device-b".to_string())).await;
+
+        let snapshots = history::list(&state).expect("snapshots listed");
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].device_id, "device-a");
+
+        let Json(restored) = restore_snapshot(State(state.clone()), Path(snapshots[0].id.clone()))
+            .await
+            .expect("restore succeeds");
+        assert!(restored.payload.ln_progress.contains_key("book"));
+        assert_eq!(restored.payload.device_id, state.get_device_id());
+
+        // The overwritten payload is kept too, and the diff shows the change
+        let snapshots = history::list(&state).expect("snapshots listed");
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].reason, SnapshotReason::Restore);
+        let Json(diff) = diff_snapshots(
+            State(state.clone()),
+            Query(DiffQuery {
+                from: snapshots[0].id.clone(),
+                to: snapshots[1].id.clone(),
+            }),
+        )
+        .await
+        .expect("diff succeeds");
+        assert_eq!(diff.ln_progress.added, vec!["book"]);
+    }
+
+    #[tokio::test]
+    async fn restore_is_refused_when_book_objects_are_gone() {
+        let root = unique_temp_dir("history-objects");
+        let state = local_folder_state(
+            root.join("data"),
+            &root.join("shared"),
+            ConflictResolution::Automatic,
+        );
+        let mut old = SyncPayload::new("device-a".to_string());
+        old.file_manifest.insert(
+            "content:book".to_string(),
+            FileReference {
+                book_id: "book".to_string(),
+                file_type: FileType::Content,
+                file_hash: "0123456789abcdef".to_string(),
+                file_size: 1,
+                last_modified: 0,
+                drive_file_id: None,
+            },
+        );
+        push(&state, &old).await;
+        push(&state, &SyncPayload::new("device-b".to_string())).await;
+
+        let snapshots = history::list(&state).expect("snapshots listed");
+        let restored = restore_snapshot(State(state.clone()), Path(snapshots[0].id.clone())).await;
+        assert!(matches!(
+            restored,
+            Err(SyncError::SnapshotIncomplete(missing)) if missing == "content:book"
+        ));
+        assert_eq!(history::list(&state).expect("snapshots listed").len(), 1);
+    }
+}
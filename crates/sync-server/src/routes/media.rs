@@ -0,0 +1,99 @@
+use axum::{Json, Router, extract::State, routing::get};
+use tracing::info;
+
+use crate::{
+    error::SyncError,
+    merge::{DeletionPolicy, merge_payloads},
+    state::SyncState,
+    types::{DeletionBehavior, MediaLibrary, SyncConfig, SyncPayload},
+};
+
+// Manga and anime state lives in Suwayomi rather than in the browser, so the
+// sync server keeps the local copy. The frontend applies its changes here
+// (or sends them with `/merge`) and reads the merged state back after a sync.
+
+pub fn router() -> Router<SyncState> {
+    Router::new().route("/", get(get_media).put(apply_media))
+}
+
+async fn get_media(State(state): State<SyncState>) -> Json<MediaLibrary> {
+    Json(state.get_media_library())
+}
+
+async fn apply_media(
+    State(state): State<SyncState>,
+    Json(changes): Json<MediaLibrary>,
+) -> Result<Json<MediaLibrary>, SyncError> {
+    info!(
+        "[MEDIA] Applying {} manga, {} anime changes",
+        changes.manga.len(),
+        changes.anime.len()
+    );
+    Ok(Json(apply_local_changes(&state, changes)?))
+}
+
+/// Merge local changes into the stored media library and save the result.
+/// Deletions made on this device are applied directly.
+pub(super) fn apply_local_changes(
+    state: &SyncState,
+    changes: MediaLibrary,
+) -> Result<MediaLibrary, SyncError> {
+    let media = merge_local_changes(state, changes);
+    state.set_media_library(&media)?;
+    Ok(media)
+}
+
+/// The stored media library with local changes applied, without saving it
+pub(super) fn merge_local_changes(state: &SyncState, changes: MediaLibrary) -> MediaLibrary {
+    let device_id = state.get_device_id();
+    let mut local = SyncPayload::new(device_id.clone());
+    local.set_media(changes);
+    let mut stored = SyncPayload::new(device_id.clone());
+    stored.set_media(state.get_media_library());
+
+    let policy = DeletionPolicy::new(DeletionBehavior::DeleteEverywhere, Vec::new());
+    let mut merged = merge_payloads(local, stored, &device_id, &policy).payload;
+    merged.take_media()
+}
+
+/// Sections turned off in the config are left out of a sync
+pub(super) fn drop_disabled(config: &SyncConfig, media: &mut MediaLibrary) {
+    if !config.manga {
+        media.manga.clear();
+        media.manga_categories.clear();
+        media.tombstones.manga.clear();
+        media.tombstones.manga_categories.clear();
+    }
+    if !config.anime {
+        media.anime.clear();
+        media.anime_categories.clear();
+        media.tombstones.anime.clear();
+        media.tombstones.anime_categories.clear();
+    }
+}
+
+/// Save the merged media library, keeping the stored copy of disabled sections
+pub(super) fn store_merged(
+    state: &SyncState,
+    config: &SyncConfig,
+    payload: &mut SyncPayload,
+) -> Result<(), SyncError> {
+    let media = payload.take_media();
+    let stored = state.get_media_library();
+    let mut kept = media.clone();
+    if !config.manga {
+        kept.manga = stored.manga;
+        kept.manga_categories = stored.manga_categories;
+        kept.tombstones.manga = stored.tombstones.manga;
+        kept.tombstones.manga_categories = stored.tombstones.manga_categories;
+    }
+    if !config.anime {
+        kept.anime = stored.anime;
+        kept.anime_categories = stored.anime_categories;
+        kept.tombstones.anime = stored.tombstones.anime;
+        kept.tombstones.anime_categories = stored.tombstones.anime_categories;
+    }
+    state.set_media_library(&kept)?;
+    payload.set_media(media);
+    Ok(())
+}
@@ -2,13 +2,26 @@ use axum::Router;
 
 use crate::state::SyncState;
 
-mod auth;
+pub(crate) mod auth;
+mod backup;
 mod config;
-mod sync;
+mod conflicts;
+mod history;
+mod media;
+pub(crate) mod profiles;
+mod reader_settings;
+mod scheduler;
+pub(crate) mod sync;
 
 pub fn router() -> Router<SyncState> {
     Router::new()
         .nest("/auth", auth::router())
+        .nest("/backup", backup::router())
         .nest("/config", config::router())
+        .nest("/conflicts", conflicts::router())
+        .nest("/history", history::router())
+        .nest("/media", media::router())
+        .nest("/reader-settings", reader_settings::router())
+        .nest("/scheduler", scheduler::router())
         .merge(sync::router())
 }
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{error::SyncError, profile};
+
+// Profiles are created here rather than on first use (see `profile`). The
+// router is only mounted for the default profile and works on the shared
+// data directory rather than on a `SyncState`.
+
+pub fn router(data_dir: PathBuf) -> Router {
+    Router::new()
+        .route("/", get(list_profiles).post(create_profile))
+        .with_state(data_dir)
+}
+
+async fn list_profiles(State(data_dir): State<PathBuf>) -> Result<Json<Vec<String>>, SyncError> {
+    Ok(Json(profile::list(&data_dir)?))
+}
+
+#[derive(Deserialize)]
+struct CreateProfileRequest {
+    name: String,
+}
+
+async fn create_profile(
+    State(data_dir): State<PathBuf>,
+    Json(req): Json<CreateProfileRequest>,
+) -> Result<(StatusCode, Json<Vec<String>>), SyncError> {
+    let status = if profile::create(&data_dir, &req.name)? {
+        info!("[PROFILE] Created profile {}", req.name);
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(profile::list(&data_dir)?)))
+}
@@ -0,0 +1,208 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    routing::{delete, get},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    error::SyncError,
+    merge::merge_reader_settings,
+    state::SyncState,
+    types::{
+        LNReaderSettings, ReaderSettingsOverride, ReaderSettingsSync, SyncConfig, SyncPayload,
+    },
+};
+
+// Reader settings are kept here as well as in the browser, so syncs started
+// by the scheduler carry them too. The frontend writes changes with
+// `PUT /reader-settings` (or sends them with `/merge`) and reads the
+// settings for this device back.
+
+pub fn router() -> Router<SyncState> {
+    Router::new()
+        .route("/", get(get_settings).put(update_settings))
+        .route("/device", delete(clear_device_override))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReaderSettingsResponse {
+    /// Settings to use on this device
+    settings: LNReaderSettings,
+    global: LNReaderSettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_override: Option<ReaderSettingsOverride>,
+}
+
+impl ReaderSettingsResponse {
+    fn new(state: &SyncState, stored: ReaderSettingsSync) -> Self {
+        let device_id = state.get_device_id();
+        Self {
+            settings: stored.effective(&device_id),
+            device_override: stored
+                .device_overrides
+                .get(&device_id)
+                .filter(|device| !device.settings.is_empty())
+                .cloned(),
+            global: stored.global,
+        }
+    }
+}
+
+async fn get_settings(State(state): State<SyncState>) -> Json<ReaderSettingsResponse> {
+    let stored = state.get_reader_settings().unwrap_or_default();
+    Json(ReaderSettingsResponse::new(&state, stored))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum ReaderSettingsScope {
+    /// Change the settings of every device
+    #[default]
+    Global,
+    /// Keep the settings that differ from the global ones for this device only
+    Device,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateReaderSettingsRequest {
+    settings: LNReaderSettings,
+    #[serde(default)]
+    scope: ReaderSettingsScope,
+}
+
+async fn update_settings(
+    State(state): State<SyncState>,
+    Json(req): Json<UpdateReaderSettingsRequest>,
+) -> Result<Json<ReaderSettingsResponse>, SyncError> {
+    info!("[READER] Updating {:?} reader settings", req.scope);
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut changes = state.get_reader_settings().unwrap_or_default();
+    match req.scope {
+        ReaderSettingsScope::Global => {
+            changes.global = req.settings;
+            changes.global_modified = now;
+        }
+        ReaderSettingsScope::Device => {
+            let device = ReaderSettingsOverride {
+                settings: changed_settings(&changes.global, &req.settings)?,
+                last_modified: now,
+            };
+            changes
+                .device_overrides
+                .insert(state.get_device_id(), device);
+        }
+    }
+    state.set_reader_settings(&changes)?;
+    Ok(Json(ReaderSettingsResponse::new(&state, changes)))
+}
+
+async fn clear_device_override(
+    State(state): State<SyncState>,
+) -> Result<Json<ReaderSettingsResponse>, SyncError> {
+    info!("[READER] Clearing device reader settings");
+    let mut changes = state.get_reader_settings().unwrap_or_default();
+    changes.device_overrides.insert(
+        state.get_device_id(),
+        ReaderSettingsOverride {
+            settings: serde_json::Map::new(),
+            last_modified: chrono::Utc::now().timestamp_millis(),
+        },
+    );
+    state.set_reader_settings(&changes)?;
+    Ok(Json(ReaderSettingsResponse::new(&state, changes)))
+}
+
+/// Settings in `settings` that differ from `global`
+fn changed_settings(
+    global: &LNReaderSettings,
+    settings: &LNReaderSettings,
+) -> Result<serde_json::Map<String, serde_json::Value>, SyncError> {
+    let serde_json::Value::Object(global) = serde_json::to_value(global)? else {
+        return Ok(serde_json::Map::new());
+    };
+    let serde_json::Value::Object(settings) = serde_json::to_value(settings)? else {
+        return Ok(serde_json::Map::new());
+    };
+    Ok(settings
+        .into_iter()
+        .filter(|(key, value)| global.get(key) != Some(value))
+        .collect())
+}
+
+/// Merge the settings the frontend sent with the stored ones, leaving them
+/// out of the sync when the section is turned off
+pub(super) fn prepare_local(
+    state: &SyncState,
+    config: &SyncConfig,
+    payload: &mut SyncPayload,
+) -> Result<(), SyncError> {
+    payload.reader_settings = local_settings(state, config, payload.reader_settings.take());
+    if let Some(settings) = &payload.reader_settings {
+        state.set_reader_settings(settings)?;
+    }
+    Ok(())
+}
+
+/// The stored settings with the ones the frontend sent merged in, without
+/// saving them
+pub(super) fn local_settings(
+    state: &SyncState,
+    config: &SyncConfig,
+    sent: Option<ReaderSettingsSync>,
+) -> Option<ReaderSettingsSync> {
+    if !config.reader_settings {
+        return None;
+    }
+    merge_reader_settings(sent, state.get_reader_settings())
+}
+
+/// Save the merged settings, unless the section is turned off
+pub(super) fn store_merged(
+    state: &SyncState,
+    config: &SyncConfig,
+    payload: &SyncPayload,
+) -> Result<(), SyncError> {
+    if config.reader_settings
+        && let Some(settings) = &payload.reader_settings
+    {
+        state.set_reader_settings(settings)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_override_keeps_only_changed_settings() {
+        let global = LNReaderSettings::default_settings();
+        let mut phone = global.clone();
+        phone.ln_font_size = 14.0;
+
+        let changed = changed_settings(&global, &phone).expect("settings compared");
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed.get("lnFontSize"), Some(&serde_json::json!(14.0)));
+
+        let mut stored = ReaderSettingsSync::default();
+        stored.device_overrides.insert(
+            "phone".to_string(),
+            ReaderSettingsOverride {
+                settings: changed,
+                last_modified: 1,
+            },
+        );
+        // A later global change still applies to the phone, apart from the
+        // overridden font size
+        stored.global.ln_theme = "light".to_string();
+        stored.global.ln_font_size = 20.0;
+        let effective = stored.effective("phone");
+        assert_eq!(effective.ln_font_size, 14.0);
+        assert_eq!(effective.ln_theme, "light");
+        assert_eq!(stored.effective("desktop").ln_font_size, 20.0);
+    }
+}
@@ -0,0 +1,54 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    scheduler::{SchedulerStatus, SyncTrigger},
+    state::SyncState,
+};
+
+pub fn router() -> Router<SyncState> {
+    Router::new()
+        .route("/status", get(get_status))
+        .route("/trigger", post(trigger_sync))
+}
+
+async fn get_status(State(state): State<SyncState>) -> Json<SchedulerStatus> {
+    Json(state.scheduler.status())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TriggerRequest {
+    trigger: SyncTrigger,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TriggerResponse {
+    /// False when the config disables syncing on this trigger or there is no
+    /// library to sync in the background
+    scheduled: bool,
+    status: SchedulerStatus,
+}
+
+async fn trigger_sync(
+    State(state): State<SyncState>,
+    Json(req): Json<TriggerRequest>,
+) -> Json<TriggerResponse> {
+    let scheduled = state
+        .scheduler
+        .trigger(&state.get_sync_config(), req.trigger);
+    info!(
+        "[SCHEDULER] Trigger {:?} received, scheduled: {}",
+        req.trigger, scheduled
+    );
+    Json(TriggerResponse {
+        scheduled,
+        status: state.scheduler.status(),
+    })
+}
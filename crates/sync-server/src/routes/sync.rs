@@ -1,60 +1,161 @@
+use std::collections::HashMap;
+
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Query, State},
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
-use tracing::{debug, info};
+use serde::Deserialize;
+use tracing::{info, warn};
 
 use crate::{
-    backend::{PushResult, SyncBackend, google_drive::GoogleDriveBackend},
+    backend::{PushResult, ensure_backend},
+    conflicts::{self, MergeSides},
+    delta::{self, BookObjects},
+    diff::{MergePreview, diff_payloads},
     error::SyncError,
-    merge::merge_payloads,
+    history::{self, SnapshotReason},
+    merge::{DeletionPolicy, merge_payloads},
+    routes::{media, reader_settings},
     state::SyncState,
-    types::{MergeRequest, MergeResponse, SyncPayload},
+    types::{ConflictResolution, MergeRequest, MergeResponse, SyncPayload},
 };
 
 pub fn router() -> Router<SyncState> {
     Router::new()
-        .route("/merge", post(merge_handler))
+        .route("/merge", post(merge_or_preview))
         .route("/pull", get(pull_handler))
         .route("/push", post(push_handler))
 }
 
-async fn ensure_backend(state: &SyncState) -> Result<(), SyncError> {
-    let mut gdrive = state.google_drive.write().await;
-
-    if gdrive.is_none() {
-        let access_token = state.get_access_token();
-        let refresh_token = state.get_refresh_token();
-
-        if access_token.is_some() && refresh_token.is_some() {
-            let mut backend = GoogleDriveBackend::new(state.clone());
-            backend.initialize().await?;
-            *gdrive = Some(backend);
-        } else {
-            return Err(SyncError::NotAuthenticated);
-        }
-    }
+#[derive(Deserialize)]
+struct MergeQuery {
+    /// Only report what the merge would change
+    #[serde(default)]
+    dry_run: bool,
+}
 
-    // Refresh token before operations
-    if let Some(backend) = gdrive.as_mut()
-        && let Err(e) = backend.refresh_token().await
-    {
-        debug!("Token refresh failed (may be okay): {}", e);
+/// `POST /merge`, or a preview of it with `?dry_run=true`
+async fn merge_or_preview(
+    State(state): State<SyncState>,
+    Query(query): Query<MergeQuery>,
+    Json(req): Json<MergeRequest>,
+) -> Result<Response, SyncError> {
+    if query.dry_run {
+        return Ok(Json(preview(&state, req).await?).into_response());
     }
-
-    Ok(())
+    Ok(merge_handler(State(state), Json(req))
+        .await?
+        .into_response())
 }
 
 async fn merge_handler(
     State(state): State<SyncState>,
     Json(req): Json<MergeRequest>,
 ) -> Result<Json<MergeResponse>, SyncError> {
+    Ok(Json(merge(state, req).await?))
+}
+
+/// Run the merge against the remote without saving or pushing anything
+async fn preview(state: &SyncState, req: MergeRequest) -> Result<MergePreview, SyncError> {
+    info!("[MERGE] Previewing sync operation...");
+    ensure_backend(state).await?;
+
+    let device_id = state.get_device_id();
+    let config = req.config.unwrap_or_else(|| state.get_sync_config());
+    let mut local_payload = req.payload;
+    let mut local_media = media::merge_local_changes(state, local_payload.take_media());
+    media::drop_disabled(&config, &mut local_media);
+    local_payload.set_media(local_media);
+    local_payload.reader_settings =
+        reader_settings::local_settings(state, &config, local_payload.reader_settings.take());
+
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
+    let remote_payload = backend
+        .pull()
+        .await?
+        .map(|(remote_payload, _)| remote_payload);
+    drop(active);
+
+    // Book content is compared by hash, as in the merge
+    delta::extract_objects(&mut local_payload)?;
+    let preview = match remote_payload {
+        Some(mut remote_payload) if remote_payload.device_id != device_id => {
+            delta::extract_objects(&mut remote_payload)?;
+            let policy =
+                DeletionPolicy::new(config.deletion_behavior.clone(), req.deletion_decisions);
+            let sides = (config.conflict_resolution == ConflictResolution::Manual)
+                .then(|| MergeSides::new(&local_payload, &remote_payload));
+            let mut outcome = merge_payloads(
+                local_payload.clone(),
+                remote_payload.clone(),
+                &device_id,
+                &policy,
+            );
+            // Held conflicts keep the remote value, as in the merge
+            if let Some(sides) = sides {
+                conflicts::preview_hold(
+                    state,
+                    sides,
+                    &mut outcome.conflicts,
+                    &mut outcome.payload,
+                )?;
+            }
+            conflicts::apply_resolutions(state, &mut outcome.payload)?;
+            MergePreview {
+                local: diff_payloads(&local_payload, &outcome.payload),
+                remote: diff_payloads(&remote_payload, &outcome.payload),
+                conflicts: outcome.conflicts,
+                pending_deletions: outcome.pending_deletions,
+            }
+        }
+        // The local payload would be pushed as it is
+        remote_payload => {
+            let mut remote_payload =
+                remote_payload.unwrap_or_else(|| SyncPayload::new(String::new()));
+            delta::extract_objects(&mut remote_payload)?;
+            MergePreview {
+                remote: diff_payloads(&remote_payload, &local_payload),
+                ..MergePreview::default()
+            }
+        }
+    };
+    info!(
+        "[MERGE] Preview: {} conflicts, {} pending deletions",
+        preview.conflicts.len(),
+        preview.pending_deletions.len()
+    );
+    Ok(preview)
+}
+
+/// Merge a local payload with the remote one and push the result
+pub(crate) async fn merge(state: SyncState, req: MergeRequest) -> Result<MergeResponse, SyncError> {
+    merge_with(state, req, true).await
+}
+
+/// Like `merge`, but leaves the book objects on the backend alone. Background
+/// syncs have no book content to send and nowhere to put downloaded content.
+pub(crate) async fn merge_reading_state(
+    state: SyncState,
+    req: MergeRequest,
+) -> Result<MergeResponse, SyncError> {
+    merge_with(state, req, false).await
+}
+
+async fn merge_with(
+    state: SyncState,
+    req: MergeRequest,
+    transfer_objects: bool,
+) -> Result<MergeResponse, SyncError> {
     info!("[MERGE] Starting sync operation...");
     ensure_backend(&state).await?;
 
-    // Apply config if provided
-    if let Some(config) = req.config {
+    // Apply config if provided. Switching backends goes through
+    // `PUT /config/backend`, so the active backend is kept as-is here.
+    if let Some(mut config) = req.config {
+        config.backend = state.get_sync_config().backend;
         state.set_sync_config(&config)?;
         info!(
             "[MERGE] Config updated - sync settings: progress={}, metadata={}, content={}, files={}",
@@ -63,7 +164,15 @@ async fn merge_handler(
     }
 
     let device_id = state.get_device_id();
-    let local_payload = req.payload;
+    let config = state.get_sync_config();
+    let mut local_payload = req.payload;
+
+    // Manga and anime state is stored here; apply whatever the frontend sent
+    // on top of it
+    let mut local_media = media::apply_local_changes(&state, local_payload.take_media())?;
+    media::drop_disabled(&config, &mut local_media);
+    local_payload.set_media(local_media);
+    reader_settings::prepare_local(&state, &config, &mut local_payload)?;
 
     // Log local data summary
     let local_progress_count = local_payload.ln_progress.len();
@@ -80,14 +189,37 @@ async fn merge_handler(
     );
 
     // Pull remote data
-    let gdrive = state.google_drive.read().await;
-    let backend = gdrive.as_ref().ok_or(SyncError::NotAuthenticated)?;
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
 
-    info!("[MERGE] Downloading remote data from Google Drive...");
-    let remote_result = backend.pull().await?;
+    info!(
+        "[MERGE] Downloading remote data from {:?}...",
+        backend.backend_type()
+    );
+    // Backends with object storage keep each book's content in its own
+    // object, so only books whose hash changed are transferred
+    let delta_sync = backend.supports_objects();
+    let local_objects = if delta_sync {
+        delta::extract_objects(&mut local_payload)?
+    } else {
+        BookObjects::new()
+    };
+    let local_manifest = local_payload.file_manifest.clone();
+
+    let mut remote_result = backend.pull().await?;
+
+    let mut remote_manifest = HashMap::new();
+    let mut remote_objects = BookObjects::new();
+    if delta_sync && let Some((remote_payload, _)) = remote_result.as_mut() {
+        remote_objects = delta::extract_objects(remote_payload)?;
+        remote_manifest = remote_payload.file_manifest.clone();
+    }
 
-    let (merged_payload, conflicts, etag) = if let Some((remote_payload, remote_etag)) =
-        remote_result
+    let policy = DeletionPolicy::new(config.deletion_behavior.clone(), req.deletion_decisions);
+    let (mut merged_payload, conflicts, pending_deletions, etag) = if let Some((
+        remote_payload,
+        remote_etag,
+    )) = remote_result
     {
         let remote_progress_count = remote_payload.ln_progress.len();
         let remote_metadata_count = remote_payload.ln_metadata.len();
@@ -101,6 +233,7 @@ async fn merge_handler(
         );
 
         let remote_device_id = remote_payload.device_id.clone();
+        history::record(&state, &remote_payload, SnapshotReason::Merge)?;
 
         // Check if same device
         if remote_device_id == device_id {
@@ -108,44 +241,93 @@ async fn merge_handler(
                 "[MERGE] Same device detected ({}), will overwrite remote",
                 device_id
             );
-            (local_payload.clone(), vec![], Some(remote_etag))
+            (local_payload.clone(), vec![], vec![], Some(remote_etag))
         } else {
             info!(
                 "[MERGE] Different device detected. Local device: {}, Remote device: {}",
                 device_id, remote_device_id
             );
             info!("[MERGE] Merging payloads...");
-            let (merged, conflicts) = merge_payloads(local_payload, remote_payload, &device_id);
-
-            let merged_progress = merged.ln_progress.len();
-            let merged_metadata = merged.ln_metadata.len();
+            let sides = (config.conflict_resolution == ConflictResolution::Manual)
+                .then(|| MergeSides::new(&local_payload, &remote_payload));
+            let mut outcome = merge_payloads(local_payload, remote_payload, &device_id, &policy);
+            if let Some(sides) = sides {
+                conflicts::hold(&state, sides, &mut outcome.conflicts, &mut outcome.payload)?;
+            }
+
+            let merged_progress = outcome.payload.ln_progress.len();
+            let merged_metadata = outcome.payload.ln_metadata.len();
             info!(
-                "[MERGE] Merge complete: {} progress entries, {} metadata entries, {} conflicts",
+                "[MERGE] Merge complete: {} progress entries, {} metadata entries, {} conflicts, {} pending deletions",
                 merged_progress,
                 merged_metadata,
-                conflicts.len()
+                outcome.conflicts.len(),
+                outcome.pending_deletions.len()
             );
 
-            (merged, conflicts, Some(remote_etag))
+            (
+                outcome.payload,
+                outcome.conflicts,
+                outcome.pending_deletions,
+                Some(remote_etag),
+            )
         }
     } else {
         info!("[MERGE] No remote data found, using local data only");
-        (local_payload, vec![], None)
+        (local_payload, vec![], vec![], None)
     };
+    // Choices made for held conflicts go out with this push
+    let resolved = conflicts::apply_resolutions(&state, &mut merged_payload)?;
+
+    let (files_to_upload, files_to_download, downloaded) = if delta_sync {
+        // Deleted books must neither be uploaded nor downloaded again
+        let mut local_manifest = local_manifest;
+        let mut live_remote_manifest = remote_manifest.clone();
+        for manifest in [&mut local_manifest, &mut live_remote_manifest] {
+            delta::drop_deleted(manifest, &merged_payload.tombstones, &pending_deletions);
+        }
 
-    drop(gdrive);
+        // Without object transfer the remote manifest stands in for the
+        // local one, so every object counts as unchanged
+        let (local_manifest, local_objects) = match transfer_objects {
+            true => (local_manifest, local_objects),
+            false => (live_remote_manifest.clone(), BookObjects::new()),
+        };
+        let (plan, objects) = delta::transfer(
+            &state,
+            backend.as_ref(),
+            &local_manifest,
+            local_objects,
+            &live_remote_manifest,
+            remote_objects,
+        )
+        .await?;
+        merged_payload.file_manifest = plan.manifest;
+        (plan.request, plan.download, objects)
+    } else {
+        (vec![], vec![], BookObjects::new())
+    };
 
     // Push merged data
-    let gdrive = state.google_drive.read().await;
-    let backend = gdrive.as_ref().ok_or(SyncError::NotAuthenticated)?;
-
-    info!("[MERGE] Uploading merged data to Google Drive...");
+    info!(
+        "[MERGE] Uploading merged data to {:?}...",
+        backend.backend_type()
+    );
     let push_result = backend.push(&merged_payload, etag.as_deref()).await?;
 
     match push_result {
         PushResult::Success { etag: new_etag } => {
             info!("[MERGE] Upload successful! New etag: {}", new_etag);
             state.set_last_etag(&new_etag)?;
+            conflicts::clear(&state, &resolved)?;
+            if delta_sync {
+                delta::remove_replaced(
+                    backend.as_ref(),
+                    &remote_manifest,
+                    &merged_payload.file_manifest,
+                )
+                .await;
+            }
         }
         PushResult::Conflict { remote_etag } => {
             return Err(SyncError::Conflict(format!(
@@ -154,8 +336,13 @@ async fn merge_handler(
         }
     }
 
+    drop(active);
+
     let now = chrono::Utc::now().timestamp_millis();
     state.set_last_sync(now)?;
+    delta::restore_objects(&mut merged_payload, downloaded)?;
+    media::store_merged(&state, &config, &mut merged_payload)?;
+    reader_settings::store_merged(&state, &config, &merged_payload)?;
 
     let final_progress = merged_payload.ln_progress.len();
     let final_metadata = merged_payload.ln_metadata.len();
@@ -168,25 +355,31 @@ async fn merge_handler(
     info!("[MERGE] Conflicts resolved: {}", conflicts.len());
     info!("[MERGE] ==================================");
 
-    Ok(Json(MergeResponse {
+    Ok(MergeResponse {
         payload: merged_payload,
         sync_timestamp: now,
-        files_to_upload: vec![],
-        files_to_download: vec![],
+        files_to_upload,
+        files_to_download,
         conflicts,
-    }))
+        pending_deletions,
+    })
 }
 
 async fn pull_handler(
     State(state): State<SyncState>,
 ) -> Result<Json<Option<SyncPayload>>, SyncError> {
+    Ok(Json(pull(&state).await?.map(|(payload, _)| payload)))
+}
+
+/// Download the remote payload and its etag
+pub(crate) async fn pull(state: &SyncState) -> Result<Option<(SyncPayload, String)>, SyncError> {
     info!("[PULL] Starting pull operation...");
-    ensure_backend(&state).await?;
+    ensure_backend(state).await?;
 
-    let gdrive = state.google_drive.read().await;
-    let backend = gdrive.as_ref().ok_or(SyncError::NotAuthenticated)?;
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
 
-    info!("[PULL] Downloading from Google Drive...");
+    info!("[PULL] Downloading from {:?}...", backend.backend_type());
     let result = backend.pull().await?;
 
     match &result {
@@ -203,7 +396,7 @@ async fn pull_handler(
         }
     }
 
-    Ok(Json(result.map(|(payload, _)| payload)))
+    Ok(result)
 }
 
 #[derive(serde::Deserialize)]
@@ -225,42 +418,366 @@ async fn push_handler(
     State(state): State<SyncState>,
     Json(req): Json<PushRequest>,
 ) -> Result<Json<PushResponse>, SyncError> {
+    Ok(Json(push(&state, req.payload, req.etag.as_deref()).await?))
+}
+
+/// Replace the remote payload, failing if it no longer matches `etag`
+pub(crate) async fn push(
+    state: &SyncState,
+    mut payload: SyncPayload,
+    etag: Option<&str>,
+) -> Result<PushResponse, SyncError> {
     info!("[PUSH] Starting push operation...");
 
-    let payload_size = req.payload.ln_progress.len();
-    let metadata_size = req.payload.ln_metadata.len();
+    let payload_size = payload.ln_progress.len();
+    let metadata_size = payload.ln_metadata.len();
     info!(
         "[PUSH] Pushing: {} progress, {} metadata entries",
         payload_size, metadata_size
     );
 
-    ensure_backend(&state).await?;
+    ensure_backend(state).await?;
 
-    let gdrive = state.google_drive.read().await;
-    let backend = gdrive.as_ref().ok_or(SyncError::NotAuthenticated)?;
+    let active = state.backend.read().await;
+    let backend = active.as_ref().ok_or(SyncError::NotAuthenticated)?;
 
-    info!("[PUSH] Uploading to Google Drive...");
-    let result = backend.push(&req.payload, req.etag.as_deref()).await?;
+    // Keep the payload being overwritten; a remote that can't be read must
+    // not block the push that is meant to replace it
+    match backend.pull().await {
+        Ok(Some((remote, _))) => {
+            history::record(state, &remote, SnapshotReason::Push)?;
+        }
+        Ok(None) => {}
+        Err(e) => warn!("[PUSH] Could not snapshot remote data: {}", e),
+    }
+
+    let resolved = conflicts::apply_resolutions(state, &mut payload)?;
+
+    info!("[PUSH] Uploading to {:?}...", backend.backend_type());
+    let result = backend.push(&payload, etag).await?;
 
     match result {
         PushResult::Success { etag } => {
             let now = chrono::Utc::now().timestamp_millis();
             state.set_last_sync(now)?;
             state.set_last_etag(&etag)?;
+            conflicts::clear(state, &resolved)?;
 
             info!(
                 "[PUSH] Upload successful! Timestamp: {}, etag: {}",
                 now, etag
             );
 
-            Ok(Json(PushResponse {
+            Ok(PushResponse {
                 success: true,
                 etag,
                 sync_timestamp: now,
-            }))
+            })
         }
         PushResult::Conflict { remote_etag } => Err(SyncError::Conflict(format!(
             "[PUSH] Conflict detected! Remote etag: {remote_etag}"
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend,
+        test_util::{local_folder_state, unique_temp_dir},
+        types::{LNParsedBook, LNProgress, MangaChapterState, MangaEntry, MediaLibrary},
+    };
+
+    fn payload_with_progress(state: &SyncState, book_id: &str, chapter: i32) -> SyncPayload {
+        let mut payload = SyncPayload::new(state.get_device_id());
+        payload.ln_progress.insert(
+            book_id.to_string(),
+            LNProgress {
+                chapter_index: chapter,
+                last_modified: Some(chrono::Utc::now().timestamp_millis()),
+                ..LNProgress::default()
+            },
+        );
+        payload
+    }
+
+    #[tokio::test]
+    async fn merge_combines_devices_through_a_shared_local_folder() {
+        let root = unique_temp_dir("two-devices");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Automatic);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Automatic);
+
+        let Json(first) = merge_handler(
+            State(device_a.clone()),
+            Json(MergeRequest {
+                payload: payload_with_progress(&device_a, "book-a", 3),
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("first merge succeeds");
+        assert_eq!(first.payload.ln_progress.len(), 1);
+
+        let Json(second) = merge_handler(
+            State(device_b.clone()),
+            Json(MergeRequest {
+                payload: payload_with_progress(&device_b, "book-b", 7),
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("second merge succeeds");
+
+        assert_eq!(second.payload.ln_progress.len(), 2);
+        assert_eq!(second.payload.ln_progress["book-a"].chapter_index, 3);
+        assert_eq!(second.payload.ln_progress["book-b"].chapter_index, 7);
+    }
+
+    #[tokio::test]
+    async fn merge_transfers_only_changed_books() {
+        let root = unique_temp_dir("delta");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Automatic);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Automatic);
+
+        let mut payload = payload_with_progress(&device_a, "book", 1);
+        payload.ln_content.insert(
+            "book".to_string(),
+            LNParsedBook {
+                chapters: vec!["<p>一</p>".to_string()],
+                image_blobs: HashMap::new(),
+                chapter_filenames: vec!["ch1.xhtml".to_string()],
+                css: None,
+            },
+        );
+        let Json(first) = merge_handler(
+            State(device_a.clone()),
+            Json(MergeRequest {
+                payload,
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("first merge succeeds");
+        // The uploader already has the content, so it is not sent back
+        assert!(first.payload.ln_content.is_empty());
+        assert!(first.files_to_download.is_empty());
+        let manifest = first.payload.file_manifest.clone();
+        assert!(manifest.contains_key("content:book"));
+
+        let Json(second) = merge_handler(
+            State(device_b.clone()),
+            Json(MergeRequest {
+                payload: SyncPayload::new(device_b.get_device_id()),
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("second merge succeeds");
+        assert_eq!(second.files_to_download, vec!["content:book"]);
+        assert_eq!(
+            second.payload.ln_content["book"].chapters,
+            vec!["<p>一</p>"]
+        );
+
+        // Sending only the manifest means "I still have this version"
+        let mut payload = payload_with_progress(&device_a, "book", 2);
+        payload.file_manifest = manifest;
+        let Json(third) = merge_handler(
+            State(device_a.clone()),
+            Json(MergeRequest {
+                payload,
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("third merge succeeds");
+        assert!(third.files_to_download.is_empty());
+        assert!(third.files_to_upload.is_empty());
+
+        let objects = std::fs::read_dir(folder.join(delta::OBJECT_FOLDER))
+            .expect("object folder exists")
+            .filter_map(Result::ok)
+            .count();
+        assert_eq!(objects, 1);
+    }
+
+    #[tokio::test]
+    async fn encrypted_library_needs_the_right_passphrase() {
+        let root = unique_temp_dir("encrypted");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Automatic);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Automatic);
+
+        let merge = |state: SyncState, payload: SyncPayload| {
+            merge_handler(
+                State(state),
+                Json(MergeRequest {
+                    payload,
+                    config: None,
+                    deletion_decisions: vec![],
+                }),
+            )
+        };
+
+        let _ = merge(
+            device_a.clone(),
+            payload_with_progress(&device_a, "book", 4),
+        )
+        .await
+        .expect("plain merge succeeds");
+        backend::change_passphrase(&device_a, Some("correct horse"))
+            .await
+            .expect("library encrypted");
+        let on_disk = std::fs::read(folder.join(backend::SYNC_FILE_NAME)).expect("sync file");
+        assert!(crate::crypto::is_encrypted(&on_disk));
+
+        let empty = SyncPayload::new(device_b.get_device_id());
+        assert!(matches!(
+            merge(device_b.clone(), empty.clone()).await,
+            Err(SyncError::PassphraseRequired)
+        ));
+        assert!(matches!(
+            backend::change_passphrase(&device_b, Some("battery staple")).await,
+            Err(SyncError::WrongPassphrase)
+        ));
+        assert!(device_b.get_encryption_passphrase().is_none());
+
+        backend::change_passphrase(&device_b, Some("correct horse"))
+            .await
+            .expect("passphrase verified");
+        let Json(merged) = merge(device_b.clone(), empty)
+            .await
+            .expect("merge with passphrase succeeds");
+        assert_eq!(merged.payload.ln_progress["book"].chapter_index, 4);
+    }
+
+    #[tokio::test]
+    async fn manga_state_reaches_the_other_device() {
+        let root = unique_temp_dir("manga");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Automatic);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Automatic);
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut changes = MediaLibrary::default();
+        changes.manga.insert(
+            "1:/manga/1".to_string(),
+            MangaEntry {
+                source_id: "1".to_string(),
+                url: "/manga/1".to_string(),
+                title: "Manga".to_string(),
+                in_library: true,
+                category_ids: vec![],
+                chapters: HashMap::from([(
+                    "/chapter/1".to_string(),
+                    MangaChapterState {
+                        read: true,
+                        last_modified: now,
+                        ..MangaChapterState::default()
+                    },
+                )]),
+                last_modified: now,
+            },
+        );
+        media::apply_local_changes(&device_a, changes).expect("changes applied");
+
+        for state in [&device_a, &device_b] {
+            let _ = merge_handler(
+                State(state.clone()),
+                Json(MergeRequest {
+                    payload: SyncPayload::new(state.get_device_id()),
+                    config: None,
+                    deletion_decisions: vec![],
+                }),
+            )
+            .await
+            .expect("merge succeeds");
+        }
+
+        let library = device_b.get_media_library();
+        assert!(library.manga["1:/manga/1"].chapters["/chapter/1"].read);
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_changes_without_pushing() {
+        let root = unique_temp_dir("dry-run");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Automatic);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Automatic);
+
+        let _ = merge_handler(
+            State(device_a.clone()),
+            Json(MergeRequest {
+                payload: payload_with_progress(&device_a, "book", 3),
+                config: None,
+                deletion_decisions: vec![],
+            }),
+        )
+        .await
+        .expect("first merge succeeds");
+
+        let request = MergeRequest {
+            payload: payload_with_progress(&device_b, "book", 5),
+            config: None,
+            deletion_decisions: vec![],
+        };
+        let preview = preview(&device_b, request).await.expect("preview succeeds");
+        assert_eq!(preview.remote.ln_progress.changed, vec!["book"]);
+        assert!(preview.remote.ln_progress.fields["book"].contains(&"chapterIndex".to_string()));
+        assert!(preview.local.ln_progress.is_empty());
+
+        // Nothing was written: device A still reads its own progress
+        assert!(device_b.get_last_sync().is_none());
+        let active = device_a.backend.read().await;
+        let backend = active.as_ref().expect("backend active");
+        let (remote, _) = backend
+            .pull()
+            .await
+            .expect("pull succeeds")
+            .expect("remote exists");
+        assert_eq!(remote.ln_progress["book"].chapter_index, 3);
+    }
+
+    #[tokio::test]
+    async fn dry_run_holds_conflicts_without_storing_them() {
+        let root = unique_temp_dir("dry-run-manual");
+        let folder = root.join("shared");
+        let device_a = local_folder_state(root.join("a"), &folder, ConflictResolution::Manual);
+        let device_b = local_folder_state(root.join("b"), &folder, ConflictResolution::Manual);
+        let request = |state: &SyncState, total_progress: f64| {
+            let mut payload = payload_with_progress(state, "book", 1);
+            if let Some(progress) = payload.ln_progress.get_mut("book") {
+                progress.total_progress = total_progress;
+            }
+            MergeRequest {
+                payload,
+                config: None,
+                deletion_decisions: vec![],
+            }
+        };
+        merge(device_a.clone(), request(&device_a, 0.3))
+            .await
+            .expect("first merge succeeds");
+
+        // B read further, but the conflict is held and the remote value kept
+        let preview = preview(&device_b, request(&device_b, 0.5))
+            .await
+            .expect("preview succeeds");
+        assert_eq!(preview.conflicts[0].resolution, "pending");
+        assert!(preview.remote.ln_progress.is_empty());
+        assert_eq!(preview.local.ln_progress.changed, vec!["book"]);
+        assert!(
+            conflicts::list(&device_b)
+                .expect("conflicts listed")
+                .is_empty()
+        );
+    }
+}
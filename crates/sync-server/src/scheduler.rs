@@ -0,0 +1,564 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{
+    backend::active_backend_type,
+    error::SyncError,
+    routes::sync::merge_reading_state,
+    state::SyncState,
+    types::{MergeRequest, PendingDeletion, SyncBackendType, SyncConfig},
+};
+
+// ============================================================================
+// Background Sync
+// ============================================================================
+//
+// Runs merges without a frontend, so headless installs stay in sync. Events
+// (app start, chapter read, ...) are debounced, periodic syncs follow
+// `sync_interval_minutes`, and failures are retried with exponential backoff.
+
+/// First retry delay after a failed sync
+const BACKOFF_BASE_MS: i64 = 30 * 1000;
+/// Longest delay between retries
+const BACKOFF_MAX_MS: i64 = 60 * 60 * 1000;
+
+/// What caused a sync
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncTrigger {
+    AppStart,
+    AppResume,
+    ChapterRead,
+    ChapterOpen,
+    Periodic,
+    Retry,
+    Manual,
+}
+
+impl SyncTrigger {
+    /// Whether the config asks for a sync on this trigger
+    fn enabled(self, config: &SyncConfig) -> bool {
+        match self {
+            SyncTrigger::AppStart => config.sync_on_app_start,
+            SyncTrigger::AppResume => config.sync_on_app_resume,
+            SyncTrigger::ChapterRead => config.sync_on_chapter_read,
+            SyncTrigger::ChapterOpen => config.sync_on_chapter_open,
+            SyncTrigger::Periodic => config.sync_interval_minutes > 0,
+            SyncTrigger::Retry | SyncTrigger::Manual => true,
+        }
+    }
+}
+
+/// Scheduler state reported by `GET /scheduler/status`
+#[derive(Debug, Clone, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulerStatus {
+    /// False without a local library to sync; nothing is scheduled then
+    pub enabled: bool,
+    pub running: bool,
+    pub last_run: Option<i64>,
+    pub last_success: Option<i64>,
+    /// Last run that found no backend configured and did nothing
+    pub last_skipped: Option<i64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<i64>,
+    pub consecutive_failures: u32,
+    pub next_run: Option<i64>,
+    pub next_trigger: Option<SyncTrigger>,
+    /// Deletions the last successful run left for the user to confirm; the
+    /// entries are kept locally until a merge answers them
+    pub pending_deletions: Vec<PendingDeletion>,
+}
+
+/// Handle to the background sync loop, shared through `SyncState`
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    status: Arc<Mutex<SchedulerStatus>>,
+    wake: Arc<Notify>,
+}
+
+impl Scheduler {
+    pub fn status(&self) -> SchedulerStatus {
+        self.lock().clone()
+    }
+
+    /// Schedule a sync for `trigger` if the config enables it, returning
+    /// whether one was scheduled
+    pub fn trigger(&self, config: &SyncConfig, trigger: SyncTrigger) -> bool {
+        if !self.lock().enabled || !trigger.enabled(config) {
+            return false;
+        }
+        let debounce = i64::from(config.sync_debounce_seconds) * 1000;
+        self.schedule_event(trigger, debounce, now());
+        true
+    }
+
+    /// Pick up a changed sync interval
+    pub fn reschedule(&self, config: &SyncConfig) {
+        let mut status = self.lock();
+        if !status.enabled {
+            return;
+        }
+        match (status.next_trigger, config.sync_interval_minutes) {
+            (Some(SyncTrigger::Periodic), 0) => {
+                status.next_run = None;
+                status.next_trigger = None;
+            }
+            (None | Some(SyncTrigger::Periodic), minutes) if minutes > 0 => {
+                let from = status.last_run.unwrap_or_else(now);
+                status.next_run = Some(from + interval_ms(minutes));
+                status.next_trigger = Some(SyncTrigger::Periodic);
+            }
+            _ => {}
+        }
+        drop(status);
+        self.wake.notify_one();
+    }
+
+    /// Debounce: every event pushes the run back, but never before a pending
+    /// retry so a failing backend is not hammered
+    fn schedule_event(&self, trigger: SyncTrigger, debounce_ms: i64, now: i64) {
+        let mut status = self.lock();
+        let mut at = now + debounce_ms;
+        if status.next_trigger == Some(SyncTrigger::Retry)
+            && let Some(retry_at) = status.next_run
+        {
+            at = at.max(retry_at);
+        }
+        status.next_run = Some(at);
+        status.next_trigger = Some(trigger);
+        drop(status);
+        self.wake.notify_one();
+    }
+
+    fn record_success(&self, config: &SyncConfig, now: i64, pending: Vec<PendingDeletion>) {
+        let mut status = self.lock();
+        status.running = false;
+        status.last_success = Some(now);
+        status.consecutive_failures = 0;
+        status.pending_deletions = pending;
+        schedule_periodic(&mut status, config, now);
+    }
+
+    /// Nothing to sync with: neither a success nor a failure to retry
+    fn record_skipped(&self, config: &SyncConfig, now: i64) {
+        let mut status = self.lock();
+        status.running = false;
+        status.last_skipped = Some(now);
+        schedule_periodic(&mut status, config, now);
+    }
+
+    fn record_failure(&self, error: &SyncError, now: i64) {
+        let mut status = self.lock();
+        status.running = false;
+        status.last_error = Some(error.user_message());
+        status.last_error_at = Some(now);
+        status.consecutive_failures += 1;
+        let retry_at = now + backoff_delay(status.consecutive_failures);
+        // An event scheduled during the run still waits for the backoff
+        status.next_run = Some(status.next_run.map_or(retry_at, |at| at.max(retry_at)));
+        status.next_trigger = Some(SyncTrigger::Retry);
+    }
+
+    /// Take the due run, if any, returning how long to wait otherwise
+    fn take_due(&self, now: i64) -> Result<SyncTrigger, Option<Duration>> {
+        let mut status = self.lock();
+        match (status.next_run, status.next_trigger) {
+            (Some(at), Some(trigger)) if at <= now => {
+                status.next_run = None;
+                status.next_trigger = None;
+                status.running = true;
+                status.last_run = Some(now);
+                Ok(trigger)
+            }
+            (Some(at), _) => Err(Some(Duration::from_millis((at - now).unsigned_abs()))),
+            (None, _) => Err(None),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerStatus> {
+        self.status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn schedule_periodic(status: &mut SchedulerStatus, config: &SyncConfig, now: i64) {
+    if status.next_run.is_none() && config.sync_interval_minutes > 0 {
+        status.next_run = Some(now + interval_ms(config.sync_interval_minutes));
+        status.next_trigger = Some(SyncTrigger::Periodic);
+    }
+}
+
+/// Delay before retry number `failures` (1-based): 30s, 1m, 2m, ... up to 1h
+fn backoff_delay(failures: u32) -> i64 {
+    let exponent = failures.saturating_sub(1).min(16);
+    (BACKOFF_BASE_MS << exponent).min(BACKOFF_MAX_MS)
+}
+
+fn interval_ms(minutes: u32) -> i64 {
+    i64::from(minutes) * 60 * 1000
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Start the background loop if a `LocalLibrary` is registered. Runs are
+/// skipped until a backend is configured.
+pub fn spawn(state: SyncState) {
+    if state.library.is_none() {
+        info!("[SCHEDULER] No local library, background sync is disabled");
+        return;
+    }
+    state.scheduler.lock().enabled = true;
+
+    tokio::spawn(async move {
+        let config = state.get_sync_config();
+        if !state.scheduler.trigger(&config, SyncTrigger::AppStart) {
+            state.scheduler.reschedule(&config);
+        }
+
+        loop {
+            match state.scheduler.take_due(now()) {
+                Ok(trigger) => run_once(&state, trigger).await,
+                Err(Some(delay)) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = state.scheduler.wake.notified() => {}
+                    }
+                }
+                Err(None) => state.scheduler.wake.notified().await,
+            }
+        }
+    });
+}
+
+async fn run_once(state: &SyncState, trigger: SyncTrigger) {
+    let config = state.get_sync_config();
+    if active_backend_type(state) == SyncBackendType::None {
+        info!(
+            "[SCHEDULER] No sync backend configured, skipping {:?}",
+            trigger
+        );
+        state.scheduler.record_skipped(&config, now());
+        return;
+    }
+
+    info!("[SCHEDULER] Starting background sync ({:?})", trigger);
+    match sync_library(state).await {
+        Ok(pending) => {
+            info!(
+                "[SCHEDULER] Background sync complete, {} deletions awaiting confirmation",
+                pending.len()
+            );
+            state.scheduler.record_success(&config, now(), pending);
+        }
+        Err(e) => {
+            warn!("[SCHEDULER] Background sync failed: {}", e);
+            state.scheduler.record_failure(&e, now());
+        }
+    }
+}
+
+/// Merge the local library with the remote, returning the deletions that
+/// need the user's confirmation
+pub(crate) async fn sync_library(state: &SyncState) -> Result<Vec<PendingDeletion>, SyncError> {
+    let library = state.library.clone().ok_or_else(|| {
+        SyncError::Other(anyhow::anyhow!(
+            "No local library is available for background sync"
+        ))
+    })?;
+
+    // The library only holds reading state, so book objects stay where they are
+    let payload = library.export_payload(&state.get_device_id()).await?;
+    let response = merge_reading_state(
+        state.clone(),
+        MergeRequest {
+            payload,
+            config: None,
+            deletion_decisions: Vec::new(),
+        },
+    )
+    .await?;
+
+    // Nobody is there to confirm deletions, so those entries stay for now
+    let mut payload = response.payload;
+    payload
+        .tombstones
+        .remove_pending(&response.pending_deletions);
+    library.apply_payload(&payload).await?;
+    Ok(response.pending_deletions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        library::LocalLibrary,
+        test_util::{local_folder_state, unique_temp_dir},
+        types::{
+            ConflictResolution, DeletionBehavior, LNParsedBook, LNProgress, SyncPayload, Tombstone,
+        },
+    };
+
+    /// Library holding one payload in memory
+    #[derive(Default)]
+    struct MemoryLibrary {
+        payload: Mutex<Option<SyncPayload>>,
+    }
+
+    #[async_trait]
+    impl LocalLibrary for MemoryLibrary {
+        async fn export_payload(&self, device_id: &str) -> Result<SyncPayload, SyncError> {
+            let stored = self.payload.lock().expect("library lock").clone();
+            Ok(stored.unwrap_or_else(|| SyncPayload::new(device_id.to_string())))
+        }
+
+        async fn apply_payload(&self, payload: &SyncPayload) -> Result<(), SyncError> {
+            *self.payload.lock().expect("library lock") = Some(payload.clone());
+            Ok(())
+        }
+    }
+
+    fn config() -> SyncConfig {
+        SyncConfig {
+            sync_on_chapter_read: true,
+            sync_interval_minutes: 10,
+            ..SyncConfig::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        assert_eq!(backoff_delay(1), 30_000);
+        assert_eq!(backoff_delay(2), 60_000);
+        assert_eq!(backoff_delay(3), 120_000);
+        assert_eq!(backoff_delay(20), BACKOFF_MAX_MS);
+    }
+
+    fn enabled_scheduler() -> Scheduler {
+        let scheduler = Scheduler::default();
+        scheduler.lock().enabled = true;
+        scheduler
+    }
+
+    #[test]
+    fn events_are_debounced_and_disabled_triggers_ignored() {
+        let scheduler = enabled_scheduler();
+        let config = config();
+        assert!(!scheduler.trigger(&config, SyncTrigger::ChapterOpen));
+        assert!(scheduler.status().next_run.is_none());
+
+        scheduler.schedule_event(SyncTrigger::ChapterRead, 30_000, 1_000);
+        scheduler.schedule_event(SyncTrigger::ChapterRead, 30_000, 5_000);
+        assert_eq!(scheduler.status().next_run, Some(35_000));
+        assert!(scheduler.take_due(34_999).is_err());
+        assert_eq!(scheduler.take_due(35_000), Ok(SyncTrigger::ChapterRead));
+        assert!(scheduler.status().running);
+    }
+
+    #[test]
+    fn failures_back_off_and_success_resumes_the_interval() {
+        let scheduler = Scheduler::default();
+        let config = config();
+        let error = SyncError::NotAuthenticated;
+
+        scheduler.record_failure(&error, 0);
+        scheduler.record_failure(&error, 0);
+        let status = scheduler.status();
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.next_run, Some(60_000));
+        assert_eq!(status.next_trigger, Some(SyncTrigger::Retry));
+
+        // Events don't cut the backoff short
+        scheduler.schedule_event(SyncTrigger::ChapterRead, 1_000, 0);
+        assert_eq!(scheduler.status().next_run, Some(60_000));
+
+        assert_eq!(scheduler.take_due(60_000), Ok(SyncTrigger::ChapterRead));
+        scheduler.record_success(&config, 60_000, Vec::new());
+        let status = scheduler.status();
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.last_success, Some(60_000));
+        assert_eq!(status.next_run, Some(60_000 + 10 * 60 * 1000));
+        assert_eq!(status.next_trigger, Some(SyncTrigger::Periodic));
+    }
+
+    #[tokio::test]
+    async fn without_a_library_nothing_is_scheduled() {
+        let state = SyncState::new(unique_temp_dir("scheduler-disabled"));
+        spawn(state.clone());
+
+        let config = config();
+        assert!(!state.scheduler.trigger(&config, SyncTrigger::ChapterRead));
+        state.scheduler.reschedule(&config);
+        let status = state.scheduler.status();
+        assert!(!status.enabled);
+        assert!(status.next_run.is_none());
+    }
+
+    #[tokio::test]
+    async fn runs_without_a_backend_are_skipped() {
+        let state = SyncState::new(unique_temp_dir("scheduler-idle"))
+            .with_library(Arc::new(MemoryLibrary::default()));
+
+        run_once(&state, SyncTrigger::Manual).await;
+        let status = state.scheduler.status();
+        assert!(status.last_skipped.is_some());
+        assert!(status.last_success.is_none());
+        assert_eq!(status.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn background_sync_merges_the_local_library() {
+        let root = unique_temp_dir("scheduler");
+
+        let library = Arc::new(MemoryLibrary::default());
+        let mut local = SyncPayload::new("device".to_string());
+        local
+            .ln_progress
+            .insert("book".to_string(), LNProgress::default());
+        library.apply_payload(&local).await.expect("library seeded");
+
+        let state = local_folder_state(
+            root.join("data"),
+            &root.join("shared"),
+            ConflictResolution::Automatic,
+        )
+        .with_library(library.clone());
+
+        run_once(&state, SyncTrigger::Manual).await;
+        let status = state.scheduler.status();
+        assert!(status.last_success.is_some(), "{:?}", status.last_error);
+        assert_eq!(status.consecutive_failures, 0);
+
+        let applied = library.payload.lock().expect("library lock").clone();
+        let applied = applied.expect("merged payload applied");
+        assert!(applied.ln_progress.contains_key("book"));
+        assert!(applied.last_modified > 0);
+    }
+
+    #[tokio::test]
+    async fn background_sync_keeps_unconfirmed_deletions() {
+        let root = unique_temp_dir("scheduler-deletions");
+        let folder = root.join("shared");
+        let ask_each_time = |state: SyncState| {
+            let mut config = state.get_sync_config();
+            config.deletion_behavior = DeletionBehavior::AskEachTime;
+            state.set_sync_config(&config).expect("config saved");
+            state
+        };
+
+        // Another device deleted the book after it was last read here
+        let now = now();
+        let frontend = ask_each_time(local_folder_state(
+            root.join("frontend"),
+            &folder,
+            ConflictResolution::Automatic,
+        ));
+        let mut payload = SyncPayload::new(frontend.get_device_id());
+        payload.tombstones.ln_progress.insert(
+            "book".to_string(),
+            Tombstone {
+                deleted_at: now,
+                device_id: None,
+            },
+        );
+        crate::routes::sync::merge(
+            frontend,
+            MergeRequest {
+                payload,
+                config: None,
+                deletion_decisions: Vec::new(),
+            },
+        )
+        .await
+        .expect("frontend merge succeeds");
+
+        let library = Arc::new(MemoryLibrary::default());
+        let mut local = SyncPayload::new("device".to_string());
+        local.ln_progress.insert(
+            "book".to_string(),
+            LNProgress {
+                last_modified: Some(now - 1_000),
+                ..LNProgress::default()
+            },
+        );
+        library.apply_payload(&local).await.expect("library seeded");
+        let state = ask_each_time(local_folder_state(
+            root.join("data"),
+            &folder,
+            ConflictResolution::Automatic,
+        ))
+        .with_library(library.clone());
+
+        run_once(&state, SyncTrigger::Manual).await;
+        let status = state.scheduler.status();
+        assert!(status.last_success.is_some(), "{:?}", status.last_error);
+        assert_eq!(status.pending_deletions.len(), 1);
+        assert_eq!(status.pending_deletions[0].id, "book");
+
+        let applied = library.payload.lock().expect("library lock").clone();
+        let applied = applied.expect("merged payload applied");
+        assert!(applied.ln_progress.contains_key("book"));
+        assert!(applied.tombstones.ln_progress.is_empty());
+    }
+
+    #[tokio::test]
+    async fn background_sync_leaves_book_objects_alone() {
+        let root = unique_temp_dir("scheduler-objects");
+        let folder = root.join("shared");
+
+        // Another device uploads a book's content as an object
+        let frontend = local_folder_state(
+            root.join("frontend"),
+            &folder,
+            ConflictResolution::Automatic,
+        );
+        let mut payload = SyncPayload::new(frontend.get_device_id());
+        payload.ln_content.insert(
+            "book".to_string(),
+            LNParsedBook {
+                chapters: vec!["<p>一</p>".to_string()],
+                image_blobs: HashMap::new(),
+                chapter_filenames: vec!["ch1.xhtml".to_string()],
+                css: None,
+            },
+        );
+        crate::routes::sync::merge(
+            frontend,
+            MergeRequest {
+                payload,
+                config: None,
+                deletion_decisions: Vec::new(),
+            },
+        )
+        .await
+        .expect("frontend merge succeeds");
+
+        let library = Arc::new(MemoryLibrary::default());
+        let state = local_folder_state(root.join("data"), &folder, ConflictResolution::Automatic)
+            .with_library(library.clone());
+        run_once(&state, SyncTrigger::Periodic).await;
+        assert!(state.scheduler.status().last_success.is_some());
+
+        let applied = library.payload.lock().expect("library lock").clone();
+        let applied = applied.expect("merged payload applied");
+        assert!(applied.ln_content.is_empty());
+        assert!(applied.file_manifest.contains_key("content:book"));
+        let objects = std::fs::read_dir(folder.join(crate::delta::OBJECT_FOLDER))
+            .expect("object folder exists")
+            .count();
+        assert_eq!(objects, 1);
+    }
+}
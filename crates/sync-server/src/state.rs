@@ -3,7 +3,13 @@ use std::{path::PathBuf, sync::Arc};
 use sled::Db;
 use tokio::sync::RwLock;
 
-use crate::{backend::google_drive::GoogleDriveBackend, types::SyncConfig};
+use crate::{
+    backend::ActiveBackend,
+    crypto::KeyCache,
+    library::LocalLibrary,
+    scheduler::Scheduler,
+    types::{MediaLibrary, ReaderSettingsSync, SyncConfig},
+};
 
 const DB_KEY_DEVICE_ID: &[u8] = b"device_id";
 const DB_KEY_ACCESS_TOKEN: &[u8] = b"google_access_token";
@@ -14,12 +20,24 @@ const DB_KEY_SYNC_CONFIG: &[u8] = b"sync_config";
 const DB_KEY_AUTH_STATE: &[u8] = b"oauth_state";
 const DB_KEY_AUTH_REDIRECT_URI: &[u8] = b"oauth_redirect_uri";
 const DB_KEY_AUTH_CODE_VERIFIER: &[u8] = b"oauth_code_verifier";
+const DB_KEY_WEBDAV_CREDENTIALS: &[u8] = b"webdav_credentials";
+const DB_KEY_SYNCYOMI_API_KEY: &[u8] = b"syncyomi_api_key";
+const DB_KEY_ENCRYPTION_PASSPHRASE: &[u8] = b"encryption_passphrase";
+const DB_KEY_MEDIA_LIBRARY: &[u8] = b"media_library";
+const DB_KEY_READER_SETTINGS: &[u8] = b"reader_settings";
 
 #[derive(Clone)]
 pub struct SyncState {
     pub db: Db,
     pub data_dir: PathBuf,
-    pub google_drive: Arc<RwLock<Option<GoogleDriveBackend>>>,
+    pub backend: ActiveBackend,
+    /// Last derived encryption key, so Argon2 does not run for every object
+    pub(crate) key_cache: KeyCache,
+    /// Local library used by background syncs, if the host provides one
+    pub library: Option<Arc<dyn LocalLibrary>>,
+    pub scheduler: Scheduler,
+    /// Passphrase used instead of the stored one (see `with_passphrase`)
+    passphrase_override: Option<Option<String>>,
 }
 
 impl SyncState {
@@ -37,20 +55,22 @@ impl SyncState {
                 .expect("Failed to generate device ID");
         }
 
-        let state = Self {
+        // The backend is initialized lazily on first use (see `backend::load_backend`)
+        Self {
             db,
             data_dir: sync_dir,
-            google_drive: Arc::new(RwLock::new(None)),
-        };
-
-        // Try to initialize Google Drive if tokens exist
-        let access_token = state.get_access_token();
-        let refresh_token = state.get_refresh_token();
-        if access_token.is_some() && refresh_token.is_some() {
-            // Will be initialized lazily on first use
+            backend: Arc::new(RwLock::new(None)),
+            key_cache: KeyCache::default(),
+            library: None,
+            scheduler: Scheduler::default(),
+            passphrase_override: None,
         }
+    }
 
-        state
+    /// Let the scheduler sync `library` without a frontend
+    pub fn with_library(mut self, library: Arc<dyn LocalLibrary>) -> Self {
+        self.library = Some(library);
+        self
     }
 
     // Device ID
@@ -99,6 +119,87 @@ impl SyncState {
         Ok(())
     }
 
+    // WebDAV Credentials
+    pub fn get_webdav_credentials(&self) -> Option<WebDavCredentials> {
+        self.db
+            .get(DB_KEY_WEBDAV_CREDENTIALS)
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_slice(&v).ok())
+    }
+
+    pub fn set_webdav_credentials(
+        &self,
+        credentials: &WebDavCredentials,
+    ) -> Result<(), sled::Error> {
+        let bytes = serde_json::to_vec(credentials).unwrap_or_default();
+        self.db.insert(DB_KEY_WEBDAV_CREDENTIALS, bytes)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn clear_webdav_credentials(&self) -> Result<(), sled::Error> {
+        self.db.remove(DB_KEY_WEBDAV_CREDENTIALS)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // SyncYomi API Key
+    pub fn get_syncyomi_api_key(&self) -> Option<String> {
+        self.db
+            .get(DB_KEY_SYNCYOMI_API_KEY)
+            .ok()
+            .flatten()
+            .map(|v| String::from_utf8_lossy(&v).to_string())
+    }
+
+    pub fn set_syncyomi_api_key(&self, api_key: &str) -> Result<(), sled::Error> {
+        self.db
+            .insert(DB_KEY_SYNCYOMI_API_KEY, api_key.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn clear_syncyomi_api_key(&self) -> Result<(), sled::Error> {
+        self.db.remove(DB_KEY_SYNCYOMI_API_KEY)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Copy of this state that encrypts with `passphrase` without storing
+    /// it, so the remote can be rewritten before a new passphrase is saved
+    pub(crate) fn with_passphrase(&self, passphrase: Option<&str>) -> Self {
+        Self {
+            passphrase_override: Some(passphrase.map(str::to_string)),
+            ..self.clone()
+        }
+    }
+
+    // End-to-end encryption passphrase (kept on this device only)
+    pub fn get_encryption_passphrase(&self) -> Option<String> {
+        if let Some(passphrase) = &self.passphrase_override {
+            return passphrase.clone();
+        }
+        self.db
+            .get(DB_KEY_ENCRYPTION_PASSPHRASE)
+            .ok()
+            .flatten()
+            .map(|v| String::from_utf8_lossy(&v).to_string())
+    }
+
+    pub fn set_encryption_passphrase(&self, passphrase: &str) -> Result<(), sled::Error> {
+        self.db
+            .insert(DB_KEY_ENCRYPTION_PASSPHRASE, passphrase.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn clear_encryption_passphrase(&self) -> Result<(), sled::Error> {
+        self.db.remove(DB_KEY_ENCRYPTION_PASSPHRASE)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
     // OAuth State (for CSRF protection)
     pub fn set_auth_state(&self, state: &str) -> Result<(), sled::Error> {
         self.db.insert(DB_KEY_AUTH_STATE, state.as_bytes())?;
@@ -207,6 +308,39 @@ impl SyncState {
         Ok(())
     }
 
+    // Manga/anime state (the local copy that `/merge` syncs)
+    pub fn get_media_library(&self) -> MediaLibrary {
+        self.db
+            .get(DB_KEY_MEDIA_LIBRARY)
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_slice(&v).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn set_media_library(&self, media: &MediaLibrary) -> Result<(), sled::Error> {
+        let bytes = serde_json::to_vec(media).unwrap_or_default();
+        self.db.insert(DB_KEY_MEDIA_LIBRARY, bytes)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    // Reader settings
+    pub fn get_reader_settings(&self) -> Option<ReaderSettingsSync> {
+        self.db
+            .get(DB_KEY_READER_SETTINGS)
+            .ok()
+            .flatten()
+            .and_then(|v| serde_json::from_slice(&v).ok())
+    }
+
+    pub fn set_reader_settings(&self, settings: &ReaderSettingsSync) -> Result<(), sled::Error> {
+        let bytes = serde_json::to_vec(settings).unwrap_or_default();
+        self.db.insert(DB_KEY_READER_SETTINGS, bytes)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
     // Upload tracking (for resumable uploads)
     pub fn get_upload_state(&self, upload_id: &str) -> Option<UploadState> {
         let key = format!("upload:{upload_id}");
@@ -241,11 +375,36 @@ impl SyncState {
 pub struct UploadState {
     pub upload_id: String,
     pub book_id: String,
-    pub file_type: String, // "content" or "file"
+    pub file_type: String, // "content", "file", "payload" or "object"
     pub file_hash: String,
     pub total_size: u64,
     pub uploaded_bytes: u64,
     pub resumable_uri: Option<String>,
     pub started_at: i64,
     pub last_chunk_at: i64,
+    /// Copy of the bytes being uploaded. Encoding is not deterministic, so a
+    /// retry continues with these rather than with freshly encoded data.
+    #[serde(default)]
+    pub spool_path: Option<PathBuf>,
+    /// Remote version the upload replaces; a session only continues on top
+    /// of the same version
+    #[serde(default)]
+    pub base_version: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDavCredentials {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub auth_type: WebDavAuthType,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebDavAuthType {
+    #[default]
+    Basic,
+    Digest,
 }
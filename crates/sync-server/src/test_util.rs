@@ -0,0 +1,39 @@
+//! Helpers shared by the crate's tests
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    SyncState,
+    types::{ConflictResolution, SyncBackendType},
+};
+
+/// Creates an empty directory under the system temp dir, unique per call
+pub(crate) fn unique_temp_dir(label: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let dir = std::env::temp_dir().join(format!("manatan-sync-{label}-{nanos}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("temp dir should be created");
+    dir
+}
+
+/// State in `data_dir` syncing through the local folder backend at `folder`
+pub(crate) fn local_folder_state(
+    data_dir: PathBuf,
+    folder: &Path,
+    conflict_resolution: ConflictResolution,
+) -> SyncState {
+    std::fs::create_dir_all(folder).expect("shared folder should be created");
+    let state = SyncState::new(data_dir);
+    let mut config = state.get_sync_config();
+    config.backend = SyncBackendType::LocalFolder;
+    config.local_folder_path = folder.display().to_string();
+    config.conflict_resolution = conflict_resolution;
+    state.set_sync_config(&config).expect("config saved");
+    state
+}
@@ -72,9 +72,15 @@ pub struct LNProgress {
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub highlights: Vec<LNHighlight>,
+
+    /// Deleted highlights (highlight id → deletion time), so a deletion on one
+    /// device is not undone by another device that still has the highlight
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[serde(alias = "deletedHighlights")]
+    pub deleted_highlights: HashMap<String, i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct LNHighlight {
     pub id: String,
@@ -84,6 +90,18 @@ pub struct LNHighlight {
     pub start_offset: i32,
     pub end_offset: i32,
     pub created_at: i64,
+
+    /// Last edit time, `created_at` if never edited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "updatedAt")]
+    pub updated_at: Option<i64>,
+}
+
+impl LNHighlight {
+    /// Time of the last change to this highlight
+    pub fn modified_at(&self) -> i64 {
+        self.updated_at.unwrap_or(self.created_at)
+    }
 }
 
 impl LNProgress {
@@ -213,6 +231,63 @@ impl LNReaderSettings {
     }
 }
 
+/// Reader settings shared by every device, with per-device overrides on top
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReaderSettingsSync {
+    pub global: LNReaderSettings,
+
+    /// When the global settings were last changed
+    #[serde(default)]
+    pub global_modified: i64,
+
+    /// Device ID → settings that differ on that device (e.g. font size on a
+    /// phone)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub device_overrides: HashMap<String, ReaderSettingsOverride>,
+}
+
+impl Default for ReaderSettingsSync {
+    fn default() -> Self {
+        Self {
+            global: LNReaderSettings::default_settings(),
+            global_modified: 0,
+            device_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl ReaderSettingsSync {
+    /// Global settings with the override of `device_id` applied
+    pub fn effective(&self, device_id: &str) -> LNReaderSettings {
+        let Some(device) = self.device_overrides.get(device_id) else {
+            return self.global.clone();
+        };
+        let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(&self.global) else {
+            return self.global.clone();
+        };
+        for (key, value) in &device.settings {
+            if settings.contains_key(key) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+        serde_json::from_value(serde_json::Value::Object(settings))
+            .unwrap_or_else(|_| self.global.clone())
+    }
+}
+
+/// Settings one device keeps apart from the global ones
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReaderSettingsOverride {
+    /// Overridden settings by their camelCase name; an empty map clears the
+    /// override on every device
+    #[serde(default)]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+
+    pub last_modified: i64,
+}
+
 // ============================================================================
 // Light Novel Metadata
 // ============================================================================
@@ -381,6 +456,93 @@ pub enum FileType {
     Content,
 }
 
+// ============================================================================
+// Manga & Anime
+// ============================================================================
+
+/// Read state of one manga chapter
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MangaChapterState {
+    pub read: bool,
+    pub bookmarked: bool,
+    /// Last page read (0-based), 0 if never opened
+    #[serde(default)]
+    pub last_page_read: i32,
+    pub last_modified: i64,
+}
+
+/// A manga in the library.
+///
+/// Suwayomi ids differ between servers, so entries are keyed by
+/// `"{source_id}:{url}"` and chapters by their URL.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MangaEntry {
+    pub source_id: String,
+    pub url: String,
+    pub title: String,
+    pub in_library: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub category_ids: Vec<String>,
+    /// Chapter URL → state
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub chapters: HashMap<String, MangaChapterState>,
+    pub last_modified: i64,
+}
+
+/// Watch state of one anime episode
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimeEpisodeState {
+    pub watched: bool,
+    pub bookmarked: bool,
+    /// Playback position in milliseconds, 0 if never started
+    #[serde(default)]
+    pub last_position_ms: i64,
+    pub last_modified: i64,
+}
+
+/// An anime in the library, keyed like `MangaEntry`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimeEntry {
+    pub source_id: String,
+    pub url: String,
+    pub title: String,
+    pub in_library: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub category_ids: Vec<String>,
+    /// Episode URL → state
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub episodes: HashMap<String, AnimeEpisodeState>,
+    pub last_modified: i64,
+}
+
+/// Manga and anime categories have the same shape as LN categories
+pub type MediaCategory = LnCategory;
+
+/// The manga and anime sections of a payload
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaLibrary {
+    #[serde(default)]
+    pub manga: HashMap<String, MangaEntry>,
+
+    #[serde(default)]
+    pub anime: HashMap<String, AnimeEntry>,
+
+    #[serde(default)]
+    pub manga_categories: HashMap<String, MediaCategory>,
+
+    #[serde(default)]
+    pub anime_categories: HashMap<String, MediaCategory>,
+
+    /// Deleted entries; only the manga and anime sections are used
+    #[serde(default, skip_serializing_if = "Tombstones::is_empty")]
+    pub tombstones: Tombstones,
+}
+
 // ============================================================================
 // Sync Payload
 // ============================================================================
@@ -429,10 +591,32 @@ pub struct SyncPayload {
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     #[serde(alias = "lnCategoryMetadata")]
     pub ln_category_metadata: HashMap<String, LnCategoryMetadata>,
+
+    /// Manga library and chapter state (entry key → entry)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub manga: HashMap<String, MangaEntry>,
+
+    /// Anime library and episode state (entry key → entry)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub anime: HashMap<String, AnimeEntry>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub manga_categories: HashMap<String, MediaCategory>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub anime_categories: HashMap<String, MediaCategory>,
+
+    /// Global reader settings and per-device overrides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reader_settings: Option<ReaderSettingsSync>,
+
+    /// Deleted entries, so the deletion can reach other devices
+    #[serde(default, skip_serializing_if = "Tombstones::is_empty")]
+    pub tombstones: Tombstones,
 }
 
 impl SyncPayload {
-    pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+    pub const CURRENT_SCHEMA_VERSION: u32 = 4;
 
     pub fn new(device_id: String) -> Self {
         Self {
@@ -442,6 +626,157 @@ impl SyncPayload {
             ..Default::default()
         }
     }
+
+    /// Move the manga and anime sections out of the payload
+    pub fn take_media(&mut self) -> MediaLibrary {
+        MediaLibrary {
+            manga: std::mem::take(&mut self.manga),
+            anime: std::mem::take(&mut self.anime),
+            manga_categories: std::mem::take(&mut self.manga_categories),
+            anime_categories: std::mem::take(&mut self.anime_categories),
+            tombstones: Tombstones {
+                manga: std::mem::take(&mut self.tombstones.manga),
+                anime: std::mem::take(&mut self.tombstones.anime),
+                manga_categories: std::mem::take(&mut self.tombstones.manga_categories),
+                anime_categories: std::mem::take(&mut self.tombstones.anime_categories),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Replace the manga and anime sections of the payload
+    pub fn set_media(&mut self, media: MediaLibrary) {
+        self.manga = media.manga;
+        self.anime = media.anime;
+        self.manga_categories = media.manga_categories;
+        self.anime_categories = media.anime_categories;
+        self.tombstones.manga = media.tombstones.manga;
+        self.tombstones.anime = media.tombstones.anime;
+        self.tombstones.manga_categories = media.tombstones.manga_categories;
+        self.tombstones.anime_categories = media.tombstones.anime_categories;
+    }
+}
+
+// ============================================================================
+// Deletions
+// ============================================================================
+
+/// Record of a deleted entry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    /// When the entry was deleted
+    #[serde(alias = "deletedAt")]
+    pub deleted_at: i64,
+
+    /// Device that deleted the entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(alias = "deviceId")]
+    pub device_id: Option<String>,
+}
+
+/// Tombstones per payload section (id → tombstone)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstones {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ln_progress: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ln_metadata: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ln_content: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ln_files: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ln_categories: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub manga: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub anime: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub manga_categories: HashMap<String, Tombstone>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub anime_categories: HashMap<String, Tombstone>,
+}
+
+impl Tombstones {
+    pub fn is_empty(&self) -> bool {
+        self.ln_progress.is_empty()
+            && self.ln_metadata.is_empty()
+            && self.ln_content.is_empty()
+            && self.ln_files.is_empty()
+            && self.ln_categories.is_empty()
+            && self.manga.is_empty()
+            && self.anime.is_empty()
+            && self.manga_categories.is_empty()
+            && self.anime_categories.is_empty()
+    }
+
+    pub fn section_mut(&mut self, section: SyncSection) -> &mut HashMap<String, Tombstone> {
+        match section {
+            SyncSection::LnProgress => &mut self.ln_progress,
+            SyncSection::LnMetadata => &mut self.ln_metadata,
+            SyncSection::LnContent => &mut self.ln_content,
+            SyncSection::LnFiles => &mut self.ln_files,
+            SyncSection::LnCategories => &mut self.ln_categories,
+            SyncSection::Manga => &mut self.manga,
+            SyncSection::Anime => &mut self.anime,
+            SyncSection::MangaCategories => &mut self.manga_categories,
+            SyncSection::AnimeCategories => &mut self.anime_categories,
+        }
+    }
+
+    /// Drop the tombstones of deletions still waiting for confirmation, so
+    /// applying the payload locally keeps those entries
+    pub fn remove_pending(&mut self, pending: &[PendingDeletion]) {
+        for deletion in pending {
+            self.section_mut(deletion.section).remove(&deletion.id);
+        }
+    }
+}
+
+/// Payload section a tombstone belongs to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncSection {
+    LnProgress,
+    LnMetadata,
+    LnContent,
+    LnFiles,
+    LnCategories,
+    Manga,
+    Anime,
+    MangaCategories,
+    AnimeCategories,
+}
+
+/// A deletion waiting for the user to confirm (`DeletionBehavior::AskEachTime`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingDeletion {
+    pub section: SyncSection,
+    pub id: String,
+    pub deleted_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+}
+
+/// The user's answer to a `PendingDeletion`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionDecision {
+    pub section: SyncSection,
+    pub id: String,
+    /// true deletes the entry everywhere, false keeps it and drops the tombstone
+    pub delete: bool,
 }
 
 /// Request body for merge endpoint
@@ -454,6 +789,10 @@ pub struct MergeRequest {
     /// What to sync (optional, uses stored config if not provided)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config: Option<SyncConfig>,
+
+    /// Answers to pending deletions from an earlier merge
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deletion_decisions: Vec<DeletionDecision>,
 }
 
 /// Response from merge endpoint
@@ -477,6 +816,11 @@ pub struct MergeResponse {
     /// Any conflicts that occurred (informational)
     #[serde(default)]
     pub conflicts: Vec<ConflictInfo>,
+
+    /// Deletions from other devices that need confirmation
+    /// (only with `DeletionBehavior::AskEachTime`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_deletions: Vec<PendingDeletion>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -502,12 +846,24 @@ pub struct SyncConfig {
     pub ln_metadata: bool,
     pub ln_content: bool,
     pub ln_files: bool,
+    #[serde(default = "default_true")]
+    pub manga: bool,
+    #[serde(default = "default_true")]
+    pub anime: bool,
+    #[serde(default = "default_true")]
+    pub reader_settings: bool,
 
     // Sync triggers (matching Tachiyomi)
     pub sync_on_chapter_read: bool,
     pub sync_on_chapter_open: bool,
     pub sync_on_app_start: bool,
     pub sync_on_app_resume: bool,
+    /// Minutes between background syncs (0 disables periodic sync)
+    #[serde(default)]
+    pub sync_interval_minutes: u32,
+    /// Seconds to wait after a trigger so a burst of events syncs once
+    #[serde(default = "default_sync_debounce_seconds")]
+    pub sync_debounce_seconds: u32,
 
     // Backend selection
     pub backend: SyncBackendType,
@@ -516,8 +872,30 @@ pub struct SyncConfig {
     pub google_drive_folder: String,
     pub google_drive_folder_type: GoogleDriveFolderType,
 
+    // WebDAV settings (credentials are stored separately in SyncState)
+    #[serde(default)]
+    pub webdav_url: String,
+    #[serde(default = "default_webdav_path")]
+    pub webdav_path: String,
+
+    // SyncYomi settings (the API key is stored separately in SyncState)
+    #[serde(default)]
+    pub syncyomi_url: String,
+
+    // Local folder settings (e.g. a Syncthing folder or USB stick)
+    #[serde(default)]
+    pub local_folder_path: String,
+
     // Deletion behavior
     pub deletion_behavior: DeletionBehavior,
+
+    /// Whether conflicting progress and metadata wait for the user
+    #[serde(default)]
+    pub conflict_resolution: ConflictResolution,
+
+    /// Remote payloads kept as snapshots before they are overwritten (0 disables)
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
 }
 
 impl Default for SyncConfig {
@@ -527,18 +905,45 @@ impl Default for SyncConfig {
             ln_metadata: true,
             ln_content: true,
             ln_files: false,
+            manga: true,
+            anime: true,
+            reader_settings: true,
             sync_on_chapter_read: false,
             sync_on_chapter_open: false,
             sync_on_app_start: false,
             sync_on_app_resume: false,
+            sync_interval_minutes: 0,
+            sync_debounce_seconds: default_sync_debounce_seconds(),
             backend: SyncBackendType::None,
             google_drive_folder: "Manatan".to_string(),
             google_drive_folder_type: GoogleDriveFolderType::Public,
+            webdav_url: String::new(),
+            webdav_path: default_webdav_path(),
+            syncyomi_url: String::new(),
+            local_folder_path: String::new(),
             deletion_behavior: DeletionBehavior::KeepEverywhere,
+            conflict_resolution: ConflictResolution::Automatic,
+            history_limit: default_history_limit(),
         }
     }
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_sync_debounce_seconds() -> u32 {
+    30
+}
+
+fn default_history_limit() -> usize {
+    20
+}
+
+fn default_webdav_path() -> String {
+    "Manatan".to_string()
+}
+
 /// Google Drive folder type selection
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -548,6 +953,17 @@ pub enum GoogleDriveFolderType {
     AppData,
 }
 
+/// How conflicting progress and metadata are settled
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictResolution {
+    /// Furthest progress and newest metadata win
+    #[default]
+    Automatic,
+    /// Keep the remote value and hold the conflict until the user picks a side
+    Manual,
+}
+
 /// Deletion behavior when syncing
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -566,4 +982,5 @@ pub enum SyncBackendType {
     GoogleDrive,
     WebDav,
     SyncYomi,
+    LocalFolder,
 }